@@ -0,0 +1,69 @@
+//! Count the allocations made while simplifying and differentiating the conformance corpus, to
+//! compare changes to how expressions are stored.
+//!
+//! Run with `cargo run --release --example allocations --features test-suite`.
+
+#![allow(special_module_name)]
+
+#[path = "../src/lib/mod.rs"]
+pub mod lib;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lib::conformance;
+use lib::Expr;
+
+/// The system allocator, counting how many allocations are made and how many bytes they are
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Run something over every expression in the corpus, and print what it allocated
+fn measure(name: &str, corpus: &[Expr], f: impl Fn(Expr) -> Expr) {
+    let (allocations, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+    );
+    for e in corpus {
+        drop(f(e.clone()));
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = BYTES.load(Ordering::Relaxed) - bytes;
+    println!(
+        "{name:<12} {:>10} allocations {:>12} bytes ({:.1} allocations per expression)",
+        allocations,
+        bytes,
+        allocations as f64 / corpus.len() as f64
+    );
+}
+
+fn main() {
+    let corpus = conformance::corpus(0, 1000);
+    println!("size_of::<Expr>() = {}", std::mem::size_of::<Expr>());
+    measure("clone", &corpus, |e| e);
+    measure("simplify", &corpus, Expr::simplified);
+    measure("derivative", &corpus, Expr::derivative);
+}
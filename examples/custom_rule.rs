@@ -0,0 +1,45 @@
+//! Check a rewrite rule written outside of the crate with the conformance checks.
+//!
+//! Run with `cargo run --example custom_rule --features test-suite`.
+
+#![allow(special_module_name)]
+
+#[path = "../src/lib/mod.rs"]
+pub mod lib;
+
+use lib::conformance;
+use lib::Expr;
+
+/// `sin(-u) = -sin(u)`, and the same for the other odd functions
+fn odd_functions(e: &mut Expr) {
+    let inner = match e {
+        Expr::Sin(u) | Expr::Arcsin(u) | Expr::Arctan(u) => u,
+        _ => return,
+    };
+    let Expr::Neg(u) = inner.as_mut() else {
+        return;
+    };
+    let u = std::mem::take(u.as_mut());
+    **inner = u;
+    *e = -std::mem::take(e);
+}
+
+/// Apply the rule everywhere, and then simplify
+fn rewrite(e: &mut Expr) {
+    e.transform_bottom_up(&mut odd_functions);
+    e.simplify();
+}
+
+fn main() {
+    // The generated corpus has no negative arguments, so add some
+    let mut corpus = conformance::corpus(1, 100);
+    corpus.extend(corpus.clone().into_iter().map(|e| e.substitute(&-Expr::X)));
+
+    match conformance::check_rewrite(rewrite, &corpus) {
+        Ok(()) => println!("the rule passed on {} expressions", corpus.len()),
+        Err(violation) => {
+            eprintln!("{violation}");
+            std::process::exit(1);
+        }
+    }
+}
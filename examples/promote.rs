@@ -0,0 +1,42 @@
+//! Time promoting a child to replace its parent, by simplifying `e^1` to `e` for bigger and
+//! bigger e. The child is moved rather than cloned, so the time shouldn't grow with its size.
+//!
+//! Run with `cargo run --release --example promote`.
+
+#![allow(special_module_name)]
+
+#[path = "../src/lib/mod.rs"]
+pub mod lib;
+
+use std::time::Instant;
+
+use lib::prelude::{c, pow, sin, x};
+use lib::{Expr, Num};
+
+const REPEATS: usize = 1000;
+
+/// A sum with `size` terms, each a few nodes
+fn huge(size: usize) -> Expr {
+    Expr::Sum(
+        (0..size)
+            .map(|n| sin(x() * n as Num) + pow(x(), c(2)))
+            .collect(),
+    )
+}
+
+fn main() {
+    for size in [10, 1_000, 100_000] {
+        let mut e = huge(size);
+        let nodes = e.node_count();
+        let start = Instant::now();
+        for _ in 0..REPEATS {
+            e = Expr::Pow(Box::new(e), Box::new(Expr::Const(1)));
+            e.simplify_one_pow();
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "{nodes:>8} nodes: {:>8.1?} per promotion",
+            elapsed / REPEATS as u32
+        );
+    }
+}
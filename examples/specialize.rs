@@ -0,0 +1,48 @@
+//! Time evaluating an expression in x and t for many values of x with t fixed, by putting t in
+//! each time against specializing the expression to t first.
+//!
+//! Run with `cargo run --release --example specialize`.
+
+#![allow(special_module_name)]
+
+#[path = "../src/lib/mod.rs"]
+pub mod lib;
+
+use std::time::Instant;
+
+use lib::prelude::{c, cos, exp, ln, pow, sin, sqrt, var, x};
+use lib::{Expr, VarId};
+
+const POINTS: usize = 100_000;
+
+fn main() {
+    let t = || var('t');
+    let e = exp(-t() * 3) * sin(x() * cos(t()) + sqrt(t() + 2))
+        + pow(x(), c(2)) * ln(t() + 1) / (t() * t() + 1);
+    // 0.37, as an expression
+    let (t_value, t_const) = (0.37, Expr::Scaled(37, -2));
+    let xs: Vec<f64> = (0..POINTS).map(|i| i as f64 * 1e-4 - 5.0).collect();
+
+    // t is put in for every point
+    let start = Instant::now();
+    let unspecialized: Vec<f64> = xs
+        .iter()
+        .map(|x| e.substitute_var(VarId('t'), &t_const).eval(*x).unwrap())
+        .collect();
+    let each_time = start.elapsed();
+
+    // t is put in once, and the parts which only use t are worked out then
+    let start = Instant::now();
+    let specialized = e.specialize(&[(VarId('t'), t_value)]);
+    let values: Vec<f64> = xs.iter().map(|x| specialized.eval(*x).unwrap()).collect();
+    let once = start.elapsed();
+
+    assert_eq!(values, unspecialized);
+    println!(
+        "{} nodes, {} after specializing",
+        e.node_count(),
+        specialized.node_count()
+    );
+    println!("putting t in each time: {each_time:?}");
+    println!("specializing first:     {once:?}");
+}
@@ -0,0 +1,679 @@
+use super::{Expr, Num, SimplifyOptions};
+
+/// Settings for [`Expr::analyze`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisOptions {
+    /// The smallest value of x which is looked at
+    pub start: f64,
+    /// The largest value of x which is looked at
+    pub end: f64,
+    /// How many evenly spaced points are sampled between the start and the end when looking for
+    /// zeros and for where the expression is defined
+    pub samples: usize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions {
+            start: -10.0,
+            end: 10.0,
+            samples: 2000,
+        }
+    }
+}
+
+/// An interval of real numbers, from `start` to `end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// The lower end of the interval
+    pub start: f64,
+    /// The upper end of the interval
+    pub end: f64,
+}
+
+/// Whether a critical point (where the derivative is 0) is a turning point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CriticalPointKind {
+    /// The expression stops increasing and starts decreasing
+    Maximum,
+    /// The expression stops decreasing and starts increasing
+    Minimum,
+    /// The expression carries on increasing or decreasing (e.g. `x^3` at 0)
+    Neither,
+}
+
+/// A point where the derivative of an expression is 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CriticalPoint {
+    /// The value of x at the point
+    pub x: f64,
+    /// The value of the expression at the point
+    pub y: f64,
+    /// Whether the point is a maximum or a minimum
+    pub kind: CriticalPointKind,
+}
+
+/// Everything needed to sketch the graph of an expression, found by [`Expr::analyze`].
+///
+/// Points are found numerically between the start and the end in the [`AnalysisOptions`]. A
+/// field is `None` when it couldn't be worked out, e.g. the critical points of an expression
+/// which can't be differentiated, or the asymptotes of something other than a rational function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurveReport {
+    /// Where the expression is defined. The ends of the intervals are where it stops being
+    /// defined, so they might not be in the domain themselves.
+    pub domain: Vec<Interval>,
+    /// The values of x where the expression is 0
+    pub x_intercepts: Vec<f64>,
+    /// The value of the expression at x = 0, if it is defined there
+    pub y_intercept: Option<f64>,
+    /// The points where the derivative is 0
+    pub critical_points: Option<Vec<CriticalPoint>>,
+    /// The values of x where the second derivative changes sign
+    pub inflection_points: Option<Vec<f64>>,
+    /// The values of x which the expression goes to infinity at
+    pub vertical_asymptotes: Option<Vec<f64>>,
+    /// The value the expression gets closer to as x goes to infinity, if there is one
+    pub horizontal_asymptote: Option<Expr>,
+    /// The line the expression gets closer to as x goes to infinity, if it isn't horizontal
+    pub oblique_asymptote: Option<Expr>,
+    /// Where the expression is increasing
+    pub increasing: Option<Vec<Interval>>,
+    /// Where the expression is decreasing
+    pub decreasing: Option<Vec<Interval>>,
+    /// Where the expression is concave up (curving upwards)
+    pub concave_up: Option<Vec<Interval>>,
+    /// Where the expression is concave down (curving downwards)
+    pub concave_down: Option<Vec<Interval>>,
+}
+
+impl Expr {
+    /// Find the domain, intercepts, critical and inflection points, asymptotes, and where an
+    /// expression is increasing and concave up, to sketch its graph.
+    ///
+    /// Asymptotes are only found for rational functions (a polynomial divided by a polynomial,
+    /// after combining fractions). Everything else is found numerically, so points which are
+    /// closer together than the gap between samples might be missed.
+    pub fn analyze(&self, options: &AnalysisOptions) -> CurveReport {
+        let f = |x: f64| self.eval(x).ok();
+        let rational = self.as_rational();
+        // Points which are outside the domain even though the samples around them aren't
+        let excluded = match &rational {
+            Some((_, denominator)) => zeros(|x| Some(eval_poly(denominator, x)), options),
+            None => vec![],
+        };
+        let domain = domain(f, &excluded, options);
+
+        let first = self.clone().try_derivative().ok().map(Expr::simplified);
+        let second = first
+            .clone()
+            .and_then(|e| e.try_derivative().ok())
+            .map(Expr::simplified);
+
+        let critical_points = first.as_ref().map(|first_expr| {
+            let d = |x| first_expr.eval(x).ok();
+            let h = (options.end - options.start) / options.samples as f64 / 2.0;
+            polynomial_zeros(first_expr, options)
+                .unwrap_or_else(|| zeros(d, options))
+                .into_iter()
+                .filter_map(|x| {
+                    let kind = match (d(x - h)?.signum(), d(x + h)?.signum()) {
+                        (l, r) if l > 0.0 && r < 0.0 => CriticalPointKind::Maximum,
+                        (l, r) if l < 0.0 && r > 0.0 => CriticalPointKind::Minimum,
+                        _ => CriticalPointKind::Neither,
+                    };
+                    Some(CriticalPoint { x, y: f(x)?, kind })
+                })
+                .collect::<Vec<_>>()
+        });
+        let inflection_points = second.as_ref().map(|d| {
+            let d = |x| d.eval(x).ok();
+            let h = (options.end - options.start) / options.samples as f64 / 2.0;
+            zeros(d, options)
+                .into_iter()
+                .filter(|x| matches!((d(x - h), d(x + h)), (Some(l), Some(r)) if l * r < 0.0))
+                .collect::<Vec<_>>()
+        });
+
+        let monotonic = |d: &Expr, points: &[f64]| {
+            let d = |x| d.eval(x).ok();
+            (
+                signed_intervals(&domain, points, |x| d(x).is_some_and(|v| v > 0.0)),
+                signed_intervals(&domain, points, |x| d(x).is_some_and(|v| v < 0.0)),
+            )
+        };
+        let (increasing, decreasing) = match (&first, &critical_points) {
+            (Some(d), Some(points)) => {
+                let points: Vec<f64> = points.iter().map(|p| p.x).collect();
+                let (up, down) = monotonic(d, &points);
+                (Some(up), Some(down))
+            }
+            _ => (None, None),
+        };
+        let (concave_up, concave_down) = match (&second, &inflection_points) {
+            (Some(d), Some(points)) => {
+                let (up, down) = monotonic(d, points);
+                (Some(up), Some(down))
+            }
+            _ => (None, None),
+        };
+
+        let (vertical_asymptotes, horizontal_asymptote, oblique_asymptote) = match &rational {
+            Some((numerator, denominator)) => {
+                let vertical = excluded
+                    .iter()
+                    .copied()
+                    .filter(|x| multiplicity(denominator, *x) > multiplicity(numerator, *x))
+                    .collect();
+                let (horizontal, oblique) = end_asymptote(numerator, denominator);
+                (Some(vertical), horizontal, oblique)
+            }
+            None => (None, None, None),
+        };
+
+        CurveReport {
+            x_intercepts: polynomial_zeros(self, options).unwrap_or_else(|| zeros(f, options)),
+            y_intercept: f(0.0),
+            domain,
+            critical_points,
+            inflection_points,
+            vertical_asymptotes,
+            horizontal_asymptote,
+            oblique_asymptote,
+            increasing,
+            decreasing,
+            concave_up,
+            concave_down,
+        }
+    }
+
+    /// Write an expression as the coefficients of a numerator and a denominator polynomial (see
+    /// [`Expr::to_coefficients`]), if it is a rational function
+    pub(super) fn as_rational(&self) -> Option<(Vec<Num>, Vec<Num>)> {
+        let combined = self.to_simplified_with(&SimplifyOptions {
+            combine_fractions: true,
+            ..Default::default()
+        });
+        let factors = match combined {
+            Expr::Prod(v) => v,
+            e => vec![e],
+        };
+        let mut numerator = vec![];
+        let mut denominator = vec![];
+        for e in factors {
+            match e {
+                Expr::Pow(a, b) if matches!(*b, Expr::Const(n) if n < 0) => {
+                    denominator.push(a.pow(-*b));
+                }
+                e => numerator.push(e),
+            }
+        }
+        let numerator = Expr::Prod(numerator).to_coefficients()?;
+        let denominator = Expr::Prod(denominator).to_coefficients()?;
+        (!denominator.is_empty()).then_some((numerator, denominator))
+    }
+}
+
+/// The horizontal or oblique asymptote of a rational function, from the leading coefficients
+fn end_asymptote(numerator: &[Num], denominator: &[Num]) -> (Option<Expr>, Option<Expr>) {
+    let (n, d) = (numerator.len(), denominator.len());
+    // A polynomial is its own asymptote, which isn't interesting
+    if d <= 1 {
+        return (None, None);
+    }
+    let leading =
+        |p: &[Num], from_top: usize| p.len().checked_sub(from_top + 1).map_or(0, |i| p[i]);
+    let (p, q) = (leading(numerator, 0), leading(denominator, 0));
+    if n < d {
+        (Some(Expr::Const(0)), None)
+    } else if n == d {
+        (Some(fraction(p, q)), None)
+    } else if n == d + 1 {
+        // (px^(k+1) + p'x^k + ...) / (qx^k + q'x^(k-1) + ...) = (p/q)x + (p'q - pq')/q^2 + ...
+        let (p1, q1) = (leading(numerator, 1), leading(denominator, 1));
+        let slope = fraction(p, q);
+        let intercept = p1
+            .checked_mul(q)
+            .zip(p.checked_mul(q1))
+            .and_then(|(a, b)| a.checked_sub(b))
+            .zip(q.checked_mul(q))
+            .map(|(a, b)| fraction(a, b));
+        (None, intercept.map(|c| (slope * Expr::X + c).simplified()))
+    } else {
+        (None, None)
+    }
+}
+
+/// A fraction of two constants, simplified
+fn fraction(n: Num, d: Num) -> Expr {
+    (Expr::Const(n) / Expr::Const(d)).simplified()
+}
+
+/// The value of a polynomial with the given coefficients
+pub(super) fn eval_poly(coeffs: &[Num], x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, c| acc * x + *c as f64)
+}
+
+/// How many times a polynomial has a factor of `x - root`
+fn multiplicity(coeffs: &[Num], root: f64) -> usize {
+    let mut coeffs: Vec<f64> = coeffs.iter().map(|c| *c as f64).collect();
+    let mut count = 0;
+    while coeffs.len() > 1 {
+        let value = coeffs.iter().rev().fold(0.0, |acc, c| acc * root + c);
+        if value.abs() > 1e-9 * (1.0 + coeffs.iter().map(|c| c.abs()).sum::<f64>()) {
+            break;
+        }
+        // Differentiate, since a root of multiplicity k is a root of the first k - 1 derivatives
+        coeffs = coeffs
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(n, c)| n as f64 * c)
+            .collect();
+        count += 1;
+    }
+    count
+}
+
+/// The sample points
+fn samples(options: &AnalysisOptions) -> impl Iterator<Item = f64> + '_ {
+    let step = (options.end - options.start) / options.samples as f64;
+    (0..=options.samples).map(move |i| options.start + i as f64 * step)
+}
+
+/// The zeros of a polynomial of degree 3 or more, which are found with
+/// [`Expr::polynomial_roots`] so that zeros close together aren't missed. Lower degrees are left to
+/// [`zeros`], which finds them exactly anyway.
+fn polynomial_zeros(e: &Expr, options: &AnalysisOptions) -> Option<Vec<f64>> {
+    if e.to_coefficients()?.len() < 4 {
+        return None;
+    }
+    let roots = e.polynomial_roots(Interval::new(options.start, options.end), 1e-12)?;
+    Some(
+        roots
+            .into_iter()
+            .filter(|x| (options.start..=options.end).contains(x))
+            .collect(),
+    )
+}
+
+/// Find the zeros of a function by looking for sign changes between samples, and for samples
+/// where its size is smallest and close to 0 (when it touches 0 without changing sign)
+fn zeros(f: impl Fn(f64) -> Option<f64>, options: &AnalysisOptions) -> Vec<f64> {
+    let points: Vec<(f64, Option<f64>)> = samples(options).map(|x| (x, f(x))).collect();
+    let mut zeros: Vec<f64> = vec![];
+    for pair in points.windows(2) {
+        if let [(a, Some(fa)), (b, Some(fb))] = pair {
+            if *fa == 0.0 {
+                zeros.push(*a);
+            } else if fa * fb < 0.0 {
+                zeros.extend(bisect(&f, *a, *b));
+            }
+        }
+    }
+    for triple in points.windows(3) {
+        if let [(_, Some(fa)), (b, Some(fb)), (_, Some(fc))] = triple {
+            if fb.abs() < fa.abs() && fb.abs() < fc.abs() && fa * fc > 0.0 {
+                zeros.extend(touching_zero(&f, *b, points[1].0 - points[0].0));
+            }
+        }
+    }
+    if let Some((x, Some(0.0))) = points.last() {
+        zeros.push(*x);
+    }
+    zeros.sort_by(f64::total_cmp);
+    zeros.dedup_by(|a, b| (*a - *b).abs() < 1e-6);
+    zeros
+}
+
+/// A zero between two points where a function has different signs. Poles also change sign, so
+/// they are left out by checking the value at the end.
+fn bisect(f: impl Fn(f64) -> Option<f64>, mut a: f64, mut b: f64) -> Option<f64> {
+    let fa_positive = f(a)? > 0.0;
+    for _ in 0..100 {
+        let mid = (a + b) / 2.0;
+        if (f(mid)? > 0.0) == fa_positive {
+            a = mid;
+        } else {
+            b = mid;
+        }
+    }
+    let x = (a + b) / 2.0;
+    (f(x)?.abs() < 1e-6).then_some(x)
+}
+
+/// A zero near a sample where the size of a function is smallest, which is found by narrowing
+/// down the smallest point within a step either side of it
+fn touching_zero(f: impl Fn(f64) -> Option<f64>, x: f64, step: f64) -> Option<f64> {
+    let (mut a, mut b) = (x - step, x + step);
+    for _ in 0..100 {
+        let (l, r) = (a + (b - a) / 3.0, b - (b - a) / 3.0);
+        if f(l)?.abs() < f(r)?.abs() {
+            b = r;
+        } else {
+            a = l;
+        }
+    }
+    let x = (a + b) / 2.0;
+    (f(x)?.abs() < 1e-9).then_some(x)
+}
+
+/// The intervals where a function is defined, found from where the samples are defined, with
+/// the excluded points taken out
+fn domain(
+    f: impl Fn(f64) -> Option<f64>,
+    excluded: &[f64],
+    options: &AnalysisOptions,
+) -> Vec<Interval> {
+    let points: Vec<(f64, bool)> = samples(options).map(|x| (x, f(x).is_some())).collect();
+    let mut intervals = vec![];
+    let mut start = points[0].1.then_some(points[0].0);
+    for pair in points.windows(2) {
+        let [(a, defined_a), (b, defined_b)] = pair else {
+            unreachable!()
+        };
+        match (defined_a, defined_b, start) {
+            (true, false, Some(s)) => {
+                intervals.push(Interval {
+                    start: s,
+                    end: boundary(&f, *a, *b),
+                });
+                start = None;
+            }
+            (false, true, _) => start = Some(boundary(&f, *b, *a)),
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        intervals.push(Interval {
+            start: s,
+            end: options.end,
+        });
+    }
+
+    // Split the intervals at the excluded points
+    for x in excluded {
+        intervals = intervals
+            .into_iter()
+            .flat_map(|i| {
+                if i.start < *x && *x < i.end {
+                    vec![
+                        Interval {
+                            start: i.start,
+                            end: *x,
+                        },
+                        Interval {
+                            start: *x,
+                            end: i.end,
+                        },
+                    ]
+                } else {
+                    vec![i]
+                }
+            })
+            .collect();
+    }
+    intervals
+}
+
+/// The edge of where a function is defined, between a point where it is defined and one where
+/// it isn't
+fn boundary(f: impl Fn(f64) -> Option<f64>, mut defined: f64, mut undefined: f64) -> f64 {
+    for _ in 0..100 {
+        let mid = (defined + undefined) / 2.0;
+        if f(mid).is_some() {
+            defined = mid;
+        } else {
+            undefined = mid;
+        }
+    }
+    defined
+}
+
+/// Split each interval of the domain at the given points, and get the parts where `holds` is
+/// true in the middle, joining parts next to each other
+fn signed_intervals(
+    domain: &[Interval],
+    points: &[f64],
+    holds: impl Fn(f64) -> bool,
+) -> Vec<Interval> {
+    let mut intervals = vec![];
+    for i in domain {
+        let mut ends: Vec<f64> = points
+            .iter()
+            .copied()
+            .filter(|x| i.start < *x && *x < i.end)
+            .collect();
+        ends.push(i.end);
+        let mut start = i.start;
+        let mut current: Option<Interval> = None;
+        for end in ends {
+            if holds((start + end) / 2.0) {
+                current = Some(match current {
+                    Some(c) => Interval { end, ..c },
+                    None => Interval { start, end },
+                });
+            } else if let Some(c) = current.take() {
+                intervals.push(c);
+            }
+            start = end;
+        }
+        intervals.extend(current);
+    }
+    intervals
+}
+
+impl CurveReport {
+    /// Write the report as a latex list, to go straight into a worksheet.
+    pub fn to_latex(&self) -> String {
+        let points = |v: &[f64]| match v {
+            [] => "none".to_string(),
+            _ => format!(
+                "${}$",
+                v.iter().map(|x| number(*x)).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        let intervals = |v: &[Interval]| match v {
+            [] => "none".to_string(),
+            _ => format!(
+                "${}$",
+                v.iter()
+                    .map(|i| format!("({}, {})", number(i.start), number(i.end)))
+                    .collect::<Vec<_>>()
+                    .join(" \\cup ")
+            ),
+        };
+        let unknown = || "unknown".to_string();
+
+        let mut items = vec![
+            format!("Domain: {}", intervals(&self.domain)),
+            format!("$x$-intercepts: {}", points(&self.x_intercepts)),
+            format!(
+                "$y$-intercept: {}",
+                self.y_intercept
+                    .map_or("none".to_string(), |y| format!("${}$", number(y)))
+            ),
+        ];
+        items.push(format!(
+            "Critical points: {}",
+            self.critical_points
+                .as_ref()
+                .map_or_else(unknown, |v| match v.as_slice() {
+                    [] => "none".to_string(),
+                    v => v
+                        .iter()
+                        .map(|p| {
+                            let kind = match p.kind {
+                                CriticalPointKind::Maximum => " (maximum)",
+                                CriticalPointKind::Minimum => " (minimum)",
+                                CriticalPointKind::Neither => "",
+                            };
+                            format!("$({}, {})${kind}", number(p.x), number(p.y))
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                })
+        ));
+        items.push(format!(
+            "Inflection points: {}",
+            self.inflection_points
+                .as_deref()
+                .map_or_else(unknown, points)
+        ));
+        items.push(format!(
+            "Vertical asymptotes: {}",
+            self.vertical_asymptotes
+                .as_deref()
+                .map_or_else(unknown, |v| match v {
+                    [] => "none".to_string(),
+                    v => format!(
+                        "${}$",
+                        v.iter()
+                            .map(|x| format!("x = {}", number(*x)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                })
+        ));
+        if let Some(e) = &self.horizontal_asymptote {
+            items.push(format!("Horizontal asymptote: $y = {}$", e.to_latex()));
+        }
+        if let Some(e) = &self.oblique_asymptote {
+            items.push(format!("Oblique asymptote: $y = {}$", e.to_latex()));
+        }
+        for (name, v) in [
+            ("Increasing", &self.increasing),
+            ("Decreasing", &self.decreasing),
+            ("Concave up", &self.concave_up),
+            ("Concave down", &self.concave_down),
+        ] {
+            items.push(format!(
+                "{name}: {}",
+                v.as_deref().map_or_else(unknown, intervals)
+            ));
+        }
+
+        let items: Vec<String> = items.into_iter().map(|i| format!("\\item {i}")).collect();
+        format!("\\begin{{itemize}}\n{}\n\\end{{itemize}}", items.join("\n"))
+    }
+}
+
+/// Write a number found numerically, rounded to 3 decimal places
+fn number(x: f64) -> String {
+    let rounded = format!("{x:.3}");
+    let rounded = rounded.trim_end_matches('0').trim_end_matches('.');
+    match rounded {
+        "-0" => "0".to_string(),
+        r => r.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, ln, pow, x};
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    fn all_close(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| close(*a, *b))
+    }
+
+    fn intervals(v: &[Interval]) -> Vec<(f64, f64)> {
+        v.iter().map(|i| (i.start, i.end)).collect()
+    }
+
+    fn intervals_close(v: &[Interval], expected: &[(f64, f64)]) -> bool {
+        v.len() == expected.len()
+            && v.iter()
+                .zip(expected)
+                .all(|(i, (start, end))| close(i.start, *start) && close(i.end, *end))
+    }
+
+    #[test]
+    fn rational() {
+        // (x^2 - 1)/(x - 2) = x + 2 + 3/(x - 2)
+        let e = (pow(x(), c(2)) - 1) / (x() - 2);
+        let report = e.analyze(&AnalysisOptions::default());
+
+        assert!(report.oblique_asymptote.unwrap().equivalent(&(x() + 2)));
+        assert_eq!(report.horizontal_asymptote, None);
+        assert!(all_close(&report.vertical_asymptotes.unwrap(), &[2.0]));
+        assert!(intervals_close(
+            &report.domain,
+            &[(-10.0, 2.0), (2.0, 10.0)]
+        ));
+        assert!(all_close(&report.x_intercepts, &[-1.0, 1.0]));
+        assert_eq!(report.y_intercept, Some(0.5));
+
+        // The derivative is (x^2 - 4x + 1)/(x - 2)^2, which is 0 at 2 ± sqrt(3)
+        let critical = report.critical_points.unwrap();
+        let root3 = 3f64.sqrt();
+        assert!(all_close(
+            &critical.iter().map(|p| p.x).collect::<Vec<_>>(),
+            &[2.0 - root3, 2.0 + root3]
+        ));
+        assert_eq!(critical[0].kind, CriticalPointKind::Maximum);
+        assert_eq!(critical[1].kind, CriticalPointKind::Minimum);
+        assert!(close(critical[1].y, 4.0 + 2.0 * root3));
+
+        // The second derivative is 6/(x - 2)^3, which changes sign at the asymptote
+        assert_eq!(report.inflection_points, Some(vec![]));
+        assert!(intervals_close(&report.concave_up.unwrap(), &[(2.0, 10.0)]));
+        let increasing = intervals(&report.increasing.unwrap());
+        assert_eq!(increasing.len(), 2);
+        assert!(close(increasing[0].1, 2.0 - root3));
+        assert!(close(increasing[1].0, 2.0 + root3));
+        assert_eq!(report.decreasing.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn horizontal() {
+        let report = (x() * 3 / (x() + 1)).analyze(&AnalysisOptions::default());
+        assert_eq!(report.horizontal_asymptote, Some(c(3)));
+        let report = (x() / (pow(x(), c(2)) + 1)).analyze(&AnalysisOptions::default());
+        assert_eq!(report.horizontal_asymptote, Some(c(0)));
+        assert_eq!(report.vertical_asymptotes, Some(vec![]));
+        assert_eq!(report.domain.len(), 1);
+    }
+
+    #[test]
+    fn cubic() {
+        // x^3 has a critical point at 0 which isn't a turning point, and an inflection point
+        let report = pow(x(), c(3)).analyze(&AnalysisOptions::default());
+        let critical = report.critical_points.unwrap();
+        assert_eq!(critical.len(), 1);
+        assert!(close(critical[0].x, 0.0));
+        assert_eq!(critical[0].kind, CriticalPointKind::Neither);
+        assert!(all_close(&report.inflection_points.unwrap(), &[0.0]));
+        assert_eq!(intervals(&report.increasing.unwrap()), vec![(-10.0, 10.0)]);
+        assert_eq!(report.oblique_asymptote, None);
+        assert_eq!(report.vertical_asymptotes, Some(vec![]));
+    }
+
+    #[test]
+    fn partial() {
+        // ln isn't a rational function, so its asymptotes aren't found
+        let report = ln(x()).analyze(&AnalysisOptions::default());
+        assert_eq!(report.vertical_asymptotes, None);
+        assert_eq!(report.y_intercept, None);
+        assert!(all_close(&report.x_intercepts, &[1.0]));
+        let domain = intervals(&report.domain);
+        assert_eq!(domain.len(), 1);
+        assert!(domain[0].0.abs() < 1e-9 && domain[0].1 == 10.0);
+        assert_eq!(intervals(&report.concave_down.unwrap()), domain);
+    }
+
+    #[test]
+    fn latex() {
+        let report = (x() * 3 / (x() + 1)).analyze(&AnalysisOptions::default());
+        let latex = report.to_latex();
+        assert!(latex.starts_with("\\begin{itemize}\n\\item Domain: $(-10, -1) \\cup (-1, 10)$\n"));
+        assert!(latex.contains("\\item Vertical asymptotes: $x = -1$\n"));
+        assert!(latex.contains("\\item Horizontal asymptote: $y = 3$\n"));
+        assert!(latex.contains("\\item Critical points: none\n"));
+        assert!(latex.ends_with("\\end{itemize}"));
+    }
+}
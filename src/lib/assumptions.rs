@@ -0,0 +1,579 @@
+use super::{Expr, Num, SimplifyWarning, Strictness};
+
+/// Facts about x which simplifications are allowed to rely on. Some rewrites are only true for
+/// some values of x (e.g. `sqrt(x^2) = x` needs `x >= 0`), so they are only done by
+/// [`Expr::simplify_with`] when the assumptions given in [`super::SimplifyOptions`] justify them.
+///
+/// The default is to assume nothing about x.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Assumptions {
+    /// x > 0
+    pub positive: bool,
+    /// x >= 0
+    pub nonnegative: bool,
+    /// x != 0
+    pub nonzero: bool,
+    /// x is a whole number, which allows e.g. `sin(πx) = 0` and `(-1)^{2x} = 1`
+    pub integer: bool,
+}
+
+impl Assumptions {
+    /// Assume that x is positive (which means it is also non-negative and non-zero).
+    pub fn positive() -> Self {
+        Assumptions {
+            positive: true,
+            nonnegative: true,
+            nonzero: true,
+            integer: false,
+        }
+    }
+
+    /// Assume that x is not zero.
+    pub fn nonzero() -> Self {
+        Assumptions {
+            nonzero: true,
+            ..Default::default()
+        }
+    }
+
+    /// Assume that x is a whole number.
+    pub fn integer() -> Self {
+        Assumptions {
+            integer: true,
+            ..Default::default()
+        }
+    }
+
+    /// Whether an expression is known to be a whole number for every x allowed by the
+    /// assumptions.
+    pub fn is_integer(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(_) => true,
+            Expr::Scaled(_, e) => *e >= 0,
+            Expr::X => self.integer,
+            Expr::Sum(v) | Expr::Prod(v) => v.iter().all(|e| self.is_integer(e)),
+            Expr::Neg(e) => self.is_integer(e),
+            Expr::Pow(a, b) => self.is_integer(a) && matches!(**b, Expr::Const(n) if n >= 0),
+            _ => false,
+        }
+    }
+
+    /// Whether an expression is an odd whole number, if it is known to be a whole number and
+    /// which it is is known, e.g. `2x + 1` is odd when x is a whole number
+    fn is_odd(&self, e: &Expr) -> Option<bool> {
+        match e {
+            Expr::Const(n) => Some(n % 2 != 0),
+            Expr::Neg(e) => self.is_odd(e),
+            Expr::Sum(v) => v
+                .iter()
+                .try_fold(false, |odd, e| Some(odd ^ self.is_odd(e)?)),
+            Expr::Prod(v) if v.iter().all(|e| self.is_integer(e)) => {
+                match v.iter().any(|e| self.is_odd(e) == Some(false)) {
+                    true => Some(false),
+                    false => v
+                        .iter()
+                        .try_fold(true, |odd, e| Some(odd & self.is_odd(e)?)),
+                }
+            }
+            Expr::Pow(a, b) if matches!(**b, Expr::Const(n) if n > 0) => self.is_odd(a),
+            _ => None,
+        }
+    }
+
+    /// Whether an expression is known to be positive for every x allowed by the assumptions.
+    pub fn is_positive(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(n) | Expr::Scaled(n, _) => *n > 0,
+            Expr::X => self.positive,
+            Expr::Sum(v) => {
+                v.iter().all(|e| self.is_nonnegative(e)) && v.iter().any(|e| self.is_positive(e))
+            }
+            Expr::Prod(v) => v.iter().all(|e| self.is_positive(e)),
+            Expr::Pow(a, b) => self.is_positive(a) || (self.is_nonzero(a) && is_even_const(b)),
+            Expr::Factorial(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether an expression is known to be non-negative for every x allowed by the assumptions.
+    pub fn is_nonnegative(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(n) | Expr::Scaled(n, _) => *n >= 0,
+            Expr::X => self.positive || self.nonnegative,
+            Expr::Sum(v) | Expr::Prod(v) => v.iter().all(|e| self.is_nonnegative(e)),
+            Expr::Pow(a, b) => {
+                self.is_nonnegative(a) || is_even_const(b) || b.root_index().is_some()
+            }
+            _ => self.is_positive(e),
+        }
+    }
+
+    /// Whether an expression is known to be non-zero for every x allowed by the assumptions.
+    pub fn is_nonzero(&self, e: &Expr) -> bool {
+        match e {
+            Expr::Const(n) | Expr::Scaled(n, _) => *n != 0,
+            Expr::X => self.positive || self.nonzero,
+            Expr::Prod(v) => v.iter().all(|e| self.is_nonzero(e)),
+            Expr::Neg(e) => self.is_nonzero(e),
+            Expr::Pow(a, _) => self.is_nonzero(a),
+            _ => self.is_positive(e),
+        }
+    }
+
+    /// The sign of an expression (1, 0 or -1) if it is the same for every x allowed by the
+    /// assumptions, e.g. `-2y^2 - 1` is always negative
+    pub(super) fn sign(&self, e: &Expr) -> Option<Num> {
+        match e {
+            Expr::Const(n) | Expr::Scaled(n, _) => Some(n.signum()),
+            Expr::Neg(e) => Some(-self.sign(e)?),
+            Expr::Prod(v) => v.iter().try_fold(1, |sign, e| Some(sign * self.sign(e)?)),
+            Expr::Sum(v) => {
+                let signs: Vec<Num> = v.iter().map(|e| self.sign(e)).collect::<Option<_>>()?;
+                if signs.iter().all(|s| *s >= 0) {
+                    signs.into_iter().max()
+                } else if signs.iter().all(|s| *s <= 0) {
+                    signs.into_iter().min()
+                } else {
+                    None
+                }
+            }
+            Expr::Pow(a, b) => match (self.sign(a)?, b.as_ref()) {
+                (1, _) => Some(1),
+                (-1, Expr::Const(n)) => Some(if n % 2 == 0 { 1 } else { -1 }),
+                _ => None,
+            },
+            e if self.is_positive(e) => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Whether powers of `base` can be multiplied together by adding up their exponents for
+    /// every x allowed by the assumptions. This is when the base isn't 0, or when the exponents
+    /// are known to all have the same sign, since e.g. `x^y * x^{-y}` is undefined at `x = 0`
+    /// but 1 is not.
+    pub(super) fn can_collect_powers(&self, base: &Expr, exponents: &[&Expr]) -> bool {
+        if self.is_nonzero(base) {
+            return true;
+        }
+        let signs: Option<Vec<Num>> = exponents.iter().map(|e| self.sign(e)).collect();
+        signs.is_some_and(|s| s.iter().all(|n| *n == 1) || s.iter().all(|n| *n == -1))
+    }
+}
+
+/// Which of the rewrites that are only true under some assumption are done by
+/// [`Expr::simplify_assuming_with`], and the warnings about the ones which were done without
+/// the assumptions justifying them
+pub(super) struct Allowance<'a> {
+    pub(super) assumptions: &'a Assumptions,
+    strictness: Strictness,
+    pub(super) warnings: Vec<SimplifyWarning>,
+}
+
+impl<'a> Allowance<'a> {
+    pub(super) fn new(assumptions: &'a Assumptions, strictness: Strictness) -> Self {
+        Allowance {
+            assumptions,
+            strictness,
+            warnings: vec![],
+        }
+    }
+
+    /// Whether a rewrite of `e` is done, given whether the assumptions justify it. If they
+    /// don't, `assumption` is what it needs, which is warned about when it is done anyway.
+    pub(super) fn allows(
+        &mut self,
+        justified: bool,
+        rule: &'static str,
+        assumption: impl FnOnce() -> String,
+        e: &Expr,
+    ) -> bool {
+        if justified {
+            return true;
+        }
+        match self.strictness {
+            Strictness::Silent => true,
+            Strictness::Warn => {
+                self.warnings.push(SimplifyWarning {
+                    rule,
+                    assumption: Some(assumption()),
+                    subexpr_latex: e.to_latex(),
+                });
+                true
+            }
+            Strictness::Refuse => false,
+        }
+    }
+}
+
+fn is_even_const(e: &Expr) -> bool {
+    matches!(e, Expr::Const(n) if n % 2 == 0)
+}
+
+impl Expr {
+    /// Apply the simplifications which are only true under some assumptions, when `assumptions`
+    /// justify them.
+    ///
+    /// List of applied simplifications:
+    /// [`Expr::simplify_root_of_even_pow`]
+    /// [`Expr::simplify_split_ln`]
+    /// [`Expr::simplify_cancel_powers`]
+    /// [`Expr::simplify_multiple_of_pi`]
+    /// [`Expr::simplify_power_of_minus_one`]
+    pub fn simplify_assuming(&mut self, assumptions: &Assumptions) {
+        self.simplify_assuming_with(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    /// [`Expr::simplify_assuming`], where the rewrites the assumptions don't justify are done or
+    /// not depending on the strictness of `allowance`
+    pub(super) fn simplify_assuming_with(&mut self, allowance: &mut Allowance) {
+        self.root_of_even_pow(allowance);
+        self.split_ln(allowance);
+        self.cancel_powers(allowance);
+        self.multiple_of_pi(allowance);
+        self.power_of_minus_one(allowance);
+    }
+
+    /// This function finds sin and cos of a whole number times π, when the number is a whole
+    /// number for every x
+    /// e.g. `sin(πx) = 0` and `cos(πx) = (-1)^x` when x is a whole number
+    pub fn simplify_multiple_of_pi(&mut self, assumptions: &Assumptions) {
+        self.multiple_of_pi(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    fn multiple_of_pi(&mut self, allowance: &mut Allowance) {
+        let (Expr::Sin(angle) | Expr::Cos(angle)) = &*self else {
+            return;
+        };
+        let Some(n) = pi_coefficient(angle) else {
+            return;
+        };
+        let assumed = Assumptions {
+            integer: true,
+            ..*allowance.assumptions
+        };
+        if !assumed.is_integer(&n) {
+            return;
+        }
+        let justified = allowance.assumptions.is_integer(&n);
+        if !allowance.allows(justified, "multiple of π", integer_x, self) {
+            return;
+        }
+        *self = match self {
+            Expr::Sin(_) => Expr::Const(0),
+            _ => Expr::Const(-1).pow(n),
+        };
+        self.power_of_minus_one(allowance);
+    }
+
+    /// This function finds -1 to the power of a whole number which is known to be even or odd
+    /// e.g. `(-1)^{2x} = 1` and `(-1)^{2x+1} = -1` when x is a whole number
+    pub fn simplify_power_of_minus_one(&mut self, assumptions: &Assumptions) {
+        self.power_of_minus_one(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    fn power_of_minus_one(&mut self, allowance: &mut Allowance) {
+        let Expr::Pow(a, b) = &*self else {
+            return;
+        };
+        if **a != Expr::Const(-1) || matches!(**b, Expr::Const(_)) {
+            return;
+        }
+        let assumed = Assumptions {
+            integer: true,
+            ..*allowance.assumptions
+        };
+        let Some(odd) = assumed.is_odd(b) else {
+            return;
+        };
+        let justified = allowance.assumptions.is_odd(b).is_some();
+        if allowance.allows(justified, "power of -1", integer_x, self) {
+            *self = Expr::Const(if odd { -1 } else { 1 });
+        }
+    }
+
+    /// This function splits the ln of a product into a sum of lns, and brings powers out of lns,
+    /// when everything in the ln is positive
+    /// e.g. `ln(2x) = ln(2) + ln(x)` and `ln(x^3) = 3ln(x)` when x > 0
+    pub fn simplify_split_ln(&mut self, assumptions: &Assumptions) {
+        self.split_ln(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    fn split_ln(&mut self, allowance: &mut Allowance) {
+        let Expr::Ln(x) = &*self else {
+            return;
+        };
+        let positive = |e: &Expr| format!("{} > 0", e.to_latex());
+        let allowed = match x.as_ref() {
+            Expr::Prod(v) => {
+                let unknown: Vec<&Expr> = v
+                    .iter()
+                    .filter(|e| !allowance.assumptions.is_positive(e))
+                    .collect();
+                let assumption = || {
+                    let facts: Vec<String> = unknown.iter().map(|e| positive(e)).collect();
+                    facts.join(", ")
+                };
+                allowance.allows(unknown.is_empty(), "split ln", assumption, self)
+            }
+            Expr::Pow(a, _) => {
+                let justified = allowance.assumptions.is_positive(a);
+                allowance.allows(justified, "split ln", || positive(a), self)
+            }
+            _ => false,
+        };
+        if !allowed {
+            return;
+        }
+        let Expr::Ln(x) = self else {
+            return;
+        };
+        match x.as_mut() {
+            Expr::Prod(v) => {
+                let mut terms: Vec<Expr> = std::mem::take(v).into_iter().map(Expr::ln).collect();
+                for e in terms.iter_mut() {
+                    e.split_ln(allowance);
+                }
+                *self = Expr::Sum(terms);
+            }
+            Expr::Pow(a, b) => {
+                let a = std::mem::take(a.as_mut());
+                let b = std::mem::take(b.as_mut());
+                let mut ln = a.ln();
+                ln.split_ln(allowance);
+                *self = b * ln;
+            }
+            _ => (),
+        }
+    }
+
+    /// This function multiplies powers of the same base in a product whose exponents might have
+    /// different signs, when the base is non-zero
+    /// e.g. `x/x = 1`, `x^3/x = x^2` and `x^y * x^{-y} = 1` when x != 0
+    ///
+    /// `x/x` isn't 1 when x is 0 (it is undefined), so this isn't done without an assumption.
+    /// Powers whose exponents are known to have the same sign are multiplied by
+    /// [`Expr::simplify_collect_powers`] instead.
+    pub fn simplify_cancel_powers(&mut self, assumptions: &Assumptions) {
+        self.cancel_powers(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    fn cancel_powers(&mut self, allowance: &mut Allowance) {
+        let Expr::Prod(v) = self else {
+            return;
+        };
+        let mut i = 0;
+        while i < v.len() {
+            let (base, n) = as_power(&v[i]);
+            // The powers which simplify_collect_powers doesn't collect without assumptions
+            let other = (i + 1..v.len()).find(|j| {
+                let (other_base, m) = as_power(&v[*j]);
+                other_base == base && !Assumptions::default().can_collect_powers(base, &[n, m])
+            });
+            let allowed = other.is_some_and(|j| {
+                let (_, m) = as_power(&v[j]);
+                let justified = allowance.assumptions.can_collect_powers(base, &[n, m]);
+                let nonzero = || format!("{} \\neq 0", base.to_latex());
+                let cancelled = Expr::Prod(vec![v[i].clone(), v[j].clone()]);
+                allowance.allows(justified, "cancel powers", nonzero, &cancelled)
+            });
+            match other {
+                Some(j) if allowed => {
+                    let (_, m) = as_power(&v[j]);
+                    let exponent = Expr::Sum(vec![n.clone(), m.clone()]).simplified();
+                    let base = base.clone();
+                    v.remove(j);
+                    v[i] = match exponent {
+                        Expr::Const(0) => Expr::Const(1),
+                        Expr::Const(1) => base,
+                        exponent => base.pow(exponent),
+                    };
+                }
+                _ => i += 1,
+            }
+        }
+    }
+}
+
+/// The assumption which the rewrites of whole numbers need
+fn integer_x() -> String {
+    "x \\in \\mathbb{Z}".to_string()
+}
+
+/// The n in an angle nπ, e.g. `2x` in `2πx`
+fn pi_coefficient(angle: &Expr) -> Option<Expr> {
+    let is_pi = |e: &Expr| matches!(e, Expr::Arccos(a) if **a == Expr::Const(-1));
+    match angle {
+        e if is_pi(e) => Some(Expr::Const(1)),
+        Expr::Prod(v) => {
+            let i = v.iter().position(is_pi)?;
+            let mut rest = v.clone();
+            rest.remove(i);
+            Some(match rest.len() {
+                1 => rest.pop().unwrap(),
+                _ => Expr::Prod(rest),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Split a factor into a base and an exponent, which is 1 if it isn't a power
+fn as_power(e: &Expr) -> (&Expr, &Expr) {
+    match e {
+        Expr::Pow(a, b) => (a, b),
+        _ => (e, &Expr::Const(1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{SimplifyOptions, SimplifyReport, VarId};
+    use super::*;
+
+    fn simplified(e: &Expr, assumptions: Assumptions) -> Expr {
+        let mut e = e.clone();
+        e.simplify_with(&SimplifyOptions {
+            assumptions,
+            ..Default::default()
+        });
+        e
+    }
+
+    #[test]
+    fn sqrt_of_square() {
+        let e = Expr::X.pow(Expr::Const(2)).sqrt();
+        assert_eq!(simplified(&e, Assumptions::default()), e);
+        assert_eq!(simplified(&e, Assumptions::nonzero()), e);
+        assert_eq!(simplified(&e, Assumptions::positive()), Expr::X);
+    }
+
+    #[test]
+    fn split_ln() {
+        let e = (Expr::X * Expr::Const(2)).ln();
+        assert_eq!(simplified(&e, Assumptions::default()), e);
+        let split = simplified(&e, Assumptions::positive());
+        assert_eq!(split, Expr::X.ln() + Expr::Const(2).ln());
+
+        let e = Expr::X.pow(Expr::Const(3)).ln();
+        assert_eq!(simplified(&e, Assumptions::default()), e);
+        assert_eq!(
+            simplified(&e, Assumptions::positive()),
+            Expr::X.ln() * Expr::Const(3)
+        );
+    }
+
+    #[test]
+    fn cancel() {
+        let e = Expr::X / Expr::X;
+        assert_eq!(simplified(&e, Assumptions::default()), e);
+        assert_eq!(simplified(&e, Assumptions::nonzero()), Expr::Const(1));
+
+        let e = Expr::X.pow(Expr::Const(3)) * Expr::X.sin() / Expr::X;
+        assert_eq!(
+            simplified(&e, Assumptions::positive()),
+            Expr::X.pow(Expr::Const(2)) * Expr::X.sin()
+        );
+    }
+
+    fn simplified_with(e: &Expr, strictness: Strictness) -> (Expr, SimplifyReport) {
+        let mut e = e.clone();
+        let report = e.simplify_with_report(&SimplifyOptions {
+            strictness,
+            ..Default::default()
+        });
+        (e, report)
+    }
+
+    #[test]
+    fn strictness() {
+        let e = Expr::X / Expr::X;
+        let (cancelled, report) = simplified_with(&e, Strictness::Warn);
+        assert_eq!(cancelled, Expr::Const(1));
+        assert_eq!(
+            report.warnings,
+            vec![SimplifyWarning {
+                rule: "cancel powers",
+                assumption: Some("x \\neq 0".to_string()),
+                subexpr_latex: "xx^{-1}".to_string(),
+            }]
+        );
+        let (cancelled, report) = simplified_with(&e, Strictness::Silent);
+        assert_eq!(cancelled, Expr::Const(1));
+        assert!(report.warnings.is_empty());
+        let (unchanged, report) = simplified_with(&e, Strictness::Refuse);
+        assert_eq!(unchanged, e);
+        assert!(report.warnings.is_empty());
+
+        // Nothing is warned about when the assumptions justify it
+        let mut cancelled = e.clone();
+        let report = cancelled.simplify_with_report(&SimplifyOptions {
+            assumptions: Assumptions::nonzero(),
+            strictness: Strictness::Warn,
+            ..Default::default()
+        });
+        assert_eq!(cancelled, Expr::Const(1));
+        assert!(report.warnings.is_empty());
+
+        let e = Expr::X.pow(Expr::Const(2)).sqrt() + (Expr::X * Expr::Var(VarId('y'))).ln();
+        let (rewritten, report) = simplified_with(&e, Strictness::Warn);
+        assert!(rewritten.equivalent(&(Expr::X + Expr::X.ln() + Expr::Var(VarId('y')).ln())));
+        let assumptions: Vec<_> = report
+            .warnings
+            .iter()
+            .map(|w| (w.rule, w.assumption.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            assumptions,
+            [
+                ("root of even power", "x \\geq 0"),
+                ("split ln", "x > 0, y > 0")
+            ]
+        );
+    }
+
+    #[test]
+    fn integers() {
+        use super::super::prelude::{c, cos, pi, pow, sin, x};
+
+        let integer = Assumptions::integer();
+        let e = sin(pi() * x());
+        assert_eq!(simplified(&e, Assumptions::default()), e);
+        assert_eq!(simplified(&e, integer), c(0));
+        assert_eq!(simplified(&cos(pi() * x() * 2), integer), c(1));
+        assert_eq!(simplified(&cos(pi() * x()), integer), pow(c(-1), x()));
+        assert_eq!(
+            simplified(&(cos(pi() * x()) * sin(pi())), Assumptions::default()),
+            c(0)
+        );
+        let e = pow(c(-1), x() * 2 + 1);
+        assert_eq!(
+            simplified(&e, Assumptions::default()),
+            e.clone().simplified()
+        );
+        assert_eq!(simplified(&e, integer), c(-1));
+        // Half of x might not be a whole number
+        let e = sin(pi() * x() / 2);
+        assert_eq!(simplified(&e, integer), e.clone().simplified());
+
+        let (rewritten, report) = simplified_with(&sin(pi() * x()), Strictness::Warn);
+        assert_eq!(rewritten, c(0));
+        assert_eq!(
+            report.warnings[0].assumption.as_deref(),
+            Some("x \\in \\mathbb{Z}")
+        );
+        assert!(!integer.is_integer(&(x() / 2)));
+        assert!(integer.is_integer(&(pow(x(), c(3)) - x() * 4)));
+        assert_eq!(integer.is_odd(&(x() * x() + x())), None);
+        assert_eq!(integer.is_odd(&(x() * 4 - 3)), Some(true));
+    }
+
+    #[test]
+    fn signs() {
+        let a = Assumptions::default();
+        assert!(a.is_positive(&(Expr::X.pow(Expr::Const(2)) + 1)));
+        assert!(!a.is_positive(&Expr::X.pow(Expr::Const(2))));
+        assert!(a.is_nonnegative(&Expr::X.pow(Expr::Const(2))));
+        assert!(!a.is_nonzero(&Expr::X));
+        assert!(Assumptions::positive().is_positive(&(Expr::X * 3)));
+    }
+}
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::{EvalError, Expr};
+
+/// An error from evaluating an expression at a point with [`Expr::eval_many`], along with the
+/// subexpression which couldn't be evaluated.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvalFailure {
+    /// The value of x which was being evaluated at
+    pub x: f64,
+    /// The indices of the children to follow from the root to get to the subexpression which
+    /// failed (see [`Expr::children`])
+    pub path: Vec<usize>,
+    /// The innermost subexpression which failed, e.g. `ln(x-2)` rather than the whole expression
+    /// it is in
+    pub expr: Expr,
+    /// Why the subexpression failed
+    pub error: EvalError,
+}
+
+impl fmt::Display for EvalFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let latex = self.expr.to_latex();
+        match self.error {
+            EvalError::Domain { .. } => write!(f, "{latex} undefined at x = {}", self.x),
+            EvalError::DivisionByZero => write!(f, "{latex} divides by zero at x = {}", self.x),
+            EvalError::Complex => write!(f, "{latex} isn't real at x = {}", self.x),
+            EvalError::UnknownVariable(v) => {
+                write!(f, "{latex} has no value for {} at x = {}", v.0, self.x)
+            }
+            EvalError::UnknownRate(..) => write!(f, "{latex} has no value at x = {}", self.x),
+            EvalError::NoBranch => write!(f, "{latex} has no branch for x = {}", self.x),
+            EvalError::IncompleteExpression(_) => write!(f, "{latex} hasn't been filled in"),
+            EvalError::TooManyTerms => write!(f, "{latex} has too many terms to add up"),
+        }
+    }
+}
+
+impl std::error::Error for EvalFailure {}
+
+impl Expr {
+    /// Evaluate the expression at each value of x, like [`Expr::eval`]. An error says which
+    /// subexpression couldn't be evaluated, so that it can be shown (e.g.
+    /// "\ln(x-2) undefined at x = 1.5").
+    pub fn eval_many(&self, xs: &[f64]) -> Vec<Result<f64, EvalFailure>> {
+        xs.iter().map(|x| self.eval_located(*x)).collect()
+    }
+
+    /// Evaluate the expression at the values of x in a range, from the start to the end in
+    /// steps of `step` (see [`Expr::eval_many`]). Each value is given with x.
+    ///
+    /// # Panics
+    /// Panics if step isn't positive.
+    pub fn eval_table(
+        &self,
+        range: RangeInclusive<f64>,
+        step: f64,
+    ) -> Vec<(f64, Result<f64, EvalFailure>)> {
+        assert!(step > 0.0, "the step should be positive");
+        let (start, end) = range.into_inner();
+        // Allow for rounding in the number of steps, so that the end is included
+        let count = ((end - start) / step + 1e-9).floor();
+        if count < 0.0 {
+            return vec![];
+        }
+        let xs: Vec<f64> = (0..=count as usize)
+            .map(|i| start + i as f64 * step)
+            .collect();
+        let values = self.eval_many(&xs);
+        xs.into_iter().zip(values).collect()
+    }
+
+    /// Evaluate the expression, keeping track of the innermost subexpression which fails
+    pub(super) fn eval_located(&self, x: f64) -> Result<f64, EvalFailure> {
+        let failure = RefCell::new(None);
+        let children = self.children();
+        let result = self.eval_node(x, &|child| {
+            child.eval_located(x).map_err(|mut f| {
+                // The body of a sum over a variable is evaluated with the variable put in, so it
+                // isn't one of the children
+                let index = children
+                    .iter()
+                    .position(|c| std::ptr::eq(*c, child))
+                    .unwrap_or(children.len() - 1);
+                f.path.insert(0, index);
+                let error = f.error;
+                *failure.borrow_mut() = Some(f);
+                error
+            })
+        });
+        result.map_err(|error| {
+            failure.into_inner().unwrap_or_else(|| EvalFailure {
+                x,
+                path: vec![],
+                expr: self.clone(),
+                error,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, ln, sqrt, x};
+    use super::super::ExprKind;
+    use super::*;
+
+    #[test]
+    fn mixed_points() {
+        // 3 + 2ln(x-2)
+        let ln = ln(x() - 2);
+        let e = c(3) + c(2) * ln.clone();
+        let values = e.eval_many(&[3.0, 1.5, 2.0 + 1.0f64.exp()]);
+        assert_eq!(values[0], Ok(3.0));
+        assert!((values[2].as_ref().unwrap() - 5.0).abs() < 1e-12);
+
+        let failure = values[1].as_ref().unwrap_err();
+        assert_eq!(failure.expr, ln);
+        assert_eq!(failure.path, vec![1, 1]);
+        assert_eq!(failure.x, 1.5);
+        assert_eq!(
+            failure.error,
+            EvalError::Domain {
+                kind: ExprKind::Ln,
+                value: -0.5
+            }
+        );
+        assert_eq!(failure.to_string(), "\\ln(x-2) undefined at x = 1.5");
+    }
+
+    #[test]
+    fn innermost() {
+        // The reciprocal fails at 0 before the sqrt or ln around it are evaluated
+        let e = ln(sqrt(x().recip() + 1));
+        let failure = e.eval_many(&[0.0]).pop().unwrap().unwrap_err();
+        assert_eq!(failure.expr, x().recip());
+        assert_eq!(failure.path, vec![0, 0, 0]);
+        assert_eq!(failure.error, EvalError::DivisionByZero);
+    }
+
+    #[test]
+    fn table() {
+        let table = Expr::X.ln().eval_table(-1.0..=1.0, 0.5);
+        let xs: Vec<f64> = table.iter().map(|(x, _)| *x).collect();
+        assert_eq!(xs, vec![-1.0, -0.5, 0.0, 0.5, 1.0]);
+        assert!(table[..3].iter().all(|(_, v)| v.is_err()));
+        assert_eq!(table[4].1, Ok(0.0));
+        assert_eq!(Expr::X.eval_table(0.0..=0.3, 0.1).len(), 4);
+        assert!(Expr::X.eval_table(1.0..=0.0, 0.1).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Expr, MathError};
+
+/// The bytes at the start of every blob written by [`Expr::to_bytes`]
+const MAGIC: &[u8; 4] = b"MPX\0";
+
+/// The version of the format written by [`Expr::to_bytes`]. This goes up whenever the way an
+/// expression is written changes, and a migration from the previous version is added to
+/// [`MIGRATIONS`].
+pub const FORMAT_VERSION: u16 = 2;
+
+/// The migrations from each old version of the format to the next, where the first one goes
+/// from version 1 to version 2. Each one rewrites the S-expression in a blob.
+const MIGRATIONS: [fn(&str) -> String; FORMAT_VERSION as usize - 1] = [recip_to_pow];
+
+impl Expr {
+    /// Write the expression as bytes which can be stored and read back with
+    /// [`Expr::from_bytes`], even by later versions of this crate.
+    ///
+    /// The bytes are a header, with the version of the format ([`FORMAT_VERSION`]), followed by
+    /// the expression as an S-expression (see [`Expr::to_sexpr`]).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        bytes.extend(self.to_sexpr().into_bytes());
+        bytes
+    }
+
+    /// Read an expression written by [`Expr::to_bytes`]. Blobs from older versions of the format
+    /// are migrated to the current one first, so they give the same expression as they did when
+    /// they were written.
+    ///
+    /// Returns [`MathError::UnsupportedFormatVersion`] for a version which isn't known (e.g. from
+    /// a newer version of this crate), rather than guessing what it means, and
+    /// [`MathError::Parse`] if the bytes aren't a blob. The positions of errors in the
+    /// S-expression are byte offsets into it, after it has been migrated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Expr, MathError> {
+        let not_a_blob = |pos| MathError::Parse {
+            pos: Some(pos),
+            msg: "not an expression written by to_bytes".to_string(),
+        };
+        let rest = bytes.strip_prefix(MAGIC).ok_or_else(|| not_a_blob(0))?;
+        let (version, payload) = match rest {
+            [low, high, payload @ ..] => (u16::from_le_bytes([*low, *high]), payload),
+            _ => return Err(not_a_blob(MAGIC.len())),
+        };
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(MathError::UnsupportedFormatVersion { version });
+        }
+        let header = MAGIC.len() + 2;
+        let sexpr = std::str::from_utf8(payload).map_err(|e| MathError::Parse {
+            pos: Some(header + e.valid_up_to()),
+            msg: "the expression isn't valid UTF-8".to_string(),
+        })?;
+        let sexpr = MIGRATIONS[version as usize - 1..]
+            .iter()
+            .fold(sexpr.to_string(), |sexpr, migrate| migrate(&sexpr));
+        Ok(Expr::from_sexpr(&sexpr)?)
+    }
+}
+
+/// Migrate from version 1, where reciprocals were written as `(recip e)`, to version 2, where
+/// they are `(^ e -1)` like any other power.
+fn recip_to_pow(sexpr: &str) -> String {
+    let mut migrated = String::with_capacity(sexpr.len());
+    // Whether each list which is open is a reciprocal
+    let mut open = vec![];
+    let mut rest = sexpr;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '(' => {
+                let trimmed = rest.trim_start();
+                let recip = trimmed.strip_prefix("recip").filter(|after| {
+                    after.starts_with(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                });
+                open.push(recip.is_some());
+                match recip {
+                    Some(after) => {
+                        migrated.push_str("(^");
+                        rest = after;
+                    }
+                    None => migrated.push('('),
+                }
+            }
+            ')' if open.pop() == Some(true) => migrated.push_str(" -1)"),
+            c => migrated.push(c),
+        }
+    }
+    migrated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::conformance;
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::*;
+
+    /// A blob written by version 1 of the format, of `1/(x+1) + 3/sin(x) - 1/y`
+    const V1_FIXTURE: &[u8] = include_bytes!("../../tests/fixtures/expr_v1.bin");
+
+    #[test]
+    fn round_trip() {
+        for e in conformance::corpus(444, 100) {
+            let bytes = e.to_bytes();
+            assert_eq!(&bytes[..4], MAGIC);
+            assert_eq!(Expr::from_bytes(&bytes), Ok(e));
+        }
+    }
+
+    #[test]
+    fn migration() {
+        assert_eq!(V1_FIXTURE[4..6], [1, 0]);
+        let e = Expr::from_bytes(V1_FIXTURE).unwrap();
+        let recip = |e: Expr| pow(e, c(-1));
+        assert_eq!(
+            e,
+            Expr::Sum(vec![
+                recip(x() + 1),
+                Expr::Prod(vec![c(3), recip(sin(x()))]),
+                Expr::Neg(Box::new(recip(var('y')))),
+            ])
+        );
+        // Written again, it is in the current format
+        assert_eq!(e.to_bytes()[4..6], FORMAT_VERSION.to_le_bytes());
+        assert_eq!(
+            recip_to_pow("(recip(recip x))"),
+            "(^(^ x -1) -1)",
+            "reciprocals can be nested"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut bytes = x().to_bytes();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Expr::from_bytes(&bytes),
+            Err(MathError::UnsupportedFormatVersion {
+                version: FORMAT_VERSION + 1
+            })
+        );
+        bytes[4..6].copy_from_slice(&[0, 0]);
+        assert_eq!(
+            Expr::from_bytes(&bytes),
+            Err(MathError::UnsupportedFormatVersion { version: 0 })
+        );
+        assert!(matches!(
+            Expr::from_bytes(b"(+ x 1)"),
+            Err(MathError::Parse { pos: Some(0), .. })
+        ));
+        assert!(matches!(
+            Expr::from_bytes(b"MPX\0\x02"),
+            Err(MathError::Parse { pos: Some(4), .. })
+        ));
+        assert!(matches!(
+            Expr::from_bytes(b"MPX\0\x02\0(+ x \xff)"),
+            Err(MathError::Parse { pos: Some(11), .. })
+        ));
+        // A version 2 blob doesn't have reciprocals
+        assert!(matches!(
+            Expr::from_bytes(b"MPX\0\x02\0(recip x)"),
+            Err(MathError::Parse { .. })
+        ));
+    }
+}
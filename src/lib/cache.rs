@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::Expr;
+
+/// A cache of simplified expressions for [`Expr::simplify_cached`], which can be shared between
+/// threads. Expressions which only differ in the order of the terms of sums and products share
+/// an entry.
+#[derive(Debug)]
+pub struct SimplifyCache {
+    /// The expressions which were simplified (with [`Expr::sign_normalize`]) and what they
+    /// simplified to, by their [`Expr::canonical_hash`]
+    entries: Mutex<HashMap<u64, (Expr, Expr)>>,
+    max_entries: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// How well a [`SimplifyCache`] has been doing.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CacheStats {
+    /// The number of lookups which found a simplified expression
+    pub hits: usize,
+    /// The number of lookups which had to simplify the expression
+    pub misses: usize,
+    /// The number of expressions in the cache
+    pub entries: usize,
+}
+
+impl SimplifyCache {
+    /// Create an empty cache which holds at most `max_entries` expressions. When it is full, an
+    /// arbitrary entry is removed to make room for a new one.
+    pub fn new(max_entries: usize) -> Self {
+        SimplifyCache {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the hit and miss counts and the number of entries.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.lock().len(),
+        }
+    }
+
+    /// Remove every entry (the counts are kept).
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, (Expr, Expr)>> {
+        // A panic while holding the lock can't leave the map half updated, so just carry on
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, hash: u64, e: &Expr) -> Option<Expr> {
+        let entry = self.lock().get(&hash).cloned();
+        // The hash could be a collision
+        let simplified = entry
+            .filter(|(original, _)| original.equivalent(&e.sign_normalize()))
+            .map(|(_, simplified)| simplified);
+        let counter = match simplified {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        simplified
+    }
+
+    fn insert(&self, hash: u64, original: Expr, simplified: Expr) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        if entries.len() >= self.max_entries && !entries.contains_key(&hash) {
+            if let Some(evicted) = entries.keys().next().cloned() {
+                entries.remove(&evicted);
+            }
+        }
+        entries.insert(hash, (original, simplified));
+    }
+}
+
+impl Expr {
+    /// Simplify an expression with [`Expr::simplify`], using the result from the cache if the
+    /// same expression (up to reordering terms and how negatives are written, see
+    /// [`Expr::canonical_hash`]) has been simplified before. Every ordering gets the result of
+    /// the first one which was simplified.
+    pub fn simplify_cached(&mut self, cache: &SimplifyCache) {
+        let hash = self.canonical_hash();
+        match cache.get(hash, self) {
+            Some(simplified) => *self = simplified,
+            None => {
+                let original = self.sign_normalize().into_owned();
+                self.simplify();
+                cache.insert(hash, original, self.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn exprs() -> Vec<Expr> {
+        (0..20)
+            .map(|n| Expr::X.pow(Expr::Const(2)) * n + Expr::X.sin() * Expr::Const(0) + n)
+            .collect()
+    }
+
+    #[test]
+    fn hits() {
+        let cache = SimplifyCache::new(100);
+        let mut a = Expr::X * 2 + 3 + Expr::Const(1);
+        let mut b = Expr::Const(1) + 3 + Expr::Const(2) * Expr::X;
+        a.simplify_cached(&cache);
+        b.simplify_cached(&cache);
+        assert_eq!(a, b);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
+        );
+    }
+
+    #[test]
+    fn sign_forms() {
+        let cache = SimplifyCache::new(100);
+        let e = || Expr::X.sin() * 3 + Expr::X;
+        let forms = [
+            Expr::Neg(Box::new(e())),
+            Expr::Prod(vec![Expr::Const(-1), e()]),
+            Expr::Prod(vec![e(), Expr::Neg(Box::new(Expr::Const(1)))]),
+        ];
+        let results: Vec<Expr> = forms
+            .into_iter()
+            .map(|mut form| {
+                form.simplify_cached(&cache);
+                form
+            })
+            .collect();
+        assert!(results.iter().all(|r| *r == results[0]), "{results:?}");
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                entries: 1
+            }
+        );
+    }
+
+    #[test]
+    fn eviction() {
+        let cache = SimplifyCache::new(5);
+        for mut e in exprs() {
+            e.simplify_cached(&cache);
+        }
+        assert_eq!(cache.stats().entries, 5);
+        assert_eq!(cache.stats().misses, 20);
+    }
+
+    #[test]
+    fn threads() {
+        let cache = Arc::new(SimplifyCache::new(1000));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        for (mut e, mut expected) in exprs().into_iter().zip(exprs()) {
+                            e.simplify_cached(&cache);
+                            expected.simplify();
+                            assert_eq!(e, expected);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 8 * 10 * 20);
+        assert_eq!(stats.entries, 20);
+        assert!(stats.misses <= 8 * 20);
+    }
+}
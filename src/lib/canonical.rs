@@ -0,0 +1,135 @@
+use std::cmp::Ordering;
+
+use super::monomial::Monomial;
+use super::Expr;
+
+impl Expr {
+    /// Get the canonical form of an expression, without changing it. The expression is
+    /// simplified, polynomials are written with their like terms collected, and the terms of
+    /// every sum and product are sorted, so expressions which only differ in how they were built
+    /// have the same canonical form.
+    ///
+    /// Only polynomials are fully canonicalized. Other expressions which are equal might still
+    /// have different canonical forms (e.g. `sin(x)^2 + cos(x)^2` and `1`).
+    pub fn canonical(&self) -> Expr {
+        let mut e = self.clone();
+        e.simplify();
+        e.transform_bottom_up(&mut Expr::canonicalize_node);
+        e
+    }
+
+    /// Whether two expressions have the same canonical form (see [`Expr::canonical`]), so they
+    /// are definitely equal. Expressions which aren't equivalent might still be equal.
+    pub fn equivalent(&self, other: &Expr) -> bool {
+        self.canonical() == other.canonical()
+    }
+
+    /// Write the canonical form of an expression (see [`Expr::canonical`]) as latex, so the
+    /// output doesn't depend on the order the expression was built in. [`Expr::to_latex`]
+    /// writes the expression exactly as it is.
+    pub fn to_latex_canonical(&self) -> String {
+        self.canonical().to_latex()
+    }
+
+    fn canonicalize_node(&mut self) {
+        if matches!(
+            self,
+            Expr::Sum(_) | Expr::Prod(_) | Expr::Pow(_, _) | Expr::Neg(_)
+        ) {
+            if let Some(coeffs) = self.to_coefficients() {
+                *self = Expr::from_coefficients(&coeffs);
+                self.transform_bottom_up(&mut Expr::sort_terms);
+                return;
+            }
+        }
+        self.sort_terms();
+    }
+
+    /// Sort the terms of a sum or product. The terms of a sum are in graded lexicographic order
+    /// of their monomials (see [`Monomial::graded_lex`]), so the highest degree terms come
+    /// first, and terms without a monomial come after the ones with.
+    pub(super) fn sort_terms(&mut self) {
+        match self {
+            Expr::Sum(v) => v.sort_by(|a, b| {
+                let monomial = |e: &Expr| Monomial::new(&e.split_const_factor().1);
+                match (monomial(a), monomial(b)) {
+                    (Some(m), Some(n)) => m.graded_lex(&n),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+                .then_with(|| a.cmp(b))
+            }),
+            Expr::Prod(v) => v.sort(),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Num, Rng, VarId};
+    use super::*;
+
+    /// Write a polynomial as a shuffled sum of terms, with each coefficient split in two and the
+    /// powers of x written as products
+    fn scrambled(coeffs: &[Num], rng: &mut Rng) -> Expr {
+        let mut terms = vec![];
+        for (n, c) in coeffs.iter().enumerate() {
+            let split = rng.range(-5, 5);
+            for c in [c - split, split] {
+                let mut factors = vec![Expr::Const(c)];
+                factors.extend((0..n).map(|_| Expr::X));
+                terms.push(Expr::Prod(factors));
+            }
+        }
+        for i in (1..terms.len()).rev() {
+            terms.swap(i, rng.below(i + 1));
+        }
+        Expr::Sum(terms)
+    }
+
+    #[test]
+    fn construction_order() {
+        let a = Expr::X.sin() + Expr::Const(2) * Expr::X + 1;
+        let b = Expr::Const(1) + Expr::X * 2 + Expr::X.sin();
+        assert_ne!(a.to_latex(), b.to_latex());
+        assert_eq!(a.to_latex_canonical(), b.to_latex_canonical());
+        assert!(a.equivalent(&b));
+        assert!(!a.equivalent(&Expr::X.sin()));
+    }
+
+    #[test]
+    fn polynomials() {
+        let mut rng = Rng::new(373);
+        for _ in 0..200 {
+            let coeffs: Vec<Num> = (0..rng.range(1, 6)).map(|_| rng.range(-9, 9)).collect();
+            let a = Expr::from_coefficients(&coeffs);
+            let b = scrambled(&coeffs, &mut rng);
+            assert!(a.equivalent(&b), "{a:?} {b:?}");
+            assert_eq!(a.to_latex_canonical(), b.to_latex_canonical());
+        }
+    }
+
+    #[test]
+    fn several_variables() {
+        let (y, z) = (|| Expr::Var(VarId('y')), || Expr::Var(VarId('z')));
+        let a = y() * y() + Expr::X * z() * y() + y() * Expr::X * Expr::X * 3 - 1;
+        let b = Expr::Const(-1)
+            + Expr::X.pow(Expr::Const(2)) * 3 * y()
+            + y() * Expr::X * z()
+            + y().pow(Expr::Const(2));
+        assert!(a.equivalent(&b));
+        assert_eq!(a.to_latex_canonical(), b.to_latex_canonical());
+        // The terms are in graded lexicographic order
+        assert_eq!(a.to_latex_canonical(), "(3)yx^{2}+xyz+y^{2}-1");
+    }
+
+    #[test]
+    fn receiver_unchanged() {
+        let e = Expr::Const(1) + Expr::X;
+        let original = e.clone();
+        e.to_latex_canonical();
+        assert_eq!(e, original);
+    }
+}
@@ -0,0 +1,193 @@
+use super::{Expr, ExprKind};
+
+/// An error from writing an expression as Rust source, or as Python with [`Expr::to_python`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CodegenError {
+    /// The expression contains something which has no value in the language (e.g. `i`, a
+    /// variable other than x, or a piecewise function in Rust, or a hole in either), so no code
+    /// is written rather than code which doesn't run.
+    Unsupported(ExprKind),
+}
+
+impl Expr {
+    /// Write the expression as a standalone Rust function taking x, e.g.
+    /// `fn f(x: f64) -> f64 { x.powi(2) + x.sin() }`, which can be pasted into a program or
+    /// included from a build script to evaluate it quickly.
+    ///
+    /// The code only uses arithmetic operators and the methods of `f64`. Powers with a whole
+    /// number exponent use `powi` (and negative exponents are written as a division), and other
+    /// powers use `powf`. Unlike [`Expr::eval`] the function can't fail, so values outside the
+    /// domain of a function give NaN or infinity.
+    ///
+    /// Returns an error if the expression contains `i`, a variable other than x, a factorial, a
+    /// sum over a range or a piecewise function.
+    pub fn to_rust_source(&self, fn_name: &str) -> Result<String, CodegenError> {
+        Ok(format!(
+            "fn {fn_name}(x: f64) -> f64 {{\n    {}\n}}\n",
+            self.rust()?
+        ))
+    }
+
+    /// Write the expression as a Rust closure taking x, e.g. `|x: f64| x.powi(2) + x.sin()`. See
+    /// [`Expr::to_rust_source`].
+    pub fn to_rust_closure(&self) -> Result<String, CodegenError> {
+        Ok(format!("|x: f64| {}", self.rust()?))
+    }
+
+    /// The Rust code for the expression, without parentheses around it
+    fn rust(&self) -> Result<String, CodegenError> {
+        let unsupported = || Err(CodegenError::Unsupported(self.kind()));
+        let join = |v: &[Expr], op| {
+            let terms = v
+                .iter()
+                .map(Expr::rust_operand)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(terms.join(op))
+        };
+        Ok(match self {
+            Expr::Const(n) => format!("{n}_f64"),
+            Expr::X => "x".to_string(),
+            Expr::E => "std::f64::consts::E".to_string(),
+            Expr::Scaled(m, e) => format!("{m}e{e}_f64"),
+            Expr::Sum(v) if v.is_empty() => "0_f64".to_string(),
+            Expr::Sum(v) => join(v, " + ")?,
+            Expr::Prod(v) if v.is_empty() => "1_f64".to_string(),
+            Expr::Prod(v) => join(v, " * ")?,
+            Expr::Neg(e) => format!("-{}", e.rust_operand()?),
+            Expr::Pow(a, b) => match (&**a, &**b) {
+                (Expr::E, _) => format!("{}.exp()", b.rust_operand()?),
+                (_, Expr::Const(-1)) => format!("1_f64 / {}", a.rust_operand()?),
+                (_, Expr::Const(n)) => match i32::try_from(*n) {
+                    Ok(n) if n < 0 => format!("1_f64 / {}.powi({})", a.rust_operand()?, -n),
+                    Ok(n) => format!("{}.powi({n})", a.rust_operand()?),
+                    Err(_) => format!("{}.powf({}_f64)", a.rust_operand()?, n),
+                },
+                _ => format!("{}.powf({})", a.rust_operand()?, b.rust()?),
+            },
+            Expr::Ln(e) => format!("{}.ln()", e.rust_operand()?),
+            Expr::Sin(e) => format!("{}.sin()", e.rust_operand()?),
+            Expr::Cos(e) => format!("{}.cos()", e.rust_operand()?),
+            Expr::Arcsin(e) => format!("{}.asin()", e.rust_operand()?),
+            Expr::Arccos(e) => format!("{}.acos()", e.rust_operand()?),
+            Expr::Arctan(e) => format!("{}.atan()", e.rust_operand()?),
+            Expr::Sinh(e) => format!("{}.sinh()", e.rust_operand()?),
+            Expr::Cosh(e) => format!("{}.cosh()", e.rust_operand()?),
+            Expr::Tanh(e) => format!("{}.tanh()", e.rust_operand()?),
+            Expr::Arsinh(e) => format!("{}.asinh()", e.rust_operand()?),
+            Expr::Arcosh(e) => format!("{}.acosh()", e.rust_operand()?),
+            Expr::Artanh(e) => format!("{}.atanh()", e.rust_operand()?),
+            Expr::Var(_)
+            | Expr::I
+            | Expr::Hole(_)
+            | Expr::Deriv(..)
+            | Expr::Factorial(_)
+            | Expr::SumOver(..)
+            | Expr::Piecewise(_) => return unsupported(),
+        })
+    }
+
+    /// The Rust code for the expression, with parentheses around it unless it can be used as
+    /// an operand or have a method called on it as it is
+    fn rust_operand(&self) -> Result<String, CodegenError> {
+        let code = self.rust()?;
+        let bare = match self {
+            Expr::Const(n) => *n >= 0,
+            Expr::Scaled(m, _) => *m >= 0,
+            Expr::Sum(v) | Expr::Prod(v) => v.len() < 2,
+            Expr::Neg(_) => false,
+            // Negative powers are written as a division
+            Expr::Pow(a, b) => match (&**a, &**b) {
+                (Expr::E, _) => true,
+                (_, Expr::Const(n)) => !i32::try_from(*n).is_ok_and(|n| n < 0),
+                _ => true,
+            },
+            _ => true,
+        };
+        Ok(if bare { code } else { format!("({code})") })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::super::prelude::{arctan, c, e, ln, pow, sin, var, x};
+    use super::super::Num;
+    use super::*;
+
+    #[test]
+    fn source() {
+        let e = c(3) * pow(x(), c(2)) + sin(x() - 1);
+        assert_eq!(
+            e.to_rust_source("f").unwrap(),
+            "fn f(x: f64) -> f64 {\n    (3_f64 * x.powi(2)) + (x + (-1_f64)).sin()\n}\n"
+        );
+        assert_eq!(x().recip().to_rust_closure().unwrap(), "|x: f64| 1_f64 / x");
+        // The exponent is too big for powi
+        let big = Num::from(i32::MAX) + 1;
+        assert_eq!(
+            pow(x(), c(big)).to_rust_closure().unwrap(),
+            format!("|x: f64| x.powf({big}_f64)")
+        );
+        assert_eq!(
+            (x() + var('y')).to_rust_closure(),
+            Err(CodegenError::Unsupported(ExprKind::Var))
+        );
+        assert_eq!(
+            Expr::Piecewise(vec![]).to_rust_closure(),
+            Err(CodegenError::Unsupported(ExprKind::Piecewise))
+        );
+    }
+
+    /// Compile the functions with rustc and check that they give the same values as eval
+    #[test]
+    fn compiles() {
+        let exprs = [
+            pow(x(), c(3)) - x() * 2 + 1,
+            -sin(x()) * pow(x() + 1, c(-2)),
+            pow(e(), -x()) + ln(pow(x(), c(2)) + 1),
+            arctan(pow(x() * x() + 2, x().recip())),
+            Expr::Scaled(-15, -1) * x() / (x().cos() + 3),
+            pow(x() + 3, c(1) / 2),
+        ];
+        let points = [-1.7, 0.3, 2.5];
+        let mut source = String::new();
+        for (i, e) in exprs.iter().enumerate() {
+            source += &e.to_rust_source(&format!("f{i}")).unwrap();
+        }
+        source += "fn main() {\n";
+        for i in 0..exprs.len() {
+            for x in points {
+                source += &format!("    println!(\"{{:?}}\", f{i}({x:?}_f64));\n");
+            }
+        }
+        source += "}\n";
+
+        let dir = std::env::temp_dir().join(format!("codegen-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.rs"), source).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let status = Command::new(rustc)
+            .args(["--edition", "2021", "-D", "warnings", "-o"])
+            .arg(dir.join("main"))
+            .arg(dir.join("main.rs"))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = Command::new(dir.join("main")).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let values: Vec<f64> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let expected = exprs
+            .iter()
+            .flat_map(|e| points.map(|x| e.eval(x).unwrap()));
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() <= 1e-12 * expected.abs().max(1.0));
+        }
+        assert_eq!(values.len(), exprs.len() * points.len());
+    }
+}
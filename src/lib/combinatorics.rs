@@ -0,0 +1,369 @@
+use std::fmt;
+
+use super::{Expr, Num};
+
+/// The most terms [`Expr::simplify_expand_polynomial`] expands a power of a sum into with
+/// [`Expr::expand_pow_multinomial`]. Bigger powers are left as they are.
+pub(super) const MAX_EXPANDED_TERMS: usize = 1000;
+
+/// An error from expanding a power of a sum with [`Expr::expand_pow_multinomial`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExpandError {
+    /// The expression isn't a sum to a constant power which is a whole number
+    NotAPowerOfASum,
+    /// The expansion would have more terms than the limit
+    TooManyTerms {
+        /// How many terms the expansion would have, or `None` if it is too many to count
+        predicted: Option<usize>,
+        /// The most terms which were allowed
+        limit: usize,
+    },
+    /// A coefficient is too big to fit in a [`Num`]
+    Overflow,
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::NotAPowerOfASum => write!(f, "the expression isn't a power of a sum"),
+            ExpandError::TooManyTerms {
+                predicted: Some(predicted),
+                limit,
+            } => write!(
+                f,
+                "the expansion would have {predicted} terms, which is more than {limit}"
+            ),
+            ExpandError::TooManyTerms {
+                predicted: None,
+                limit,
+            } => write!(f, "the expansion would have more than {limit} terms"),
+            ExpandError::Overflow => write!(f, "a coefficient of the expansion overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+/// Find n! as a number, or `None` if it overflows.
+pub(super) fn factorial(n: u32) -> Option<Num> {
+    (1..=n as Num).try_fold(1 as Num, |acc, i| acc.checked_mul(i))
+}
+
+/// Find the binomial coefficient n choose k as a number, or `None` if it overflows.
+pub(super) fn binomial(n: u32, k: u32) -> Option<Num> {
+    if k > n {
+        return Some(0);
+    }
+    let k = k.min(n - k) as Num;
+    let n = n as Num;
+    // Each partial product is itself a binomial coefficient, so the division is always exact
+    (0..k).try_fold(1 as Num, |acc, i| Some(acc.checked_mul(n - i)? / (i + 1)))
+}
+
+impl Expr {
+    /// Get n! as an expression. This is a constant, unless it would overflow, in which case the
+    /// factorial is left unevaluated.
+    pub fn factorial_const(n: u32) -> Self {
+        match factorial(n) {
+            Some(n) => Expr::Const(n),
+            None => Expr::Const(n as Num).factorial(),
+        }
+    }
+
+    /// Get the binomial coefficient n choose k (`n!/(k!(n-k)!)`) as an expression. This is a
+    /// constant, unless it would overflow, in which case it is left as a fraction of factorials.
+    pub fn binomial(n: u32, k: u32) -> Self {
+        match binomial(n, k) {
+            Some(n) => Expr::Const(n),
+            None => {
+                Expr::factorial_const(n) / (Expr::factorial_const(k) * Expr::factorial_const(n - k))
+            }
+        }
+    }
+
+    /// Expand a power of a sum of two terms with the binomial theorem
+    /// e.g. `(x+1)^2 = x^2 + 2x + 1`
+    ///
+    /// Returns `None` if the expression isn't a sum of two terms to a non-negative constant power.
+    pub fn expand_binomial(&self) -> Option<Self> {
+        let Expr::Pow(base, exponent) = self else {
+            return None;
+        };
+        let (Expr::Sum(terms), Expr::Const(n)) = (base.as_ref(), exponent.as_ref()) else {
+            return None;
+        };
+        let [a, b] = terms.as_slice() else {
+            return None;
+        };
+        let n = u32::try_from(*n).ok()?;
+
+        // (a + b)^n = sum of (n choose k) a^(n-k) b^k
+        Some(Expr::Sum(
+            (0..=n)
+                .map(|k| {
+                    Expr::Prod(vec![
+                        Expr::binomial(n, k),
+                        a.clone().pow(Expr::Const((n - k) as Num)),
+                        b.clone().pow(Expr::Const(k as Num)),
+                    ])
+                })
+                .collect(),
+        ))
+    }
+
+    /// Expand a power of a sum with the multinomial theorem, e.g. `(x+y+1)^2` is
+    /// `x^2 + 2xy + 2x + y^2 + 2y + 1`. Each term is made directly with its multinomial
+    /// coefficient, rather than by multiplying the sum out one factor at a time, and like terms
+    /// are collected.
+    ///
+    /// A sum of k terms to the power n has `\binom{n+k-1}{k-1}` terms when it is expanded, which
+    /// grows very quickly, so this gives [`ExpandError::TooManyTerms`] instead of expanding when
+    /// that is more than `max_terms`.
+    pub fn expand_pow_multinomial(&self, max_terms: usize) -> Result<Self, ExpandError> {
+        let Expr::Pow(base, exponent) = self else {
+            return Err(ExpandError::NotAPowerOfASum);
+        };
+        let (Expr::Sum(terms), Expr::Const(n)) = (base.as_ref(), exponent.as_ref()) else {
+            return Err(ExpandError::NotAPowerOfASum);
+        };
+        let n = u32::try_from(*n).map_err(|_| ExpandError::NotAPowerOfASum)?;
+        let k = u32::try_from(terms.len()).map_err(|_| ExpandError::NotAPowerOfASum)?;
+        let predicted = match k {
+            0 => Some(1),
+            k => n
+                .checked_add(k - 1)
+                .and_then(|top| binomial(top, k - 1))
+                .and_then(|count| usize::try_from(count).ok()),
+        };
+        if predicted.is_none_or(|predicted| predicted > max_terms) {
+            return Err(ExpandError::TooManyTerms {
+                predicted,
+                limit: max_terms,
+            });
+        }
+
+        let mut expanded = vec![];
+        let mut powers = vec![0; terms.len()];
+        multinomial_terms(terms, n, 0, 1, &mut powers, &mut expanded)?;
+        let mut expanded = Expr::Sum(expanded);
+        expanded.simplify_collect_like_terms();
+        expanded.simplify_singleton();
+        Ok(expanded)
+    }
+}
+
+/// Add the terms of the expansion of `(terms)^n` to `expanded`, for the powers of the terms from
+/// `i` on which add up to `n`, where the powers before `i` are in `powers` and `coefficient` is
+/// the multinomial coefficient so far
+fn multinomial_terms(
+    terms: &[Expr],
+    n: u32,
+    i: usize,
+    coefficient: Num,
+    powers: &mut [u32],
+    expanded: &mut Vec<Expr>,
+) -> Result<(), ExpandError> {
+    if i + 1 < terms.len() {
+        // The powers are chosen from the biggest down, so the terms come out in the usual order
+        for power in (0..=n).rev() {
+            let choose = binomial(n, power).ok_or(ExpandError::Overflow)?;
+            let coefficient = coefficient
+                .checked_mul(choose)
+                .ok_or(ExpandError::Overflow)?;
+            powers[i] = power;
+            multinomial_terms(terms, n - power, i + 1, coefficient, powers, expanded)?;
+        }
+        return Ok(());
+    }
+    if let Some(last) = powers.get_mut(i) {
+        *last = n;
+    }
+    let mut coefficient = coefficient;
+    let mut factors = vec![];
+    for (term, &power) in terms.iter().zip(powers.iter()) {
+        match (term, power) {
+            (_, 0) => (),
+            // Constant terms are multiplied into the coefficient
+            (Expr::Const(c), power) => {
+                let c = c.checked_pow(power).ok_or(ExpandError::Overflow)?;
+                coefficient = coefficient.checked_mul(c).ok_or(ExpandError::Overflow)?;
+            }
+            (term, 1) => factors.push(term.clone()),
+            (term, power) => factors.push(term.clone().pow(Expr::Const(Num::from(power)))),
+        }
+    }
+    if coefficient == 0 {
+        return Ok(());
+    }
+    if coefficient != 1 || factors.is_empty() {
+        factors.insert(0, Expr::Const(coefficient));
+    }
+    expanded.push(match factors.len() {
+        1 => factors.pop().unwrap(),
+        _ => Expr::Prod(factors),
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, var, x};
+    use super::super::{DerivativeError, EvalError, ExprKind, VarId};
+    use super::*;
+
+    #[test]
+    fn factorials() {
+        assert_eq!(Expr::factorial_const(0), Expr::Const(1));
+        assert_eq!(Expr::factorial_const(5), Expr::Const(120));
+        assert_eq!(Expr::factorial_const(100), Expr::Const(100).factorial());
+
+        let mut e = Expr::Const(5).factorial();
+        e.simplify();
+        assert_eq!(e, Expr::Const(120));
+
+        // Negative factorials are left alone, and can't be evaluated
+        let mut e = Expr::Const(-1).factorial();
+        e.simplify();
+        assert_eq!(e, Expr::Const(-1).factorial());
+        assert_eq!(
+            e.eval(0.0),
+            Err(EvalError::Domain {
+                kind: ExprKind::Factorial,
+                value: -1.0
+            })
+        );
+
+        assert_eq!(
+            Expr::X.factorial().try_derivative(),
+            Err(DerivativeError::NotDifferentiable(ExprKind::Factorial))
+        );
+        assert_eq!(Expr::X.factorial().eval(4.0), Ok(24.0));
+        assert_eq!((Expr::X + 1).factorial().to_latex(), "(x+1)!");
+    }
+
+    #[test]
+    fn binomials() {
+        assert_eq!(Expr::binomial(5, 2), Expr::Const(10));
+        assert_eq!(Expr::binomial(5, 7), Expr::Const(0));
+        assert_eq!(Expr::binomial(60, 30), Expr::Const(118264581564861424));
+        assert!(matches!(Expr::binomial(200, 100), Expr::Prod(_)));
+    }
+
+    #[test]
+    fn expand() {
+        let e = (Expr::X + 1).pow(Expr::Const(5));
+        let expanded = e.expand_binomial().unwrap();
+        assert_eq!(expanded.to_coefficients(), Some(vec![1, 5, 10, 10, 5, 1]));
+        let Expr::Sum(terms) = &expanded else {
+            panic!("expected a sum");
+        };
+        let coefficients: Vec<_> = terms
+            .iter()
+            .map(|t| match t {
+                Expr::Prod(v) => v[0].clone(),
+                _ => panic!("expected a product"),
+            })
+            .collect();
+        assert_eq!(coefficients, [1, 5, 10, 10, 5, 1].map(Expr::Const).to_vec());
+        assert_eq!(Expr::X.pow(Expr::Const(2)).expand_binomial(), None);
+    }
+
+    #[test]
+    fn num_size() {
+        // 25! and 3^50 don't fit in an i64, but do fit in an i128
+        let factorial = Expr::Const(25).factorial().simplified();
+        let power = Expr::Const(3).pow(Expr::Const(50)).simplified();
+        #[cfg(not(feature = "num-i128"))]
+        {
+            assert_eq!(factorial, Expr::Const(25).factorial());
+            assert_eq!(power, Expr::Const(3).pow(Expr::Const(50)));
+        }
+        #[cfg(feature = "num-i128")]
+        {
+            assert_eq!(factorial, Expr::Const(15511210043330985984000000));
+            assert_eq!(power, Expr::Const(717897987691852588770249));
+        }
+    }
+
+    #[test]
+    fn multinomial() {
+        let e = pow(x() + 1, c(5)).expand_pow_multinomial(100).unwrap();
+        assert_eq!(e.to_coefficients(), Some(vec![1, 5, 10, 10, 5, 1]));
+        assert_eq!(e.terms().len(), 6);
+
+        let (y, z) = (|| var('y'), || var('z'));
+        let e = pow(x() + y() + z(), c(2))
+            .expand_pow_multinomial(100)
+            .unwrap();
+        assert_eq!(e.terms().len(), 6);
+        let expected = pow(x(), c(2))
+            + pow(y(), c(2))
+            + pow(z(), c(2))
+            + x() * y() * 2
+            + x() * z() * 2
+            + y() * z() * 2;
+        assert!(e.equivalent(&expected), "{e:?}");
+        // Expanding polynomials goes through it for sums which aren't polynomials in x
+        let mut expanded = pow(x() + y() + z(), c(2));
+        expanded.simplify_expand_polynomial();
+        assert_eq!(expanded, e);
+
+        // The constant terms are multiplied in, and like terms are collected
+        let e = pow(x() + y() + 1, c(4))
+            .expand_pow_multinomial(100)
+            .unwrap();
+        assert_eq!(e.terms().len(), 15);
+        let at = |x: f64, y: f64| e.specialize(&[(VarId('y'), y)]).eval(x).unwrap();
+        assert_eq!(at(2.0, 3.0), 6f64.powi(4));
+        let e = pow(x() + x() * 2, c(3))
+            .expand_pow_multinomial(100)
+            .unwrap();
+        assert!(e.equivalent(&(pow(x(), c(3)) * 27)), "{e:?}");
+
+        assert_eq!(pow(x() + 1, c(0)).expand_pow_multinomial(100), Ok(c(1)));
+        assert_eq!(
+            pow(x(), c(2)).expand_pow_multinomial(100),
+            Err(ExpandError::NotAPowerOfASum)
+        );
+        assert_eq!(
+            pow(x() + 1, c(-2)).expand_pow_multinomial(100),
+            Err(ExpandError::NotAPowerOfASum)
+        );
+    }
+
+    #[test]
+    fn multinomial_guard() {
+        // (a + b + ... + j)^20 would have 10015005 terms
+        let e = pow(Expr::Sum(('a'..='j').map(var).collect()), c(20));
+        assert_eq!(
+            e.expand_pow_multinomial(MAX_EXPANDED_TERMS),
+            Err(ExpandError::TooManyTerms {
+                predicted: Some(10015005),
+                limit: MAX_EXPANDED_TERMS
+            })
+        );
+        let mut simplified = e.clone();
+        simplified.simplify_expand_polynomial();
+        assert_eq!(simplified, e);
+        // Just at the limit is fine
+        let e = pow(x() + var('y') + 1, c(3));
+        assert_eq!(e.expand_pow_multinomial(10).unwrap().terms().len(), 10);
+        assert!(matches!(
+            e.expand_pow_multinomial(9),
+            Err(ExpandError::TooManyTerms { .. })
+        ));
+        // A coefficient which doesn't fit
+        assert_eq!(
+            pow(x() + 1000, c(20)).expand_pow_multinomial(100),
+            Err(ExpandError::Overflow)
+        );
+        // The number of terms is too big to even work out
+        assert_eq!(
+            pow(x() + var('y'), c(u32::MAX as Num)).expand_pow_multinomial(100),
+            Err(ExpandError::TooManyTerms {
+                predicted: None,
+                limit: 100
+            })
+        );
+    }
+}
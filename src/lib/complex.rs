@@ -0,0 +1,90 @@
+use super::Expr;
+
+impl Expr {
+    /// This function multiplies the imaginary units in a product together
+    /// e.g. `2i * 3i = -6` and `i * i * i = -i`
+    pub fn simplify_imaginary_units(&mut self) {
+        if let Expr::Prod(v) = self {
+            let count = v.iter().filter(|e| **e == Expr::I).count();
+            if count < 2 {
+                return;
+            }
+            v.retain(|e| *e != Expr::I);
+            match count % 4 {
+                1 => v.push(Expr::I),
+                2 => v.push(Expr::Const(-1)),
+                3 => v.extend([Expr::Const(-1), Expr::I]),
+                _ => (),
+            }
+            if v.is_empty() {
+                v.push(Expr::Const(1));
+            }
+        }
+    }
+
+    /// This function raises i to constant powers, which repeat every 4
+    /// e.g. `i^2 = -1` and `i^5 = i`
+    pub fn simplify_imaginary_pow(&mut self) {
+        if let Expr::Pow(a, b) = self {
+            if let (Expr::I, Expr::Const(n)) = (a.as_ref(), b.as_ref()) {
+                *self = match n.rem_euclid(4) {
+                    0 => Expr::Const(1),
+                    1 => Expr::I,
+                    2 => Expr::Const(-1),
+                    _ => -Expr::I,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::EvalError;
+    use super::*;
+
+    fn simplified(mut e: Expr) -> Expr {
+        e.simplify();
+        e
+    }
+
+    #[test]
+    fn products() {
+        assert_eq!(
+            simplified((Expr::Const(2) * Expr::I) * (Expr::Const(3) * Expr::I)),
+            Expr::Const(-6)
+        );
+        assert_eq!(
+            simplified(Expr::I * Expr::I * Expr::I),
+            Expr::Prod(vec![Expr::I, Expr::Const(-1)])
+        );
+        assert_eq!(simplified(Expr::I * Expr::X), Expr::I * Expr::X);
+    }
+
+    #[test]
+    fn powers() {
+        assert_eq!(simplified(Expr::I.pow(Expr::Const(5))), Expr::I);
+        assert_eq!(simplified(Expr::I.pow(Expr::Const(2))), Expr::Const(-1));
+        assert_eq!(simplified(Expr::I.pow(Expr::Const(-1))), -Expr::I);
+        assert_eq!(simplified(Expr::I.pow(Expr::Const(8))), Expr::Const(1));
+    }
+
+    #[test]
+    fn roots() {
+        let e = Expr::X.pow(Expr::Const(2)) + 1;
+        assert_eq!(e.roots(false), Some(vec![]));
+        assert_eq!(e.roots(true), Some(vec![Expr::I, -Expr::I]));
+    }
+
+    #[test]
+    fn constant() {
+        assert_eq!(Expr::I.to_latex(), "i");
+        assert_eq!(
+            (Expr::I * Expr::X).derivative().eval(1.0),
+            Err(EvalError::Complex)
+        );
+        let mut derivative = (Expr::I * Expr::X).derivative();
+        derivative.simplify();
+        assert_eq!(derivative, Expr::I);
+    }
+}
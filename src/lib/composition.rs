@@ -0,0 +1,166 @@
+use std::collections::BTreeSet;
+
+use super::substitute::fresh_var;
+use super::{Expr, Num, VarId};
+
+impl Expr {
+    /// Put `inner` in for x, so that the result is this expression of `inner`, e.g. composing
+    /// `x^2 + 1` with `2x - 3` gives `(2x - 3)^2 + 1`.
+    ///
+    /// When the result is a polynomial it is multiplied out and its like terms are collected
+    /// (e.g. into `4x^2 - 12x + 10`), and otherwise it is simplified.
+    pub fn compose(&self, inner: &Expr) -> Expr {
+        let composed = self.substitute(inner);
+        match composed.to_coefficients() {
+            Some(coeffs) => Expr::from_coefficients(&coeffs).simplified(),
+            None => composed.simplified(),
+        }
+    }
+
+    /// Split an expression into `f(ax + b)`, giving f, a and b, so that composing f with
+    /// `ax + b` (see [`Expr::compose`]) gives back the expression, e.g. `sin(3x+1)` is
+    /// `(sin(x), 3, 1)`. Every x in the expression has to be inside the same `ax + b`.
+    ///
+    /// Returns `None` if there is no such inner function other than x itself (e.g. for
+    /// `sin(x^2)`), or if the expression doesn't use x.
+    pub fn decompose_affine(&self) -> Option<(Expr, Num, Num)> {
+        let mut inner = None;
+        let f = pull_out_affine(self, &mut inner)?;
+        match inner? {
+            (1, 0) => None,
+            (a, b) => Some((f, a, b)),
+        }
+    }
+
+    /// The derivative of the expression with respect to a subexpression u which uses x, as if u
+    /// were the variable, e.g. the derivative of `\sin^2 x + 3\sin x` with respect to `\sin x`
+    /// is `2\sin x + 3`. This is the outer derivative in the chain rule.
+    ///
+    /// Every occurrence of u (see [`Expr::replace`]) is replaced with a new variable, which is
+    /// differentiated with respect to and then replaced with u again. Returns `None` if the
+    /// expression uses x anywhere other than in an occurrence of u, since then it isn't a
+    /// function of u alone, or if it can't be differentiated.
+    pub fn derivative_wrt_expr(&self, u: &Expr) -> Option<Expr> {
+        if !u.uses_x() {
+            return None;
+        }
+        // The new variable can't be the index of a sum either, or it would be captured
+        let mut avoid = BTreeSet::new();
+        self.var_names(&mut avoid);
+        u.var_names(&mut avoid);
+        let var = fresh_var(&avoid);
+        let in_u = self.replace(u, &Expr::Var(var));
+        if in_u.uses_x() {
+            return None;
+        }
+        let derivative = in_u.try_partial_derivative(var).ok()?.simplified();
+        Some(derivative.substitute_var(var, u))
+    }
+
+    /// Add the name of every variable in the expression to `names`, bound or not
+    fn var_names(&self, names: &mut BTreeSet<VarId>) {
+        match self {
+            Expr::Var(v) | Expr::SumOver(v, ..) => {
+                names.insert(*v);
+            }
+            _ => (),
+        }
+        for child in self.children() {
+            child.var_names(names);
+        }
+    }
+}
+
+/// Replace the biggest subexpressions of `e` which are `ax + b` (with a not 0) with x, where a
+/// and b have to be the same for all of them and are put in `inner`. `None` if they aren't all
+/// the same.
+fn pull_out_affine(e: &Expr, inner: &mut Option<(Num, Num)>) -> Option<Expr> {
+    if !e.uses_x() {
+        return Some(e.clone());
+    }
+    if let Some(&[b, a]) = e.to_coefficients().as_deref() {
+        if inner.get_or_insert((a, b)) != &(a, b) {
+            return None;
+        }
+        return Some(Expr::X);
+    }
+    let mut f = e.clone();
+    for child in f.children_mut() {
+        *child = pull_out_affine(child, inner)?;
+    }
+    Some(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::conformance;
+    use super::super::prelude::{c, cos, exp, pow, sin, sum_over, var, x};
+    use super::*;
+
+    #[test]
+    fn composition() {
+        let e = (pow(x(), c(2)) + 1).compose(&(x() * 2 - 3));
+        assert_eq!(e.to_coefficients(), Some(vec![10, -12, 4]));
+        assert_eq!(e, Expr::from_coefficients(&[10, -12, 4]).simplified());
+        // Not a polynomial, so it is just simplified
+        let e = sin(x()).compose(&(x() + x()));
+        assert_eq!(e, sin(x() * 2).simplified());
+        assert_eq!(c(5).compose(&x()), c(5));
+    }
+
+    #[test]
+    fn decomposition() {
+        assert_eq!(sin(x() * 3 + 1).decompose_affine(), Some((sin(x()), 3, 1)));
+        // Every x is inside the same 2x - 5
+        let e = exp(x() * 2 - 5) * pow(x() * 2 - 5, c(3));
+        let (f, a, b) = e.decompose_affine().unwrap();
+        assert_eq!((f.clone(), a, b), (exp(x()) * pow(x(), c(3)), 2, -5));
+        assert!(f.compose(&(x() * a + b)).equivalent(&e));
+
+        assert_eq!(sin(pow(x(), c(2))).decompose_affine(), None);
+        assert_eq!(cos(x()).decompose_affine(), None);
+        assert_eq!((sin(x() * 3) + x()).decompose_affine(), None);
+        assert_eq!(c(2).decompose_affine(), None);
+    }
+
+    #[test]
+    fn derivative_wrt_expr() {
+        let e = pow(sin(x()), c(2)) + sin(x()) * 3;
+        let derivative = e.derivative_wrt_expr(&sin(x())).unwrap();
+        crate::assert_expr_eq!(derivative, sin(x()) * 2 + 3);
+        // The chain rule gives the derivative with respect to x back
+        let chain = derivative * sin(x()).derivative();
+        let expected = e.clone().derivative();
+        conformance::check_value_preserving(&expected, &chain, &[-1.3, 0.2, 2.9]).unwrap();
+
+        // u is kept as it was inside the result
+        let square = || pow(x(), c(2));
+        let e = pow(square() + 1, c(3));
+        crate::assert_expr_eq!(
+            e.derivative_wrt_expr(&square()).unwrap(),
+            pow(square() + 1, c(2)) * 3
+        );
+        assert_eq!(c(4).derivative_wrt_expr(&square()), Some(c(0)));
+
+        // x is used outside of sin(x), and u has to use x
+        assert_eq!(
+            (pow(sin(x()), c(2)) + x()).derivative_wrt_expr(&sin(x())),
+            None
+        );
+        assert_eq!(e.derivative_wrt_expr(&c(2)), None);
+    }
+
+    #[test]
+    fn no_stray_variables() {
+        // The first name a new variable would take is the index of a sum, so it would be captured
+        let e = sum_over('j', c(1), var('n'), var('j') * sin(x())) + var('k') * sin(x());
+        let derivative = e.derivative_wrt_expr(&sin(x())).unwrap();
+        assert!(derivative.free_vars().is_subset(&e.free_vars()));
+        assert!(!derivative.uses_x());
+        let expected = sum_over('j', c(1), var('n'), var('j')) + var('k');
+        assert!(
+            derivative.equivalent(&expected.simplified()),
+            "{derivative:?}"
+        );
+    }
+}
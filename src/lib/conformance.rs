@@ -0,0 +1,212 @@
+//! Checks for the invariants which every simplification in this crate keeps, so that rewrites
+//! written outside of it can be tested in the same way. These need the `test-suite` feature.
+//!
+//! A rewrite is any `fn(&mut Expr)`, like the `simplify_*` passes on [`Expr`]. Run it over
+//! [`corpus`] and check the results with [`check_value_preserving`], [`check_idempotent`] and
+//! [`check_no_panic`], which give the first [`Violation`] found.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::{generate_derivative_problem, Difficulty, EvalError, Expr, Rng};
+
+/// The points [`check_value_preserving`] is usually given, which avoid the integers so that
+/// expressions like `ln(x-2)` are defined at some of them
+pub const SAMPLES: &[f64] = &[-7.3, -2.5, -1.1, -0.4, 0.3, 0.9, 1.7, 2.6, 4.2, 9.1];
+
+/// How far apart values can be before [`check_value_preserving`] counts them as different,
+/// relative to their size (or absolute for values smaller than 1)
+const TOLERANCE: f64 = 1e-9;
+
+/// A broken invariant, found by one of the checks
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Rewriting changed the value of an expression at a point where it was defined
+    ValueChanged {
+        /// The expression before rewriting
+        before: Expr,
+        /// The expression after rewriting
+        after: Expr,
+        /// Where they differ
+        x: f64,
+        /// The value of `before` at x
+        expected: f64,
+        /// The value of `after` at x
+        found: Result<f64, EvalError>,
+    },
+    /// Rewriting an expression a second time changed it again
+    NotIdempotent {
+        /// The expression rewritten once
+        once: Expr,
+        /// The expression rewritten twice
+        twice: Expr,
+    },
+    /// The function panicked on an expression
+    Panicked {
+        /// The expression it was given
+        expr: Expr,
+        /// The panic message, if it was a string
+        message: String,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::ValueChanged {
+                before,
+                after,
+                x,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} = {expected} but {} = {found:?} at x = {x}",
+                before.to_latex(),
+                after.to_latex()
+            ),
+            Violation::NotIdempotent { once, twice } => write!(
+                f,
+                "{} changed to {} when rewritten again",
+                once.to_latex(),
+                twice.to_latex()
+            ),
+            Violation::Panicked { expr, message } => {
+                write!(f, "panicked on {}: {message}", expr.to_latex())
+            }
+        }
+    }
+}
+
+/// A list of expressions to run checks over, which is always the same for the same seed and
+/// size. These are the generated derivative problems of every difficulty (see
+/// [`generate_derivative_problem`]) followed by their derivatives, so they cover sums, products,
+/// powers, trig functions and ln.
+pub fn corpus(seed: u64, size: usize) -> Vec<Expr> {
+    let mut rng = Rng::new(seed);
+    let difficulties = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+    let mut corpus = vec![];
+    for i in 0.. {
+        if corpus.len() >= size {
+            break;
+        }
+        let difficulty = difficulties[i % difficulties.len()];
+        let Ok(problem) = generate_derivative_problem(&mut rng, difficulty) else {
+            continue;
+        };
+        corpus.push(problem.expr);
+        corpus.push(problem.derivative);
+    }
+    corpus.truncate(size);
+    corpus
+}
+
+/// Check that `after` has the same value as `before` at each sample point where `before` is
+/// defined. Where `before` can't be evaluated `after` can be anything, since rewriting is allowed
+/// to remove a hole (e.g. `x/x = 1`).
+pub fn check_value_preserving(
+    before: &Expr,
+    after: &Expr,
+    samples: &[f64],
+) -> Result<(), Violation> {
+    for x in samples {
+        let Ok(expected) = before.eval(*x) else {
+            continue;
+        };
+        if !expected.is_finite() {
+            continue;
+        }
+        let found = after.eval(*x);
+        let close =
+            matches!(found, Ok(v) if (v - expected).abs() <= TOLERANCE * expected.abs().max(1.0));
+        if !close {
+            return Err(Violation::ValueChanged {
+                before: before.clone(),
+                after: after.clone(),
+                x: *x,
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check that rewriting each expression in the corpus a second time leaves it alone
+pub fn check_idempotent(rewrite: impl Fn(&mut Expr), corpus: &[Expr]) -> Result<(), Violation> {
+    for e in corpus {
+        let mut once = e.clone();
+        rewrite(&mut once);
+        let mut twice = once.clone();
+        rewrite(&mut twice);
+        if once != twice {
+            return Err(Violation::NotIdempotent { once, twice });
+        }
+    }
+    Ok(())
+}
+
+/// Check that a function doesn't panic on any expression in the corpus. The panic is still
+/// printed by the panic hook.
+pub fn check_no_panic(f: impl Fn(&Expr), corpus: &[Expr]) -> Result<(), Violation> {
+    for e in corpus {
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(e))) {
+            let message = match payload.downcast::<String>() {
+                Ok(s) => *s,
+                Err(payload) => payload
+                    .downcast::<&str>()
+                    .map_or_else(|_| String::new(), |s| s.to_string()),
+            };
+            return Err(Violation::Panicked {
+                expr: e.clone(),
+                message,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Run every check on a rewrite over the corpus: it doesn't panic, is idempotent, and keeps the
+/// value of each expression at the [`SAMPLES`].
+pub fn check_rewrite(rewrite: impl Fn(&mut Expr), corpus: &[Expr]) -> Result<(), Violation> {
+    check_no_panic(|e| rewrite(&mut e.clone()), corpus)?;
+    check_idempotent(&rewrite, corpus)?;
+    for e in corpus {
+        let mut after = e.clone();
+        rewrite(&mut after);
+        check_value_preserving(e, &after, SAMPLES)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, ln, x};
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        assert_eq!(corpus(1, 30), corpus(1, 30));
+        assert_ne!(corpus(1, 30), corpus(2, 30));
+        assert_eq!(corpus(1, 7).len(), 7);
+        assert_eq!(corpus(1, 30)[..7], corpus(1, 7));
+    }
+
+    #[test]
+    fn violations() {
+        let broken = |e: &mut Expr| *e = e.clone() + 1;
+        assert!(matches!(
+            check_value_preserving(&x(), &(x() + 1), SAMPLES),
+            Err(Violation::ValueChanged { x, expected, found: Ok(_), .. }) if x == expected
+        ));
+        assert!(matches!(
+            check_idempotent(broken, &[x()]),
+            Err(Violation::NotIdempotent { .. })
+        ));
+        let e = check_no_panic(|_| panic!("oops"), &[x()]).unwrap_err();
+        assert_eq!(e.to_string(), "panicked on x: oops");
+
+        // ln(x) isn't defined at the negative samples, so they aren't compared
+        assert_eq!(check_value_preserving(&ln(x()), &c(0), &[-1.0]), Ok(()));
+    }
+}
@@ -0,0 +1,246 @@
+use super::Expr;
+
+/// Whether an expression is continuous or differentiable at a point, found by
+/// [`Expr::is_continuous_at`] and [`Expr::is_differentiable_at`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContinuityResult {
+    /// It is, as far as the values near the point show
+    Yes,
+    /// It isn't, for this reason
+    No(Discontinuity),
+    /// The values near the point don't settle down enough to tell (e.g. `sin(1/x)` at 0)
+    Unknown,
+}
+
+/// Why an expression isn't continuous or differentiable at a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discontinuity {
+    /// The limits from both sides are the same, but the value at the point is different or
+    /// isn't defined (e.g. `sin(x)/x` at 0)
+    Removable,
+    /// The limits from each side are different (e.g. at the step of a piecewise expression)
+    Jump,
+    /// The expression goes off to infinity (e.g. `1/x` at 0)
+    VerticalAsymptote,
+    /// The expression is continuous, but the slopes from each side are different (e.g. `|x|` at
+    /// 0)
+    Corner,
+    /// The expression is continuous, but its slope goes off to infinity (e.g. `\sqrt[3]{x}` at 0)
+    VerticalTangent,
+}
+
+/// The limit of a function from one side of a point, found by [`one_sided_limit`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Limit {
+    /// The values get close to `value`, to within about `error`
+    Finite { value: f64, error: f64 },
+    /// The values get bigger and bigger
+    Infinite,
+    /// The function isn't defined on this side
+    Undefined,
+    /// The values don't settle down
+    Unknown,
+}
+
+/// The powers of 10 which are the distances from the point that [`one_sided_limit`] looks at
+const STEPS: std::ops::RangeInclusive<i32> = 3..=9;
+
+/// How small the last change in the values has to be, relative to their size, for them to have
+/// settled down
+const SETTLED: f64 = 1e-3;
+
+impl Expr {
+    /// Find whether the expression is continuous at `x`, which is when it is defined there and
+    /// its limits from both sides are its value.
+    ///
+    /// The limits are found numerically, by evaluating the expression closer and closer to `x`,
+    /// so the answer can be wrong for expressions which change a lot very close to the point. At
+    /// the end of the domain (e.g. `\sqrt{x}` at 0) only the side where it is defined counts.
+    pub fn is_continuous_at(&self, x: f64) -> ContinuityResult {
+        let f = |t: f64| self.eval(t).ok();
+        let left = one_sided_limit(f, x, -1.0);
+        let right = one_sided_limit(f, x, 1.0);
+        let limit = match (left, right) {
+            (Limit::Infinite, _) | (_, Limit::Infinite) => {
+                return ContinuityResult::No(Discontinuity::VerticalAsymptote)
+            }
+            (Limit::Unknown, _) | (_, Limit::Unknown) | (Limit::Undefined, Limit::Undefined) => {
+                return ContinuityResult::Unknown
+            }
+            (Limit::Finite { .. }, Limit::Finite { .. }) if !close(left, right) => {
+                return ContinuityResult::No(Discontinuity::Jump)
+            }
+            (Limit::Finite { .. }, _) => left,
+            (_, limit) => limit,
+        };
+        match f(x).filter(|v| v.is_finite()) {
+            Some(value) if close(limit, exact(value)) => ContinuityResult::Yes,
+            _ => ContinuityResult::No(Discontinuity::Removable),
+        }
+    }
+
+    /// Find whether the expression is differentiable at `x`, which is when it is continuous there
+    /// (see [`Expr::is_continuous_at`]) and its slope is the same from both sides.
+    ///
+    /// The slope from each side is the limit of the derivative, or of the difference quotient
+    /// `(f(t) - f(x))/(t - x)` when the expression can't be differentiated symbolically.
+    pub fn is_differentiable_at(&self, x: f64) -> ContinuityResult {
+        match self.is_continuous_at(x) {
+            ContinuityResult::Yes => (),
+            result => return result,
+        }
+        let (left, right) = match self.clone().try_derivative() {
+            Ok(derivative) => {
+                let f = |t: f64| derivative.eval(t).ok();
+                (one_sided_limit(f, x, -1.0), one_sided_limit(f, x, 1.0))
+            }
+            Err(_) => {
+                let Ok(value) = self.eval(x) else {
+                    return ContinuityResult::Unknown;
+                };
+                let f = |t: f64| Some((self.eval(t).ok()? - value) / (t - x));
+                (one_sided_limit(f, x, -1.0), one_sided_limit(f, x, 1.0))
+            }
+        };
+        match (left, right) {
+            (Limit::Infinite, _) | (_, Limit::Infinite) => {
+                ContinuityResult::No(Discontinuity::VerticalTangent)
+            }
+            (Limit::Unknown, _) | (_, Limit::Unknown) | (Limit::Undefined, Limit::Undefined) => {
+                ContinuityResult::Unknown
+            }
+            (Limit::Finite { .. }, Limit::Finite { .. }) if !close(left, right) => {
+                ContinuityResult::No(Discontinuity::Corner)
+            }
+            _ => ContinuityResult::Yes,
+        }
+    }
+}
+
+/// The limit of `f` at `x` from the side given by the sign of `side`, found by evaluating it at
+/// distances of `10^{-3}` down to `10^{-9}` from `x` (relative to the size of `x`)
+fn one_sided_limit(f: impl Fn(f64) -> Option<f64>, x: f64, side: f64) -> Limit {
+    let scale = x.abs().max(1.0);
+    let values: Vec<Option<f64>> = STEPS
+        .map(|n| f(x + side * scale * 10f64.powi(-n)).filter(|v| v.is_finite()))
+        .collect();
+    if values.iter().all(Option::is_none) {
+        return Limit::Undefined;
+    }
+    let Some(values) = values.into_iter().collect::<Option<Vec<f64>>>() else {
+        return Limit::Unknown;
+    };
+    let changes: Vec<f64> = values.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    let (first, last) = (changes[0], changes[changes.len() - 1]);
+    let value = values[values.len() - 1];
+    if last <= first && last <= SETTLED * value.abs().max(1.0) {
+        return Limit::Finite { value, error: last };
+    }
+    // The values keep growing in size with the same sign, and not more slowly (like ln(x) at 0)
+    let growing = values
+        .windows(2)
+        .all(|w| w[1].abs() > w[0].abs() && w[0].signum() == w[1].signum());
+    if growing && last >= first * 0.9 {
+        Limit::Infinite
+    } else {
+        Limit::Unknown
+    }
+}
+
+/// A value which is known exactly, as a limit
+fn exact(value: f64) -> Limit {
+    Limit::Finite { value, error: 0.0 }
+}
+
+/// Whether two finite limits are close enough to be the same, given how accurately they are
+/// known
+fn close(a: Limit, b: Limit) -> bool {
+    match (a, b) {
+        (Limit::Finite { value: a, error: e }, Limit::Finite { value: b, error: f }) => {
+            (a - b).abs() <= 10.0 * (e + f) + 1e-9 * a.abs().max(b.abs()).max(1.0)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, ln, pow, sin, sqrt, x};
+    use super::super::Condition;
+    use super::*;
+
+    /// |x|, as `-x` when x < 0 and x otherwise
+    fn abs() -> Expr {
+        Expr::Piecewise(vec![
+            (Condition::Less(x(), c(0)), -x()),
+            (Condition::Otherwise, x()),
+        ])
+    }
+
+    #[test]
+    fn continuity() {
+        assert_eq!(abs().is_continuous_at(0.0), ContinuityResult::Yes);
+        assert_eq!(
+            abs().is_differentiable_at(0.0),
+            ContinuityResult::No(Discontinuity::Corner)
+        );
+        let e = x().recip();
+        for result in [e.is_continuous_at(0.0), e.is_differentiable_at(0.0)] {
+            assert_eq!(
+                result,
+                ContinuityResult::No(Discontinuity::VerticalAsymptote)
+            );
+        }
+        let e = pow(x(), c(2));
+        assert_eq!(e.is_continuous_at(3.0), ContinuityResult::Yes);
+        assert_eq!(e.is_differentiable_at(3.0), ContinuityResult::Yes);
+    }
+
+    #[test]
+    fn discontinuities() {
+        assert_eq!(
+            (sin(x()) / x()).is_continuous_at(0.0),
+            ContinuityResult::No(Discontinuity::Removable)
+        );
+        assert_eq!(
+            ln(x()).is_continuous_at(0.0),
+            ContinuityResult::No(Discontinuity::VerticalAsymptote)
+        );
+        assert_eq!(
+            sin(x().recip()).is_continuous_at(0.0),
+            ContinuityResult::Unknown
+        );
+        // Only the side where it is defined counts
+        assert_eq!(sqrt(x()).is_continuous_at(0.0), ContinuityResult::Yes);
+        assert_eq!(
+            pow(x(), c(1) / 3).is_differentiable_at(1.0),
+            ContinuityResult::Yes
+        );
+    }
+
+    #[test]
+    fn junctions() {
+        // A step at 1, and a piece which meets the next one with a different slope at 2
+        let e = Expr::Piecewise(vec![
+            (Condition::Less(x(), c(1)), c(0)),
+            (Condition::Less(x(), c(2)), x()),
+            (Condition::Otherwise, pow(x(), c(2)) - 2),
+        ]);
+        assert_eq!(
+            e.is_continuous_at(1.0),
+            ContinuityResult::No(Discontinuity::Jump)
+        );
+        assert_eq!(e.is_continuous_at(2.0), ContinuityResult::Yes);
+        assert_eq!(
+            e.is_differentiable_at(2.0),
+            ContinuityResult::No(Discontinuity::Corner)
+        );
+        assert_eq!(e.is_differentiable_at(1.5), ContinuityResult::Yes);
+        // Joined smoothly
+        let e = Expr::Piecewise(vec![
+            (Condition::Less(x(), c(0)), c(0)),
+            (Condition::Otherwise, pow(x(), c(2))),
+        ]);
+        assert_eq!(e.is_differentiable_at(0.0), ContinuityResult::Yes);
+    }
+}
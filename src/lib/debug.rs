@@ -0,0 +1,154 @@
+use std::fmt;
+
+use super::Expr;
+
+/// `{:?}` writes the expression on one line in the same way as a derived `Debug`, e.g.
+/// `Sum([Prod([Const(3), X]), Const(1)])`. `{:#?}` writes it as a tree with one node per line
+/// (see [`Expr::debug_tree`]).
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.write_str(self.debug_tree(usize::MAX).trim_end());
+        }
+        match self {
+            Expr::Const(n) => f.debug_tuple("Const").field(n).finish(),
+            Expr::X => f.write_str("X"),
+            Expr::Var(v) => f.debug_tuple("Var").field(v).finish(),
+            Expr::I => f.write_str("I"),
+            Expr::E => f.write_str("E"),
+            Expr::Scaled(m, e) => f.debug_tuple("Scaled").field(m).field(e).finish(),
+            Expr::Hole(id) => f.debug_tuple("Hole").field(id).finish(),
+            Expr::Deriv(v, t) => f.debug_tuple("Deriv").field(v).field(t).finish(),
+            Expr::Sum(v) => f.debug_tuple("Sum").field(v).finish(),
+            Expr::Prod(v) => f.debug_tuple("Prod").field(v).finish(),
+            Expr::Pow(a, b) => f.debug_tuple("Pow").field(a).field(b).finish(),
+            Expr::SumOver(k, from, to, e) => f
+                .debug_tuple("SumOver")
+                .field(k)
+                .field(from)
+                .field(to)
+                .field(e)
+                .finish(),
+            Expr::Neg(e)
+            | Expr::Ln(e)
+            | Expr::Sin(e)
+            | Expr::Cos(e)
+            | Expr::Arcsin(e)
+            | Expr::Arccos(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Cosh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Arcosh(e)
+            | Expr::Artanh(e)
+            | Expr::Factorial(e) => f
+                .debug_tuple(&format!("{:?}", self.kind()))
+                .field(e)
+                .finish(),
+            Expr::Piecewise(v) => f.debug_tuple("Piecewise").field(v).finish(),
+        }
+    }
+}
+
+impl Expr {
+    /// Write the expression as a tree with one node per line, e.g.
+    ///
+    /// ```text
+    /// Sum
+    /// ├─ Prod
+    /// │  ├─ Const(3)
+    /// │  └─ X
+    /// └─ Const(1)
+    /// ```
+    ///
+    /// Nodes more than `max_depth` levels below the root have their children left out, and are
+    /// followed by `…` and the number of nodes which were left out.
+    pub fn debug_tree(&self, max_depth: usize) -> String {
+        let mut tree = String::new();
+        self.write_tree(&mut tree, "", "", max_depth);
+        tree
+    }
+
+    fn write_tree(&self, tree: &mut String, first_prefix: &str, prefix: &str, depth: usize) {
+        tree.push_str(first_prefix);
+        let children = self.children();
+        if children.is_empty() {
+            tree.push_str(&format!("{self:?}\n"));
+            return;
+        }
+        match self {
+            Expr::SumOver(k, ..) => tree.push_str(&format!("SumOver({})", k.0)),
+            _ => tree.push_str(&format!("{:?}", self.kind())),
+        }
+        if depth == 0 {
+            tree.push_str(&format!(" … ({} nodes)\n", self.node_count() - 1));
+            return;
+        }
+        tree.push('\n');
+        for (i, child) in children.iter().enumerate() {
+            let (first, rest) = match i == children.len() - 1 {
+                true => ("└─ ", "   "),
+                false => ("├─ ", "│  "),
+            };
+            child.write_tree(
+                tree,
+                &format!("{prefix}{first}"),
+                &format!("{prefix}{rest}"),
+                depth - 1,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::VarId;
+    use super::*;
+
+    fn example() -> Expr {
+        (Expr::Const(3) * Expr::X + 1) * Expr::X.sin().ln()
+    }
+
+    #[test]
+    fn compact() {
+        assert_eq!(
+            format!("{:?}", example()),
+            "Prod([Sum([Prod([Const(3), X]), Const(1)]), Ln(Sin(X))])"
+        );
+        assert_eq!(
+            format!("{:?}", Expr::Var(VarId('y')).pow(Expr::E)),
+            "Pow(Var(VarId('y')), E)"
+        );
+    }
+
+    #[test]
+    fn tree() {
+        assert_eq!(
+            format!("{:#?}", example()),
+            "\
+Prod
+├─ Sum
+│  ├─ Prod
+│  │  ├─ Const(3)
+│  │  └─ X
+│  └─ Const(1)
+└─ Ln
+   └─ Sin
+      └─ X"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(
+            example().debug_tree(1),
+            "\
+Prod
+├─ Sum … (4 nodes)
+└─ Ln … (2 nodes)
+"
+        );
+        assert_eq!(example().debug_tree(0), "Prod … (8 nodes)\n");
+    }
+}
@@ -1,54 +1,595 @@
-use super::Expr;
+use super::{Expr, ExprKind, HoleId, Num};
+
+/// An error from differentiating an expression.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DerivativeError {
+    /// The expression contains a function which has no derivative, like a factorial.
+    NotDifferentiable(ExprKind),
+    /// The expression has a hole which hasn't been filled in
+    IncompleteExpression(HoleId),
+}
 
 impl Expr {
     /// Find the derivative of an expression.
+    ///
+    /// The derivative isn't simplified, but it is always tidy (see [`Expr::try_derivative`]).
+    ///
+    /// # Panics
+    /// Panics if the expression can't be differentiated. Use [`Expr::try_derivative`] if it
+    /// might contain something like a factorial.
     pub fn derivative(self) -> Self {
-        match self {
+        self.try_derivative()
+            .expect("expression should be differentiable")
+    }
+
+    /// Find the derivative of an expression, or an error if it contains something which can't be
+    /// differentiated (e.g. a factorial, which is only defined for whole numbers).
+    ///
+    /// The derivative isn't simplified, but the zeros and ones left by the rules are tidied
+    /// away, so it is guaranteed that:
+    /// - no sum has a term which is `0`, and no product has a factor which is `1`
+    /// - no sum or product is empty or has only one term or factor
+    /// - no power has an exponent of `0` or `1`
+    /// - no sum or product has only constants in it, unless working it out would overflow, so
+    ///   e.g. the exponents from the power rule are always folded
+    pub fn try_derivative(self) -> Result<Self, DerivativeError> {
+        let mut derivative = self.differentiate(&mut None)?;
+        derivative.tidy_derivative();
+        Ok(derivative)
+    }
+
+    /// Find the derivative of an expression along with the rule used for each subexpression, so
+    /// that the working can be shown.
+    ///
+    /// The steps are in the order they would be written out, so a step comes before the steps
+    /// for the derivatives it needs. The derivative is tidied like the one from
+    /// [`Expr::try_derivative`], but the derivatives in the steps are as the rules gave them.
+    pub fn derivative_with_steps(self) -> Result<(Self, Vec<Step>), DerivativeError> {
+        let mut steps = Some(vec![]);
+        let mut derivative = self.differentiate(&mut steps)?;
+        derivative.tidy_derivative();
+        Ok((derivative, steps.unwrap_or_default()))
+    }
+
+    /// Remove the zeros and ones the rules leave in a derivative, and fold arithmetic on
+    /// constants, so that it has the shape [`Expr::try_derivative`] guarantees. Nothing else is
+    /// simplified.
+    fn tidy_derivative(&mut self) {
+        self.transform_bottom_up(&mut |e| match e {
+            Expr::Sum(v) => {
+                v.retain(|e| *e != Expr::Const(0));
+                if let Some(total) = fold_consts(v, Num::checked_add) {
+                    *e = Expr::Const(total);
+                } else if v.len() <= 1 {
+                    *e = v.pop().unwrap_or(Expr::Const(0));
+                }
+            }
+            Expr::Prod(v) if v.contains(&Expr::Const(0)) => *e = Expr::Const(0),
+            Expr::Prod(v) => {
+                v.retain(|e| *e != Expr::Const(1));
+                if let Some(product) = fold_consts(v, Num::checked_mul) {
+                    *e = Expr::Const(product);
+                } else if v.len() <= 1 {
+                    *e = v.pop().unwrap_or(Expr::Const(1));
+                }
+            }
+            Expr::Pow(_, b) if **b == Expr::Const(0) => *e = Expr::Const(1),
+            Expr::Pow(a, b) if **b == Expr::Const(1) => *e = std::mem::take(&mut **a),
+            Expr::Neg(_) => *e = std::mem::take(e).normalized_sign(),
+            _ => (),
+        });
+    }
+
+    /// Differentiate an expression, recording the steps when `steps` is `Some`.
+    fn differentiate(self, steps: &mut Option<Vec<Step>>) -> Result<Self, DerivativeError> {
+        // Reserve a spot for this step, so that it comes before the steps of its subexpressions
+        let recorded = steps.as_ref().map(|v| (v.len(), self.clone()));
+        let (rule, chain, derivative) = match self {
             // The derivative of a constant is 0
-            Expr::Const(_) => Expr::Const(0),
-            Expr::Prod(v) if v.is_empty() => Expr::Const(0),
-            Expr::Pow(_, b) if matches!(*b, Expr::Const(0)) => Expr::Const(0),
+            Expr::Const(_)
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Deriv(..) => (Rule::Constant, false, Expr::Const(0)),
+            Expr::Hole(id) => return Err(DerivativeError::IncompleteExpression(id)),
+            Expr::Prod(v) if v.is_empty() => (Rule::Constant, false, Expr::Const(0)),
+            Expr::Pow(_, b) if matches!(*b, Expr::Const(0)) => {
+                (Rule::Constant, false, Expr::Const(0))
+            }
             // Simplifications
-            Expr::Prod(mut v) if v.len() == 1 => v.pop().unwrap().derivative(),
-            Expr::Pow(a, b) if matches!(*b, Expr::Const(1)) => a.derivative(),
+            Expr::Prod(mut v) if v.len() == 1 => return v.pop().unwrap().differentiate(steps),
+            Expr::Pow(a, b) if matches!(*b, Expr::Const(1)) => return a.differentiate(steps),
             // The derivative of x is 1
-            Expr::X => Expr::Const(1),
+            Expr::X => (Rule::Variable, false, Expr::Const(1)),
             // The derivative of a sum of expressions is the sum of the expressions' derivatives
             // Maybe it is better to use an itermut to skip the collection but the borrow checker
             // was being annoying
-            Expr::Sum(v) => Expr::Sum(v.into_iter().map(|x| x.derivative()).collect()),
+            Expr::Sum(v) => (
+                Rule::Sum,
+                false,
+                Expr::Sum(
+                    v.into_iter()
+                        .map(|x| x.differentiate(steps))
+                        .collect::<Result<_, _>>()?,
+                ),
+            ),
             // The derivative of a negative expression is negative the derivative of the expression
             // when made positiv
-            Expr::Neg(e) => -e.derivative(),
+            Expr::Neg(e) => (Rule::Negation, false, -e.differentiate(steps)?),
             // Product rule (ab)' = a'b + ab'
             Expr::Prod(mut v) => {
+                let rule = if v.iter().filter(|e| e.uses_x()).count() > 1 {
+                    Rule::Product
+                } else {
+                    Rule::ConstantMultiple
+                };
                 // This should never panic because we have already checked the vector length
                 let a = v.pop().unwrap();
                 let b = Expr::Prod(v);
 
-                // This a.clone() is ugly, especially when b exists without the clone
-                a.clone() * b.clone().derivative() + b * a.derivative()
+                let derivative =
+                    &a * b.clone().differentiate(steps)? + b * a.differentiate(steps)?;
+                (rule, false, derivative)
             }
-            // Power rule (x^a)' = ax^(a-1)
-            Expr::Pow(a, b) if matches!(*b, Expr::Const(_)) => {
-                let dec = *b.clone() - Expr::Const(1);
+            // Power rule (x^a)' = ax^(a-1), for any exponent which is a number but a square root
+            Expr::Pow(a, b)
+                if matches!(*b, Expr::Const(_))
+                    || (b.root_index() != Some(2) && b.exact_value().is_some()) =>
+            {
+                // Work out n-1 now so that it isn't left as a sum for simplify to fold
+                let dec = match *b {
+                    Expr::Const(n) => n.checked_sub(1).map(Expr::Const),
+                    _ => None,
+                }
+                .unwrap_or_else(|| &*b - Expr::Const(1));
                 // Chain rule
-                *b * a.clone().pow(dec) * a.derivative()
+                let chain = is_chain(&a);
+                (
+                    Rule::Power,
+                    chain,
+                    *b * a.clone().pow(dec) * a.differentiate(steps)?,
+                )
+            }
+            // (sqrt(u))' = u'/(2sqrt(u))
+            Expr::Pow(a, b) if b.root_index() == Some(2) => {
+                let chain = is_chain(&a);
+                let derivative =
+                    (Expr::Const(2) * a.clone().sqrt()).recip() * a.differentiate(steps)?;
+                (Rule::Root, chain, derivative)
+            }
+            // (e^u)' = e^u * u'
+            Expr::Pow(ref a, ref b) if **a == Expr::E => {
+                let derivative = self.clone() * b.clone().differentiate(steps)?;
+                (Rule::Exponential, is_chain(b), derivative)
             }
             // a^b = e^(lna * b) so then the derivative is just a^b * (lna * b)'
-            Expr::Pow(ref a, ref b) => (a.clone().ln() * *b.clone()).derivative() * self,
+            Expr::Pow(ref a, ref b) => {
+                let derivative = (a.clone().ln() * &**b).differentiate(steps)? * self;
+                (Rule::Exponential, false, derivative)
+            }
 
             // A bunch of rules + chain rule added in
-            Expr::Ln(x) => (1 / *x.clone()) * x.derivative(),
-            Expr::Sin(x) => Expr::Cos(x.clone()) * x.derivative(),
-            Expr::Cos(x) => -Expr::Sin(x.clone()) * x.derivative(),
+            Expr::Ln(x) => (Rule::Ln, is_chain(&x), chain(x.clone().recip(), *x, steps)?),
+            Expr::Sin(x) => (Rule::Sin, is_chain(&x), chain(x.clone().cos(), *x, steps)?),
+            Expr::Cos(x) => (Rule::Cos, is_chain(&x), chain(-x.clone().sin(), *x, steps)?),
             Expr::Arcsin(x) => {
-                (1 - x.clone().pow(Expr::Const(2))).pow(Expr::Const(1) / 2) * x.derivative()
+                let outer = (1 - x.clone().pow(Expr::Const(2))).sqrt().recip();
+                (Rule::Arcsin, is_chain(&x), chain(outer, *x, steps)?)
             }
             Expr::Arccos(x) => {
-                -(1 - x.clone().pow(Expr::Const(2))).pow(Expr::Const(1) / 2) * x.derivative()
+                let outer = -(1 - x.clone().pow(Expr::Const(2))).sqrt().recip();
+                (Rule::Arccos, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            Expr::Arctan(x) => {
+                let outer = (1 + x.clone().pow(Expr::Const(2))).recip();
+                (Rule::Arctan, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            Expr::Sinh(x) => (
+                Rule::Sinh,
+                is_chain(&x),
+                chain(x.clone().cosh(), *x, steps)?,
+            ),
+            Expr::Cosh(x) => (
+                Rule::Cosh,
+                is_chain(&x),
+                chain(x.clone().sinh(), *x, steps)?,
+            ),
+            Expr::Tanh(x) => {
+                let outer = 1 - x.clone().tanh().pow(Expr::Const(2));
+                (Rule::Tanh, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            Expr::Arsinh(x) => {
+                let outer = (x.clone().pow(Expr::Const(2)) + 1).sqrt().recip();
+                (Rule::Arsinh, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            Expr::Arcosh(x) => {
+                let outer = (x.clone().pow(Expr::Const(2)) - 1).sqrt().recip();
+                (Rule::Arcosh, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            Expr::Artanh(x) => {
+                let outer = (1 - x.clone().pow(Expr::Const(2))).recip();
+                (Rule::Artanh, is_chain(&x), chain(outer, *x, steps)?)
+            }
+            // The derivative of a sum is the sum of the derivatives, as long as the number of
+            // terms doesn't depend on x
+            Expr::SumOver(k, from, to, e) if !from.uses_x() && !to.uses_x() => (
+                Rule::Sum,
+                false,
+                e.differentiate(steps)?.sum_over(k, *from, *to),
+            ),
+            Expr::SumOver(..) => return Err(DerivativeError::NotDifferentiable(ExprKind::SumOver)),
+            Expr::Factorial(e) if !e.uses_x() => (Rule::Constant, false, Expr::Const(0)),
+            // The factorial is only defined on whole numbers, so it has no derivative (the gamma
+            // function would be needed)
+            Expr::Factorial(_) => {
+                return Err(DerivativeError::NotDifferentiable(ExprKind::Factorial))
+            }
+            // Each branch is differentiated on its own, keeping the conditions. This is wrong at
+            // the points where the branches meet if the function isn't smooth there (e.g. |x| at
+            // 0), since that isn't checked.
+            Expr::Piecewise(v) => (
+                Rule::Piecewise,
+                false,
+                Expr::Piecewise(
+                    v.into_iter()
+                        .map(|(c, e)| Ok((c, e.differentiate(steps)?)))
+                        .collect::<Result<_, _>>()?,
+                ),
+            ),
+        };
+        if let (Some(steps), Some((index, expr))) = (steps.as_mut(), recorded) {
+            let step = Step {
+                rule,
+                chain,
+                expr,
+                derivative: derivative.clone(),
+            };
+            steps.insert(index, step);
+        }
+        Ok(derivative)
+    }
+}
+
+/// The chain rule `f(g(x))' = f'(g(x))g'(x)`, given `f'(g(x))` and g. The factors of both are
+/// put in one product, and g' is left out when it is 1 (i.e. g is x), so that nested functions
+/// don't build a product for each level.
+fn chain(outer: Expr, inner: Expr, steps: &mut Option<Vec<Step>>) -> Result<Expr, DerivativeError> {
+    let mut factors = match outer {
+        Expr::Prod(v) => v,
+        outer => vec![outer],
+    };
+    match inner.differentiate(steps)? {
+        Expr::Const(1) => (),
+        Expr::Prod(v) => factors.extend(v),
+        inner => factors.push(inner),
+    }
+    Ok(match factors.len() {
+        1 => factors.pop().unwrap(),
+        _ => Expr::Prod(factors),
+    })
+}
+
+/// The constants in a sum or product folded with `op`, if there are at least two and all of it
+/// is constants, and the answer doesn't overflow
+fn fold_consts(v: &[Expr], op: fn(Num, Num) -> Option<Num>) -> Option<Num> {
+    let (first, rest) = v.split_first().filter(|(_, rest)| !rest.is_empty())?;
+    let value = |e: &Expr| match e {
+        Expr::Const(n) => Some(*n),
+        _ => None,
+    };
+    rest.iter()
+        .try_fold(value(first)?, |total, e| op(total, value(e)?))
+}
+
+/// Whether differentiating a function of this expression needs the chain rule, i.e. it isn't
+/// just x or a constant.
+fn is_chain(inner: &Expr) -> bool {
+    *inner != Expr::X && inner.uses_x()
+}
+
+/// A rule used to differentiate an expression, see [`Step`].
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Rule {
+    /// The derivative of a constant is 0
+    Constant,
+    /// The derivative of x is 1
+    Variable,
+    /// `(a+b)' = a' + b'`
+    Sum,
+    /// `(-a)' = -a'`
+    Negation,
+    /// `(ca)' = ca'` for a constant c
+    ConstantMultiple,
+    /// `(ab)' = a'b + ab'`
+    Product,
+    /// `(x^n)' = nx^(n-1)` for a constant n
+    Power,
+    /// `(sqrt(x))' = 1/(2sqrt(x))`
+    Root,
+    /// `(e^x)' = e^x`, and `(a^b)' = a^b(ln(a)b)'` for other powers which aren't constant
+    Exponential,
+    /// `ln(x)' = 1/x`
+    Ln,
+    /// `sin(x)' = cos(x)`
+    Sin,
+    /// `cos(x)' = -sin(x)`
+    Cos,
+    /// `arcsin(x)' = 1/sqrt(1-x^2)`
+    Arcsin,
+    /// `arccos(x)' = -1/sqrt(1-x^2)`
+    Arccos,
+    /// `arctan(x)' = 1/(1+x^2)`
+    Arctan,
+    /// `sinh(x)' = cosh(x)`
+    Sinh,
+    /// `cosh(x)' = sinh(x)`
+    Cosh,
+    /// `tanh(x)' = 1-tanh(x)^2`
+    Tanh,
+    /// `arsinh(x)' = 1/sqrt(x^2+1)`
+    Arsinh,
+    /// `arcosh(x)' = 1/sqrt(x^2-1)`
+    Arcosh,
+    /// `artanh(x)' = 1/(1-x^2)`
+    Artanh,
+    /// The derivative of a piecewise function is the derivative of each branch, with the same
+    /// conditions
+    Piecewise,
+    /// `f(g(x))' = f'(g(x))g'(x)`. This is never the [`Step::rule`] of a step, but is used along
+    /// with another rule when [`Step::chain`] is set.
+    Chain,
+}
+
+/// One step of working out a derivative with [`Expr::derivative_with_steps`]: the derivative of
+/// a subexpression and the rule used to find it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Step {
+    /// The rule used to differentiate the expression
+    pub rule: Rule,
+    /// Whether the chain rule was needed as well, because the rule was applied to a function of
+    /// something other than x
+    pub chain: bool,
+    /// The expression which was differentiated
+    pub expr: Expr,
+    /// Its derivative (before simplifying)
+    pub derivative: Expr,
+}
+
+impl Step {
+    /// Whether this step used a rule, including [`Rule::Chain`].
+    pub fn uses(&self, rule: Rule) -> bool {
+        self.rule == rule || (rule == Rule::Chain && self.chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Num;
+    use super::*;
+
+    #[test]
+    fn steps() {
+        // (x^2 sin(2x))'
+        let e = Expr::X.pow(Expr::Const(2)) * (Expr::Const(2) * Expr::X).sin();
+        let (derivative, steps) = e.clone().derivative_with_steps().unwrap();
+        assert_eq!(derivative, e.clone().derivative());
+        assert_eq!(steps[0].rule, Rule::Product);
+        assert_eq!(steps[0].expr, e);
+
+        let sin = steps.iter().find(|s| s.rule == Rule::Sin).unwrap();
+        assert!(sin.uses(Rule::Chain));
+        let power = steps.iter().find(|s| s.rule == Rule::Power).unwrap();
+        assert!(!power.uses(Rule::Chain));
+        assert!(steps.iter().any(|s| s.rule == Rule::ConstantMultiple));
+    }
+
+    #[test]
+    fn power_rule_exponent() {
+        let x_pow = |n| Expr::X.pow(Expr::Const(n));
+        let first = x_pow(3).derivative();
+        crate::assert_expr_eq!(first, Expr::Prod(vec![Expr::Const(3), x_pow(2)]));
+        // (3 * x^2)' = x^2 * 0 + 3 * (2 * x^1 * 1), with the zeros and ones tidied away
+        crate::assert_expr_eq!(
+            first.derivative(),
+            Expr::Prod(vec![
+                Expr::Const(3),
+                Expr::Prod(vec![Expr::Const(2), Expr::X])
+            ])
+        );
+        // The steps still have what the rules gave
+        let (_, steps) = x_pow(2).derivative_with_steps().unwrap();
+        crate::assert_expr_eq!(
+            steps[0].derivative,
+            Expr::Prod(vec![Expr::Const(2), x_pow(1), Expr::Const(1)])
+        );
+
+        // n-1 doesn't fit, so it is left as a sum
+        crate::assert_expr_eq!(
+            x_pow(Num::MIN).derivative(),
+            Expr::Prod(vec![
+                Expr::Const(Num::MIN),
+                Expr::X.pow(Expr::Const(Num::MIN) - Expr::Const(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn nested_chain() {
+        use super::super::prelude::{c, cos, ln, pow, sin, x};
+        let inner = || pow(x(), c(2)) + 1;
+        let e = sin(cos(ln(inner())));
+        let derivative = e.clone().derivative();
+        // cos(cos(ln(u))) * -sin(ln(u)) * u^-1 * u', with each level's factors in one product
+        // rather than a product nested inside each level's. This was 35 nodes before, and 32
+        // before the derivative was tidied.
+        assert_eq!(derivative.node_count(), 27);
+        let Expr::Prod(v) = &derivative else {
+            panic!("{derivative:?}")
+        };
+        assert_eq!(v.len(), 4);
+        assert_eq!(v[2], inner().recip());
+
+        // Check the derivatives of the functions of functions against finite differences
+        let h = 1e-6;
+        for e in [
+            e,
+            ln(sin(x()) + 2),
+            cos(pow(x(), c(3))),
+            Expr::Arcsin(Box::new(x() * Expr::Scaled(3, -1))),
+            Expr::Arccos(Box::new(sin(x()) * Expr::Scaled(5, -1))),
+            Expr::Arctan(Box::new(ln(pow(x(), c(2)) + 1))),
+        ] {
+            let derivative = e.clone().derivative();
+            for x in [-1.3, 0.4, 2.1] {
+                let estimate = (e.eval(x + h).unwrap() - e.eval(x - h).unwrap()) / (2.0 * h);
+                let exact = derivative.eval(x).unwrap();
+                assert!(
+                    (estimate - exact).abs() < 1e-6,
+                    "{e:?} at {x}: {exact} != {estimate}"
+                );
+            }
+        }
+    }
+
+    /// Expressions of a kind which use x, along with points where they and their derivatives
+    /// are defined. The arguments of functions aren't just x, so that the chain rule is checked
+    /// too. There are none for kinds which can't be evaluated as real numbers or differentiated.
+    fn examples(kind: ExprKind) -> Vec<(Expr, Vec<f64>)> {
+        use super::super::prelude::*;
+        use super::super::Condition;
+        let points = || vec![-2.1, -0.7, 0.3, 1.6];
+        let small = || vec![-0.9, -0.2, 0.4, 0.8];
+        let inner = || pow(x(), c(2)) * Expr::Scaled(5, -1) + x();
+        let examples = match kind {
+            ExprKind::Const => vec![(c(7), points())],
+            ExprKind::X => vec![(x(), points())],
+            ExprKind::E => vec![(e(), points())],
+            ExprKind::Scaled => vec![(Expr::Scaled(-25, -1), points())],
+            ExprKind::Var
+            | ExprKind::I
+            | ExprKind::Hole
+            | ExprKind::Deriv
+            | ExprKind::Factorial => vec![],
+            ExprKind::Sum => vec![(x() + sin(x()) + 4, points())],
+            ExprKind::Prod => vec![
+                (x() * cos(x()) * ln(pow(x(), c(2)) + 1), points()),
+                (Expr::Prod(vec![c(3), x(), c(-2)]), points()),
+            ],
+            ExprKind::Neg => vec![(-pow(x(), c(3)), points())],
+            ExprKind::Pow => vec![
+                (pow(x(), c(5)), points()),
+                (pow(x() + 3, c(-2)), points()),
+                (sqrt(pow(x(), c(2)) + 1), points()),
+                (pow(x() + 3, x()), points()),
+                (exp(sin(x())), points()),
+            ],
+            ExprKind::Ln => vec![(ln(pow(x(), c(2)) + 1), points())],
+            ExprKind::Sin => vec![(sin(inner()), points())],
+            ExprKind::Cos => vec![(cos(inner()), points())],
+            ExprKind::Arcsin => vec![(arcsin(x() * Expr::Scaled(9, -1)), small())],
+            ExprKind::Arccos => vec![(arccos(x() * Expr::Scaled(9, -1)), small())],
+            ExprKind::Arctan => vec![(arctan(inner()), points())],
+            ExprKind::Sinh => vec![(sinh(inner()), points())],
+            ExprKind::Cosh => vec![(cosh(inner()), points())],
+            ExprKind::Tanh => vec![(tanh(inner()), points())],
+            ExprKind::Arsinh => vec![(arsinh(inner()), points())],
+            ExprKind::Arcosh => vec![(arcosh(pow(x(), c(2)) + 2), points())],
+            ExprKind::Artanh => vec![(artanh(x() * Expr::Scaled(9, -1)), small())],
+            ExprKind::SumOver => vec![(sum_over('k', c(1), c(4), var('k') * sin(x())), points())],
+            ExprKind::Piecewise => vec![(
+                Expr::Piecewise(vec![
+                    (Condition::Less(x(), c(0)), pow(x(), c(2))),
+                    (Condition::Otherwise, sin(x())),
+                ]),
+                points(),
+            )],
+        };
+        for (e, _) in &examples {
+            assert_eq!(e.kind(), kind);
+        }
+        examples
+    }
+
+    /// The derivative of every kind of expression is close to a central finite difference
+    #[test]
+    fn every_kind() {
+        let h = 1e-6;
+        for kind in ExprKind::ALL {
+            for (e, points) in examples(kind) {
+                let derivative = e.clone().derivative();
+                for x in points {
+                    let estimate = (e.eval(x + h).unwrap() - e.eval(x - h).unwrap()) / (2.0 * h);
+                    let exact = derivative.eval(x).unwrap();
+                    assert!(
+                        (estimate - exact).abs() < 1e-6 * exact.abs().max(1.0),
+                        "{e:?} at {x}: {exact} != {estimate}"
+                    );
+                }
             }
-            Expr::Arctan(x) => 1 / (1 + x.clone().pow(Expr::Const(2))) * x.derivative(),
         }
     }
+
+    /// Reciprocals are negative powers, so they go through the power rule like any other power
+    #[test]
+    fn reciprocals() {
+        let check = |latex: &str, exact: fn(f64) -> f64| {
+            let derivative = Expr::from_latex(latex).unwrap().try_derivative().unwrap();
+            for x in [0.3, 1.0, 2.5] {
+                let value = derivative.eval(x).unwrap();
+                assert!((value - exact(x)).abs() < 1e-9, "{latex} at {x}");
+            }
+        };
+        check("\\frac{1}{x}", |x| -1.0 / (x * x));
+        check("\\frac{1}{x^2+1}", |x| -2.0 * x / (x * x + 1.0).powi(2));
+        check("x^x", |x| x.powf(x) * (x.ln() + 1.0));
+    }
+
+    /// Every generated problem and every part of it can be differentiated, with no kind left
+    /// out, and every derivative is tidy
+    #[test]
+    fn generated() {
+        let corpus = super::super::conformance::corpus(424, 300);
+        for e in &corpus {
+            let mut parts = vec![];
+            e.clone()
+                .transform_bottom_up(&mut |e| parts.push(e.clone()));
+            for part in parts {
+                let derivative = part.clone().try_derivative();
+                assert!(derivative.is_ok(), "{part:?}");
+                assert_tidy(&derivative.unwrap());
+            }
+        }
+        for kind in ExprKind::ALL {
+            for (e, _) in examples(kind) {
+                assert_tidy(&e.derivative());
+            }
+        }
+    }
+
+    /// Check that a derivative has the shape [`Expr::try_derivative`] guarantees
+    fn assert_tidy(derivative: &Expr) {
+        let mut check = |e: &mut Expr| {
+            let folded = match e {
+                Expr::Sum(v) => {
+                    assert!(!v.contains(&Expr::Const(0)), "a zero term in {e:?}");
+                    fold_consts(v, Num::checked_add)
+                }
+                Expr::Prod(v) => {
+                    assert!(!v.contains(&Expr::Const(1)), "a factor of one in {e:?}");
+                    fold_consts(v, Num::checked_mul)
+                }
+                Expr::Pow(_, b) => {
+                    assert!(!matches!(**b, Expr::Const(0 | 1)), "{e:?}");
+                    None
+                }
+                _ => None,
+            };
+            assert!(folded.is_none(), "unfolded constants in {e:?}");
+            if let Expr::Sum(v) | Expr::Prod(v) = e {
+                assert!(v.len() > 1, "{e:?} doesn't need to be a sum or product");
+            }
+        };
+        derivative.clone().transform_bottom_up(&mut check);
+    }
 }
@@ -0,0 +1,172 @@
+use std::fmt;
+
+use super::Expr;
+
+/// A difference between two expressions found by [`Expr::diff_structure`]. The paths are the
+/// indices of the children to follow from the root of each expression to get to the differing
+/// subexpressions (see [`Expr::children`]). When a term has nothing to match it, the path for
+/// the other expression is to the sum or product it would have been in.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExprDiff {
+    /// Where the difference is in the first expression
+    pub left_path: Vec<usize>,
+    /// Where the difference is in the second expression
+    pub right_path: Vec<usize>,
+    /// The subexpression of the first expression, or `None` if a term of the second expression
+    /// has nothing to match it
+    pub left: Option<Expr>,
+    /// The subexpression of the second expression, or `None` if a term of the first expression
+    /// has nothing to match it
+    pub right: Option<Expr>,
+}
+
+impl fmt::Display for ExprDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let latex = |e: &Option<Expr>| e.as_ref().map_or("nothing".to_string(), Expr::to_latex);
+        if self.left_path == self.right_path {
+            write!(f, "at {:?}: ", self.left_path)?;
+        } else {
+            write!(f, "at {:?} and {:?}: ", self.left_path, self.right_path)?;
+        }
+        write!(f, "{} != {}", latex(&self.left), latex(&self.right))
+    }
+}
+
+impl Expr {
+    /// Find where two expressions differ. The terms of sums and products are matched up with
+    /// equal terms first and then with terms of the same kind, so reordering terms isn't a
+    /// difference.
+    ///
+    /// Each difference is as deep in the trees as possible, e.g. `3x` and `2x` only differ at the
+    /// constant.
+    pub fn diff_structure(&self, other: &Expr) -> Vec<ExprDiff> {
+        let mut diffs = vec![];
+        diff(self, other, &mut vec![], &mut vec![], &mut diffs);
+        diffs
+    }
+}
+
+fn diff(
+    a: &Expr,
+    b: &Expr,
+    a_path: &mut Vec<usize>,
+    b_path: &mut Vec<usize>,
+    diffs: &mut Vec<ExprDiff>,
+) {
+    if a == b {
+        return;
+    }
+    let (a_children, b_children) = (a.children(), b.children());
+    let pairs = match (a, b) {
+        (Expr::Sum(_), Expr::Sum(_)) | (Expr::Prod(_), Expr::Prod(_)) => {
+            pair_terms(&a_children, &b_children)
+        }
+        _ if a.kind() == b.kind() && !a_children.is_empty() => {
+            (0..a_children.len()).map(|i| (Some(i), Some(i))).collect()
+        }
+        _ => {
+            diffs.push(ExprDiff {
+                left_path: a_path.clone(),
+                right_path: b_path.clone(),
+                left: Some(a.clone()),
+                right: Some(b.clone()),
+            });
+            return;
+        }
+    };
+
+    for pair in pairs {
+        match pair {
+            (Some(i), Some(j)) => {
+                a_path.push(i);
+                b_path.push(j);
+                diff(a_children[i], b_children[j], a_path, b_path, diffs);
+                a_path.pop();
+                b_path.pop();
+            }
+            (i, j) => {
+                let path = |path: &Vec<usize>, i: Option<usize>| {
+                    let mut path = path.clone();
+                    path.extend(i);
+                    path
+                };
+                diffs.push(ExprDiff {
+                    left_path: path(a_path, i),
+                    right_path: path(b_path, j),
+                    left: i.map(|i| a_children[i].clone()),
+                    right: j.map(|j| b_children[j].clone()),
+                });
+            }
+        }
+    }
+}
+
+/// Match up the terms of two sums or products, first with equal terms, then with terms of the
+/// same kind and then in order. Equal pairs aren't returned, and terms without a match are paired
+/// with `None`.
+fn pair_terms(a: &[&Expr], b: &[&Expr]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut a_left: Vec<usize> = (0..a.len()).collect();
+    let mut b_left: Vec<usize> = (0..b.len()).collect();
+    let mut pairs = vec![];
+
+    let mut take_matches = |matches: &dyn Fn(&Expr, &Expr) -> bool, keep: bool| {
+        a_left.retain(
+            |&i| match b_left.iter().position(|&j| matches(a[i], b[j])) {
+                Some(position) => {
+                    let j = b_left.remove(position);
+                    if keep {
+                        pairs.push((Some(i), Some(j)));
+                    }
+                    false
+                }
+                None => true,
+            },
+        );
+    };
+    take_matches(&|a, b| a == b, false);
+    take_matches(&|a, b| a.kind() == b.kind(), true);
+    take_matches(&|_, _| true, true);
+
+    pairs.extend(a_left.into_iter().map(|i| (Some(i), None)));
+    pairs.extend(b_left.into_iter().map(|j| (None, Some(j))));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Num;
+    use super::*;
+
+    fn quadratic(b: Num) -> Expr {
+        Expr::X.pow(Expr::Const(2)) + Expr::Const(b) * Expr::X + 1
+    }
+
+    #[test]
+    fn one_term() {
+        let diffs = quadratic(3).diff_structure(&quadratic(2));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].left_path, vec![1, 0]);
+        assert_eq!(diffs[0].left, Some(Expr::Const(3)));
+        assert_eq!(diffs[0].right, Some(Expr::Const(2)));
+        assert_eq!(diffs[0].to_string(), "at [1, 0]: 3 != 2");
+    }
+
+    #[test]
+    fn equal() {
+        assert!(quadratic(3).diff_structure(&quadratic(3)).is_empty());
+        let reordered = Expr::Const(1) + Expr::X.pow(Expr::Const(2)) + Expr::Const(3) * Expr::X;
+        assert!(quadratic(3).diff_structure(&reordered).is_empty());
+    }
+
+    #[test]
+    fn missing_term() {
+        let diffs = quadratic(3).diff_structure(&(Expr::X.pow(Expr::Const(2)) + 1));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].left, Some(Expr::Const(3) * Expr::X));
+        assert_eq!(diffs[0].right, None);
+        assert_eq!(diffs[0].right_path, vec![]);
+
+        let diffs = Expr::X.sin().diff_structure(&Expr::X.cos());
+        assert_eq!(diffs[0].to_string(), "at []: \\sin(x) != \\cos(x)");
+    }
+}
@@ -0,0 +1,134 @@
+use super::{Expr, VarId};
+
+/// The arbitrary constant in the general solution of a differential equation.
+pub const CONSTANT: VarId = VarId('C');
+
+/// A general solution of a differential equation, where [`CONSTANT`] is the arbitrary constant.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OdeSolution {
+    /// y is equal to this expression
+    Explicit(Expr),
+    /// y couldn't be separated out, but this expression (in terms of x and y) is equal to 0
+    Implicit(Expr),
+}
+
+/// Solve a separable differential equation `y' = f(x)g(y)`, given the right hand side with y
+/// written as `Expr::Var(y)`.
+///
+/// Both sides of `∫1/g(y) dy = ∫f(x) dx + C` are integrated, and then y is separated out if the
+/// left side is simple enough (e.g. `ln(y)`). Returns `None` if the equation isn't separable or
+/// one of the integrals can't be done.
+pub fn solve_separable(dy_dx_rhs: &Expr, y: VarId) -> Option<OdeSolution> {
+    let factors = match dy_dx_rhs {
+        Expr::Prod(v) => v.clone(),
+        e => vec![e.clone()],
+    };
+    let mut f = vec![];
+    let mut g = vec![];
+    for factor in factors {
+        match (factor.uses_x(), factor.uses_var(y)) {
+            (_, false) => f.push(factor),
+            (false, true) => g.push(factor),
+            (true, true) => return None,
+        }
+    }
+
+    let y_expr = Expr::Var(y);
+    // Integrate with respect to y by writing it as x for a bit
+    let mut left = product(g).recip().replace(&y_expr, &Expr::X);
+    left.simplify();
+    let mut left = left.integrate()?.substitute(&y_expr);
+    left.simplify();
+    let mut right = product(f).integrate()?;
+    right.simplify();
+
+    let constant = Expr::Var(CONSTANT);
+    Some(if left == y_expr {
+        OdeSolution::Explicit(right + constant)
+    } else if left == y_expr.clone().ln() {
+        // e^(F(x) + C) = Ce^F(x), for a different C
+        OdeSolution::Explicit(constant * right.exp())
+    } else {
+        OdeSolution::Implicit(left - right - constant)
+    })
+}
+
+fn product(mut factors: Vec<Expr>) -> Expr {
+    match factors.len() {
+        0 => Expr::Const(1),
+        1 => factors.pop().unwrap(),
+        _ => Expr::Prod(factors),
+    }
+}
+
+/// Solve a first order linear differential equation `y' + p(x)y = q(x)` for y, with the
+/// integrating factor `μ = e^∫p`, so that `y = (∫μq dx + C)/μ`.
+///
+/// Returns `None` if one of the integrals can't be done.
+pub fn solve_linear_first_order(p: &Expr, q: &Expr) -> Option<Expr> {
+    let mut integral_of_p = p.integrate()?;
+    integral_of_p.simplify();
+    let mut integrand = integral_of_p.clone().exp() * q;
+    integrand.simplify();
+    let mut y = (integrand.integrate()? + Expr::Var(CONSTANT)) * (-integral_of_p).exp();
+    y.simplify();
+    Some(y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that a solution is right at a few points, when C is set to 3
+    fn check(solution: &Expr, expected: impl Fn(f64) -> f64) {
+        let solution = solution.replace(&Expr::Var(CONSTANT), &Expr::Const(3));
+        for x in [-1.0, 0.0, 0.5, 2.0] {
+            assert!((solution.eval(x).unwrap() - expected(x)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn separable() {
+        let y = VarId('y');
+        // y' = xy, so y = Ce^(x^2/2)
+        let Some(OdeSolution::Explicit(solution)) = solve_separable(&(Expr::X * Expr::Var(y)), y)
+        else {
+            panic!("expected an explicit solution");
+        };
+        check(&solution, |x| 3.0 * f64::exp(x * x / 2.0));
+
+        // y' = 2x, so y = x^2 + C
+        let Some(OdeSolution::Explicit(solution)) = solve_separable(&(Expr::X * 2), y) else {
+            panic!("expected an explicit solution");
+        };
+        check(&solution, |x| x * x + 3.0);
+
+        // y' = y, so y = Ce^x
+        let Some(OdeSolution::Explicit(solution)) = solve_separable(&Expr::Var(y), y) else {
+            panic!("expected an explicit solution");
+        };
+        check(&solution, |x| 3.0 * f64::exp(x));
+
+        // y' = x/cos(y), so sin(y) = x^2/2 + C
+        let rhs = Expr::X * Expr::Var(y).cos().recip();
+        assert!(matches!(
+            solve_separable(&rhs, y),
+            Some(OdeSolution::Implicit(_))
+        ));
+    }
+
+    #[test]
+    fn linear() {
+        // y' + y = x, so y = x - 1 + Ce^-x
+        let solution = solve_linear_first_order(&Expr::Const(1), &Expr::X).unwrap();
+        check(&solution, |x| x - 1.0 + 3.0 * f64::exp(-x));
+    }
+
+    #[test]
+    fn unsolvable() {
+        let y = VarId('y');
+        assert_eq!(solve_separable(&(Expr::X * Expr::Var(y)).sin(), y), None);
+        let p = Expr::X.sin().sin();
+        assert_eq!(solve_linear_first_order(&p, &Expr::X), None);
+    }
+}
@@ -0,0 +1,144 @@
+use super::Expr;
+
+/// The number of points sampled when looking for singularities in an interval.
+const SAMPLES: usize = 1000;
+
+impl Expr {
+    /// Whether the expression has a singularity (a point where it can't be evaluated) somewhere
+    /// in the closed interval `[a, b]`.
+    ///
+    /// This looks for zeros of everything the expression divides by or takes the ln of, as well
+    /// as any sampled point where evaluation fails. It is numerical, so a singularity that is
+    /// extremely close to (but not on) a zero of a denominator may be reported.
+    pub fn has_singularity_in(&self, a: f64, b: f64) -> bool {
+        let (a, b) = if a <= b { (a, b) } else { (b, a) };
+        if sample_points(a, b).any(|x| self.eval(x).is_err()) {
+            return true;
+        }
+
+        let mut zero_sensitive = Vec::new();
+        self.zero_sensitive_terms(&mut zero_sensitive);
+        zero_sensitive.into_iter().any(|u| has_zero_in(u, a, b))
+    }
+
+    /// Collect every subexpression that can't be zero, i.e. the bases of negative powers and the
+    /// arguments of ln.
+    fn zero_sensitive_terms<'a>(&'a self, terms: &mut Vec<&'a Expr>) {
+        match self {
+            Expr::Const(_)
+            | Expr::X
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Hole(_)
+            | Expr::Deriv(..) => (),
+            Expr::Sum(v) | Expr::Prod(v) => {
+                for e in v {
+                    e.zero_sensitive_terms(terms);
+                }
+            }
+            Expr::Pow(a, b) => {
+                if !matches!(**b, Expr::Const(n) if n >= 0) {
+                    terms.push(a);
+                }
+                a.zero_sensitive_terms(terms);
+                b.zero_sensitive_terms(terms);
+            }
+            Expr::Ln(e) => {
+                terms.push(e);
+                e.zero_sensitive_terms(terms);
+            }
+            Expr::Neg(e)
+            | Expr::Sin(e)
+            | Expr::Cos(e)
+            | Expr::Arcsin(e)
+            | Expr::Arccos(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Cosh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Arcosh(e)
+            | Expr::Artanh(e)
+            | Expr::Factorial(e) => e.zero_sensitive_terms(terms),
+            Expr::SumOver(..) | Expr::Piecewise(_) => {
+                for e in self.children() {
+                    e.zero_sensitive_terms(terms);
+                }
+            }
+        }
+    }
+}
+
+fn sample_points(a: f64, b: f64) -> impl Iterator<Item = f64> {
+    (0..=SAMPLES).map(move |i| a + (b - a) * i as f64 / SAMPLES as f64)
+}
+
+/// Whether `u` has a zero in `[a, b]`, found either as a sign change between samples, or as a
+/// turning point (where `u'` changes sign) which touches zero.
+fn has_zero_in(u: &Expr, a: f64, b: f64) -> bool {
+    if !sign_changes(a, b, |x| u.eval(x).ok()).is_empty() {
+        return true;
+    }
+    let Ok(du) = u.clone().try_derivative() else {
+        return false;
+    };
+    sign_changes(a, b, |x| du.eval(x).ok())
+        .into_iter()
+        .any(|(lo, hi)| {
+            let turning_point = bisect(lo, hi, |x| du.eval(x).ok());
+            matches!(u.eval(turning_point), Ok(v) if v.abs() < 1e-9)
+        })
+}
+
+/// Find every pair of adjacent sample points in `[a, b]` where `f` changes sign (or is zero).
+fn sign_changes(a: f64, b: f64, f: impl Fn(f64) -> Option<f64>) -> Vec<(f64, f64)> {
+    let points: Vec<_> = sample_points(a, b)
+        .filter_map(|x| Some((x, f(x)?)))
+        .collect();
+    let mut changes: Vec<_> = points
+        .windows(2)
+        .filter(|w| w[1].1 == 0.0 || w[0].1.signum() != w[1].1.signum())
+        .map(|w| (w[0].0, w[1].0))
+        .collect();
+    if let Some((x, y)) = points.first() {
+        if *y == 0.0 {
+            changes.insert(0, (*x, *x));
+        }
+    }
+    changes
+}
+
+/// Find a zero of `f` between two points where it has different signs
+fn bisect(mut lo: f64, mut hi: f64, f: impl Fn(f64) -> Option<f64>) -> f64 {
+    let lo_sign = f(lo).map_or(0.0, f64::signum);
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        match f(mid) {
+            Some(v) if v.signum() == lo_sign && v != 0.0 => lo = mid,
+            _ => hi = mid,
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn singularities() {
+        let recip_square = Expr::X.pow(Expr::Const(-2));
+        assert!(recip_square.has_singularity_in(-1.0, 1.0));
+        assert!(!recip_square.has_singularity_in(1.0, 2.0));
+
+        // A double root in the denominator has no sign change
+        let double_root = (Expr::X.pow(Expr::Const(2)) - Expr::X * 2 + 1).recip();
+        assert!(double_root.has_singularity_in(0.123, 3.0));
+        assert!(!double_root.has_singularity_in(1.5, 3.0));
+
+        assert!(Expr::X.ln().has_singularity_in(-2.0, -1.0));
+        assert!(!Expr::X.sin().has_singularity_in(-10.0, 10.0));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use super::{Condition, Expr};
+
+/// Settings for how an expression is written by [`Expr::to_dot_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Write subexpressions which are the same as one node, with an edge from each place it is
+    /// used, which shows how much of the tree could be shared. By default every subexpression
+    /// has its own node, so the graph is a tree.
+    pub merge_identical: bool,
+}
+
+impl Expr {
+    /// Write the expression tree as a Graphviz digraph (see [`Expr::to_dot_with`]).
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&DotOptions::default())
+    }
+
+    /// Write the expression tree as a Graphviz digraph, which can be drawn with `dot -Tsvg`.
+    ///
+    /// Each subexpression is a node, labelled with its variant and what is in it (e.g. `Const
+    /// 3`, `Pow`, `sin`), with an edge to each of its children. The children are drawn left to
+    /// right in order, and the edges of the variants whose children mean different things are
+    /// labelled (e.g. the base and exponent of a [`Expr::Pow`]).
+    pub fn to_dot_with(&self, options: &DotOptions) -> String {
+        let mut graph = Graph {
+            dot: String::from("digraph expr {\n    ordering=out;\n    node [shape=box];\n"),
+            ids: HashMap::new(),
+            next_id: 0,
+            options,
+        };
+        graph.write(self);
+        graph.dot.push_str("}\n");
+        graph.dot
+    }
+
+    /// The label of this node in a graph, without its children
+    fn dot_label(&self) -> String {
+        match self {
+            Expr::Const(n) => format!("Const {n}"),
+            Expr::X => "X".to_string(),
+            Expr::Var(v) => format!("Var {}", v.0),
+            Expr::I => "I".to_string(),
+            Expr::E => "E".to_string(),
+            Expr::Scaled(m, e) => format!("Scaled {m}e{e}"),
+            Expr::Hole(id) => format!("Hole {}", id.0),
+            Expr::Deriv(v, t) => format!("Deriv d{}/d{}", v.0, t.0),
+            Expr::SumOver(k, ..) => format!("SumOver {}", k.0),
+            Expr::Sum(_) | Expr::Prod(_) | Expr::Neg(_) | Expr::Pow(..) | Expr::Piecewise(_) => {
+                format!("{:?}", self.kind())
+            }
+            _ => format!("{:?}", self.kind()).to_lowercase(),
+        }
+    }
+
+    /// The labels of the edges to the children of this node, in the same order as
+    /// [`Expr::children`], along with which side of the node they leave from
+    fn dot_edges(&self) -> Vec<Option<(String, &'static str)>> {
+        match self {
+            Expr::Pow(..) => vec![
+                Some(("base".to_string(), "sw")),
+                Some(("exponent".to_string(), "se")),
+            ],
+            Expr::SumOver(..) => vec![
+                Some(("from".to_string(), "sw")),
+                Some(("to".to_string(), "s")),
+                Some(("body".to_string(), "se")),
+            ],
+            Expr::Piecewise(v) => v
+                .iter()
+                .enumerate()
+                .flat_map(|(i, (condition, _))| {
+                    let op = match condition {
+                        Condition::Less(..) => "<",
+                        Condition::LessEq(..) => "≤",
+                        Condition::Equal(..) => "=",
+                        Condition::Otherwise => "",
+                    };
+                    let sides = condition.sides().into_iter().zip(["left of", "right of"]);
+                    sides
+                        .map(move |(_, side)| Some((format!("{} {side} {op}", i + 1), "s")))
+                        .chain([Some((format!("{} value", i + 1), "s"))])
+                })
+                .collect(),
+            e => e.children().iter().map(|_| None).collect(),
+        }
+    }
+}
+
+/// A graph being written by [`Expr::to_dot_with`]
+struct Graph<'a> {
+    dot: String,
+    /// The nodes which have been written, when they are merged
+    ids: HashMap<&'a Expr, usize>,
+    next_id: usize,
+    options: &'a DotOptions,
+}
+
+impl<'a> Graph<'a> {
+    /// Write the node for an expression and everything below it, and get its id
+    fn write(&mut self, e: &'a Expr) -> usize {
+        if let Some(id) = self.ids.get(e) {
+            return *id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        if self.options.merge_identical {
+            self.ids.insert(e, id);
+        }
+        self.dot.push_str(&format!(
+            "    n{id} [label=\"{}\"];\n",
+            escape(&e.dot_label())
+        ));
+        for (child, edge) in e.children().into_iter().zip(e.dot_edges()) {
+            let child_id = self.write(child);
+            let attributes = match edge {
+                Some((label, port)) => format!(" [label=\"{}\", tailport={port}]", escape(&label)),
+                None => String::new(),
+            };
+            self.dot
+                .push_str(&format!("    n{id} -> n{child_id}{attributes};\n"));
+        }
+        id
+    }
+}
+
+/// Escape text to go in a quoted string in a graph
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::*;
+
+    #[test]
+    fn golden() {
+        let e = pow(x() + 1, c(2)) * sin(var('y'));
+        assert_eq!(
+            e.to_dot(),
+            "digraph expr {
+    ordering=out;
+    node [shape=box];
+    n0 [label=\"Prod\"];
+    n1 [label=\"Pow\"];
+    n2 [label=\"Sum\"];
+    n3 [label=\"X\"];
+    n2 -> n3;
+    n4 [label=\"Const 1\"];
+    n2 -> n4;
+    n1 -> n2 [label=\"base\", tailport=sw];
+    n5 [label=\"Const 2\"];
+    n1 -> n5 [label=\"exponent\", tailport=se];
+    n0 -> n1;
+    n6 [label=\"sin\"];
+    n7 [label=\"Var y\"];
+    n6 -> n7;
+    n0 -> n6;
+}
+"
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let dot = (var('"') + var('\\')).to_dot();
+        assert!(dot.contains("[label=\"Var \\\"\"]"), "{dot}");
+        assert!(dot.contains("[label=\"Var \\\\\"]"), "{dot}");
+        assert_eq!(escape("a\nb"), "a\\nb");
+    }
+
+    /// The numbers of nodes and edges in a graph
+    fn counts(dot: &str) -> (usize, usize) {
+        let lines = dot.lines().filter(|l| {
+            l.strip_prefix("    n")
+                .is_some_and(|l| l.starts_with(|c: char| c.is_ascii_digit()))
+        });
+        let (edges, nodes): (Vec<&str>, Vec<&str>) = lines.partition(|l| l.contains("->"));
+        (nodes.len(), edges.len())
+    }
+
+    #[test]
+    fn merging() {
+        // sin(x) + 1 is used three times, and sin(x) once more on its own
+        let inner = || sin(x()) + 1;
+        let e = Expr::Sum(vec![
+            pow(inner(), c(2)) * inner(),
+            Expr::Sum(vec![inner(), sin(x())]),
+        ]);
+        assert_eq!(counts(&e.to_dot()), (e.node_count(), e.node_count() - 1));
+
+        let merged = e.to_dot_with(&DotOptions {
+            merge_identical: true,
+        });
+        // The distinct subexpressions are the two sums, the product, the power, 2, sin(x) + 1,
+        // sin(x), x and 1, and each has an edge to each of its children
+        assert_eq!(counts(&merged), (9, 2 + 2 + 2 + 2 + 2 + 1));
+    }
+}
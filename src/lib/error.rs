@@ -0,0 +1,324 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{
+    CodegenError, DerivativeError, EvalError, EvalFailure, ExpandError, Expr, ExprKind, FitError,
+    HoleId, NotLinear, ParseError, RootError, VarId,
+};
+
+/// An error from anything in this crate which can fail. Each module has its own error type with
+/// only the errors it can give, and they can all be converted into this with `?` or
+/// [`From`], so that code using several of them can return one type.
+///
+/// [`MathError::In`] adds the subexpression an error came from, so an error can be a chain from
+/// the whole expression down to the part which actually failed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum MathError {
+    /// A function was given a value outside of its domain (e.g. `ln(-1)` or `arcsin(2)`)
+    Domain {
+        /// The function that was being evaluated
+        op: ExprKind,
+        /// The value that was given to the function
+        value: f64,
+    },
+    /// Something was divided by zero
+    DivisionByZero,
+    /// A value isn't a real number
+    Complex,
+    /// A number got too big to fit in a [`Num`](super::Num)
+    Overflow {
+        /// The kind of expression whose result was too big
+        op: ExprKind,
+    },
+    /// A variable other than x has no value
+    Unbound {
+        /// The variable
+        var: VarId,
+    },
+    /// A rate of change (see [`Expr::Deriv`]) has no value
+    UnboundRate {
+        /// The variable which is changing
+        var: VarId,
+        /// The variable it is changing with respect to
+        wrt: VarId,
+    },
+    /// None of the conditions of a [`Expr::Piecewise`] hold
+    NoBranch,
+    /// Text couldn't be read as an expression
+    Parse {
+        /// The byte offset where the problem is, or `None` if the text ended too early
+        pos: Option<usize>,
+        /// What the problem is
+        msg: String,
+    },
+    /// Something in an expression isn't supported by the operation, e.g. the derivative of a
+    /// factorial
+    Unsupported {
+        /// The kind of expression which isn't supported
+        feature: ExprKind,
+    },
+    /// An equation of a system isn't linear (see [`MathError::In`] for the term which isn't)
+    NotLinear {
+        /// The index of the equation
+        equation: usize,
+    },
+    /// An expression is nested too deeply for an operation to finish
+    DepthExceeded,
+    /// A polynomial couldn't be fitted to some points
+    Fit(FitError),
+    /// A root of a system couldn't be found
+    Root(RootError),
+    /// A power of a sum couldn't be expanded
+    Expand(ExpandError),
+    /// An expression has a hole which hasn't been filled in (see [`Expr::Hole`])
+    IncompleteExpression(HoleId),
+    /// A [`Expr::SumOver`] has too many terms to add them up
+    TooManyTerms,
+    /// A numeric method took too many steps without getting close enough to the answer
+    NotConverged,
+    /// Bytes from [`Expr::to_bytes`] are in a version of the format which isn't known, e.g.
+    /// because they were written by a newer version of this crate
+    UnsupportedFormatVersion {
+        /// The version the bytes say they are in
+        version: u16,
+    },
+    /// An error which came from a subexpression
+    In {
+        /// The subexpression
+        expr: Box<Expr>,
+        /// The error it gave
+        error: Box<MathError>,
+    },
+}
+
+impl MathError {
+    /// Record that this error came from `expr`. Errors from a subexpression can be wrapped again
+    /// with the expressions around it, so that the whole path is kept.
+    pub fn within(self, expr: &Expr) -> Self {
+        MathError::In {
+            expr: Box::new(expr.clone()),
+            error: Box::new(self),
+        }
+    }
+
+    /// The error without any of the subexpressions it came from
+    pub fn root_cause(&self) -> &MathError {
+        match self {
+            MathError::In { error, .. } => error.root_cause(),
+            e => e,
+        }
+    }
+
+    /// The innermost subexpression the error came from, if it was recorded
+    pub fn expr(&self) -> Option<&Expr> {
+        match self {
+            MathError::In { expr, error } => error.expr().or(Some(expr)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |kind: &ExprKind| format!("{kind:?}").to_lowercase();
+        match self {
+            MathError::Domain { op, value } => write!(f, "{} isn't defined at {value}", name(op)),
+            MathError::DivisionByZero => write!(f, "division by zero"),
+            MathError::Complex => write!(f, "the value isn't a real number"),
+            MathError::Overflow { op } => write!(f, "{} overflowed", name(op)),
+            MathError::Unbound { var } => write!(f, "{} has no value", var.0),
+            MathError::UnboundRate { var, wrt } => {
+                write!(f, "d{}/d{} has no value", var.0, wrt.0)
+            }
+            MathError::NoBranch => write!(f, "none of the conditions hold"),
+            MathError::Parse { msg, .. } => write!(f, "{msg}"),
+            MathError::Unsupported { feature } => write!(f, "{} isn't supported", name(feature)),
+            MathError::NotLinear { equation } => {
+                write!(f, "equation {} isn't linear", equation + 1)
+            }
+            MathError::DepthExceeded => write!(f, "the expression is nested too deeply"),
+            MathError::Fit(error) => write!(f, "{error}"),
+            MathError::Root(error) => write!(f, "{error}"),
+            MathError::Expand(error) => write!(f, "{error}"),
+            MathError::IncompleteExpression(id) => write!(f, "hole {} isn't filled in", id.0),
+            MathError::TooManyTerms => write!(f, "there are too many terms to add up"),
+            MathError::NotConverged => write!(f, "the answer couldn't be found accurately"),
+            MathError::UnsupportedFormatVersion { version } => {
+                write!(f, "version {version} of the format isn't supported")
+            }
+            MathError::In { expr, error } => write!(f, "{error} in {}", expr.to_latex()),
+        }
+    }
+}
+
+impl Error for MathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MathError::In { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<EvalError> for MathError {
+    fn from(error: EvalError) -> Self {
+        match error {
+            EvalError::Domain { kind, value } => MathError::Domain { op: kind, value },
+            EvalError::DivisionByZero => MathError::DivisionByZero,
+            EvalError::Complex => MathError::Complex,
+            EvalError::UnknownVariable(var) => MathError::Unbound { var },
+            EvalError::UnknownRate(var, wrt) => MathError::UnboundRate { var, wrt },
+            EvalError::NoBranch => MathError::NoBranch,
+            EvalError::IncompleteExpression(id) => MathError::IncompleteExpression(id),
+            EvalError::TooManyTerms => MathError::TooManyTerms,
+        }
+    }
+}
+
+impl From<EvalFailure> for MathError {
+    fn from(failure: EvalFailure) -> Self {
+        MathError::from(failure.error).within(&failure.expr)
+    }
+}
+
+impl From<ParseError> for MathError {
+    fn from(error: ParseError) -> Self {
+        let pos = match &error {
+            ParseError::UnsupportedCommand { position, .. }
+            | ParseError::Unexpected { position, .. }
+            | ParseError::UnknownHead { position, .. }
+            | ParseError::WrongArity { position, .. }
+            | ParseError::NumberTooLarge { position }
+            | ParseError::DigitGroups { position } => Some(*position),
+            ParseError::UnexpectedEnd => None,
+        };
+        MathError::Parse {
+            pos,
+            msg: error.to_string(),
+        }
+    }
+}
+
+impl From<DerivativeError> for MathError {
+    fn from(error: DerivativeError) -> Self {
+        match error {
+            DerivativeError::NotDifferentiable(kind) => MathError::Unsupported { feature: kind },
+            DerivativeError::IncompleteExpression(id) => MathError::IncompleteExpression(id),
+        }
+    }
+}
+
+impl From<CodegenError> for MathError {
+    fn from(error: CodegenError) -> Self {
+        match error {
+            CodegenError::Unsupported(kind) => MathError::Unsupported { feature: kind },
+        }
+    }
+}
+
+impl From<FitError> for MathError {
+    fn from(error: FitError) -> Self {
+        MathError::Fit(error)
+    }
+}
+
+impl From<ExpandError> for MathError {
+    fn from(error: ExpandError) -> Self {
+        MathError::Expand(error)
+    }
+}
+
+impl From<RootError> for MathError {
+    fn from(error: RootError) -> Self {
+        MathError::Root(error)
+    }
+}
+
+impl From<NotLinear> for MathError {
+    fn from(error: NotLinear) -> Self {
+        MathError::NotLinear {
+            equation: error.equation,
+        }
+        .within(&error.term)
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression like [`Expr::eval`], but with an error which says which
+    /// subexpression couldn't be evaluated, e.g. "ln isn't defined at -0.5 in \ln(x-2)".
+    pub fn try_eval(&self, x: f64) -> Result<f64, MathError> {
+        Ok(self.eval_located(x)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, factorial, ln, var, x};
+    use super::super::{Equation, LinearSystem};
+    use super::*;
+
+    #[test]
+    fn eval() {
+        let e = c(3) + c(2) * ln(x() - 2);
+        let error = e.try_eval(1.5).unwrap_err();
+        assert_eq!(
+            *error.root_cause(),
+            MathError::Domain {
+                op: ExprKind::Ln,
+                value: -0.5
+            }
+        );
+        assert_eq!(error.expr(), Some(&ln(x() - 2)));
+        assert_eq!(error.to_string(), "ln isn't defined at -0.5 in \\ln(x-2)");
+        assert_eq!(e.try_eval(3.0), Ok(3.0));
+
+        let error = (x() + var('y')).try_eval(1.0).unwrap_err();
+        assert_eq!(*error.root_cause(), MathError::Unbound { var: VarId('y') });
+        assert_eq!(error.to_string(), "y has no value in y");
+    }
+
+    #[test]
+    fn conversions() {
+        fn derivative_then_eval(latex: &str) -> Result<f64, MathError> {
+            let e = Expr::from_latex(latex)?;
+            e.try_derivative()?.try_eval(0.0)
+        }
+        assert!(matches!(
+            derivative_then_eval("x+"),
+            Err(MathError::Parse { pos: None, .. })
+        ));
+        assert_eq!(
+            MathError::from(factorial(x()).try_derivative().unwrap_err()),
+            MathError::Unsupported {
+                feature: ExprKind::Factorial
+            }
+        );
+        assert_eq!(derivative_then_eval("x^2"), Ok(0.0));
+
+        let equations = vec![Equation::new(x() * var('y'), c(2))];
+        let error = MathError::from(LinearSystem::from_equations(equations).unwrap_err());
+        assert_eq!(*error.root_cause(), MathError::NotLinear { equation: 0 });
+        assert_eq!(
+            error.to_string(),
+            format!("equation 1 isn't linear in {}", (x() * var('y')).to_latex())
+        );
+    }
+
+    #[test]
+    fn chain() {
+        let inner = ln(x());
+        let outer = inner.clone() + 1;
+        let error = MathError::DivisionByZero.within(&inner).within(&outer);
+        assert_eq!(error.expr(), Some(&inner));
+        assert_eq!(error.root_cause(), &MathError::DivisionByZero);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "division by zero in {} in {}",
+                inner.to_latex(),
+                outer.to_latex()
+            )
+        );
+        assert!(error.source().is_some());
+    }
+}
@@ -0,0 +1,208 @@
+use super::{Expr, ExprKind, HoleId, Num, VarId};
+
+/// The most terms a [`Expr::SumOver`] can have to be evaluated
+pub(super) const MAX_SUM_TERMS: Num = 1_000_000;
+
+/// An error from evaluating an expression.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EvalError {
+    /// A function was given a value outside of its domain (e.g. `ln(-1)` or `arcsin(2)`)
+    Domain {
+        /// The function that was being evaluated
+        kind: ExprKind,
+        /// The value that was given to the function
+        value: f64,
+    },
+    /// Zero was raised to a negative power (i.e. something was divided by zero)
+    DivisionByZero,
+    /// The expression contains the imaginary unit, so its value isn't a real number
+    Complex,
+    /// The expression contains a variable other than x, which has no value
+    UnknownVariable(VarId),
+    /// The expression contains a rate of change (see [`Expr::Deriv`]) which has no value
+    UnknownRate(VarId, VarId),
+    /// None of the conditions of a [`Expr::Piecewise`] hold
+    NoBranch,
+    /// The expression has a hole which hasn't been filled in
+    IncompleteExpression(HoleId),
+    /// A [`Expr::SumOver`] has more than [`MAX_SUM_TERMS`] terms to add up
+    TooManyTerms,
+}
+
+impl Expr {
+    /// Evaluate the expression as a float, with x set to the given value.
+    pub fn eval(&self, x: f64) -> Result<f64, EvalError> {
+        self.eval_node(x, &|e| e.eval(x))
+    }
+
+    /// Evaluate just this node of the expression, using `eval` to evaluate its children.
+    pub(super) fn eval_node(
+        &self,
+        x: f64,
+        eval: &dyn Fn(&Expr) -> Result<f64, EvalError>,
+    ) -> Result<f64, EvalError> {
+        let domain = |kind, value| Err(EvalError::Domain { kind, value });
+        Ok(match self {
+            Expr::Const(n) => *n as f64,
+            Expr::X => x,
+            Expr::Var(v) => return Err(EvalError::UnknownVariable(*v)),
+            Expr::I => return Err(EvalError::Complex),
+            Expr::Hole(id) => return Err(EvalError::IncompleteExpression(*id)),
+            Expr::Deriv(v, t) => return Err(EvalError::UnknownRate(*v, *t)),
+            Expr::E => std::f64::consts::E,
+            Expr::Scaled(m, e) => scaled_value(*m, *e),
+            Expr::Sum(v) => v.iter().try_fold(0.0, |acc, e| Ok(acc + eval(e)?))?,
+            Expr::Prod(v) => v.iter().try_fold(1.0, |acc, e| Ok(acc * eval(e)?))?,
+            Expr::Neg(e) => -eval(e)?,
+            Expr::Pow(a, b) => {
+                let a = eval(a)?;
+                let b = eval(b)?;
+                if a == 0.0 && b < 0.0 {
+                    return Err(EvalError::DivisionByZero);
+                }
+                let result = a.powf(b);
+                if result.is_nan() {
+                    return domain(ExprKind::Pow, a);
+                }
+                result
+            }
+            Expr::Ln(e) => {
+                let v = eval(e)?;
+                if v <= 0.0 {
+                    return domain(ExprKind::Ln, v);
+                }
+                v.ln()
+            }
+            Expr::Sin(e) => eval(e)?.sin(),
+            Expr::Cos(e) => eval(e)?.cos(),
+            Expr::Arcsin(e) => {
+                let v = eval(e)?;
+                if !(-1.0..=1.0).contains(&v) {
+                    return domain(ExprKind::Arcsin, v);
+                }
+                v.asin()
+            }
+            Expr::Arccos(e) => {
+                let v = eval(e)?;
+                if !(-1.0..=1.0).contains(&v) {
+                    return domain(ExprKind::Arccos, v);
+                }
+                v.acos()
+            }
+            Expr::Arctan(e) => eval(e)?.atan(),
+            Expr::Sinh(e) => eval(e)?.sinh(),
+            Expr::Cosh(e) => eval(e)?.cosh(),
+            Expr::Tanh(e) => eval(e)?.tanh(),
+            Expr::Arsinh(e) => eval(e)?.asinh(),
+            Expr::Arcosh(e) => {
+                let v = eval(e)?;
+                if v < 1.0 {
+                    return domain(ExprKind::Arcosh, v);
+                }
+                v.acosh()
+            }
+            Expr::Artanh(e) => {
+                let v = eval(e)?;
+                if !(-1.0 < v && v < 1.0) {
+                    return domain(ExprKind::Artanh, v);
+                }
+                v.atanh()
+            }
+            Expr::Factorial(e) => {
+                let v = eval(e)?;
+                if v < 0.0 || v.fract() != 0.0 {
+                    return domain(ExprKind::Factorial, v);
+                }
+                // Anything past 170! is infinite as a float anyway
+                (1..=v.min(171.0) as u32).map(f64::from).product()
+            }
+            Expr::SumOver(k, from, to, e) => {
+                let bound = |b| {
+                    let v = eval(b)?;
+                    match v.fract() == 0.0 {
+                        true => Ok(v as Num),
+                        false => Err(EvalError::Domain {
+                            kind: ExprKind::SumOver,
+                            value: v,
+                        }),
+                    }
+                };
+                let (from, to) = (bound(from)?, bound(to)?);
+                if to
+                    .checked_sub(from)
+                    .is_none_or(|terms| terms >= MAX_SUM_TERMS)
+                {
+                    return Err(EvalError::TooManyTerms);
+                }
+                let mut sum = 0.0;
+                for n in from..=to {
+                    sum += eval(&e.substitute_var(*k, &Expr::Const(n)))?;
+                }
+                sum
+            }
+            Expr::Piecewise(v) => {
+                for (condition, e) in v {
+                    if condition.holds(eval)? {
+                        return eval(e);
+                    }
+                }
+                return Err(EvalError::NoBranch);
+            }
+        })
+    }
+}
+
+/// The value of `m * 10^e` as a float, rounded correctly, so that a float written as a scaled
+/// constant (see [`Expr::fold_constants`]) evaluates to exactly the same float
+pub(super) fn scaled_value(m: Num, e: i32) -> f64 {
+    // When m and the power of ten are exact floats, one multiplication or division rounds
+    // correctly. Otherwise the rounding is left to the standard library.
+    if m.unsigned_abs() < 1 << 53 && e.unsigned_abs() <= 22 {
+        let power = 10f64.powi(e.abs());
+        return match e < 0 {
+            true => m as f64 / power,
+            false => m as f64 * power,
+        };
+    }
+    format!("{m}e{e}").parse().unwrap_or(f64::NAN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eval() {
+        let e = Expr::X.pow(Expr::Const(2)) * 3 + Expr::X.sin() - 1;
+        assert!((e.eval(2.0).unwrap() - (11.0 + 2f64.sin())).abs() < 1e-12);
+        assert_eq!(
+            Expr::X.ln().eval(-1.0),
+            Err(EvalError::Domain {
+                kind: ExprKind::Ln,
+                value: -1.0
+            })
+        );
+        assert_eq!(Expr::X.recip().eval(0.0), Err(EvalError::DivisionByZero));
+    }
+
+    #[test]
+    fn sum_over() {
+        let k = VarId('k');
+        // 1x + 2x + 3x + 4x
+        let e = (Expr::Var(k) * Expr::X).sum_over(k, Expr::Const(1), Expr::Const(4));
+        assert_eq!(e.eval(0.5), Ok(5.0));
+        let e = Expr::Var(k).sum_over(k, Expr::Const(1), Expr::X);
+        assert_eq!(e.eval(0.0), Ok(0.0));
+        assert_eq!(
+            e.eval(1.5),
+            Err(EvalError::Domain {
+                kind: ExprKind::SumOver,
+                value: 1.5
+            })
+        );
+        let e = Expr::Var(k).sum_over(k, Expr::Const(1), Expr::Const(Num::MAX));
+        assert_eq!(e.eval(0.0), Err(EvalError::TooManyTerms));
+        let e = Expr::Var(k).sum_over(k, Expr::Const(Num::MIN), Expr::Const(Num::MAX));
+        assert_eq!(e.eval(0.0), Err(EvalError::TooManyTerms));
+    }
+}
@@ -0,0 +1,464 @@
+use super::simplify::gcd;
+use super::{Expr, Num};
+
+/// A term of a sum, split into a constant factor and the other factors with their exponents
+struct Factors {
+    constant: Num,
+    factors: Vec<(Expr, Num)>,
+}
+
+impl Factors {
+    /// Split a term into factors, or get `None` if the constant factor would overflow
+    fn new(term: &Expr) -> Option<Self> {
+        let mut factors = Factors {
+            constant: 1,
+            factors: vec![],
+        };
+        factors.add_factor(term).then_some(factors)
+    }
+
+    fn add_factor(&mut self, factor: &Expr) -> bool {
+        match factor {
+            Expr::Prod(v) => v.iter().all(|e| self.add_factor(e)),
+            Expr::Neg(e) => self.multiply_constant(-1) && self.add_factor(e),
+            Expr::Const(n) => self.multiply_constant(*n),
+            Expr::Pow(a, b) => match b.as_ref() {
+                Expr::Const(n) if *n > 0 => self.add_power(a, *n),
+                _ => self.add_power(factor, 1),
+            },
+            _ => self.add_power(factor, 1),
+        }
+    }
+
+    fn multiply_constant(&mut self, n: Num) -> bool {
+        match self.constant.checked_mul(n) {
+            Some(c) => {
+                self.constant = c;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn add_power(&mut self, base: &Expr, n: Num) -> bool {
+        match self.factors.iter_mut().find(|(e, _)| e == base) {
+            Some((_, exp)) => match exp.checked_add(n) {
+                Some(sum) => {
+                    *exp = sum;
+                    true
+                }
+                None => false,
+            },
+            None => {
+                self.factors.push((base.clone(), n));
+                true
+            }
+        }
+    }
+
+    /// The square root of the term, if its constant is a perfect square and every factor is to
+    /// an even power
+    fn square_root(&self) -> Option<Self> {
+        let constant = square_root(self.constant)?;
+        let factors = self
+            .factors
+            .iter()
+            .map(|(base, n)| (n % 2 == 0).then(|| (base.clone(), n / 2)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Factors { constant, factors })
+    }
+
+    /// The product of two terms, or `None` if the constant overflows
+    fn times(&self, other: &Factors) -> Option<Self> {
+        let mut product = Factors {
+            constant: self.constant,
+            factors: self.factors.clone(),
+        };
+        let multiplied = product.multiply_constant(other.constant)
+            && other
+                .factors
+                .iter()
+                .all(|(base, n)| product.add_power(base, *n));
+        multiplied.then_some(product)
+    }
+
+    /// Whether the terms are the same (giving 1) or only differ in sign (giving -1), ignoring
+    /// the order of the factors
+    fn compare(&self, other: &Factors) -> Option<Num> {
+        let same_factors = self.factors.len() == other.factors.len()
+            && self
+                .factors
+                .iter()
+                .all(|(base, n)| other.exponent_of(base) == *n);
+        match (same_factors, self.constant) {
+            (false, _) | (_, 0) => None,
+            (true, c) if c == other.constant => Some(1),
+            (true, c) if Some(c) == other.constant.checked_neg() => Some(-1),
+            _ => None,
+        }
+    }
+
+    fn into_expr(self) -> Expr {
+        product(self.factors, self.constant)
+    }
+
+    fn exponent_of(&self, base: &Expr) -> Num {
+        self.factors
+            .iter()
+            .find(|(e, _)| e == base)
+            .map_or(0, |(_, n)| *n)
+    }
+}
+
+/// The square root of a constant, if it is a perfect square
+pub(super) fn square_root(n: Num) -> Option<Num> {
+    if n < 0 {
+        return None;
+    }
+    if n == 0 {
+        return Some(0);
+    }
+    // The float square root might be a long way out for big numbers, but one step of Newton's
+    // method gets it to the exact one or next to it
+    let guess = ((n as f64).sqrt() as Num).max(1);
+    let guess = (guess + n / guess) / 2;
+    (guess - 1..=guess + 1).find(|r| r.checked_mul(*r) == Some(n))
+}
+
+/// Multiply some factors back together, with the constant at the end
+fn product(factors: Vec<(Expr, Num)>, constant: Num) -> Expr {
+    let mut v: Vec<Expr> = factors
+        .into_iter()
+        .filter(|(_, n)| *n != 0)
+        .map(|(base, n)| {
+            if n == 1 {
+                base
+            } else {
+                base.pow(Expr::Const(n))
+            }
+        })
+        .collect();
+    if constant != 1 || v.is_empty() {
+        v.push(Expr::Const(constant));
+    }
+    if v.len() == 1 {
+        v.pop().unwrap()
+    } else {
+        Expr::Prod(v)
+    }
+}
+
+impl Expr {
+    /// This function takes the factors which every term of a sum has in common out of the sum
+    /// e.g. `2x + 2y = 2(x + y)` and `x^2sin(x) + xcos(x) = x(xsin(x) + cos(x))`
+    ///
+    /// The common constant is the gcd of the constants in each term, and a factor is common when
+    /// every term has it to some power, in which case the lowest power is taken out. This is the
+    /// opposite of expanding, and isn't done by [`Expr::simplify`]. It is enabled with
+    /// [`super::SimplifyOptions::factor_common_terms`].
+    pub fn simplify_factor_common_terms(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        if v.len() < 2 {
+            return;
+        }
+        let Some(terms) = v.iter().map(Factors::new).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+
+        let Some(constant) = terms.iter().try_fold(0, |g, term| gcd(g, term.constant)) else {
+            return;
+        };
+        let common: Vec<(Expr, Num)> = terms[0]
+            .factors
+            .iter()
+            .map(|(base, _)| {
+                let n = terms.iter().map(|t| t.exponent_of(base)).min().unwrap();
+                (base.clone(), n)
+            })
+            .filter(|(_, n)| *n > 0)
+            .collect();
+        if constant <= 1 && common.is_empty() {
+            return;
+        }
+        let constant = constant.max(1);
+
+        let rest = Expr::Sum(
+            terms
+                .into_iter()
+                .map(|term| {
+                    let factors = term
+                        .factors
+                        .into_iter()
+                        .map(|(base, n)| {
+                            let common = common
+                                .iter()
+                                .find(|(e, _)| *e == base)
+                                .map_or(0, |(_, n)| *n);
+                            (base, n - common)
+                        })
+                        .collect();
+                    product(factors, term.constant / constant)
+                })
+                .collect(),
+        );
+        let mut factors: Vec<Expr> = vec![];
+        if constant != 1 {
+            factors.push(Expr::Const(constant));
+        }
+        if !common.is_empty() {
+            factors.push(product(common, 1));
+        }
+        factors.push(rest);
+        *self = Expr::Prod(factors);
+    }
+
+    /// This function factors sums which are perfect squares or a difference of two squares
+    /// e.g. `x^2 + 2x + 1 = (x + 1)^2`, `u^2 - 2uv + v^2 = (u - v)^2` and
+    /// `sin(x)^2 - cos(x)^2 = (sin(x) + cos(x))(sin(x) - cos(x))`
+    ///
+    /// u and v can be anything, and are found by taking the square root of each term, which
+    /// works when the constant factor is a perfect square and every other factor is to an even
+    /// power. Terms are compared up to the order of sums and products, so the sums and products
+    /// inside u and v might be reordered. Like [`Expr::simplify_factor_common_terms`] this is
+    /// enabled with [`super::SimplifyOptions::factor_common_terms`].
+    pub fn simplify_factor_special_forms(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        if !matches!(v.len(), 2 | 3) {
+            return;
+        }
+        let Some(terms) = v
+            .iter()
+            .map(|term| {
+                let mut term = term.clone();
+                term.transform_bottom_up(&mut Expr::sort_terms);
+                Factors::new(&term)
+            })
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+
+        if let [a, b] = terms.as_slice() {
+            // u^2 - v^2 = (u + v)(u - v), with the positive term as u^2
+            for (a, b) in [(a, b), (b, a)] {
+                let negated = Factors {
+                    constant: b.constant.saturating_neg(),
+                    factors: b.factors.clone(),
+                };
+                if let (Some(u), Some(v)) = (a.square_root(), negated.square_root()) {
+                    let minus_v = product(v.factors.clone(), -v.constant);
+                    let (u, v) = (u.into_expr(), v.into_expr());
+                    *self = Expr::Prod(vec![
+                        Expr::Sum(vec![u.clone(), v]),
+                        Expr::Sum(vec![u, minus_v]),
+                    ]);
+                    return;
+                }
+            }
+            return;
+        }
+
+        // u^2 ± 2uv + v^2 = (u ± v)^2, where the middle term can be any of the three
+        for middle in 0..3 {
+            let (i, j) = match middle {
+                0 => (1, 2),
+                1 => (0, 2),
+                _ => (0, 1),
+            };
+            let (Some(u), Some(v)) = (terms[i].square_root(), terms[j].square_root()) else {
+                continue;
+            };
+            let Some(twice) = u
+                .times(&v)
+                .and_then(|mut uv| uv.multiply_constant(2).then_some(uv))
+            else {
+                continue;
+            };
+            let Some(sign) = twice.compare(&terms[middle]) else {
+                continue;
+            };
+            let v = if sign == 1 {
+                v.into_expr()
+            } else {
+                product(v.factors, -v.constant)
+            };
+            *self = Expr::Sum(vec![u.into_expr(), v]).pow(Expr::Const(2));
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, ln, pow, sin, var, x};
+    use super::super::{SimplifyOptions, VarId};
+    use super::*;
+
+    fn factored(mut e: Expr) -> Expr {
+        e.simplify_factor_common_terms();
+        e
+    }
+
+    fn special(mut e: Expr) -> Expr {
+        e.simplify_factor_special_forms();
+        e
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(factored(x() * 2 + var('y') * 2), c(2) * (x() + var('y')));
+        // 6x - 4 = 2(3x - 2)
+        assert_eq!(
+            factored(Expr::Sum(vec![x() * 6, c(-4)])),
+            c(2) * Expr::Sum(vec![x() * 3, c(-2)])
+        );
+    }
+
+    #[test]
+    fn symbolic() {
+        assert_eq!(
+            factored(x() * sin(x()) + x() * cos(x())),
+            Expr::Prod(vec![x(), sin(x()) + cos(x())])
+        );
+        // x^3 + x^2sin(x) = x^2(x + sin(x)), where a bare x counts as x^1
+        assert_eq!(
+            factored(pow(x(), c(3)) + pow(x(), c(2)) * sin(x())),
+            Expr::Prod(vec![pow(x(), c(2)), x() + sin(x())])
+        );
+        assert_eq!(
+            factored(x() + x() * cos(x())),
+            Expr::Prod(vec![x(), c(1) + cos(x())])
+        );
+    }
+
+    #[test]
+    fn mixed() {
+        // 4x^2 + 6x = 2x(2x + 3)
+        assert_eq!(
+            factored(pow(x(), c(2)) * 4 + x() * 6),
+            Expr::Prod(vec![c(2), x(), Expr::Sum(vec![x() * c(2), c(3)])])
+        );
+        let e = pow(x(), c(2)) * sin(x()) * 3 - x() * sin(x()) * 6;
+        assert_eq!(
+            factored(e),
+            Expr::Prod(vec![c(3), x() * sin(x()), Expr::Sum(vec![x(), c(-2)])])
+        );
+    }
+
+    #[test]
+    fn no_common_factor() {
+        for e in [
+            x() + sin(x()),
+            x() * 2 + 3,
+            pow(x(), c(2)) + var('y'),
+            Expr::Sum(vec![x() * 2]),
+        ] {
+            assert_eq!(factored(e.clone()), e);
+        }
+    }
+
+    #[test]
+    fn options() {
+        let e = x() * 2 + var('y') * 2;
+        assert_eq!(e.to_simplified_with(&SimplifyOptions::default()), e);
+        let options = SimplifyOptions {
+            factor_common_terms: true,
+            ..Default::default()
+        };
+        assert_eq!(e.simplified_with(&options), (x() + var('y')) * c(2));
+    }
+
+    #[test]
+    fn perfect_squares() {
+        let y = || var('y');
+        // x^2 + 2x + 1 = (x + 1)^2
+        let e = pow(x(), c(2)) + x() * 2 + 1;
+        assert_eq!(special(e), pow(x() + 1, c(2)));
+        // 4x^2 - 12xy + 9y^2 = (2x - 3y)^2, with the middle term written first
+        let e = Expr::Sum(vec![
+            x() * y() * -12,
+            pow(x(), c(2)) * 4,
+            pow(y(), c(2)) * 9,
+        ]);
+        let factored = special(e.clone());
+        assert!(matches!(&factored, Expr::Pow(_, n) if **n == c(2)));
+        for (x, y) in [(1.5, c(-2)), (0.3, Expr::Scaled(7, -1))] {
+            let values = [&e, &factored].map(|e| e.substitute_var(VarId('y'), &y).eval(x));
+            assert!((values[0].unwrap() - values[1].unwrap()).abs() < 1e-9);
+        }
+        // sin(x)^2 + 2sin(x)cos(x) + cos(x)^2 = (sin(x) + cos(x))^2
+        let e = pow(sin(x()), c(2)) + sin(x()) * cos(x()) * 2 + pow(cos(x()), c(2));
+        assert_eq!(special(e), pow(sin(x()) + cos(x()), c(2)));
+        // u and v can be sums, compared up to their order
+        let e = pow(x() + 1, c(2)) - (c(1) + x()) * ln(x()) * 2 + pow(ln(x()), c(2));
+        assert_eq!(
+            special(e),
+            pow(Expr::Sum(vec![x() + 1, ln(x()) * c(-1)]), c(2))
+        );
+    }
+
+    #[test]
+    fn difference_of_squares() {
+        assert_eq!(special(pow(x(), c(2)) - 9), (x() + 3) * (x() + c(-3)));
+        // The positive term can come second
+        let e = Expr::Sum(vec![-pow(cos(x()), c(2)), pow(sin(x()), c(2))]);
+        assert_eq!(
+            special(e),
+            (sin(x()) + cos(x())) * (sin(x()) + cos(x()) * c(-1))
+        );
+        let e = pow(x(), c(4)) * 25 - pow(var('y'), c(6));
+        assert_eq!(
+            special(e),
+            (pow(x(), c(2)) * 5 + pow(var('y'), c(3)))
+                * (pow(x(), c(2)) * 5 + pow(var('y'), c(3)) * c(-1))
+        );
+    }
+
+    #[test]
+    fn not_special() {
+        let y = || var('y');
+        for e in [
+            // The middle term is 3uv rather than 2uv
+            pow(x(), c(2)) + x() * y() * 3 + pow(y(), c(2)),
+            // The signs don't fit
+            pow(x(), c(2)) + x() * 2 - 1,
+            pow(x(), c(2)) + 9,
+            -pow(x(), c(2)) - 9,
+            // x^3 and 8 aren't squares
+            pow(x(), c(3)) - 1,
+            pow(x(), c(2)) - 8,
+            pow(x(), c(2)) + x() + y() + 1,
+        ] {
+            assert_eq!(special(e.clone()), e);
+        }
+    }
+
+    #[test]
+    fn square_roots() {
+        assert_eq!(square_root(0), Some(0));
+        assert_eq!(square_root(1), Some(1));
+        assert_eq!(square_root(144), Some(12));
+        assert_eq!(square_root(145), None);
+        assert_eq!(square_root(-4), None);
+        let big: Num = 3_037_000_499;
+        assert_eq!(square_root(big * big), Some(big));
+        assert_eq!(square_root(big * big - 1), None);
+    }
+
+    #[test]
+    fn special_options() {
+        let options = SimplifyOptions {
+            factor_common_terms: true,
+            ..Default::default()
+        };
+        // 2x^2 - 2 = 2(x + 1)(x - 1)
+        let e = (pow(x(), c(2)) * 2 - 2).simplified_with(&options);
+        assert!(e.equivalent(&(pow(x(), c(2)) * 2 - 2)));
+        let Expr::Prod(v) = &e else { panic!("{e:?}") };
+        assert_eq!(v[..2], [x() + 1, x() + c(-1)]);
+    }
+}
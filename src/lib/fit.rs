@@ -0,0 +1,223 @@
+use std::fmt;
+
+use super::fold::float_const;
+use super::{Expr, Num};
+
+/// An error from fitting a polynomial to points with [`Expr::fit_polynomial`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FitError {
+    /// There aren't enough points to find the coefficients, which needs at least `degree + 1`
+    TooFewPoints {
+        /// How many points there are
+        points: usize,
+        /// The degree of the polynomial
+        degree: usize,
+    },
+    /// Two points have the same x
+    DuplicateX(f64),
+    /// A point isn't finite
+    NotFinite,
+    /// The equations for the coefficients couldn't be solved, because the points are too close
+    /// together for the degree (see [`Expr::fit_polynomial`])
+    IllConditioned,
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FitError::TooFewPoints { points, degree } => write!(
+                f,
+                "a polynomial of degree {degree} needs at least {} points but there are {points}",
+                degree + 1
+            ),
+            FitError::DuplicateX(x) => write!(f, "there is more than one point with x = {x}"),
+            FitError::NotFinite => write!(f, "a point isn't finite"),
+            FitError::IllConditioned => write!(f, "the points can't be fitted accurately"),
+        }
+    }
+}
+
+impl std::error::Error for FitError {}
+
+impl Expr {
+    /// Find the polynomial of a degree which is closest to some points, by least squares. When
+    /// there are exactly `degree + 1` points the polynomial goes through all of them.
+    ///
+    /// The coefficients are floats, written as [`Expr::Scaled`] constants (or plain constants
+    /// when they are whole numbers), and zero coefficients are left out.
+    ///
+    /// The least squares fit solves the normal equations, which lose accuracy as the degree goes
+    /// up and as the x values get further from 0, since the powers of x get very different in
+    /// size. Low degrees with x values in a range like -10 to 10 are fitted to close to float
+    /// accuracy, but high degrees should be avoided. An error is given when elimination finds a
+    /// pivot which is too small to divide by.
+    pub fn fit_polynomial(points: &[(f64, f64)], degree: usize) -> Result<Expr, FitError> {
+        let coeffs = fit_coefficients(points, degree)?;
+        let mut terms = vec![];
+        for (n, c) in coeffs.iter().enumerate() {
+            let c = float_const(*c).ok_or(FitError::NotFinite)?;
+            if c == Expr::Const(0) {
+                continue;
+            }
+            let power = match n {
+                0 => {
+                    terms.push(c);
+                    continue;
+                }
+                1 => Expr::X,
+                _ => Expr::X.pow(Expr::Const(n as Num)),
+            };
+            terms.push(match c {
+                Expr::Const(1) => power,
+                c => Expr::Prod(vec![c, power]),
+            });
+        }
+        Ok(match terms.len() {
+            0 => Expr::Const(0),
+            1 => terms.pop().unwrap(),
+            _ => Expr::Sum(terms),
+        })
+    }
+}
+
+/// The coefficients of the fitted polynomial, where the nth is the coefficient of `x^n`
+fn fit_coefficients(points: &[(f64, f64)], degree: usize) -> Result<Vec<f64>, FitError> {
+    if points.len() <= degree {
+        return Err(FitError::TooFewPoints {
+            points: points.len(),
+            degree,
+        });
+    }
+    if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+        return Err(FitError::NotFinite);
+    }
+    let mut xs: Vec<f64> = points.iter().map(|(x, _)| *x).collect();
+    xs.sort_by(f64::total_cmp);
+    if let Some(pair) = xs.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(FitError::DuplicateX(pair[0]));
+    }
+
+    let powers = |x: f64| {
+        (0..=degree).scan(1.0, move |power, _| {
+            let p = *power;
+            *power *= x;
+            Some(p)
+        })
+    };
+    let rows: Vec<Vec<f64>> = if points.len() == degree + 1 {
+        // Interpolate, by solving the Vandermonde system
+        points
+            .iter()
+            .map(|(x, y)| powers(*x).chain([*y]).collect())
+            .collect()
+    } else {
+        // The normal equations (A^T A) c = A^T y, where the rows of A are the powers of each x
+        let mut rows = vec![vec![0.0; degree + 2]; degree + 1];
+        for (x, y) in points {
+            let p: Vec<f64> = powers(*x).collect();
+            for (i, row) in rows.iter_mut().enumerate() {
+                for j in 0..=degree {
+                    row[j] += p[i] * p[j];
+                }
+                row[degree + 1] += p[i] * y;
+            }
+        }
+        rows
+    };
+    solve(rows).ok_or(FitError::IllConditioned)
+}
+
+/// Solve a square system of equations, where each row has the coefficients and then the right
+/// hand side, by Gaussian elimination with partial pivoting
+pub(super) fn solve(mut rows: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let n = rows.len();
+    let scale = rows
+        .iter()
+        .flat_map(|row| &row[..n])
+        .fold(0.0f64, |max, c| max.max(c.abs()));
+    for col in 0..n {
+        let pivot = (col..n).max_by(|a, b| rows[*a][col].abs().total_cmp(&rows[*b][col].abs()))?;
+        if rows[pivot][col].abs() <= scale * 1e-14 {
+            return None;
+        }
+        rows.swap(col, pivot);
+        let pivot_row = rows[col].clone();
+        for row in rows.iter_mut().skip(col + 1) {
+            let factor = row[col] / pivot_row[col];
+            for (c, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                *c -= factor * p;
+            }
+        }
+    }
+    let mut solution = vec![0.0; n];
+    for i in (0..n).rev() {
+        let rest: f64 = (i + 1..n).map(|j| rows[i][j] * solution[j]).sum();
+        solution[i] = (rows[i][n] - rest) / rows[i][i];
+    }
+    Some(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cubic(x: f64) -> f64 {
+        2.0 * x.powi(3) - 3.0 * x.powi(2) + 0.5 * x - 7.0
+    }
+
+    #[test]
+    fn cubic_samples() {
+        let points: Vec<(f64, f64)> = (-5..=6)
+            .map(|i| i as f64 * 0.7)
+            .map(|x| (x, cubic(x)))
+            .collect();
+        let coeffs = fit_coefficients(&points, 3).unwrap();
+        for (found, expected) in coeffs.iter().zip([-7.0, 0.5, -3.0, 2.0]) {
+            assert!((found - expected).abs() < 1e-9, "{coeffs:?}");
+        }
+        let fitted = Expr::fit_polynomial(&points, 3).unwrap();
+        for x in [-2.5, 0.1, 3.3] {
+            assert!((fitted.eval(x).unwrap() - cubic(x)).abs() < 1e-8);
+        }
+
+        // A line through points which aren't on one
+        let points = [(0.0, 0.0), (1.0, 1.0), (2.0, 1.0), (3.0, 2.0)];
+        let coeffs = fit_coefficients(&points, 1).unwrap();
+        assert!((coeffs[0] - 0.1).abs() < 1e-12 && (coeffs[1] - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interpolation() {
+        // x^2 - 1 goes through these points exactly
+        let points = [(-1.0, 0.0), (2.0, 3.0), (3.0, 8.0)];
+        let fitted = Expr::fit_polynomial(&points, 2).unwrap();
+        assert_eq!(fitted, Expr::from_coefficients(&[-1, 0, 1]));
+        let fitted = Expr::fit_polynomial(&[(1.0, 2.5), (3.0, 2.5)], 1).unwrap();
+        assert_eq!(fitted, Expr::Scaled(25, -1));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Expr::fit_polynomial(&[(0.0, 1.0), (1.0, 2.0)], 2),
+            Err(FitError::TooFewPoints {
+                points: 2,
+                degree: 2
+            })
+        );
+        assert_eq!(
+            Expr::fit_polynomial(&[(0.0, 1.0), (1.0, 2.0), (0.0, 3.0)], 1),
+            Err(FitError::DuplicateX(0.0))
+        );
+        assert_eq!(
+            Expr::fit_polynomial(&[(0.0, f64::NAN), (1.0, 2.0)], 1),
+            Err(FitError::NotFinite)
+        );
+        // Points this close together can't tell the coefficients of x^5 apart
+        let points: Vec<(f64, f64)> = (0..8).map(|i| (1.0 + i as f64 * 1e-6, 1.0)).collect();
+        assert_eq!(
+            Expr::fit_polynomial(&points, 5),
+            Err(FitError::IllConditioned)
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use super::ratio::Ratio;
+use super::scaled::scaled;
+use super::{Expr, Num};
+
+/// The most terms a [`Expr::SumOver`] can have for [`Expr::fold_constants`] to add them up
+const MAX_SUM_TERMS: Num = 1000;
+
+/// The most nodes [`Expr::as_constant`] looks at
+const MAX_CONSTANT_NODES: usize = 32;
+
+/// Which constant subexpressions [`Expr::fold_constants`] replaces with their value
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConstFoldMode {
+    /// Only fold subexpressions whose value is a whole number or a fraction, e.g. `2^10`,
+    /// `ln(1)` and `sin(0)`
+    Exact,
+    /// Fold anything which doesn't use x or a variable, giving a float (as a
+    /// [`Expr::Scaled`]) when the value isn't exact, e.g. `sin(1)`
+    Numeric,
+}
+
+impl Expr {
+    /// Replace subexpressions which don't use x or any variables with their value, and give the
+    /// number of subexpressions which were replaced. Subexpressions which use x are never
+    /// changed, apart from their constant parts.
+    ///
+    /// In [`ConstFoldMode::Exact`] values are found exactly, so only arithmetic on whole numbers
+    /// and fractions and functions at special values (like `cos(0) = 1` and `ln(e) = 1`) are
+    /// folded. Sin and cos of multiples of π/6 and π/4 are folded too, with square roots where
+    /// they are needed (e.g. `\sqrt{2}/2`). In [`ConstFoldMode::Numeric`] anything else is
+    /// evaluated as a float, and written as a [`Expr::Scaled`] with the shortest mantissa which
+    /// is the same float when it is read back as a decimal. Nothing is folded if it can't be
+    /// evaluated (e.g. `ln(-1)`).
+    ///
+    /// This doesn't simplify anything else, so it can be used to make an expression quicker to
+    /// evaluate many times without changing its shape.
+    pub fn fold_constants(&mut self, mode: ConstFoldMode) -> usize {
+        if self.kind().is_atom() {
+            return 0;
+        }
+        let constant = !self.uses_x() && self.free_vars().is_empty();
+        if constant {
+            let folded = match mode {
+                ConstFoldMode::Exact => self
+                    .exact_value()
+                    .map(Ratio::to_expr)
+                    .or_else(|| self.special_trig_value()),
+                ConstFoldMode::Numeric => self
+                    .exact_value()
+                    .map(Ratio::to_expr)
+                    .or_else(|| self.eval(0.0).ok().and_then(float_const)),
+            };
+            match folded {
+                Some(folded) if folded != *self => {
+                    *self = folded;
+                    return 1;
+                }
+                // A fraction is already as folded as it can be
+                Some(_) => return 0,
+                None => (),
+            }
+        }
+        let folded = self
+            .children_mut()
+            .into_iter()
+            .map(|e| e.fold_constants(mode))
+            .sum();
+        // Special angles are only folded here, so a sum of them might only be a fraction now
+        if folded > 0 && constant && mode == ConstFoldMode::Exact {
+            if let Some(value) = self.exact_value() {
+                *self = value.to_expr();
+            }
+        }
+        folded
+    }
+
+    /// Put `x` in for x, and work out as much of the result exactly as possible with
+    /// [`ConstFoldMode::Exact`] folding before simplifying it, e.g. `x sin(x) + 1` at 0 is
+    /// exactly 1. Unlike [`Expr::eval`] the value can be an expression, like `sin(1) + 1`, and
+    /// `x` can use x.
+    pub fn eval_symbolic(&self, x: &Expr) -> Expr {
+        let mut e = self.substitute(x);
+        e.fold_constants(ConstFoldMode::Exact);
+        e.simplify();
+        e
+    }
+
+    /// The exact value at 0, see [`Expr::eval_symbolic`]
+    pub fn eval_at_zero(&self) -> Expr {
+        self.eval_symbolic(&Expr::Const(0))
+    }
+
+    /// The exact value at 1, see [`Expr::eval_symbolic`]
+    pub fn eval_at_one(&self) -> Expr {
+        self.eval_symbolic(&Expr::Const(1))
+    }
+
+    /// The value of the expression if it is a whole number which can be worked out exactly,
+    /// e.g. `3`, `-(3)`, `2^10`, `ln(e) + 4` or `6/3`. This is what
+    /// [`ConstFoldMode::Exact`] folding would turn the expression into, without changing it.
+    ///
+    /// This is called on every product and power while simplifying, so only expressions of up
+    /// to [`MAX_CONSTANT_NODES`] nodes are worked out.
+    pub fn as_constant(&self) -> Option<Num> {
+        let mut budget = MAX_CONSTANT_NODES;
+        match self {
+            Expr::Const(n) => Some(*n),
+            _ => self
+                .exact_value_within(&mut budget)
+                .filter(|value| value.den == 1)
+                .map(|value| value.num),
+        }
+    }
+
+    /// The value of the expression as a float, if it doesn't use x or any variables and can be
+    /// evaluated, e.g. `sin(1) + 2`. This is what [`ConstFoldMode::Numeric`] folding would
+    /// turn the expression into.
+    pub fn as_f64(&self) -> Option<f64> {
+        if self.uses_x() || !self.free_vars().is_empty() {
+            return None;
+        }
+        self.eval(0.0).ok()
+    }
+
+    /// The exact value of a subexpression which doesn't use x, if it is a fraction
+    pub(super) fn exact_value(&self) -> Option<Ratio> {
+        let mut budget = usize::MAX;
+        self.exact_value_within(&mut budget)
+    }
+
+    /// [`Expr::exact_value`], giving up once more than `budget` nodes have been looked at
+    fn exact_value_within(&self, budget: &mut usize) -> Option<Ratio> {
+        *budget = budget.checked_sub(1)?;
+        match self {
+            Expr::Const(n) => Some(Ratio::whole(*n)),
+            Expr::Scaled(m, e) => Ratio::whole(10).pow(Num::from(*e))?.mul(Ratio::whole(*m)),
+            Expr::Sum(v) => v
+                .iter()
+                .try_fold(Ratio::ZERO, |acc, e| acc.add(e.exact_value_within(budget)?)),
+            Expr::Prod(v) => v
+                .iter()
+                .try_fold(Ratio::ONE, |acc, e| acc.mul(e.exact_value_within(budget)?)),
+            Expr::Neg(e) => e.exact_value_within(budget)?.neg(),
+            Expr::Pow(a, b) => {
+                let exponent = b.exact_value_within(budget).filter(|b| b.den == 1)?.num;
+                if exponent == 0 && (**a == Expr::E || a.exact_value_within(budget)? != Ratio::ZERO)
+                {
+                    return Some(Ratio::ONE);
+                }
+                let base = a.exact_value_within(budget)?;
+                match (base.den, base.num.unsigned_abs()) {
+                    // Powers of 0, 1 and -1 don't overflow, however big the exponent is
+                    (1, 0) => (exponent > 0).then_some(Ratio::ZERO),
+                    (1, 1) => Some(Ratio::whole(if exponent % 2 == 0 { 1 } else { base.num })),
+                    _ => base.pow(exponent),
+                }
+            }
+            Expr::Ln(e) if **e == Expr::E => Some(Ratio::ONE),
+            Expr::Ln(e) => (e.exact_value_within(budget)? == Ratio::ONE).then_some(Ratio::ZERO),
+            Expr::Sin(e)
+            | Expr::Arcsin(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Artanh(e) => {
+                (e.exact_value_within(budget)? == Ratio::ZERO).then_some(Ratio::ZERO)
+            }
+            Expr::Cos(e) | Expr::Cosh(e) => {
+                (e.exact_value_within(budget)? == Ratio::ZERO).then_some(Ratio::ONE)
+            }
+            Expr::Arccos(e) | Expr::Arcosh(e) => {
+                (e.exact_value_within(budget)? == Ratio::ONE).then_some(Ratio::ZERO)
+            }
+            Expr::Factorial(e) => {
+                let n = e
+                    .exact_value_within(budget)
+                    .filter(|n| n.den == 1 && n.num >= 0)?
+                    .num;
+                (1..=n).try_fold(Ratio::ONE, |acc, k| acc.mul(Ratio::whole(k)))
+            }
+            Expr::SumOver(k, from, to, e) => {
+                let mut bound = |b: &Expr| {
+                    b.exact_value_within(budget)
+                        .filter(|b| b.den == 1)
+                        .map(|b| b.num)
+                };
+                let (from, to) = (bound(from)?, bound(to)?);
+                if to.checked_sub(from)? >= MAX_SUM_TERMS {
+                    return None;
+                }
+                (from..=to).try_fold(Ratio::ZERO, |acc, n| {
+                    acc.add(
+                        e.substitute_var(*k, &Expr::Const(n))
+                            .exact_value_within(budget)?,
+                    )
+                })
+            }
+            Expr::X
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Hole(_)
+            | Expr::Deriv(..)
+            | Expr::Piecewise(_) => None,
+        }
+    }
+}
+
+/// A constant with the value of a float, which is a plain constant if it is a whole number and
+/// otherwise a scaled constant with the shortest mantissa that reads back as the same float
+pub(super) fn float_const(v: f64) -> Option<Expr> {
+    if !v.is_finite() {
+        return None;
+    }
+    // Whole numbers past 2^53 might not be exact, so they are written in the same way as
+    // fractions
+    if v.fract() == 0.0 && v.abs() < 2f64.powi(53) {
+        return Some(Expr::Const(v as Num));
+    }
+    // Floats are written like `-1.2345e-3`
+    let written = format!("{v:e}");
+    let (mantissa, exponent) = written.split_once('e')?;
+    let exponent: i32 = exponent.parse().ok()?;
+    let decimals = mantissa.split_once('.').map_or(0, |(_, d)| d.len());
+    let mantissa: Num = mantissa.replace('.', "").parse().ok()?;
+    Some(scaled(mantissa, exponent - decimals as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, e, factorial, ln, pow, sin, sum_over, var, x};
+    use super::super::Rng;
+    use super::*;
+
+    #[test]
+    fn exact() {
+        let mut folded = sin(c(0)) + ln(c(1)) * x() + pow(c(2), c(10)) + cos(c(0)) / c(3);
+        assert_eq!(folded.fold_constants(ConstFoldMode::Exact), 4);
+        assert_eq!(
+            folded,
+            c(0) + c(0) * x() + c(1024) + (c(1) / c(3)).simplified()
+        );
+        // Folding again doesn't change anything
+        assert_eq!(folded.fold_constants(ConstFoldMode::Exact), 0);
+
+        let mut sums = factorial(c(5)) + sum_over('k', c(1), c(4), var('k'));
+        assert_eq!(sums.fold_constants(ConstFoldMode::Exact), 1);
+        assert_eq!(sums, c(130));
+        let mut powers = pow(c(-1), c(1_000_000_001)) * pow(e(), c(0));
+        assert_eq!(powers.fold_constants(ConstFoldMode::Exact), 1);
+        assert_eq!(powers, c(-1));
+        let big = 1_000_000_000_000_000_000;
+        for (e, value) in [
+            (pow(c(0), c(big)), Some(Ratio::ZERO)),
+            (pow(c(0), c(-big)), None),
+            (pow(c(1), c(-big)), Some(Ratio::ONE)),
+            (pow(c(-1), c(big + 1)), Some(Ratio::whole(-1))),
+            (pow(c(2), c(big)), None),
+            (pow(c(2), c(-3)), Some(Ratio::whole(8).recip().unwrap())),
+            (pow(c(Num::MIN), c(2)), None),
+            (pow(c(Num::MIN), c(1)), Some(Ratio::whole(Num::MIN))),
+            (
+                pow(c(-2), c(Num::BITS as Num - 1)),
+                Some(Ratio::whole(Num::MIN)),
+            ),
+        ] {
+            assert_eq!(e.exact_value(), value, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn exact_and_numeric() {
+        let mixed = || sin(c(1)) * x() + ln(e()) + pow(c(2), c(-2)) + cos(x() * 2);
+        let mut exact = mixed();
+        assert_eq!(exact.fold_constants(ConstFoldMode::Exact), 2);
+        assert_eq!(
+            exact,
+            sin(c(1)) * x() + c(1) + (c(1) / c(4)).simplified() + cos(x() * 2)
+        );
+
+        // sin(1) can only be folded numerically, and the term using x is left alone
+        let mut numeric = mixed();
+        assert_eq!(numeric.fold_constants(ConstFoldMode::Numeric), 3);
+        let Expr::Sum(v) = &numeric else {
+            panic!("{numeric:?}")
+        };
+        let Expr::Prod(v) = &v[0] else {
+            panic!("{numeric:?}")
+        };
+        assert!(matches!(v[0], Expr::Scaled(..)));
+        assert_eq!(v[0].eval(0.0), Ok(1f64.sin()));
+        assert_eq!(v[1], x());
+        assert_eq!(numeric.eval(0.7).unwrap(), mixed().eval(0.7).unwrap());
+    }
+
+    #[test]
+    fn untouched() {
+        for mode in [ConstFoldMode::Exact, ConstFoldMode::Numeric] {
+            for mut e in [sin(x()), pow(x(), c(2)) + var('y'), ln(c(-1)), c(1) / c(0)] {
+                let before = e.clone();
+                assert_eq!(e.fold_constants(mode), 0);
+                assert_eq!(e, before);
+            }
+        }
+    }
+
+    #[test]
+    fn symbolic() {
+        assert_eq!((sin(x()) * x() + 1).eval_symbolic(&c(0)), c(1));
+        assert_eq!(pow(x() + 1, c(3)).eval_symbolic(&c(2)), c(27));
+        assert_eq!((ln(x()) + cos(x() - 1)).eval_at_one(), c(1));
+        assert_eq!((pow(x(), c(2)) - x() * 3).eval_at_zero(), c(0));
+        // Values which aren't exact are left as they are
+        let e = (sin(x()) + x() + 1).eval_at_one();
+        assert!(e.equivalent(&(sin(c(1)) + 2)));
+        // x can be put in terms of x
+        let e = pow(x(), c(2)).eval_symbolic(&(x() + 1));
+        assert!(e.equivalent(&pow(x() + 1, c(2))));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(float_const(0.25), Some(Expr::Scaled(25, -2)));
+        assert_eq!(float_const(-1.5e30), Some(Expr::Scaled(-15, 29)));
+        assert_eq!(float_const(3.0), Some(c(3)));
+        assert_eq!(float_const(f64::NAN), None);
+        // Floats are written so that they evaluate to exactly the same float
+        let mut rng = Rng::new(429);
+        let random = (0..1000).map(|_| {
+            let v = rng.next_u64() as f64 / u64::MAX as f64 - 0.5;
+            v * 10f64.powi(rng.range(-30, 30) as i32)
+        });
+        for v in [0.1, 1.0 / 3.0, 2f64.sqrt(), 1e-300, -7.25e300]
+            .into_iter()
+            .chain(random)
+        {
+            let folded = float_const(v).unwrap().eval(0.0).unwrap();
+            assert_eq!(folded, v);
+        }
+    }
+
+    #[test]
+    fn constants() {
+        for e in [
+            c(3),
+            Expr::Neg(Box::new(c(-3))),
+            Expr::Sum(vec![c(3)]),
+            Expr::Prod(vec![c(3)]),
+            Expr::Prod(vec![c(6), c(2).recip()]),
+            ln(e()) + 2,
+            pow(c(3), c(1)) * cos(c(0)),
+            Expr::Scaled(3, 0),
+            sum_over('k', c(0), c(2), var('k')),
+        ] {
+            assert_eq!(e.as_constant(), Some(3), "{e:?}");
+            assert_eq!(e.as_f64(), Some(3.0), "{e:?}");
+        }
+        // Fractions and values which aren't exact only have a float value
+        assert_eq!((c(1) / c(2)).as_constant(), None);
+        assert_eq!((c(1) / c(2)).as_f64(), Some(0.5));
+        assert_eq!(sin(c(1)).as_constant(), None);
+        assert_eq!(sin(c(1)).as_f64(), Some(1f64.sin()));
+        for e in [x(), sin(x()) * c(0), var('y') + 1, ln(c(-1)), c(1) / c(0)] {
+            assert_eq!(e.as_constant(), None, "{e:?}");
+            assert_eq!(e.as_f64(), None, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn big_constants() {
+        // These used to panic or never finish while simplifying
+        let e = x() * pow(c(Num::MIN), c(2));
+        assert_eq!(e.clone().simplified(), e);
+        assert_eq!(pow(c(0), c(1_000_000_000_000_000_000)).simplified(), c(0));
+        assert_eq!(
+            (x() * pow(c(0), c(1_000_000_000_000_000_000))).simplified(),
+            c(0)
+        );
+        // Only small expressions are worked out by as_constant
+        let ones = Expr::Sum(vec![c(1); 100]);
+        assert_eq!(ones.exact_value(), Some(Ratio::whole(100)));
+        assert_eq!(ones.as_constant(), None);
+    }
+}
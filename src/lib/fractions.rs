@@ -0,0 +1,227 @@
+use super::simplify::gcd;
+use super::{Expr, Num};
+
+/// A term of a sum, split into the factors on the top and the bottom of a fraction. The
+/// denominator is stored as bases with (positive) exponents.
+struct Fraction {
+    numerator: Vec<Expr>,
+    denominator: Vec<(Expr, Num)>,
+    constant_denominator: Num,
+}
+
+impl Fraction {
+    /// Split a term, or give `None` if the constants in its denominator overflow
+    fn new(term: &Expr) -> Option<Self> {
+        let mut fraction = Fraction {
+            numerator: vec![],
+            denominator: vec![],
+            constant_denominator: 1,
+        };
+        fraction.add_factor(term)?;
+        Some(fraction)
+    }
+
+    fn add_factor(&mut self, factor: &Expr) -> Option<()> {
+        match factor {
+            Expr::Prod(v) => {
+                for e in v {
+                    self.add_factor(e)?;
+                }
+            }
+            Expr::Neg(e) => {
+                self.numerator.push(Expr::Const(-1));
+                self.add_factor(e)?;
+            }
+            Expr::Pow(a, b) => match (a.as_ref(), b.as_ref()) {
+                (Expr::Const(d), Expr::Const(-1)) if *d != 0 => {
+                    self.constant_denominator = self.constant_denominator.checked_mul(*d)?;
+                }
+                (_, Expr::Const(n)) if *n < 0 => {
+                    let n = n.checked_neg()?;
+                    match self.denominator.iter_mut().find(|(e, _)| e == a.as_ref()) {
+                        Some((_, exp)) => *exp = exp.checked_add(n)?,
+                        None => self.denominator.push((*a.clone(), n)),
+                    }
+                }
+                _ => self.numerator.push(factor.clone()),
+            },
+            _ => self.numerator.push(factor.clone()),
+        }
+        Some(())
+    }
+
+    fn exponent_of(&self, base: &Expr) -> Num {
+        self.denominator
+            .iter()
+            .find(|(e, _)| e == base)
+            .map_or(0, |(_, n)| *n)
+    }
+}
+
+impl Expr {
+    /// This function writes a sum of fractions over their lowest common denominator
+    /// e.g. `1/x + 1/(x+1) = (2x+1)/(x(x+1))`
+    ///
+    /// The numerator is expanded and its like terms collected when it is a polynomial. This is
+    /// the opposite of splitting a fraction into partial fractions, and isn't done by
+    /// [`Expr::simplify`], which leaves fractions as separate terms. It is enabled with
+    /// [`super::SimplifyOptions::combine_fractions`].
+    pub fn simplify_combine_fractions(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        // Anything which overflows is left as it is
+        let Some(fractions) = v.iter().map(Fraction::new).collect::<Option<Vec<_>>>() else {
+            return;
+        };
+        if fractions
+            .iter()
+            .all(|f| f.denominator.is_empty() && f.constant_denominator == 1)
+        {
+            return;
+        }
+
+        // The lowest common denominator takes the highest power of each base
+        let mut denominator: Vec<(Expr, Num)> = vec![];
+        let mut constant_denominator: Num = 1;
+        for fraction in &fractions {
+            for (base, n) in &fraction.denominator {
+                match denominator.iter_mut().find(|(e, _)| e == base) {
+                    Some((_, exp)) => *exp = (*exp).max(*n),
+                    None => denominator.push((base.clone(), *n)),
+                }
+            }
+            let d = fraction.constant_denominator;
+            let lcm = gcd(constant_denominator, d)
+                .and_then(|gcd| (constant_denominator / gcd).checked_mul(d));
+            let Some(lcm) = lcm else {
+                return;
+            };
+            constant_denominator = lcm;
+        }
+
+        // Scale each numerator by whatever its denominator is missing from the common one
+        let numerator = Expr::Sum(
+            fractions
+                .into_iter()
+                .map(|fraction| {
+                    let mut factors = fraction.numerator.clone();
+                    factors.push(Expr::Const(
+                        constant_denominator / fraction.constant_denominator,
+                    ));
+                    for (base, n) in &denominator {
+                        match n - fraction.exponent_of(base) {
+                            0 => (),
+                            1 => factors.push(base.clone()),
+                            n => factors.push(base.clone().pow(Expr::Const(n))),
+                        }
+                    }
+                    Expr::Prod(factors)
+                })
+                .collect(),
+        );
+        let mut numerator = match numerator.to_coefficients() {
+            Some(coeffs) => Expr::from_coefficients(&coeffs),
+            None => numerator,
+        };
+        numerator.simplify();
+
+        let mut factors: Vec<Expr> = denominator
+            .into_iter()
+            .map(|(base, n)| {
+                if n == 1 {
+                    base
+                } else {
+                    base.pow(Expr::Const(n))
+                }
+            })
+            .collect();
+        if constant_denominator != 1 {
+            factors.insert(0, Expr::Const(constant_denominator));
+        }
+        let denominator = if factors.len() == 1 {
+            factors.pop().unwrap()
+        } else {
+            Expr::Prod(factors)
+        };
+        *self = numerator * denominator.recip();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{conformance, SimplifyOptions};
+    use super::*;
+
+    fn check(mut e: Expr, numerator: &[Num], denominator: Expr) {
+        let original = e.clone();
+        e.simplify_combine_fractions();
+        let Expr::Prod(v) = &e else {
+            panic!("expected a fraction, got {e:?}");
+        };
+        assert_eq!(v[0].to_coefficients().as_deref(), Some(numerator));
+        assert_eq!(v[1], denominator.recip());
+        conformance::check_value_preserving(&original, &e, &[0.5, 1.5, 2.0, 3.25]).unwrap();
+    }
+
+    #[test]
+    fn two_terms() {
+        check(
+            Expr::X.recip() + (Expr::X + 1).recip(),
+            &[1, 2],
+            Expr::Prod(vec![Expr::X, Expr::X + 1]),
+        );
+        check(Expr::X + Expr::Const(3) / Expr::X, &[3, 0, 1], Expr::X);
+    }
+
+    #[test]
+    fn three_terms() {
+        // 1/x + 1/x^2 + 1/(x+1) = (2x^2 + 2x + 1)/(x^2(x+1))
+        check(
+            Expr::X.recip() + Expr::X.pow(Expr::Const(-2)) + (Expr::X + 1).recip(),
+            &[1, 2, 2],
+            Expr::Prod(vec![Expr::X.pow(Expr::Const(2)), Expr::X + 1]),
+        );
+        // x/2 + 1/3 + 1/x = (3x^2 + 2x + 6)/(6x)
+        check(
+            Expr::X / 2 + Expr::Const(1) / 3 - Expr::Const(-1) / Expr::X,
+            &[6, 2, 3],
+            Expr::Prod(vec![Expr::Const(6), Expr::X]),
+        );
+    }
+
+    #[test]
+    fn simplify_with() {
+        let mut e = Expr::X.recip() + (Expr::X + 1).recip();
+        e.simplify_with(&SimplifyOptions::default());
+        assert!(matches!(e, Expr::Sum(_)));
+        e.simplify_with(&SimplifyOptions::aggressive());
+        assert!(matches!(e, Expr::Prod(_)));
+        assert!((e.eval(2.0).unwrap() - 5.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn overflow() {
+        // The common denominator of these doesn't fit, so the sum is left alone
+        let big = Num::MAX / 2 + 1;
+        let e = Expr::Const(1) / big + Expr::Const(1) / (big - 1);
+        let mut combined = e.clone();
+        combined.simplify_combine_fractions();
+        assert_eq!(combined, e);
+        let e = Expr::X.pow(Expr::Const(Num::MIN)) + Expr::X.recip();
+        let mut combined = e.clone();
+        combined.simplify_combine_fractions();
+        assert_eq!(combined, e);
+        let e = Expr::Prod(vec![Expr::Const(big).recip(), Expr::Const(4).recip()]) + Expr::X;
+        let mut combined = e.clone();
+        combined.simplify_combine_fractions();
+        assert_eq!(combined, e);
+    }
+
+    #[test]
+    fn no_denominators() {
+        let mut e = Expr::X + Expr::X.sin();
+        e.simplify_combine_fractions();
+        assert_eq!(e, Expr::X + Expr::X.sin());
+    }
+}
@@ -0,0 +1,107 @@
+//! The traits from `num-traits`, so that expressions can be used in generic numeric code, e.g. a
+//! matrix type whose entries are any `T: Zero + One + Mul`. The operators on [`Expr`] only build
+//! the tree without simplifying it, so they never panic and work for any expressions.
+
+use num_traits::{One, Pow, Zero};
+
+use super::{Expr, Num};
+
+impl Zero for Expr {
+    fn zero() -> Self {
+        Expr::Const(0)
+    }
+
+    /// Whether the expression is equivalent to 0 (see [`Expr::equivalent`]), e.g. `x - x`
+    fn is_zero(&self) -> bool {
+        self.equivalent(&Expr::Const(0))
+    }
+}
+
+impl One for Expr {
+    fn one() -> Self {
+        Expr::Const(1)
+    }
+
+    /// Whether the expression is equivalent to 1 (see [`Expr::equivalent`]), e.g. `x + 1 - x`
+    fn is_one(&self) -> bool {
+        self.equivalent(&Expr::Const(1))
+    }
+}
+
+/// The expression to a constant power, e.g. `Pow::pow(x, 3)` is `x^3`
+impl Pow<u32> for Expr {
+    type Output = Expr;
+
+    fn pow(self, n: u32) -> Expr {
+        Expr::pow(self, Expr::Const(Num::from(n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Add, Mul};
+
+    use super::super::prelude::{c, pow, var, x};
+    use super::*;
+
+    /// Multiply two matrices of any numbers, like generic code outside of this crate would
+    fn mat_mul<T, const N: usize>(a: &[[T; N]; N], b: &[[T; N]; N]) -> [[T; N]; N]
+    where
+        T: Zero + Clone + Add<Output = T> + Mul<Output = T>,
+    {
+        std::array::from_fn(|i| {
+            std::array::from_fn(|j| {
+                (0..N).fold(T::zero(), |acc, k| acc + a[i][k].clone() * b[k][j].clone())
+            })
+        })
+    }
+
+    /// The nth power of a matrix, using only the traits
+    fn mat_pow<T, const N: usize>(a: &[[T; N]; N], n: usize) -> [[T; N]; N]
+    where
+        T: Zero + One + Clone + Add<Output = T> + Mul<Output = T>,
+    {
+        let identity = std::array::from_fn(|i| {
+            std::array::from_fn(|j| if i == j { T::one() } else { T::zero() })
+        });
+        (0..n).fold(identity, |acc, _| mat_mul(&acc, a))
+    }
+
+    #[test]
+    fn traits() {
+        assert!(Expr::zero().is_zero());
+        assert!((x() - x()).is_zero());
+        assert!(!x().is_zero());
+        assert!(Expr::one().is_one());
+        assert!((x() + 1 - x()).is_one());
+        // x/x isn't 1 at 0
+        assert!(!(x() / x()).is_one());
+        assert!(!(x() + 1).is_one());
+        assert_eq!(Pow::pow(x() + 1, 3u32), pow(x() + 1, c(3)));
+        // Every operator gives an expression, even for the smallest constant
+        let min = Expr::Const(Num::MIN);
+        assert_eq!(-(-min.clone()), min);
+        let _ = min.clone() * min.clone() + min.clone() - min;
+    }
+
+    #[test]
+    fn matrices() {
+        let (a, b) = (|| var('a'), || var('b'));
+        let m = [[a(), b()], [c(0), x()]];
+        let product = mat_mul(&m, &[[x(), c(1)], [c(2), a()]]);
+        let expected = [
+            [a() * x() + b() * 2, a() + a() * b()],
+            [c(2) * x(), pow(x(), c(1)) * a()],
+        ];
+        for (entry, expected) in product.iter().flatten().zip(expected.iter().flatten()) {
+            assert!(entry.equivalent(expected), "{entry:?}");
+        }
+
+        // [[1, 1], [0, 1]]^n is [[1, n], [0, 1]]
+        let shear = [[c(1), x()], [c(0), c(1)]];
+        let power = mat_pow(&shear, 5);
+        assert!(power[0][1].equivalent(&(x() * 5)));
+        assert!(power[1][0].is_zero());
+        assert!(power[0][0].is_one() && power[1][1].is_one());
+    }
+}
@@ -0,0 +1,425 @@
+use super::{Condition, Expr};
+use std::borrow::Cow;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+
+/// An expression along with a hash of the whole tree, which is computed once when it is created.
+/// Comparing two of these checks the hashes first, so comparing unequal expressions (the common
+/// case when looking for like terms) usually doesn't need to walk either tree.
+#[derive(Debug, Clone)]
+pub struct HashedExpr {
+    expr: Expr,
+    hash: u64,
+}
+
+impl HashedExpr {
+    /// Hash an expression.
+    pub fn new(expr: Expr) -> Self {
+        let mut hasher = DefaultHasher::new();
+        expr.hash(&mut hasher);
+        HashedExpr {
+            hash: hasher.finish(),
+            expr,
+        }
+    }
+
+    /// The hash of the expression.
+    pub fn hash_value(&self) -> u64 {
+        self.hash
+    }
+
+    /// Get the expression back.
+    pub fn into_inner(self) -> Expr {
+        self.expr
+    }
+}
+
+impl From<Expr> for HashedExpr {
+    fn from(expr: Expr) -> Self {
+        HashedExpr::new(expr)
+    }
+}
+
+impl Deref for HashedExpr {
+    type Target = Expr;
+
+    fn deref(&self) -> &Expr {
+        &self.expr
+    }
+}
+
+impl PartialEq for HashedExpr {
+    fn eq(&self, other: &Self) -> bool {
+        // Equal hashes could still be a collision, so the trees have to be compared too
+        self.hash == other.hash && self.expr == other.expr
+    }
+}
+
+impl Eq for HashedExpr {}
+
+impl Hash for HashedExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl Expr {
+    /// A hash of the expression which doesn't depend on the order of the terms of sums and
+    /// products, so e.g. `x + sin(x)` and `sin(x) + x` hash the same, or on how negatives are
+    /// written (see [`Expr::sign_normalize`]), so `-(3x)` and `(-3)x` hash the same. Other
+    /// children are hashed in order, so `a^b` and `b^a` hash differently. The hash only depends
+    /// on the expression, so it is the same in every run of the program, and nothing is changed
+    /// or cloned unless there are negatives to move.
+    ///
+    /// Expressions with different hashes are never equal up to the order of terms, but equal
+    /// hashes can be a collision. They also don't mean the expressions are equal in any other
+    /// way, since e.g. `2x` and `x + x` hash differently. Check for a collision with
+    /// [`Expr::equivalent`] before relying on a match, e.g. when using this as a cache key.
+    pub fn canonical_hash(&self) -> u64 {
+        self.sign_normalize().ordered_hash()
+    }
+
+    /// Write the negatives in the expression in one way, so that the different ways the same
+    /// thing can be written (`-(3)` and `-3`, `-x` and `(-1)x`, `-(3x)` and `(-3)x`) become the
+    /// same, without simplifying anything else. A product has its sign on its constant factor if
+    /// it has one, and as a [`Expr::Neg`] around it otherwise, with no negated factors or
+    /// factors of -1 inside it, and double negatives are removed.
+    ///
+    /// The expression is only cloned if something is changed. Doing it again changes nothing.
+    pub fn sign_normalize(&self) -> Cow<'_, Expr> {
+        if !self.needs_sign_normalizing() {
+            return Cow::Borrowed(self);
+        }
+        let mut e = self.clone();
+        e.transform_bottom_up(&mut Expr::normalize_sign_node);
+        Cow::Owned(e)
+    }
+
+    /// Whether [`Expr::sign_normalize`] would change anything
+    fn needs_sign_normalizing(&self) -> bool {
+        let changes = match self {
+            Expr::Neg(e) => match e.as_ref() {
+                Expr::Const(n) => n.checked_neg().is_some(),
+                Expr::Scaled(m, _) => m.checked_neg().is_some(),
+                Expr::Neg(_) => true,
+                Expr::Prod(v) => coefficient(v).and_then(negated).is_some(),
+                _ => false,
+            },
+            Expr::Prod(v) => {
+                v.len() > 1
+                    && v.iter()
+                        .any(|e| matches!(e, Expr::Neg(_) | Expr::Const(-1)))
+            }
+            _ => false,
+        };
+        changes || self.children().iter().any(|e| e.needs_sign_normalizing())
+    }
+
+    /// Normalize the sign of a node whose children are already normalized
+    fn normalize_sign_node(&mut self) {
+        match self {
+            Expr::Neg(e) => match e.as_mut() {
+                Expr::Neg(inner) => *self = std::mem::replace(inner.as_mut(), Expr::Const(0)),
+                inner => {
+                    let negative = match inner {
+                        Expr::Prod(v) => {
+                            negate_coefficient(v).then(|| Expr::Prod(std::mem::take(v)))
+                        }
+                        inner => negated(inner),
+                    };
+                    if let Some(negative) = negative {
+                        *self = negative;
+                    }
+                }
+            },
+            Expr::Prod(v) if v.len() > 1 => {
+                let mut negative = false;
+                for factor in v.iter_mut() {
+                    if let Expr::Neg(e) = factor {
+                        *factor = std::mem::replace(e.as_mut(), Expr::Const(0));
+                        negative = !negative;
+                    }
+                }
+                while v.len() > 1 {
+                    let Some(i) = v.iter().position(|e| *e == Expr::Const(-1)) else {
+                        break;
+                    };
+                    v.remove(i);
+                    negative = !negative;
+                }
+                if v.len() == 1 {
+                    *self = v.pop().unwrap();
+                }
+                if negative {
+                    // Put the sign on the product in the same way as a negated one
+                    *self = Expr::Neg(Box::new(std::mem::replace(self, Expr::Const(0))));
+                    self.normalize_sign_node();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// [`Expr::canonical_hash`], without normalizing the signs first
+    fn ordered_hash(&self) -> u64 {
+        let label = match self {
+            Expr::Const(n) => mix(*n as u64 ^ mix((*n >> 63) as u64)),
+            Expr::Var(v) => mix(v.0 as u64),
+            Expr::Hole(id) => mix(id.0 as u64),
+            Expr::Deriv(v, t) => mix(v.0 as u64) ^ mix(mix(t.0 as u64)),
+            Expr::Scaled(m, e) => mix(*m as u64) ^ mix(mix(*e as u64)),
+            Expr::SumOver(k, ..) => mix(k.0 as u64),
+            Expr::Piecewise(v) => v.iter().fold(0, |acc, (condition, _)| {
+                let tag = match condition {
+                    Condition::Less(..) => 1,
+                    Condition::LessEq(..) => 2,
+                    Condition::Equal(..) => 3,
+                    Condition::Otherwise => 4,
+                };
+                mix(acc ^ tag)
+            }),
+            _ => 0,
+        };
+        let children = match self {
+            // Wrapping addition doesn't depend on the order, and mixing each child first keeps
+            // sums like `a + a` from cancelling out
+            Expr::Sum(v) | Expr::Prod(v) => {
+                v.iter()
+                    .fold(0u64, |acc, e| acc.wrapping_add(mix(e.ordered_hash())))
+                    ^ mix(v.len() as u64)
+            }
+            _ => self
+                .children()
+                .iter()
+                .fold(0, |acc, e| mix(acc ^ e.ordered_hash())),
+        };
+        mix(mix(self.kind() as u64 + 1) ^ label ^ mix(children))
+    }
+}
+
+/// The constant factor of a product which its sign goes on, which is the first constant other
+/// than 1 (since -1 is written as a negative instead), looking inside products in it too
+fn coefficient(v: &[Expr]) -> Option<&Expr> {
+    v.iter().find_map(|e| match e {
+        Expr::Const(n) if *n != 1 => Some(e),
+        Expr::Scaled(..) => Some(e),
+        Expr::Prod(v) => coefficient(v),
+        _ => None,
+    })
+}
+
+/// Negate the [`coefficient`] of a product, unless there isn't one or it would overflow, and
+/// get whether it was negated
+fn negate_coefficient(v: &mut [Expr]) -> bool {
+    for e in v.iter_mut() {
+        match e {
+            Expr::Const(n) if *n != 1 => (),
+            Expr::Scaled(..) => (),
+            Expr::Prod(v) if coefficient(v).is_some() => return negate_coefficient(v),
+            _ => continue,
+        }
+        return match negated(e) {
+            Some(negative) => {
+                *e = negative;
+                true
+            }
+            None => false,
+        };
+    }
+    false
+}
+
+/// The negative of a constant, unless it would overflow
+fn negated(e: &Expr) -> Option<Expr> {
+    match e {
+        Expr::Const(n) => Some(Expr::Const(n.checked_neg()?)),
+        Expr::Scaled(m, exp) => Some(Expr::Scaled(m.checked_neg()?, *exp)),
+        _ => None,
+    }
+}
+
+/// Scramble the bits of a number (the splitmix64 finalizer), so that combining hashes doesn't
+/// leave patterns
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, pow, sin, var, x};
+    use super::super::{conformance, Num};
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn equality() {
+        let a = HashedExpr::new(Expr::X.sin() + 1);
+        let b = HashedExpr::new(Expr::X.sin() + 1);
+        let c = HashedExpr::new(Expr::X.cos() + 1);
+        assert_eq!(a, b);
+        assert_eq!(a.hash_value(), b.hash_value());
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn collisions() {
+        // Force a collision, which must still be told apart by the full comparison
+        let a = HashedExpr::new(Expr::X);
+        let b = HashedExpr {
+            expr: Expr::Const(1),
+            hash: a.hash,
+        };
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn canonical_hash_commutes() {
+        let a = x() + sin(x()) * var('y') + c(3);
+        let b = c(3) + var('y') * sin(x()) + x();
+        assert_eq!(a.canonical_hash(), b.canonical_hash());
+        assert_ne!(a.canonical_hash(), (x() + sin(x()) + c(3)).canonical_hash());
+        // Repeated terms don't cancel out
+        assert_ne!(
+            (x() + x()).canonical_hash(),
+            Expr::Sum(vec![]).canonical_hash()
+        );
+        assert_ne!(
+            (x() + x()).canonical_hash(),
+            (cos(x()) + cos(x())).canonical_hash()
+        );
+        // A sum and a product of the same terms are different
+        assert_ne!((x() + c(2)).canonical_hash(), (x() * c(2)).canonical_hash());
+    }
+
+    #[test]
+    fn canonical_hash_ordered() {
+        let corpus = conformance::corpus(403, 100);
+        for (a, b) in corpus.iter().zip(corpus.iter().skip(1)) {
+            if a == b {
+                continue;
+            }
+            let ab = pow(a.clone(), b.clone()).canonical_hash();
+            let ba = pow(b.clone(), a.clone()).canonical_hash();
+            assert_ne!(ab, ba, "{a:?} {b:?}");
+            assert_ne!(a.canonical_hash(), b.canonical_hash(), "{a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn canonical_hash_stable() {
+        // The same in every run, and for every build (no random state)
+        let e = pow(x(), c(2)) * 3 + sin(var('y')) - c(-4);
+        assert_eq!(e.canonical_hash(), 5804129438370312994);
+    }
+
+    /// The ways of writing the negative of an expression which [`Expr::sign_normalize`] makes
+    /// the same
+    fn negatives(e: &Expr) -> [Expr; 3] {
+        [
+            Expr::Neg(Box::new(e.clone())),
+            Expr::Prod(vec![c(-1), e.clone()]),
+            Expr::Prod(vec![e.clone(), Expr::Neg(Box::new(c(1)))]),
+        ]
+    }
+
+    #[test]
+    fn sign_forms() {
+        let forms = [
+            c(-3),
+            Expr::Neg(Box::new(c(3))),
+            Expr::Prod(vec![c(-1), c(3)]),
+        ];
+        for form in &forms {
+            assert_eq!(form.sign_normalize().as_ref(), &c(-3));
+        }
+        // The sign goes on the constant factor of a product, or in front of it
+        let e = Expr::Neg(Box::new(Expr::Prod(vec![x(), c(3), var('y')])));
+        assert_eq!(
+            e.sign_normalize().as_ref(),
+            &Expr::Prod(vec![x(), c(-3), var('y')])
+        );
+        let e = Expr::Prod(vec![
+            Expr::Neg(Box::new(x())),
+            c(-1),
+            Expr::Neg(Box::new(var('y'))),
+        ]);
+        assert_eq!(
+            e.sign_normalize().as_ref(),
+            &Expr::Neg(Box::new(Expr::Prod(vec![x(), var('y')])))
+        );
+        assert_eq!(
+            Expr::Neg(Box::new(Expr::Neg(Box::new(sin(x())))))
+                .sign_normalize()
+                .as_ref(),
+            &sin(x())
+        );
+        // Nothing is cloned when there is nothing to move
+        let e = pow(x(), c(-2)) * 3 - sin(x());
+        assert!(matches!(e.sign_normalize(), Cow::Borrowed(_)));
+        // The sign stays outside when moving it would overflow
+        let e = Expr::Neg(Box::new(Expr::Prod(vec![c(Num::MIN), x()])));
+        assert!(matches!(e.sign_normalize(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn sign_normalize_properties() {
+        let corpus = conformance::corpus(449, 100);
+        let samples = [-2.5, -0.7, 0.3, 1.1, 2.9];
+        for e in &corpus {
+            let forms = negatives(e);
+            let hash = forms[0].canonical_hash();
+            for form in [e.clone()].iter().chain(&forms) {
+                let normalized = form.sign_normalize();
+                assert_eq!(
+                    normalized.sign_normalize().as_ref(),
+                    normalized.as_ref(),
+                    "{form:?}"
+                );
+                conformance::check_value_preserving(form, &normalized, &samples)
+                    .unwrap_or_else(|v| panic!("{form:?}: {v:?}"));
+            }
+            for form in &forms[1..] {
+                assert_eq!(form.canonical_hash(), hash, "{form:?}");
+            }
+        }
+    }
+
+    fn products(n: Num) -> Vec<Expr> {
+        (0..n)
+            .map(|i| {
+                // The terms only differ at the end, so comparing them walks most of the tree
+                Expr::Prod(vec![
+                    (Expr::X + 1).sin(),
+                    Expr::X.pow(Expr::Const(2)).cos(),
+                    Expr::Const(i),
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_distinct_products() {
+        let terms = products(5000);
+        let start = Instant::now();
+        let mut equal = 0;
+        for a in &terms {
+            equal += terms.iter().filter(|b| *b == a).count();
+        }
+        let plain = start.elapsed();
+        assert_eq!(equal, terms.len());
+
+        let terms: Vec<_> = terms.into_iter().map(HashedExpr::new).collect();
+        let start = Instant::now();
+        let mut equal = 0;
+        for a in &terms {
+            equal += terms.iter().filter(|b| *b == a).count();
+        }
+        let hashed = start.elapsed();
+        assert_eq!(equal, terms.len());
+
+        println!("plain: {plain:?}, hashed: {hashed:?}");
+    }
+}
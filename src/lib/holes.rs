@@ -0,0 +1,292 @@
+use std::collections::BTreeMap;
+
+use super::{Expr, HoleId};
+
+/// The expression each hole of a template stands for, from [`Expr::match_template`]
+type Bindings = BTreeMap<HoleId, Expr>;
+
+impl Expr {
+    /// The ids of the holes in the expression, each once, in the order they are first found
+    /// (see [`Expr::Hole`]).
+    pub fn holes(&self) -> Vec<HoleId> {
+        let mut holes = vec![];
+        self.collect_holes(&mut holes);
+        holes
+    }
+
+    fn collect_holes(&self, holes: &mut Vec<HoleId>) {
+        match self {
+            Expr::Hole(id) if !holes.contains(id) => holes.push(*id),
+            _ => {
+                for e in self.children() {
+                    e.collect_holes(holes);
+                }
+            }
+        }
+    }
+
+    /// Replace every hole with an id with an expression. Other holes are left as they are.
+    pub fn fill_hole(&self, id: HoleId, value: &Expr) -> Expr {
+        let mut e = self.clone();
+        e.transform_bottom_up(&mut |e| {
+            if *e == Expr::Hole(id) {
+                *e = value.clone();
+            }
+        });
+        e
+    }
+
+    /// Match a template with holes against an expression without them, and find what each hole
+    /// has to be filled with for the template to be the expression, e.g. `\square x^2 + \square`
+    /// matches `3x^2 + 5` with the holes as 3 and 5.
+    ///
+    /// The terms of sums and the factors of products can be in any order. A hole which is a term
+    /// of a sum (or a factor of a product) takes all of the terms which aren't matched by
+    /// anything else, so `\square + x` matches `x^2 + x + 1` with the hole as `x^2 + 1`, and is
+    /// 0 (or 1) when there are none left. A hole used more than once has to match equivalent
+    /// expressions each time. Parts of the template without holes only have to be equivalent
+    /// (see [`Expr::equivalent`]) to the part of the expression they match.
+    ///
+    /// If the template doesn't match as it is written, the simplified template is matched
+    /// against the simplified expression. `None` means they don't match either way.
+    pub fn match_template(&self, expr: &Expr) -> Option<BTreeMap<HoleId, Expr>> {
+        matches(self, expr, Bindings::new()).or_else(|| {
+            let (template, expr) = (self.clone().simplified(), expr.clone().simplified());
+            matches(&template, &expr, Bindings::new())
+        })
+    }
+}
+
+/// Match a template against an expression, adding to the bindings found so far
+fn matches(template: &Expr, e: &Expr, bindings: Bindings) -> Option<Bindings> {
+    if template.holes().is_empty() {
+        return (template == e || template.equivalent(e)).then_some(bindings);
+    }
+    match template {
+        Expr::Hole(id) => bind(bindings, *id, e.clone()),
+        Expr::Sum(terms) => {
+            let targets = match e {
+                Expr::Sum(v) => v.iter().collect(),
+                e => vec![e],
+            };
+            match_terms(terms, targets, bindings, Expr::Sum)
+        }
+        Expr::Prod(factors) => {
+            let targets = match e {
+                Expr::Prod(v) => v.iter().collect(),
+                e => vec![e],
+            };
+            match_terms(factors, targets, bindings, Expr::Prod)
+        }
+        _ => {
+            let (children, targets) = (template.children(), e.children());
+            if shell(template) != shell(e) || children.len() != targets.len() {
+                return None;
+            }
+            children
+                .into_iter()
+                .zip(targets)
+                .try_fold(bindings, |bindings, (child, target)| {
+                    matches(child, target, bindings)
+                })
+        }
+    }
+}
+
+/// The expression with each of its children replaced by 0, to compare everything about a node
+/// apart from its children (e.g. the variable of a [`Expr::SumOver`])
+fn shell(e: &Expr) -> Expr {
+    let mut e = e.clone();
+    for child in e.children_mut() {
+        *child = Expr::Const(0);
+    }
+    e
+}
+
+fn bind(mut bindings: Bindings, id: HoleId, e: Expr) -> Option<Bindings> {
+    match bindings.get(&id) {
+        Some(bound) => (*bound == e || bound.equivalent(&e)).then_some(bindings),
+        None => {
+            bindings.insert(id, e);
+            Some(bindings)
+        }
+    }
+}
+
+/// Match the terms of a sum (or factors of a product) against terms of an expression in any
+/// order. `combine` makes the terms which are left over into one expression for a hole.
+fn match_terms(
+    terms: &[Expr],
+    targets: Vec<&Expr>,
+    bindings: Bindings,
+    combine: fn(Vec<Expr>) -> Expr,
+) -> Option<Bindings> {
+    let (holes, rest): (Vec<&Expr>, Vec<&Expr>) =
+        terms.iter().partition(|e| matches!(e, Expr::Hole(_)));
+    assign(&rest, targets, bindings, &mut |left, bindings| {
+        fill_leftovers(&holes, left, bindings, combine)
+    })
+}
+
+/// Match each template term with a different target, trying every way until `finish` accepts
+/// the targets which are left
+fn assign(
+    terms: &[&Expr],
+    targets: Vec<&Expr>,
+    bindings: Bindings,
+    finish: &mut dyn FnMut(Vec<&Expr>, Bindings) -> Option<Bindings>,
+) -> Option<Bindings> {
+    let Some((first, rest)) = terms.split_first() else {
+        return finish(targets, bindings);
+    };
+    for i in 0..targets.len() {
+        let Some(bindings) = matches(first, targets[i], bindings.clone()) else {
+            continue;
+        };
+        let mut left = targets.clone();
+        left.remove(i);
+        if let Some(bindings) = assign(rest, left, bindings, finish) {
+            return Some(bindings);
+        }
+    }
+    None
+}
+
+/// Give the targets left over to the holes which are terms themselves. One hole takes all of
+/// them, and several holes take one each.
+fn fill_leftovers(
+    holes: &[&Expr],
+    left: Vec<&Expr>,
+    bindings: Bindings,
+    combine: fn(Vec<Expr>) -> Expr,
+) -> Option<Bindings> {
+    match (holes, left.len()) {
+        ([], 0) => Some(bindings),
+        ([], _) => None,
+        ([hole], n) => {
+            let mut left: Vec<Expr> = left.into_iter().cloned().collect();
+            let value = match n {
+                // The empty sum is 0 and the empty product is 1
+                0 => match combine(vec![]) {
+                    Expr::Prod(_) => Expr::Const(1),
+                    _ => Expr::Const(0),
+                },
+                1 => left.pop().unwrap(),
+                _ => combine(left),
+            };
+            matches(hole, &value, bindings)
+        }
+        (holes, n) if n == holes.len() => assign(holes, left, bindings, &mut |_, b| Some(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, x};
+    use super::super::{DerivativeError, EvalError, MathError};
+    use super::*;
+
+    fn hole(id: u32) -> Expr {
+        Expr::Hole(HoleId(id))
+    }
+
+    fn bindings(values: &[(u32, Expr)]) -> Option<Bindings> {
+        Some(
+            values
+                .iter()
+                .map(|(id, e)| (HoleId(*id), e.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn filling() {
+        let template = Expr::from_latex("\\square\\cdot x^2 + \\square").unwrap();
+        assert_eq!(template, hole(0) * pow(x(), c(2)) + hole(1));
+        assert_eq!(template.holes(), vec![HoleId(0), HoleId(1)]);
+        assert_eq!(template.to_latex(), "\\square_{0}x^{2}+\\square_{1}");
+        assert_eq!(Expr::from_latex(&template.to_latex()).unwrap(), template);
+
+        let filled = template.fill_hole(HoleId(1), &c(5));
+        assert_eq!(filled.holes(), vec![HoleId(0)]);
+        let filled = filled.fill_hole(HoleId(0), &c(3));
+        assert!(filled.holes().is_empty());
+        assert_eq!(filled.eval(2.0), Ok(17.0));
+    }
+
+    #[test]
+    fn incomplete() {
+        let template = hole(0) * pow(x(), c(2)) + hole(1);
+        assert_eq!(
+            template.eval(1.0),
+            Err(EvalError::IncompleteExpression(HoleId(0)))
+        );
+        assert_eq!(
+            template.try_eval(1.0).unwrap_err().root_cause(),
+            &MathError::IncompleteExpression(HoleId(0))
+        );
+        assert_eq!(
+            template.clone().try_derivative(),
+            Err(DerivativeError::IncompleteExpression(HoleId(0)))
+        );
+        // Holes are only like terms of holes with the same id
+        let e = hole(0) + hole(1) + hole(0) + x();
+        assert_eq!(
+            e.simplified(),
+            Expr::Sum(vec![Expr::Prod(vec![hole(0), c(2)]), hole(1), x()])
+        );
+    }
+
+    #[test]
+    fn matching() {
+        let template = Expr::from_latex("\\square\\cdot x^2 + \\square").unwrap();
+        let expected = bindings(&[(0, c(3)), (1, c(5))]);
+        for latex in ["3x^2+5", "5 + x^2 \\cdot 3"] {
+            let e = Expr::from_latex(latex).unwrap();
+            let found = template.match_template(&e);
+            assert_eq!(found, expected, "{latex}");
+            let mut filled = template.clone();
+            for (id, value) in found.unwrap() {
+                filled = filled.fill_hole(id, &value);
+            }
+            assert!(filled.equivalent(&e));
+        }
+        // A hole which is a term on its own takes whatever is left over
+        assert_eq!(
+            template.match_template(&(pow(x(), c(2)) * 3)),
+            bindings(&[(0, c(3)), (1, c(0))])
+        );
+        // and a hole which is a factor on its own is 1 when nothing is left over
+        let e = pow(x(), c(2)) + 5;
+        let found = template.match_template(&e);
+        assert_eq!(found, bindings(&[(0, c(1)), (1, c(5))]));
+        let mut filled = template.clone();
+        for (id, value) in found.unwrap() {
+            filled = filled.fill_hole(id, &value);
+        }
+        assert!(filled.equivalent(&e));
+        assert_eq!(
+            (hole(0) + x()).match_template(&(pow(x(), c(2)) + x() + 1)),
+            bindings(&[(0, pow(x(), c(2)) + 1)])
+        );
+        // Holes can be inside other expressions
+        assert_eq!(
+            sin(hole(0) * x()).match_template(&sin(x() * 4)),
+            bindings(&[(0, c(4))])
+        );
+    }
+
+    #[test]
+    fn mismatches() {
+        let template = Expr::from_latex("\\square\\cdot x^2 + \\square").unwrap();
+        for latex in ["3x^3+5", "\\sin(x)", "2x+5"] {
+            let e = Expr::from_latex(latex).unwrap();
+            assert_eq!(template.match_template(&e), None, "{latex}");
+        }
+        // The same hole has to be the same each time
+        let twice = hole(0) * x() + hole(0);
+        assert_eq!(twice.match_template(&(x() * 2 + 2)), bindings(&[(0, c(2))]));
+        assert_eq!(twice.match_template(&(x() * 2 + 3)), None);
+    }
+}
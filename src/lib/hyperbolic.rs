@@ -0,0 +1,223 @@
+use super::{Expr, Num};
+
+impl Expr {
+    /// This function takes the sign out of sinh, cosh and tanh of a negative expression
+    /// e.g. `sinh(-x) = -sinh(x)`, `tanh(-2x) = -tanh(2x)` and `cosh(-x) = cosh(x)`
+    ///
+    /// sinh and tanh are odd and cosh is even. An argument counts as negative when it is a
+    /// negation, a negative constant, or a product with a negative constant factor.
+    pub fn simplify_hyperbolic_parity(&mut self) {
+        let (Expr::Sinh(arg) | Expr::Cosh(arg) | Expr::Tanh(arg)) = self else {
+            return;
+        };
+        let Some(negated) = negated(arg) else {
+            return;
+        };
+        **arg = negated;
+        if !matches!(self, Expr::Cosh(_)) {
+            *self = Expr::Neg(Box::new(std::mem::take(self)));
+        }
+    }
+
+    /// This function replaces `cosh(u)^2 - sinh(u)^2` in a sum with 1
+    /// e.g. `cosh(x)^2 + 2 - sinh(x)^2 = 3` and `3sinh(x)^2 - 3cosh(x)^2 = -3`
+    ///
+    /// The two terms can be anywhere in the sum, as long as their constant factors are opposite.
+    pub fn simplify_hyperbolic_identity(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        // The constant factor of each term which is a square of cosh or sinh, with its argument
+        let squares: Vec<Option<(Num, bool, Expr)>> = v
+            .iter()
+            .map(|e| {
+                let (c, term) = e.split_const_factor();
+                match term {
+                    Expr::Pow(a, b) if *b == Expr::Const(2) => match *a {
+                        Expr::Cosh(u) => Some((c, true, *u)),
+                        Expr::Sinh(u) => Some((c, false, *u)),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+            })
+            .collect();
+        let mut used = vec![false; v.len()];
+        let mut consts = vec![];
+        for (i, square) in squares.iter().enumerate() {
+            let Some((c, true, u)) = square else {
+                continue;
+            };
+            let matching = squares.iter().enumerate().position(|(j, other)| {
+                !used[j]
+                    && matches!(other, Some((d, false, w)) if Some(*d) == c.checked_neg() && w == u)
+            });
+            if let Some(j) = matching {
+                (used[i], used[j]) = (true, true);
+                consts.push(Expr::Const(*c));
+            }
+        }
+        if consts.is_empty() {
+            return;
+        }
+        let mut used = used.into_iter();
+        v.retain(|_| !used.next().unwrap());
+        v.extend(consts);
+    }
+
+    /// Write tanh in terms of e, as `tanh(u) = (e^(2u) - 1)/(e^(2u) + 1)`. This isn't done by
+    /// [`Expr::simplify`], but can be applied to every subexpression with
+    /// [`Expr::transform_bottom_up`].
+    pub fn simplify_tanh_to_exp(&mut self) {
+        let Expr::Tanh(u) = self else {
+            return;
+        };
+        let power = (Expr::Const(2) * std::mem::take(u.as_mut())).exp();
+        *self = (power.clone() - 1) * (power + 1).recip();
+    }
+}
+
+/// The negative of an expression, if it has a negative sign which can be taken out
+fn negated(e: &Expr) -> Option<Expr> {
+    match e {
+        Expr::Neg(e) => Some((**e).clone()),
+        Expr::Const(n) if *n < 0 => Some(Expr::Const(n.checked_neg()?)),
+        Expr::Prod(_) => {
+            let (c, term) = e.split_const_factor();
+            match c.checked_neg()? {
+                n if n <= 0 => None,
+                1 => Some(term),
+                n => Some(match term {
+                    Expr::Prod(mut v) => {
+                        v.push(Expr::Const(n));
+                        Expr::Prod(v)
+                    }
+                    term => Expr::Prod(vec![term, Expr::Const(n)]),
+                }),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{arcosh, arsinh, artanh, c, cosh, pow, sinh, tanh, var, x};
+    use super::super::{conformance, ConstFoldMode, EvalError, ExprKind};
+    use super::*;
+
+    /// Check the derivative of an expression against a central difference at a few points
+    fn check_derivative(e: &Expr) {
+        let derivative = e.clone().derivative();
+        let h = 1e-6;
+        for x in [-1.3, -0.2, 0.4, 2.1] {
+            let estimate = (e.eval(x + h).unwrap() - e.eval(x - h).unwrap()) / (2.0 * h);
+            let exact = derivative.eval(x).unwrap();
+            assert!(
+                (estimate - exact).abs() < 1e-6 * exact.abs().max(1.0),
+                "{e:?} at {x}"
+            );
+        }
+    }
+
+    #[test]
+    fn derivatives() {
+        for f in [sinh, cosh, tanh] {
+            check_derivative(&f(x()));
+            check_derivative(&f(pow(x(), c(2)) * 3 - x()));
+            check_derivative(&(f(x()) * x()));
+        }
+        assert_eq!(sinh(x()).derivative().simplified(), cosh(x()));
+        assert_eq!(cosh(x()).derivative().simplified(), sinh(x()));
+    }
+
+    #[test]
+    fn parity() {
+        assert_eq!(sinh(-x()).simplified(), -sinh(x()));
+        assert_eq!(cosh(-x()).simplified(), cosh(x()));
+        assert_eq!(tanh(x() * -2).simplified(), -tanh(x() * 2));
+        assert_eq!(cosh(c(-3)).simplified(), cosh(c(3)));
+        for e in [sinh(x()), cosh(x() - 1), tanh(x() * 2)] {
+            assert_eq!(e.clone().simplified(), e);
+        }
+    }
+
+    #[test]
+    fn identity() {
+        let square = |e: Expr| pow(e, c(2));
+        assert_eq!((square(cosh(x())) - square(sinh(x()))).simplified(), c(1));
+        let u = || x() * var('y');
+        let e = square(sinh(u())) * 3 + x() + square(cosh(u())) * -3;
+        assert_eq!(e.simplified(), x() + -3);
+        // The arguments and the factors have to match
+        for e in [
+            square(cosh(x())) - square(sinh(x() * 2)),
+            square(cosh(x())) * 2 - square(sinh(x())),
+            square(cosh(x())) + square(sinh(x())),
+        ] {
+            let simplified = e.simplified();
+            assert!(
+                simplified.uses_x() && simplified.node_count() > 3,
+                "{simplified:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn evaluation() {
+        assert_eq!(sinh(x()).eval(0.5).unwrap(), 0.5f64.sinh());
+        assert_eq!(cosh(x()).eval(-0.5).unwrap(), 0.5f64.cosh());
+        assert_eq!(tanh(x()).eval(3.0).unwrap(), 3.0f64.tanh());
+        assert_eq!(
+            Expr::from_latex("\\sinh x + cosh(2x)").unwrap(),
+            sinh(x()) + cosh(c(2) * x())
+        );
+    }
+
+    #[test]
+    fn exponential_form() {
+        let mut e = tanh(x() * 3 - 1);
+        let tanh = e.clone();
+        e.transform_bottom_up(&mut Expr::simplify_tanh_to_exp);
+        assert!(!e.is_hyperbolic());
+        conformance::check_value_preserving(&tanh, &e, &[-0.8, 0.1, 0.5]).unwrap();
+    }
+
+    #[test]
+    fn inverses() {
+        for (e, f) in [
+            (sinh(arsinh(x())), 2.5),
+            (cosh(arcosh(x())), 2.5),
+            (tanh(artanh(x())), 0.5),
+        ] {
+            assert!((e.eval(f).unwrap() - f).abs() < 1e-12);
+        }
+        assert_eq!(
+            arcosh(x()).eval(0.5),
+            Err(EvalError::Domain {
+                kind: ExprKind::Arcosh,
+                value: 0.5
+            })
+        );
+        for v in [-1.0, 1.0, 3.0] {
+            assert_eq!(
+                artanh(x()).eval(v),
+                Err(EvalError::Domain {
+                    kind: ExprKind::Artanh,
+                    value: v
+                })
+            );
+        }
+        assert_eq!(
+            Expr::from_latex("\\sinh^{-1} x + arcosh(x) + \\tanh^{-1}(2x)").unwrap(),
+            arsinh(x()) + arcosh(x()) + artanh(c(2) * x())
+        );
+        assert_eq!(
+            Expr::from_latex(&artanh(x() + 1).to_latex()).unwrap(),
+            artanh(x() + 1)
+        );
+        let mut e = arcosh(c(1)) + artanh(c(0));
+        e.fold_constants(ConstFoldMode::Exact);
+        assert_eq!(e.simplified(), c(0));
+    }
+}
@@ -0,0 +1,357 @@
+use super::simplify::gcd;
+use super::{Assumptions, Expr, Num, SimplifyOptions};
+
+/// How many times integration by parts can be nested by [`Expr::integrate`]. This is enough for
+/// `x^3sin(x)`.
+const PARTS_DEPTH: usize = 3;
+
+impl Expr {
+    /// Find an antiderivative of an expression (INCOMPLETE!). The constant of integration is left
+    /// out.
+    ///
+    /// Returns `None` if the expression can't be integrated with the rules that are implemented.
+    /// Currently these are:
+    /// - constants, sums and constant multiples
+    /// - powers of x (with `1/x` integrating to `ln(x)`)
+    /// - `sin(x)`, `cos(x)`, `ln(x)` and `e^x`
+    /// - u-substitution, for `f(g(x))g'(x)` where f can be integrated with these rules (see
+    ///   [`Expr::integrate_by_substitution`])
+    /// - integration by parts, for products like `xsin(x)` (see [`Expr::integrate_by_parts`])
+    pub fn integrate(&self) -> Option<Self> {
+        self.integrate_with_depth(PARTS_DEPTH)
+    }
+
+    /// Like [`Expr::integrate`], but integration by parts is only nested up to `depth` times
+    /// (so with a depth of 0 it isn't used at all). Each use of it needs another integral, which
+    /// might need it again, so this stops integrals which can't be done from taking forever.
+    pub fn integrate_with_depth(&self, depth: usize) -> Option<Self> {
+        self.integrate_table(depth)
+            .or_else(|| self.integrate_by_substitution(depth))
+            .or_else(|| self.integrate_by_parts(depth))
+    }
+
+    /// Integrate with the rules for specific functions
+    fn integrate_table(&self, depth: usize) -> Option<Self> {
+        if !self.uses_x() {
+            return Some(self.clone() * Expr::X);
+        }
+        match self {
+            Expr::X => Some(Expr::X.pow(Expr::Const(2)) / 2),
+            Expr::Sum(v) => Some(Expr::Sum(
+                v.iter()
+                    .map(|e| e.integrate_with_depth(depth))
+                    .collect::<Option<_>>()?,
+            )),
+            Expr::Neg(e) => Some(-e.integrate_with_depth(depth)?),
+            // Pull out constant factors, which works when there is only one factor left
+            Expr::Prod(v) => {
+                let (mut variable, constant): (Vec<_>, Vec<_>) =
+                    v.iter().cloned().partition(|e| e.uses_x());
+                if variable.len() != 1 {
+                    return None;
+                }
+                let integral = variable.pop().unwrap().integrate_with_depth(depth)?;
+                Some(if constant.is_empty() {
+                    integral
+                } else {
+                    Expr::Prod(constant) * integral
+                })
+            }
+            // Power rule, x^n = x^(n+1)/(n+1)
+            Expr::Pow(a, b) if **a == Expr::X => match **b {
+                Expr::Const(-1) => Some(Expr::X.ln()),
+                Expr::Const(n) => {
+                    let n: Num = n.checked_add(1)?;
+                    Some(Expr::X.pow(Expr::Const(n)) / n)
+                }
+                _ => None,
+            },
+            Expr::Pow(a, b) if **a == Expr::E && **b == Expr::X => Some(self.clone()),
+            Expr::Sin(e) if **e == Expr::X => Some(-Expr::X.cos()),
+            Expr::Cos(e) if **e == Expr::X => Some(Expr::X.sin()),
+            // Integration by parts with u = ln(x) and dv = 1
+            Expr::Ln(e) if **e == Expr::X => Some(Expr::X * Expr::X.ln() - Expr::X),
+            _ => None,
+        }
+    }
+
+    /// Integrate an expression of the form `f(g(x))g'(x)` (up to a constant factor) with the
+    /// substitution `u = g(x)`, e.g. `2xcos(x^2) = sin(x^2)'` or `sin(x)^3cos(x) = (sin(x)^4/4)'`.
+    ///
+    /// Every subexpression is tried as g, largest first. The factors which only depend on x
+    /// through g make up f, and the other factors have to be a constant multiple of g'.
+    ///
+    /// `depth` is passed on to [`Expr::integrate_with_depth`] for integrating f.
+    pub fn integrate_by_substitution(&self, depth: usize) -> Option<Self> {
+        let factors = match self {
+            Expr::Prod(v) => v.as_slice(),
+            e => std::slice::from_ref(e),
+        };
+        let mut candidates = vec![];
+        self.collect_subexpressions(&mut candidates);
+        candidates.into_iter().find_map(|g| {
+            if *g == Expr::X || !g.uses_x() || g == self {
+                return None;
+            }
+            let (outer, rest): (Vec<_>, Vec<_>) = factors
+                .iter()
+                .partition(|e| !e.replace(g, &Expr::Const(0)).uses_x());
+            let mut derivative = g.clone().try_derivative().ok()?;
+            derivative.simplify();
+            let rest = Expr::Prod(rest.into_iter().cloned().collect());
+            let (p, q) = constant_multiple(&rest, &derivative)?;
+
+            // f(u), written with x as u
+            let f = Expr::Prod(outer.into_iter().map(|e| e.replace(g, &Expr::X)).collect());
+            let integral = f.integrate_with_depth(depth)?.substitute(g);
+            Some(match (p, q) {
+                (1, 1) => integral,
+                (p, 1) => Expr::Const(p) * integral,
+                (p, q) => Expr::Const(p) / Expr::Const(q) * integral,
+            })
+        })
+    }
+
+    /// Integrate a product of two functions of x (or a single log or inverse trig function) by
+    /// parts, `∫u dv = uv - ∫v du`, e.g. `∫xcos(x) = xsin(x) + cos(x)`.
+    ///
+    /// u is picked with the LIATE rule: logs, then inverse trig functions, then powers of x and
+    /// then trig functions. This needs another integral, which is done with a `depth` one lower,
+    /// and nothing is done when `depth` is 0.
+    pub fn integrate_by_parts(&self, depth: usize) -> Option<Self> {
+        let depth = depth.checked_sub(1)?;
+        let mut constant = vec![];
+        let mut variable = vec![];
+        split_factors(self, &mut constant, &mut variable);
+        let (u, dv) = match variable.as_slice() {
+            [u] if liate_rank(u) <= 1 => (u.clone(), Expr::Const(1)),
+            [a, b] if liate_rank(a) <= liate_rank(b) => (a.clone(), b.clone()),
+            [a, b] => (b.clone(), a.clone()),
+            _ => return None,
+        };
+
+        let v = dv.integrate_with_depth(depth)?;
+        let mut v_du = &v * u.clone().try_derivative().ok()?;
+        // Cancelling things like x^2/x only changes v du where it isn't defined, so the integral
+        // is still right everywhere else
+        v_du.simplify_with(&SimplifyOptions {
+            assumptions: Assumptions::nonzero(),
+            ..Default::default()
+        });
+        let integral = u * v - v_du.integrate_with_depth(depth)?;
+        Some(if constant.is_empty() {
+            integral
+        } else {
+            Expr::Prod(constant) * integral
+        })
+    }
+
+    /// Collect every subexpression, with an expression before its children
+    fn collect_subexpressions<'a>(&'a self, subexpressions: &mut Vec<&'a Expr>) {
+        subexpressions.push(self);
+        for e in self.children() {
+            e.collect_subexpressions(subexpressions);
+        }
+    }
+
+    /// Find the definite integral of the expression from `a` to `b`, as an exact expression.
+    ///
+    /// Returns `None` if the expression can't be integrated (see [`Expr::integrate`]), or if it
+    /// has a singularity in the interval, since the fundamental theorem of calculus doesn't apply
+    /// then (e.g. the integral of `1/x^2` from -1 to 1).
+    pub fn integrate_definite(&self, a: Num, b: Num) -> Option<Self> {
+        if self.has_singularity_in(a as f64, b as f64) {
+            return None;
+        }
+        let antiderivative = self.integrate()?;
+        let mut result = antiderivative.eval_symbolic(&Expr::Const(b))
+            - antiderivative.eval_symbolic(&Expr::Const(a));
+        result.simplify();
+        Some(result)
+    }
+}
+
+/// If `a` is a constant multiple of `b`, get the multiple as a fraction `(p, q)` so that
+/// `a = p/q * b`.
+fn constant_multiple(a: &Expr, b: &Expr) -> Option<(Num, Num)> {
+    let (p, q) = match (a.to_coefficients(), b.to_coefficients()) {
+        (Some(a), Some(b)) => {
+            let i = b.iter().position(|c| *c != 0)?;
+            let (p, q) = (*a.get(i)?, b[i]);
+            let proportional = a.len() == b.len()
+                && a.iter()
+                    .zip(&b)
+                    .all(|(x, y)| x.checked_mul(q) == y.checked_mul(p));
+            proportional.then_some((p, q))?
+        }
+        _ => {
+            let (a_constant, a) = split_constant(a.canonical());
+            let (b_constant, b) = split_constant(b.canonical());
+            if a != b {
+                return None;
+            }
+            (
+                a_constant.0.checked_mul(b_constant.1)?,
+                a_constant.1.checked_mul(b_constant.0)?,
+            )
+        }
+    };
+    if q == 0 || p == 0 {
+        return None;
+    }
+    let divisor = gcd(p, q)?.checked_mul(q.signum())?;
+    Some((p.checked_div(divisor)?, q / divisor))
+}
+
+/// Split a product into the factors which are constant and which use x, with negatives split
+/// into a factor of -1
+fn split_factors(e: &Expr, constant: &mut Vec<Expr>, variable: &mut Vec<Expr>) {
+    match e {
+        Expr::Prod(v) => {
+            for e in v {
+                split_factors(e, constant, variable);
+            }
+        }
+        Expr::Neg(e) => {
+            constant.push(Expr::Const(-1));
+            split_factors(e, constant, variable);
+        }
+        e if e.uses_x() => variable.push(e.clone()),
+        e => constant.push(e.clone()),
+    }
+}
+
+/// How good a function is to pick as u for integration by parts, where lower is better. This is
+/// the LIATE rule (logs, inverse trig, algebraic, trig, exponential).
+fn liate_rank(e: &Expr) -> usize {
+    match e {
+        Expr::Ln(_) => 0,
+        e if e.is_inverse_trig() => 1,
+        e if e.to_coefficients().is_some() => 2,
+        Expr::Pow(a, b) if **a == Expr::X && !b.uses_x() => 2,
+        Expr::Pow(a, _) if **a == Expr::E => 4,
+        e if e.is_trig() => 3,
+        _ => 4,
+    }
+}
+
+/// Split the constant factors (constants and reciprocals of constants) off of an expression, as
+/// a fraction `(numerator, denominator)`.
+fn split_constant(e: Expr) -> ((Num, Num), Expr) {
+    let Expr::Prod(v) = e else {
+        return ((1, 1), e);
+    };
+    let mut constant = (1, 1);
+    let mut rest = vec![];
+    for e in v {
+        match e {
+            Expr::Const(n) => constant.0 *= n,
+            Expr::Pow(ref a, ref b) if **b == Expr::Const(-1) && matches!(**a, Expr::Const(_)) => {
+                if let Expr::Const(d) = **a {
+                    constant.1 *= d;
+                }
+            }
+            e => rest.push(e),
+        }
+    }
+    let rest = match rest.len() {
+        1 => rest.pop().unwrap(),
+        _ => Expr::Prod(rest),
+    };
+    (constant, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that the derivative of the integral of an expression is the expression
+    fn check_integral(e: Expr) {
+        let integral = e.integrate().unwrap();
+        let derivative = integral.derivative();
+        for x in [0.5, 1.0, 1.5, 2.0, 3.7] {
+            let expected = e.eval(x).unwrap();
+            assert!((derivative.eval(x).unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn integrate() {
+        check_integral(Expr::Const(3));
+        check_integral(Expr::X);
+        check_integral(Expr::X.pow(Expr::Const(4)) * 5 - Expr::X.recip());
+        check_integral(Expr::X.sin() + Expr::X.cos() * Expr::Const(2) + Expr::X.ln());
+        assert_eq!(Expr::X.sin().sin().integrate(), None);
+        assert_eq!(Expr::X.sin().sin().integrate_with_depth(0), None);
+    }
+
+    #[test]
+    fn substitution() {
+        let square = || Expr::X.pow(Expr::Const(2));
+        // 2xcos(x^2)
+        check_integral(Expr::Const(2) * Expr::X * square().cos());
+        // xsin(x^2), which needs a factor of 1/2
+        check_integral(Expr::X * square().sin());
+        // sin(x)^3cos(x)
+        check_integral(Expr::X.sin().pow(Expr::Const(3)) * Expr::X.cos());
+        // 2x(x^2+1)^5
+        check_integral((square() + 1).pow(Expr::Const(5)) * 2 * Expr::X);
+        // cos(x)/sin(x) = ln(sin(x))'
+        check_integral(Expr::X.cos() / Expr::X.sin());
+        // (2x+3)/(x^2+3x) = ln(x^2+3x)'
+        check_integral((Expr::Const(2) * Expr::X + 3) / (square() + Expr::Const(3) * Expr::X));
+        // (2x+3)e^(x^2+3x)
+        check_integral(
+            (Expr::Const(2) * Expr::X + 3) * (square() + Expr::Const(3) * Expr::X).exp(),
+        );
+        // sin(3x+1), where the derivative of the inside is a constant
+        check_integral((Expr::Const(3) * Expr::X + 1).sin());
+
+        assert_eq!((Expr::X * square().sin().sin()).integrate(), None);
+    }
+
+    #[test]
+    fn parts() {
+        // ∫xcos(x) = xsin(x) + cos(x)
+        let integral = (Expr::X * Expr::X.cos()).integrate().unwrap();
+        for x in [0.0, 1.0, 2.5] {
+            let expected = x * f64::sin(x) + f64::cos(x);
+            assert!((integral.eval(x).unwrap() - expected).abs() < 1e-9);
+        }
+        check_integral(Expr::X * Expr::X.sin());
+        check_integral(Expr::X * Expr::X.ln());
+        // Twice and three times
+        check_integral(Expr::X.pow(Expr::Const(2)) * Expr::X.sin() * 3);
+        check_integral(-(Expr::X.pow(Expr::Const(3)) * Expr::X.cos()));
+        check_integral(Expr::X.pow(Expr::Const(2)) * Expr::X.exp());
+        // dv = dx, and then u-substitution
+        check_integral(Expr::Arctan(Box::new(Expr::X)));
+        assert_eq!(
+            (Expr::X.pow(Expr::Const(2)) * Expr::X.sin()).integrate_with_depth(1),
+            None
+        );
+    }
+
+    #[test]
+    fn parts_gives_up() {
+        // Every integral by parts here needs another one
+        assert_eq!((Expr::X.ln() * Expr::X.sin()).integrate(), None);
+        assert_eq!((Expr::X.sin() * Expr::X.sin().sin()).integrate(), None);
+    }
+
+    #[test]
+    fn definite() {
+        // ∫₀² x² dx = 8/3
+        let result = Expr::X
+            .pow(Expr::Const(2))
+            .integrate_definite(0, 2)
+            .unwrap();
+        assert_eq!(result, Expr::Const(8) / 3);
+
+        let result = (Expr::X * 2 + 1).integrate_definite(1, 3).unwrap();
+        assert_eq!(result, Expr::Const(10));
+
+        // The fundamental theorem would give -2 for this
+        assert_eq!(Expr::X.pow(Expr::Const(-2)).integrate_definite(-1, 1), None);
+    }
+}
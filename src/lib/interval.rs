@@ -0,0 +1,509 @@
+use std::f64::consts::{E, FRAC_PI_2, PI, TAU};
+
+use super::eval::MAX_SUM_TERMS;
+use super::{Condition, EvalError, Expr, ExprKind, Interval, Num};
+
+/// Past this size floats are at least 1 apart, which is too coarse to find where sin turns
+const MAX_TRIG_INPUT: f64 = (1u64 << 52) as f64;
+
+/// Settings for [`Expr::eval_interval_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntervalOptions {
+    /// Give an unbounded interval when dividing by an interval which contains 0, rather than
+    /// [`EvalError::DivisionByZero`]. Dividing by exactly 0 is still an error.
+    pub unbounded_division: bool,
+}
+
+impl Interval {
+    /// The interval from `start` to `end`.
+    pub fn new(start: f64, end: f64) -> Self {
+        Interval { start, end }
+    }
+
+    /// The interval containing just one number.
+    pub fn point(x: f64) -> Self {
+        Interval { start: x, end: x }
+    }
+
+    /// Whether the interval contains a number.
+    pub fn contains(&self, x: f64) -> bool {
+        self.start <= x && x <= self.end
+    }
+
+    /// The length of the interval.
+    pub fn width(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// Make the interval a little bigger, to allow for rounding when it was worked out
+    fn outward(self) -> Self {
+        Interval {
+            start: self.start.next_down(),
+            end: self.end.next_up(),
+        }
+    }
+
+    /// The smallest interval containing every one of some numbers, allowing for rounding
+    fn hull(values: &[f64]) -> Self {
+        let start = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let end = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        Interval::new(start, end).outward()
+    }
+
+    fn union(self, other: Interval) -> Self {
+        Interval::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    fn add(self, other: Interval) -> Self {
+        Interval::new(self.start + other.start, self.end + other.end).outward()
+    }
+
+    fn neg(self) -> Self {
+        Interval::new(-self.end, -self.start)
+    }
+
+    fn mul(self, other: Interval) -> Self {
+        // 0 * infinity is 0 here, since an unbounded end is never actually reached
+        let times = |a: f64, b: f64| if a == 0.0 || b == 0.0 { 0.0 } else { a * b };
+        Interval::hull(&[
+            times(self.start, other.start),
+            times(self.start, other.end),
+            times(self.end, other.start),
+            times(self.end, other.end),
+        ])
+    }
+
+    fn recip(self, options: &IntervalOptions) -> Result<Self, EvalError> {
+        if self.start > 0.0 || self.end < 0.0 {
+            return Ok(Interval::hull(&[1.0 / self.end, 1.0 / self.start]));
+        }
+        if !options.unbounded_division || (self.start == 0.0 && self.end == 0.0) {
+            return Err(EvalError::DivisionByZero);
+        }
+        Ok(match (self.start == 0.0, self.end == 0.0) {
+            (true, _) => Interval::new((1.0 / self.end).next_down(), f64::INFINITY),
+            (_, true) => Interval::new(f64::NEG_INFINITY, (1.0 / self.start).next_up()),
+            _ => Interval::new(f64::NEG_INFINITY, f64::INFINITY),
+        })
+    }
+
+    /// The interval to the power of a whole number
+    fn powi(self, n: Num, options: &IntervalOptions) -> Result<Self, EvalError> {
+        if n < 0 {
+            // -Num::MIN doesn't fit, but Num::MAX - 1 is even too, and either power is too big
+            // to be anything but 0, 1 or infinite
+            let n = n.checked_neg().unwrap_or(Num::MAX - 1);
+            return self.powi(n, options)?.recip(options);
+        }
+        // Powers too big for an i32 are all 0, 1 or infinite, so only whether it is odd matters
+        let n = i32::try_from(n).unwrap_or(i32::MAX - 1 + (n % 2) as i32);
+        if n == 0 {
+            return Ok(Interval::point(1.0));
+        }
+        if n == 1 {
+            return Ok(self);
+        }
+        if n % 2 == 1 {
+            // Odd powers are increasing, and an end at 0 stays exactly at 0
+            let hull = Interval::hull(&[self.start.powi(n), self.end.powi(n)]);
+            let exactly_zero = |end: f64, rounded: f64| if end == 0.0 { 0.0 } else { rounded };
+            return Ok(Interval::new(
+                exactly_zero(self.start, hull.start),
+                exactly_zero(self.end, hull.end),
+            ));
+        }
+        let (low, high) = (self.start.abs(), self.end.abs());
+        if self.contains(0.0) {
+            Ok(Interval::new(0.0, low.max(high).powi(n).next_up()))
+        } else {
+            Ok(Interval::hull(&[low.powi(n), high.powi(n)]))
+        }
+    }
+
+    /// Apply an increasing function to the interval
+    fn increasing(self, f: impl Fn(f64) -> f64) -> Self {
+        Interval::hull(&[f(self.start), f(self.end)])
+    }
+
+    fn sin(self) -> Self {
+        if self.width() >= TAU
+            || !self.width().is_finite()
+            || self.start.abs() >= MAX_TRIG_INPUT
+            || self.end.abs() >= MAX_TRIG_INPUT
+        {
+            return Interval::new(-1.0, 1.0);
+        }
+        let mut values = vec![self.start.sin(), self.end.sin()];
+        // The turning points are at π/2 + kπ. The interval is widened a little so that one just
+        // outside of it because of rounding still counts.
+        let widened = self.outward().outward();
+        let first = ((widened.start - FRAC_PI_2) / PI).ceil();
+        let mut k = first;
+        while FRAC_PI_2 + k * PI <= widened.end {
+            values.push(if k.rem_euclid(2.0) == 0.0 { 1.0 } else { -1.0 });
+            k += 1.0;
+        }
+        let hull = Interval::hull(&values);
+        Interval::new(hull.start.max(-1.0), hull.end.min(1.0))
+    }
+
+    fn cos(self) -> Self {
+        self.add(Interval::point(FRAC_PI_2).outward()).sin()
+    }
+
+    /// cosh is decreasing below 0 and increasing above it, with its smallest value 1 at 0
+    fn cosh(self) -> Self {
+        let hull = Interval::hull(&[self.start.cosh(), self.end.cosh()]);
+        if self.contains(0.0) {
+            Interval::new(1.0, hull.end)
+        } else {
+            Interval::new(hull.start.max(1.0), hull.end)
+        }
+    }
+}
+
+impl Expr {
+    /// Find an interval which contains the value of the expression for every x in an interval.
+    /// Every operation rounds outwards, so the value is always inside, although the interval
+    /// can be wider than the real range (e.g. `x - x` on `[0, 1]` gives `[-1, 1]`).
+    ///
+    /// This is an error if the expression can't be evaluated somewhere in the interval, e.g.
+    /// `ln(x)` on `[-1, 1]`, or `1/x` on an interval containing 0 (see
+    /// [`Expr::eval_interval_with`] to allow that).
+    pub fn eval_interval(&self, x: Interval) -> Result<Interval, EvalError> {
+        self.eval_interval_with(x, &IntervalOptions::default())
+    }
+
+    /// Find an interval which contains the value of the expression for every x in an interval,
+    /// like [`Expr::eval_interval`], with some settings.
+    pub fn eval_interval_with(
+        &self,
+        x: Interval,
+        options: &IntervalOptions,
+    ) -> Result<Interval, EvalError> {
+        let eval = |e: &Expr| e.eval_interval_with(x, options);
+        let domain = |kind, value| Err(EvalError::Domain { kind, value });
+        Ok(match self {
+            Expr::Const(n) => exact(*n as f64, n.unsigned_abs() <= 1 << f64::MANTISSA_DIGITS),
+            Expr::X => x,
+            Expr::Var(v) => return Err(EvalError::UnknownVariable(*v)),
+            Expr::I => return Err(EvalError::Complex),
+            Expr::Hole(id) => return Err(EvalError::IncompleteExpression(*id)),
+            Expr::Deriv(v, t) => return Err(EvalError::UnknownRate(*v, *t)),
+            Expr::E => Interval::point(E).outward(),
+            Expr::Scaled(m, e) => {
+                // Working out the power of ten can round a few times
+                let v = *m as f64 * 10f64.powi(*e);
+                let error = v.abs() * 1e-15;
+                Interval::new(v - error, v + error).outward()
+            }
+            Expr::Sum(v) => v
+                .iter()
+                .try_fold(Interval::point(0.0), |acc, e| Ok(acc.add(eval(e)?)))?,
+            Expr::Prod(v) => v
+                .iter()
+                .try_fold(Interval::point(1.0), |acc, e| Ok(acc.mul(eval(e)?)))?,
+            Expr::Neg(e) => eval(e)?.neg(),
+            Expr::Pow(a, b) => match (a.as_ref(), b.as_ref()) {
+                (_, Expr::Const(n)) => eval(a)?.powi(*n, options)?,
+                (Expr::E, _) => eval(b)?.increasing(f64::exp),
+                _ => {
+                    // a^b = e^(b ln(a)), which needs a to be positive (or 0 for a positive b)
+                    let a = eval(a)?;
+                    let b = eval(b)?;
+                    if a.start < 0.0 || (a.start == 0.0 && b.start <= 0.0) {
+                        return domain(ExprKind::Pow, a.start);
+                    }
+                    b.mul(a.increasing(f64::ln)).increasing(f64::exp)
+                }
+            },
+            Expr::Ln(e) => {
+                let v = eval(e)?;
+                if v.start <= 0.0 {
+                    return domain(ExprKind::Ln, v.start);
+                }
+                v.increasing(f64::ln)
+            }
+            Expr::Sin(e) => eval(e)?.sin(),
+            Expr::Cos(e) => eval(e)?.cos(),
+            Expr::Arcsin(e) | Expr::Arccos(e) => {
+                let v = eval(e)?;
+                for end in [v.start, v.end] {
+                    if !(-1.0..=1.0).contains(&end) {
+                        return domain(self.kind(), end);
+                    }
+                }
+                match self {
+                    Expr::Arcsin(_) => v.increasing(f64::asin),
+                    _ => v.neg().increasing(|x| (-x).acos()),
+                }
+            }
+            Expr::Arctan(e) => eval(e)?.increasing(f64::atan),
+            Expr::Sinh(e) => eval(e)?.increasing(f64::sinh),
+            Expr::Cosh(e) => eval(e)?.cosh(),
+            Expr::Tanh(e) => eval(e)?.increasing(f64::tanh),
+            Expr::Arsinh(e) => eval(e)?.increasing(f64::asinh),
+            Expr::Arcosh(e) => {
+                let v = eval(e)?;
+                if v.start < 1.0 {
+                    return domain(ExprKind::Arcosh, v.start);
+                }
+                v.increasing(f64::acosh)
+            }
+            Expr::Artanh(e) => {
+                let v = eval(e)?;
+                for end in [v.start, v.end] {
+                    if !(-1.0 < end && end < 1.0) {
+                        return domain(ExprKind::Artanh, end);
+                    }
+                }
+                v.increasing(f64::atanh)
+            }
+            Expr::Factorial(e) => {
+                // Only whole numbers have a factorial, so only a single one can be evaluated
+                let v = eval(e)?;
+                let n = whole(v, ExprKind::Factorial)?;
+                if n < 0 {
+                    return domain(ExprKind::Factorial, v.start);
+                }
+                let value = Expr::Const(n).factorial().eval(0.0)?;
+                exact(value, n <= 18)
+            }
+            Expr::SumOver(k, from, to, e) => {
+                let from = whole(eval(from)?, ExprKind::SumOver)?;
+                let to = whole(eval(to)?, ExprKind::SumOver)?;
+                if to
+                    .checked_sub(from)
+                    .is_none_or(|terms| terms >= MAX_SUM_TERMS)
+                {
+                    return Err(EvalError::TooManyTerms);
+                }
+                let mut sum = Interval::point(0.0);
+                for n in from..=to {
+                    sum = sum.add(eval(&e.substitute_var(*k, &Expr::Const(n)))?);
+                }
+                sum
+            }
+            Expr::Piecewise(v) => {
+                // Every branch which might be taken somewhere in the interval is included, up
+                // to one which is always taken
+                let mut result: Option<Interval> = None;
+                for (condition, e) in v {
+                    let Some(always) = condition.holds_on(&eval)? else {
+                        continue;
+                    };
+                    let branch = eval(e)?;
+                    result = Some(result.map_or(branch, |r| r.union(branch)));
+                    if always {
+                        return Ok(result.unwrap());
+                    }
+                }
+                return Err(EvalError::NoBranch);
+            }
+        })
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds for some values in intervals, using `eval` to find the
+    /// interval of each side. This is `None` if it never holds, `Some(true)` if it always holds,
+    /// and `Some(false)` if it might hold.
+    fn holds_on(
+        &self,
+        eval: &dyn Fn(&Expr) -> Result<Interval, EvalError>,
+    ) -> Result<Option<bool>, EvalError> {
+        let sides = match self {
+            Condition::Less(a, b) | Condition::LessEq(a, b) | Condition::Equal(a, b) => {
+                (eval(a)?, eval(b)?)
+            }
+            Condition::Otherwise => return Ok(Some(true)),
+        };
+        let (a, b) = sides;
+        let (always, never) = match self {
+            Condition::Less(..) => (a.end < b.start, a.start >= b.end),
+            Condition::LessEq(..) => (a.end <= b.start, a.start > b.end),
+            _ => (
+                a.width() == 0.0 && a == b,
+                a.end < b.start || b.end < a.start,
+            ),
+        };
+        Ok((!never).then_some(always))
+    }
+}
+
+/// A float which is either exactly a number, or within rounding of it
+fn exact(value: f64, is_exact: bool) -> Interval {
+    match is_exact {
+        true => Interval::point(value),
+        false => Interval::point(value).outward(),
+    }
+}
+
+/// The whole number an interval is exactly, or a domain error
+fn whole(v: Interval, kind: ExprKind) -> Result<Num, EvalError> {
+    if v.width() != 0.0 || v.start.fract() != 0.0 {
+        return Err(EvalError::Domain {
+            kind,
+            value: v.start,
+        });
+    }
+    Ok(v.start as Num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, cosh, pow, sin, sqrt, tanh, x};
+    use super::super::VarId;
+    use super::*;
+
+    fn encloses(outer: Interval, start: f64, end: f64) -> bool {
+        outer.start <= start
+            && end <= outer.end
+            && start - outer.start < 1e-9
+            && outer.end - end < 1e-9
+    }
+
+    #[test]
+    fn polynomials() {
+        // x^2 - 2x on [0, 3] is at most [-6, 9] term by term
+        let e = pow(x(), c(2)) - x() * 2;
+        let v = e.eval_interval(Interval::new(0.0, 3.0)).unwrap();
+        assert!(encloses(v, -6.0, 9.0), "{v:?}");
+
+        // Even powers of an interval containing 0 start at 0
+        let v = pow(x(), c(2))
+            .eval_interval(Interval::new(-2.0, 1.0))
+            .unwrap();
+        assert!(encloses(v, 0.0, 4.0), "{v:?}");
+        assert_eq!(v.start, 0.0);
+        let v = pow(x(), c(3))
+            .eval_interval(Interval::new(-2.0, 1.0))
+            .unwrap();
+        assert!(encloses(v, -8.0, 1.0), "{v:?}");
+        let v = pow(x(), c(4))
+            .eval_interval(Interval::new(-3.0, -1.0))
+            .unwrap();
+        assert!(encloses(v, 1.0, 81.0), "{v:?}");
+
+        // Exactly the value at a point
+        let v = (x() * 3 + 1).eval_interval(Interval::point(2.0)).unwrap();
+        assert!(encloses(v, 7.0, 7.0), "{v:?}");
+    }
+
+    #[test]
+    fn trig() {
+        // Spans π/2, so the top is 1 even though both ends are below it
+        let v = sin(x()).eval_interval(Interval::new(1.5, 1.6)).unwrap();
+        assert_eq!(v.end, 1.0);
+        assert!(v.start <= 1.5f64.sin().min(1.6f64.sin()));
+        // Just short of π/2
+        let v = sin(x()).eval_interval(Interval::new(1.0, 1.5)).unwrap();
+        assert!(encloses(v, 1f64.sin(), 1.5f64.sin()), "{v:?}");
+        // Spans both π/2 and 3π/2
+        let v = sin(x()).eval_interval(Interval::new(1.0, 5.0)).unwrap();
+        assert_eq!(v, Interval::new(-1.0, 1.0));
+        // cos has its top at 0 and its bottom at π
+        let v = cos(x()).eval_interval(Interval::new(-0.5, 0.5)).unwrap();
+        assert!(encloses(v, 0.5f64.cos(), 1.0), "{v:?}");
+        let v = cos(x()).eval_interval(Interval::new(3.0, 3.2)).unwrap();
+        assert!(v.start <= -1.0 && v.end >= 3f64.cos().max(3.2f64.cos()));
+        let v = sin(x()).eval_interval(Interval::new(0.0, 100.0)).unwrap();
+        assert_eq!(v, Interval::new(-1.0, 1.0));
+        // Floats this big are too far apart to step between turning points
+        for big in [1e17, -1e17, 2f64.powi(53)] {
+            let v = sin(x()).eval_interval(Interval::point(big)).unwrap();
+            assert_eq!(v, Interval::new(-1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn hyperbolic() {
+        // cosh has its bottom at 0
+        let v = cosh(x()).eval_interval(Interval::new(-1.0, 2.0)).unwrap();
+        assert!(encloses(v, 1.0, 2f64.cosh()), "{v:?}");
+        assert_eq!(v.start, 1.0);
+        let v = cosh(x()).eval_interval(Interval::new(-3.0, -1.0)).unwrap();
+        assert!(encloses(v, 1f64.cosh(), 3f64.cosh()), "{v:?}");
+        let v = tanh(x()).eval_interval(Interval::new(-1.0, 0.5)).unwrap();
+        assert!(encloses(v, -1f64.tanh(), 0.5f64.tanh()), "{v:?}");
+    }
+
+    #[test]
+    fn samples_inside() {
+        let e = sin(x() * 3) * pow(x(), c(2)) + cos(x()).exp() - sqrt(x() + 5);
+        let interval = Interval::new(-1.3, 2.1);
+        let v = e.eval_interval(interval).unwrap();
+        for i in 0..=100 {
+            let x = interval.start + interval.width() * i as f64 / 100.0;
+            assert!(v.contains(e.eval(x).unwrap()));
+        }
+    }
+
+    #[test]
+    fn division() {
+        let e = x().recip();
+        let v = e.eval_interval(Interval::new(1.0, 4.0)).unwrap();
+        assert!(encloses(v, 0.25, 1.0), "{v:?}");
+        let around_zero = Interval::new(-1.0, 2.0);
+        assert_eq!(e.eval_interval(around_zero), Err(EvalError::DivisionByZero));
+
+        let options = IntervalOptions {
+            unbounded_division: true,
+        };
+        let v = e.eval_interval_with(around_zero, &options).unwrap();
+        assert_eq!(v, Interval::new(f64::NEG_INFINITY, f64::INFINITY));
+        let v = e
+            .eval_interval_with(Interval::new(0.0, 2.0), &options)
+            .unwrap();
+        assert!(v.start <= 0.5 && v.start > 0.49 && v.end == f64::INFINITY);
+        assert_eq!(
+            e.eval_interval_with(Interval::point(0.0), &options),
+            Err(EvalError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn domains() {
+        assert!(matches!(
+            x().ln().eval_interval(Interval::new(-1.0, 1.0)),
+            Err(EvalError::Domain {
+                kind: ExprKind::Ln,
+                ..
+            })
+        ));
+        assert!(x().ln().eval_interval(Interval::new(0.5, 1.0)).is_ok());
+        let piecewise = Expr::Piecewise(vec![
+            (Condition::Less(x(), c(0)), -x()),
+            (Condition::Otherwise, x()),
+        ]);
+        // Both branches might be taken, and each is evaluated on the whole interval
+        let v = piecewise.eval_interval(Interval::new(-2.0, 1.0)).unwrap();
+        assert!(encloses(v, -2.0, 2.0), "{v:?}");
+        let v = piecewise.eval_interval(Interval::new(1.0, 3.0)).unwrap();
+        assert!(encloses(v, 1.0, 3.0), "{v:?}");
+
+        let sum = Expr::Var(VarId('k')).sum_over(VarId('k'), c(0), c(1 << 40));
+        assert_eq!(
+            sum.eval_interval(Interval::point(0.0)),
+            Err(EvalError::TooManyTerms)
+        );
+    }
+
+    #[test]
+    fn huge_powers() {
+        // The smallest power can't be negated, and is even
+        let v = pow(x(), c(Num::MIN))
+            .eval_interval(Interval::new(-3.0, -2.0))
+            .unwrap();
+        assert!(v.start <= 0.0 && v.end >= 0.0 && v.end < 1e-300, "{v:?}");
+        let v = pow(x(), c(Num::MAX))
+            .eval_interval(Interval::new(-3.0, -2.0))
+            .unwrap();
+        assert_eq!(v.start, f64::NEG_INFINITY);
+        let v = pow(x(), c(Num::MAX - 1))
+            .eval_interval(Interval::new(-3.0, -2.0))
+            .unwrap();
+        assert_eq!(v.end, f64::INFINITY);
+        assert!(v.start > 0.0);
+    }
+}
@@ -0,0 +1,315 @@
+use super::{Expr, Interval, Num};
+
+/// The most times an interval is halved while separating roots, which is more than enough to get
+/// down to the gap between floats
+const MAX_SPLITS: usize = 2000;
+
+/// A polynomial with whole coefficients, where the nth element is the coefficient of `x^n`. These
+/// are wider than [`Num`] since the coefficients grow while finding a Sturm sequence.
+type Poly = Vec<i128>;
+
+impl Expr {
+    /// Find intervals which each contain exactly one real root of a polynomial in x, and which
+    /// don't overlap, in order. Only roots in `interval` are found, including ones at its ends.
+    ///
+    /// This uses the Sturm sequence of the polynomial, which counts the roots between two points
+    /// exactly, and halves intervals until each has one root. The signs in the sequence are found
+    /// with [`Expr::eval_interval`], so they are never wrong because of rounding. A repeated root
+    /// is only found once, since the polynomial is made square-free first (e.g. `(x-1)^2(x+2)`
+    /// has the same roots as `(x-1)(x+2)`).
+    ///
+    /// Roots which are so close together that the polynomial can't be evaluated accurately
+    /// enough between them (e.g. `10^9` times closer than its coefficients are big) share an
+    /// interval. An end of `interval` which is very close to a root is moved out a little, so a
+    /// root just outside of it can be included.
+    ///
+    /// Returns `None` if the expression isn't a polynomial with integer coefficients, if it is
+    /// 0, or if a coefficient overflows while finding the sequence.
+    pub fn isolate_roots(&self, interval: Interval) -> Option<Vec<(f64, f64)>> {
+        let sequence = sturm_sequence(&self.to_coefficients()?)?;
+        let count = |x: f64| sign_changes(&sequence, x);
+
+        // Move the ends out a little if they are on (or too near) a root, so that it is counted
+        let room = interval.width() / 4.0;
+        let (start, start_count) = nearby(interval.start, -1.0, room, count)?;
+        let (end, end_count) = nearby(interval.end, 1.0, room, count)?;
+        let mut isolated = vec![];
+        let mut stack = vec![(start, start_count, end, end_count)];
+        let mut splits = 0;
+        while let Some((a, count_a, b, count_b)) = stack.pop() {
+            // The number of roots in (a, b]
+            let roots = count_a.saturating_sub(count_b);
+            if roots == 0 {
+                continue;
+            }
+            let mid = a + (b - a) / 2.0;
+            if roots == 1 || splits >= MAX_SPLITS || mid <= a || mid >= b {
+                isolated.push((a, b));
+                continue;
+            }
+            splits += 1;
+            let Some((mid, count_mid)) = nearby(mid, 1.0, (b - a) / 4.0, count) else {
+                isolated.push((a, b));
+                continue;
+            };
+            stack.push((a, count_a, mid, count_mid));
+            stack.push((mid, count_mid, b, count_b));
+        }
+        isolated.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Some(isolated)
+    }
+
+    /// Find each real root of a polynomial in `interval` to within about `tolerance`, by
+    /// narrowing down the intervals from [`Expr::isolate_roots`]. Repeated roots are only given
+    /// once.
+    pub(super) fn polynomial_roots(&self, interval: Interval, tolerance: f64) -> Option<Vec<f64>> {
+        let sequence = sturm_sequence(&self.to_coefficients()?)?;
+        let count = |x: f64| sign_changes(&sequence, x);
+        let roots = self
+            .isolate_roots(interval)?
+            .into_iter()
+            .map(|(mut a, mut b)| {
+                let Some(mut count_a) = count(a) else {
+                    return a + (b - a) / 2.0;
+                };
+                for _ in 0..MAX_SPLITS {
+                    let mid = a + (b - a) / 2.0;
+                    if b - a <= tolerance || mid <= a || mid >= b {
+                        break;
+                    }
+                    // The root is too close to the middle to tell which side it is on
+                    let Some(count_mid) = count(mid) else {
+                        return mid;
+                    };
+                    if count_a > count_mid {
+                        b = mid;
+                    } else {
+                        (a, count_a) = (mid, count_mid);
+                    }
+                }
+                a + (b - a) / 2.0
+            })
+            .collect();
+        Some(roots)
+    }
+}
+
+/// A point near `x` where the number of sign changes is known, along with the number of sign
+/// changes there. Points are tried further and further away, alternating sides and starting
+/// with `direction`, up to `room` away.
+fn nearby(
+    x: f64,
+    direction: f64,
+    room: f64,
+    count: impl Fn(f64) -> Option<usize>,
+) -> Option<(f64, usize)> {
+    if let Some(n) = count(x) {
+        return Some((x, n));
+    }
+    let mut offset = (room.abs() * 1e-12).max(x.abs() * f64::EPSILON * 4.0);
+    while offset <= room.abs() {
+        for point in [x + direction * offset, x - direction * offset] {
+            if let Some(n) = count(point) {
+                return Some((point, n));
+            }
+        }
+        offset *= 2.0;
+    }
+    None
+}
+
+/// The number of sign changes in a Sturm sequence at a point, or `None` if the sign of one of
+/// the polynomials couldn't be told for certain (because it is too close to 0)
+fn sign_changes(sequence: &[Expr], x: f64) -> Option<usize> {
+    let mut changes = 0;
+    let mut last = None;
+    for p in sequence {
+        let v = p.eval_interval(Interval::point(x)).ok()?;
+        let positive = match (v.start > 0.0, v.end < 0.0) {
+            (true, _) => true,
+            (_, true) => false,
+            _ => return None,
+        };
+        if last.is_some_and(|last| last != positive) {
+            changes += 1;
+        }
+        last = Some(positive);
+    }
+    Some(changes)
+}
+
+/// The Sturm sequence of the square-free part of a polynomial, with each polynomial scaled by a
+/// positive number to have whole coefficients with no common factor. Returns `None` for the zero
+/// polynomial or on overflow.
+fn sturm_sequence(coeffs: &[Num]) -> Option<Vec<Expr>> {
+    // Num is already i128 with the num-i128 feature
+    #[allow(clippy::useless_conversion)]
+    let p: Poly = trim(coeffs.iter().map(|c| i128::from(*c)).collect());
+    if p.is_empty() {
+        return None;
+    }
+    let square_free = primitive(&pseudo_divide(&p, &poly_gcd(&p, &derivative(&p)?)?)?.0);
+
+    let mut sequence = vec![square_free.clone()];
+    let mut next = primitive(&derivative(&square_free)?);
+    while !next.is_empty() {
+        let rem = pseudo_divide(sequence.last().unwrap(), &next)?.1;
+        sequence.push(next);
+        next = primitive(&rem.into_iter().map(|c| -c).collect::<Poly>());
+    }
+    sequence.iter().map(|p| to_expr(p)).collect()
+}
+
+fn to_expr(p: &[i128]) -> Option<Expr> {
+    let coeffs = p
+        .iter()
+        .map(|c| Num::try_from(*c).ok())
+        .collect::<Option<Vec<Num>>>()?;
+    Some(Expr::from_coefficients(&coeffs))
+}
+
+fn trim(mut p: Poly) -> Poly {
+    while p.last() == Some(&0) {
+        p.pop();
+    }
+    p
+}
+
+fn derivative(p: &[i128]) -> Option<Poly> {
+    let d = p
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(n, c)| c.checked_mul(n as i128))
+        .collect::<Option<Poly>>()?;
+    Some(trim(d))
+}
+
+/// Divide `a` by `b` after scaling `a` by a positive number so that every coefficient stays
+/// whole, giving the quotient and the remainder. The signs of the remainder are the same as they
+/// would be dividing exactly, which is what a Sturm sequence needs.
+fn pseudo_divide(a: &[i128], b: &[i128]) -> Option<(Poly, Poly)> {
+    let lead = *b.last()?;
+    let mut rem = a.to_vec();
+    let mut quotient = vec![0; a.len().saturating_sub(b.len()) + 1];
+    let mut scalings = 0;
+    while rem.len() >= b.len() && !rem.is_empty() {
+        let shift = rem.len() - b.len();
+        let factor = *rem.last()?;
+        for c in quotient.iter_mut().chain(rem.iter_mut()) {
+            *c = c.checked_mul(lead)?;
+        }
+        scalings += 1;
+        quotient[shift] = factor;
+        for (i, c) in b.iter().enumerate() {
+            rem[shift + i] = rem[shift + i].checked_sub(c.checked_mul(factor)?)?;
+        }
+        // The leading term is always cancelled exactly
+        rem.pop();
+        rem = trim(rem);
+    }
+    if lead < 0 && scalings % 2 == 1 {
+        quotient
+            .iter_mut()
+            .chain(rem.iter_mut())
+            .for_each(|c| *c = -*c);
+    }
+    Some((trim(quotient), rem))
+}
+
+fn poly_gcd(a: &[i128], b: &[i128]) -> Option<Poly> {
+    let (mut a, mut b) = (primitive(a), primitive(b));
+    while !b.is_empty() {
+        let rem = primitive(&pseudo_divide(&a, &b)?.1);
+        (a, b) = (b, rem);
+    }
+    Some(a)
+}
+
+/// Divide a polynomial by the common factor of its coefficients, which keeps the numbers small
+/// without changing any signs
+fn primitive(p: &[i128]) -> Poly {
+    let common = p.iter().fold(0, |g, c| gcd(g, *c)).max(1);
+    p.iter().map(|c| c / common).collect()
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, x};
+    use super::*;
+
+    fn check(e: &Expr, interval: Interval, roots: &[f64]) {
+        let isolated = e.isolate_roots(interval).unwrap();
+        assert_eq!(isolated.len(), roots.len(), "{isolated:?}");
+        for ((a, b), root) in isolated.iter().zip(roots) {
+            assert!(a < root && root <= b, "{root} isn't in ({a}, {b}]");
+        }
+        for pair in isolated.windows(2) {
+            assert!(pair[0].1 <= pair[1].0, "{isolated:?}");
+        }
+    }
+
+    #[test]
+    fn simple() {
+        // (x-1)(x-2)(x-3)
+        let e = (x() - 1) * (x() - 2) * (x() - 3);
+        check(&e, Interval::new(-10.0, 10.0), &[1.0, 2.0, 3.0]);
+        check(&e, Interval::new(1.5, 10.0), &[2.0, 3.0]);
+        // Roots at the ends of the interval are included
+        check(&e, Interval::new(1.0, 3.0), &[1.0, 2.0, 3.0]);
+        // x^3 - 2 has one real root
+        let e = pow(x(), c(3)) - 2;
+        check(&e, Interval::new(-5.0, 5.0), &[2f64.cbrt()]);
+    }
+
+    #[test]
+    fn clustered() {
+        // (x-1)(1000x-1001), with roots at 1 and 1.001
+        let e = (x() - 1) * (x() * 1000 - 1001);
+        check(&e, Interval::new(0.0, 2.0), &[1.0, 1.001]);
+        // (x-1)(10^6 x - (10^6 + 1)), with roots 10^-6 apart
+        let e = (x() - 1) * (x() * 1_000_000 - 1_000_001);
+        check(&e, Interval::new(-4.0, 4.0), &[1.0, 1.000001]);
+        // (x-1)(x-1.01)(x-1.02)(x+5)
+        let e = (x() - 1) * (x() * 100 - 101) * (x() * 50 - 51) * (x() + 5);
+        check(&e, Interval::new(-10.0, 10.0), &[-5.0, 1.0, 1.01, 1.02]);
+    }
+
+    #[test]
+    fn repeated() {
+        // (x-1)^3(x+2)^2 has its roots counted once each
+        let e = pow(x() - 1, c(3)) * pow(x() + 2, c(2));
+        check(&e, Interval::new(-10.0, 10.0), &[-2.0, 1.0]);
+        let roots = e
+            .polynomial_roots(Interval::new(-10.0, 10.0), 1e-12)
+            .unwrap();
+        assert!((roots[0] + 2.0).abs() < 1e-9 && (roots[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_roots() {
+        let e = pow(x(), c(4)) + pow(x(), c(2)) + 1;
+        assert_eq!(e.isolate_roots(Interval::new(-100.0, 100.0)), Some(vec![]));
+        assert_eq!(c(5).isolate_roots(Interval::new(-1.0, 1.0)), Some(vec![]));
+        assert_eq!(c(0).isolate_roots(Interval::new(-1.0, 1.0)), None);
+        assert_eq!(x().sin().isolate_roots(Interval::new(-1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn refine() {
+        // x^5 - x - 1 has one real root, near 1.1673
+        let e = pow(x(), c(5)) - x() - 1;
+        let roots = e.polynomial_roots(Interval::new(-3.0, 3.0), 1e-12).unwrap();
+        assert_eq!(roots.len(), 1);
+        let root = roots[0];
+        assert!((root.powi(5) - root - 1.0).abs() < 1e-9);
+    }
+}
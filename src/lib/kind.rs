@@ -0,0 +1,389 @@
+use super::Expr;
+
+/// The kind of an expression, without any of its contents. This mirrors the variants of [`Expr`]
+/// and is useful for quick checks (e.g. "is this a trig function") without matching every
+/// variant.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum ExprKind {
+    /// See [`Expr::Const`]
+    Const,
+    /// See [`Expr::X`]
+    X,
+    /// See [`Expr::Var`]
+    Var,
+    /// See [`Expr::I`]
+    I,
+    /// See [`Expr::E`]
+    E,
+    /// See [`Expr::Scaled`]
+    Scaled,
+    /// See [`Expr::Sum`]
+    Sum,
+    /// See [`Expr::Prod`]
+    Prod,
+    /// See [`Expr::Neg`]
+    Neg,
+    /// See [`Expr::Pow`]
+    Pow,
+    /// See [`Expr::Ln`]
+    Ln,
+    /// See [`Expr::Sin`]
+    Sin,
+    /// See [`Expr::Cos`]
+    Cos,
+    /// See [`Expr::Arcsin`]
+    Arcsin,
+    /// See [`Expr::Arccos`]
+    Arccos,
+    /// See [`Expr::Arctan`]
+    Arctan,
+    /// See [`Expr::Sinh`]
+    Sinh,
+    /// See [`Expr::Cosh`]
+    Cosh,
+    /// See [`Expr::Tanh`]
+    Tanh,
+    /// See [`Expr::Arsinh`]
+    Arsinh,
+    /// See [`Expr::Arcosh`]
+    Arcosh,
+    /// See [`Expr::Artanh`]
+    Artanh,
+    /// See [`Expr::Factorial`]
+    Factorial,
+    /// See [`Expr::SumOver`]
+    SumOver,
+    /// See [`Expr::Piecewise`]
+    Piecewise,
+    /// See [`Expr::Hole`]. This is last so that adding it didn't change the hashes of the
+    /// other kinds (see [`Expr::canonical_hash`]).
+    Hole,
+    /// See [`Expr::Deriv`]
+    Deriv,
+}
+
+impl ExprKind {
+    /// Every kind, in the order they are declared
+    pub const ALL: [ExprKind; 27] = [
+        ExprKind::Const,
+        ExprKind::X,
+        ExprKind::Var,
+        ExprKind::I,
+        ExprKind::E,
+        ExprKind::Scaled,
+        ExprKind::Sum,
+        ExprKind::Prod,
+        ExprKind::Neg,
+        ExprKind::Pow,
+        ExprKind::Ln,
+        ExprKind::Sin,
+        ExprKind::Cos,
+        ExprKind::Arcsin,
+        ExprKind::Arccos,
+        ExprKind::Arctan,
+        ExprKind::Sinh,
+        ExprKind::Cosh,
+        ExprKind::Tanh,
+        ExprKind::Arsinh,
+        ExprKind::Arcosh,
+        ExprKind::Artanh,
+        ExprKind::Factorial,
+        ExprKind::SumOver,
+        ExprKind::Piecewise,
+        ExprKind::Hole,
+        ExprKind::Deriv,
+    ];
+
+    /// Whether this kind is a trig function (e.g. sin, cos)
+    pub fn is_trig(self) -> bool {
+        matches!(self, ExprKind::Sin | ExprKind::Cos)
+    }
+
+    /// Whether this kind is an inverse trig function (e.g. arcsin, arctan)
+    pub fn is_inverse_trig(self) -> bool {
+        matches!(self, ExprKind::Arcsin | ExprKind::Arccos | ExprKind::Arctan)
+    }
+
+    /// Whether this kind is a hyperbolic function (e.g. sinh, tanh)
+    pub fn is_hyperbolic(self) -> bool {
+        matches!(self, ExprKind::Sinh | ExprKind::Cosh | ExprKind::Tanh)
+    }
+
+    /// Whether this kind is an inverse hyperbolic function (e.g. arsinh, artanh)
+    pub fn is_inverse_hyperbolic(self) -> bool {
+        matches!(self, ExprKind::Arsinh | ExprKind::Arcosh | ExprKind::Artanh)
+    }
+
+    /// Whether this kind is a function applied to a single expression (e.g. ln, sin, arccos).
+    /// Neg is not counted as a function.
+    pub fn is_unary_function(self) -> bool {
+        matches!(self, ExprKind::Ln | ExprKind::Factorial)
+            || self.is_trig()
+            || self.is_inverse_trig()
+            || self.is_hyperbolic()
+            || self.is_inverse_hyperbolic()
+    }
+
+    /// Whether this kind has no children.
+    pub fn is_atom(self) -> bool {
+        self.arity() == Some(0)
+    }
+
+    /// The number of children an expression of this kind has, or `None` if it can have any
+    /// number of children (sums, products and piecewise functions).
+    pub fn arity(self) -> Option<usize> {
+        match self {
+            ExprKind::Const
+            | ExprKind::X
+            | ExprKind::Var
+            | ExprKind::I
+            | ExprKind::E
+            | ExprKind::Scaled
+            | ExprKind::Hole
+            | ExprKind::Deriv => Some(0),
+            ExprKind::Sum | ExprKind::Prod | ExprKind::Piecewise => None,
+            ExprKind::Pow => Some(2),
+            ExprKind::SumOver => Some(3),
+            _ => Some(1),
+        }
+    }
+
+    /// Whether an expression of this kind needs brackets when it is written as a factor of a
+    /// product, e.g. `x(5)` rather than `x5`.
+    pub fn needs_parens_in_prod(self) -> bool {
+        matches!(
+            self,
+            ExprKind::Sum | ExprKind::Const | ExprKind::Scaled | ExprKind::Neg | ExprKind::SumOver
+        )
+    }
+
+    /// Whether an expression of this kind needs brackets when it is the base of a power, e.g.
+    /// `(x+1)^{2}` rather than `x+1^{2}`.
+    pub fn needs_parens_in_pow_base(self) -> bool {
+        matches!(
+            self,
+            ExprKind::Sum
+                | ExprKind::Prod
+                | ExprKind::Scaled
+                | ExprKind::Neg
+                | ExprKind::SumOver
+                | ExprKind::Piecewise
+        )
+    }
+}
+
+impl Expr {
+    /// Get the kind of the expression.
+    pub fn kind(&self) -> ExprKind {
+        match self {
+            Expr::Const(_) => ExprKind::Const,
+            Expr::X => ExprKind::X,
+            Expr::Var(_) => ExprKind::Var,
+            Expr::I => ExprKind::I,
+            Expr::E => ExprKind::E,
+            Expr::Scaled(..) => ExprKind::Scaled,
+            Expr::Hole(_) => ExprKind::Hole,
+            Expr::Deriv(..) => ExprKind::Deriv,
+            Expr::Sum(_) => ExprKind::Sum,
+            Expr::Prod(_) => ExprKind::Prod,
+            Expr::Neg(_) => ExprKind::Neg,
+            Expr::Pow(_, _) => ExprKind::Pow,
+            Expr::Ln(_) => ExprKind::Ln,
+            Expr::Sin(_) => ExprKind::Sin,
+            Expr::Cos(_) => ExprKind::Cos,
+            Expr::Arcsin(_) => ExprKind::Arcsin,
+            Expr::Arccos(_) => ExprKind::Arccos,
+            Expr::Arctan(_) => ExprKind::Arctan,
+            Expr::Sinh(_) => ExprKind::Sinh,
+            Expr::Cosh(_) => ExprKind::Cosh,
+            Expr::Tanh(_) => ExprKind::Tanh,
+            Expr::Arsinh(_) => ExprKind::Arsinh,
+            Expr::Arcosh(_) => ExprKind::Arcosh,
+            Expr::Artanh(_) => ExprKind::Artanh,
+            Expr::Factorial(_) => ExprKind::Factorial,
+            Expr::SumOver(..) => ExprKind::SumOver,
+            Expr::Piecewise(_) => ExprKind::Piecewise,
+        }
+    }
+
+    /// Whether the expression is a trig function. See [`ExprKind::is_trig`]
+    pub fn is_trig(&self) -> bool {
+        self.kind().is_trig()
+    }
+
+    /// Whether the expression is an inverse trig function. See [`ExprKind::is_inverse_trig`]
+    pub fn is_inverse_trig(&self) -> bool {
+        self.kind().is_inverse_trig()
+    }
+
+    /// Whether the expression is a hyperbolic function. See [`ExprKind::is_hyperbolic`]
+    pub fn is_hyperbolic(&self) -> bool {
+        self.kind().is_hyperbolic()
+    }
+
+    /// Whether the expression is an inverse hyperbolic function. See
+    /// [`ExprKind::is_inverse_hyperbolic`]
+    pub fn is_inverse_hyperbolic(&self) -> bool {
+        self.kind().is_inverse_hyperbolic()
+    }
+
+    /// Whether the expression is a unary function. See [`ExprKind::is_unary_function`]
+    pub fn is_unary_function(&self) -> bool {
+        self.kind().is_unary_function()
+    }
+
+    /// The number of children the expression has. Unlike [`ExprKind::arity`] this is always
+    /// known, since sums, products and piecewise functions can just be counted.
+    pub fn arity(&self) -> usize {
+        match self {
+            Expr::Sum(v) | Expr::Prod(v) => v.len(),
+            Expr::Piecewise(_) => self.children().len(),
+            _ => self.kind().arity().unwrap_or(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Condition, HoleId, VarId};
+    use super::*;
+
+    fn all() -> Vec<(Expr, ExprKind)> {
+        let x = || Box::new(Expr::X);
+        vec![
+            (Expr::Const(3), ExprKind::Const),
+            (Expr::X, ExprKind::X),
+            (Expr::Var(VarId('y')), ExprKind::Var),
+            (Expr::I, ExprKind::I),
+            (Expr::E, ExprKind::E),
+            (Expr::Scaled(6, 23), ExprKind::Scaled),
+            (Expr::Hole(HoleId(2)), ExprKind::Hole),
+            (Expr::Deriv(VarId('y'), VarId('x')), ExprKind::Deriv),
+            (Expr::Sum(vec![Expr::X, Expr::X]), ExprKind::Sum),
+            (Expr::Prod(vec![Expr::X]), ExprKind::Prod),
+            (Expr::Neg(x()), ExprKind::Neg),
+            (Expr::Pow(x(), x()), ExprKind::Pow),
+            (Expr::Ln(x()), ExprKind::Ln),
+            (Expr::Sin(x()), ExprKind::Sin),
+            (Expr::Cos(x()), ExprKind::Cos),
+            (Expr::Arcsin(x()), ExprKind::Arcsin),
+            (Expr::Arccos(x()), ExprKind::Arccos),
+            (Expr::Arctan(x()), ExprKind::Arctan),
+            (Expr::Sinh(x()), ExprKind::Sinh),
+            (Expr::Cosh(x()), ExprKind::Cosh),
+            (Expr::Tanh(x()), ExprKind::Tanh),
+            (Expr::Arsinh(x()), ExprKind::Arsinh),
+            (Expr::Arcosh(x()), ExprKind::Arcosh),
+            (Expr::Artanh(x()), ExprKind::Artanh),
+            (Expr::Factorial(x()), ExprKind::Factorial),
+            (Expr::SumOver(VarId('k'), x(), x(), x()), ExprKind::SumOver),
+            (
+                Expr::Piecewise(vec![
+                    (Condition::Less(Expr::X, Expr::Const(0)), Expr::X),
+                    (Condition::Otherwise, Expr::X),
+                ]),
+                ExprKind::Piecewise,
+            ),
+        ]
+    }
+
+    #[test]
+    fn kinds() {
+        for (e, kind) in all() {
+            assert_eq!(e.kind(), kind);
+        }
+        let kinds: Vec<ExprKind> = all().into_iter().map(|(_, kind)| kind).collect();
+        assert_eq!(kinds.len(), ExprKind::ALL.len());
+        assert!(ExprKind::ALL.iter().all(|kind| kinds.contains(kind)));
+    }
+
+    /// This match fails to compile when a kind is added, as a reminder to add it to
+    /// [`ExprKind::ALL`]
+    #[test]
+    fn all_kinds() {
+        for (i, kind) in ExprKind::ALL.into_iter().enumerate() {
+            assert_eq!(kind as usize, i);
+            match kind {
+                ExprKind::Const
+                | ExprKind::X
+                | ExprKind::Var
+                | ExprKind::I
+                | ExprKind::E
+                | ExprKind::Scaled
+                | ExprKind::Sum
+                | ExprKind::Prod
+                | ExprKind::Neg
+                | ExprKind::Pow
+                | ExprKind::Ln
+                | ExprKind::Sin
+                | ExprKind::Cos
+                | ExprKind::Arcsin
+                | ExprKind::Arccos
+                | ExprKind::Arctan
+                | ExprKind::Sinh
+                | ExprKind::Cosh
+                | ExprKind::Tanh
+                | ExprKind::Arsinh
+                | ExprKind::Arcosh
+                | ExprKind::Artanh
+                | ExprKind::Factorial
+                | ExprKind::SumOver
+                | ExprKind::Piecewise
+                | ExprKind::Hole
+                | ExprKind::Deriv => (),
+            }
+        }
+    }
+
+    #[test]
+    fn groups() {
+        for (e, kind) in all() {
+            let trig = matches!(kind, ExprKind::Sin | ExprKind::Cos);
+            let inverse_trig =
+                matches!(kind, ExprKind::Arcsin | ExprKind::Arccos | ExprKind::Arctan);
+            let hyperbolic = matches!(kind, ExprKind::Sinh | ExprKind::Cosh | ExprKind::Tanh);
+            let inverse_hyperbolic =
+                matches!(kind, ExprKind::Arsinh | ExprKind::Arcosh | ExprKind::Artanh);
+            assert_eq!(e.is_trig(), trig);
+            assert_eq!(e.is_inverse_trig(), inverse_trig);
+            assert_eq!(e.is_hyperbolic(), hyperbolic);
+            assert_eq!(e.is_inverse_hyperbolic(), inverse_hyperbolic);
+            assert_eq!(
+                e.is_unary_function(),
+                trig || inverse_trig
+                    || hyperbolic
+                    || inverse_hyperbolic
+                    || matches!(kind, ExprKind::Ln | ExprKind::Factorial)
+            );
+        }
+    }
+
+    #[test]
+    fn arity() {
+        for (e, kind) in all() {
+            let expected = match kind {
+                ExprKind::Const
+                | ExprKind::X
+                | ExprKind::Var
+                | ExprKind::I
+                | ExprKind::E
+                | ExprKind::Scaled
+                | ExprKind::Hole
+                | ExprKind::Deriv => 0,
+                ExprKind::Sum => 2,
+                ExprKind::Prod => 1,
+                ExprKind::Pow => 2,
+                ExprKind::SumOver => 3,
+                ExprKind::Piecewise => 4,
+                _ => 1,
+            };
+            assert_eq!(e.arity(), expected);
+            if let Some(n) = kind.arity() {
+                assert_eq!(n, expected);
+            }
+        }
+        assert_eq!(ExprKind::Sum.arity(), None);
+        assert_eq!(ExprKind::Prod.arity(), None);
+        assert_eq!(ExprKind::Piecewise.arity(), None);
+    }
+}
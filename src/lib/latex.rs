@@ -1,89 +1,728 @@
-use super::Expr;
+//! Writing expressions, and statements and worked solutions about them, as latex.
+
+use std::collections::BTreeMap;
+
+use super::{Condition, DerivativeError, Expr, ExprKind, Num, Rule, VarId};
+
+/// Settings for how an expression is written by [`Expr::to_latex_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatexOptions {
+    /// Start a new line (with `\\`) between two terms of a sum when the line would otherwise be
+    /// longer than this many characters. Only the outermost sum is split, so the lines should be
+    /// put in something like an `align` environment. By default expressions are written on one
+    /// line.
+    pub line_width: Option<usize>,
+    /// Start a new line between two terms of the outermost sum after this many terms, like
+    /// [`LatexOptions::line_width`]. A line is started when either of them is reached.
+    pub max_terms_per_line: Option<usize>,
+    /// Whether the expression is written after the `&` of an alignment environment (like
+    /// `align`), so that a new line has to start with `&` to line up. New lines start with
+    /// `&\quad` then, so that they are indented past the `=`.
+    pub aligned: bool,
+    /// How fractions and brackets are written
+    pub style: LatexStyle,
+    /// With [`LatexStyle::Inline`], fractions which are inside this many fractions or exponents
+    /// are written as `a/b` instead of with `\tfrac`, so that they stay readable. By default
+    /// only the outermost fractions use `\tfrac`.
+    pub inline_fraction_depth: usize,
+    /// Names to write variables as instead of their letters, e.g. `VarId('a')` as `x_1`. The
+    /// names are text, so they are escaped with [`latex_escape`] (see [`display_name`]).
+    pub var_names: BTreeMap<VarId, String>,
+    /// How the digits of big numbers are split up to make them easier to read
+    pub digit_grouping: DigitGrouping,
+}
+
+impl Default for LatexOptions {
+    fn default() -> Self {
+        LatexOptions {
+            line_width: None,
+            max_terms_per_line: None,
+            aligned: false,
+            style: LatexStyle::default(),
+            inline_fraction_depth: 1,
+            var_names: BTreeMap::new(),
+            digit_grouping: DigitGrouping::default(),
+        }
+    }
+}
+
+/// How [`Expr::to_latex_with`] writes fractions and brackets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LatexStyle {
+    /// Write the expression as it is stored, so division is a power of -1 (e.g. `x(x+1)^{-1}`)
+    #[default]
+    Plain,
+    /// For text, where an expression should be about as tall as a line: negative powers are
+    /// written as `\tfrac{a}{b}`, or as `a/b` when they are nested (see
+    /// [`LatexOptions::inline_fraction_depth`])
+    Inline,
+    /// For an expression on its own line: negative powers are written as `\frac{a}{b}`, and
+    /// brackets around fractions are sized with `\left( \right)`
+    Display,
+}
+
+/// How [`Expr::to_latex_with`] splits up the digits of numbers, in groups of three from the
+/// right. Numbers written with separators can be read back with
+/// [`super::ParseOptions::digit_groups`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigitGrouping {
+    /// Write all the digits together, e.g. `1234567`
+    #[default]
+    None,
+    /// Put a thin space between the groups, e.g. `1\,234\,567`
+    ThinSpace,
+    /// Put a comma between the groups, e.g. `1{,}234{,}567`. The comma is in braces so that it
+    /// isn't followed by a space.
+    Comma,
+}
+
+/// Where a subexpression is being written
+#[derive(Clone, Copy)]
+struct Context<'a> {
+    options: &'a LatexOptions,
+    /// Whether this is the whole expression, rather than part of another one or inside
+    /// `\left( \right)`, and so can be split over lines
+    outermost: bool,
+    /// How many fractions and exponents this is inside of
+    depth: usize,
+}
+
+impl<'a> Context<'a> {
+    /// The context for the children of an expression
+    fn inner(self) -> Self {
+        Context {
+            outermost: false,
+            ..self
+        }
+    }
+
+    /// The context for the parts of a fraction or an exponent, which are written smaller
+    fn nested(self) -> Self {
+        Context {
+            outermost: false,
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+
+    /// Put brackets around some latex, which are sized to fit it in the display style if it has
+    /// a fraction in it
+    fn parens(self, latex: &str) -> String {
+        if self.options.style == LatexStyle::Display && latex.contains("\\frac") {
+            format!("\\left({latex}\\right)")
+        } else {
+            format!("({latex})")
+        }
+    }
+
+    /// Write a number, with its digits grouped as the options say
+    fn number(self, n: Num) -> String {
+        let separator = match self.options.digit_grouping {
+            DigitGrouping::None => return n.to_string(),
+            DigitGrouping::ThinSpace => "\\,",
+            DigitGrouping::Comma => "{,}",
+        };
+        let digits = n.unsigned_abs().to_string();
+        let mut grouped = if n < 0 { "-" } else { "" }.to_string();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped += separator;
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// Write a variable, with its name from the options if it has one
+    fn var(self, v: VarId) -> String {
+        match self.options.var_names.get(&v) {
+            Some(name) => display_name(name),
+            None => display_name(&v.0.to_string()),
+        }
+    }
+}
+
+/// Write that the derivative of `original` (`order` times) is `derivative`, e.g.
+/// `\frac{d}{dx}\left(x^{2}\right) = 2x`, or `\frac{d^{2}}{dx^{2}}\left(x^{3}\right) = 6x` for a
+/// second derivative.
+///
+/// # Panics
+/// Panics if the order is 0.
+pub fn derivative_statement(original: &Expr, derivative: &Expr, order: usize) -> String {
+    derivative_statement_with(original, derivative, order, &LatexOptions::default())
+}
+
+/// Write that the derivative of `original` is `derivative` like [`derivative_statement`], with
+/// some settings for how the expressions are written. The derivative can be split over lines,
+/// but `original` is always on one line, since it is inside `\left( \right)`.
+///
+/// # Panics
+/// Panics if the order is 0.
+pub fn derivative_statement_with(
+    original: &Expr,
+    derivative: &Expr,
+    order: usize,
+    options: &LatexOptions,
+) -> String {
+    format!(
+        "{} = {}",
+        d_dx(original, order, options),
+        derivative.to_latex_with(options)
+    )
+}
+
+/// Write the definition of a function of x, e.g. `f(x) = x^{2}`. The name is escaped like the
+/// name of a variable (see [`display_name`]).
+pub fn function_definition(name: &str, body: &Expr) -> String {
+    function_definition_with(name, body, &LatexOptions::default())
+}
+
+/// Write the definition of a function of x like [`function_definition`], with some settings for
+/// how the body is written.
+pub fn function_definition_with(name: &str, body: &Expr, options: &LatexOptions) -> String {
+    format!(
+        "{}(x) = {}",
+        display_name(name),
+        body.to_latex_with(options)
+    )
+}
+
+/// The derivative (`order` times) of an expression, which isn't worked out, e.g.
+/// `\frac{d}{dx}\left(x^{2}\right)`
+fn d_dx(e: &Expr, order: usize, options: &LatexOptions) -> String {
+    let operator = match order {
+        0 => panic!("a derivative should have an order of at least 1"),
+        1 => "\\frac{d}{dx}".to_string(),
+        n => format!("\\frac{{d^{{{n}}}}}{{dx^{{{n}}}}}"),
+    };
+    let context = Context {
+        options,
+        outermost: false,
+        depth: 0,
+    };
+    format!("{operator}\\left({}\\right)", e.latex(context))
+}
+
+/// A worked solution written as a chain of equations, which is turned into a latex `align`
+/// block with [`Derivation::to_latex`]. Each step is an expression equal to the one before it, or
+/// a new equation, and can have a note saying why it is true.
+///
+/// A step which is the same as the one before it is left out.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Derivation {
+    /// How the expressions are written, see [`Derivation::with_options`]
+    options: LatexOptions,
+    lines: Vec<Line>,
+    /// The start of a chain which doesn't have a line yet, because only one expression is in it
+    start: Option<(String, Option<String>)>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Line {
+    lhs: String,
+    rhs: String,
+    annotation: Option<String>,
+}
+
+impl Derivation {
+    /// Create a derivation with no steps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a derivation with no steps, whose expressions are written with some settings.
+    /// They are always written as [`LatexOptions::aligned`], so the right hand sides can be
+    /// split over lines (e.g. with [`LatexOptions::max_terms_per_line`]) which line up after
+    /// the `=`. The first expression in a chain and the left hand sides are kept on one line.
+    pub fn with_options(options: LatexOptions) -> Self {
+        Derivation {
+            options: LatexOptions {
+                aligned: true,
+                ..options
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Add an expression which is equal to the previous one.
+    pub fn push(&mut self, e: &Expr) -> &mut Self {
+        self.push_latex(self.chained(e), None)
+    }
+
+    /// Add an expression which is equal to the previous one, with a note saying why.
+    pub fn push_annotated(&mut self, e: &Expr, annotation: &str) -> &mut Self {
+        self.push_latex(self.chained(e), Some(annotation))
+    }
+
+    /// Add an equation, which starts a new chain of equal expressions.
+    pub fn push_equation(&mut self, lhs: &Expr, rhs: &Expr, annotation: Option<&str>) -> &mut Self {
+        let lhs = self.one_line(lhs);
+        self.push_latex_equation(lhs, rhs.to_latex_with(&self.options), annotation)
+    }
+
+    /// Write an expression which goes on the left of the `&=`, on one line
+    fn one_line(&self, e: &Expr) -> String {
+        e.to_latex_with(&LatexOptions {
+            line_width: None,
+            max_terms_per_line: None,
+            aligned: false,
+            ..self.options.clone()
+        })
+    }
+
+    /// Write an expression which is pushed onto a chain, which goes on the left of the `&=` if
+    /// it starts the derivation
+    fn chained(&self, e: &Expr) -> String {
+        match self.lines.is_empty() && self.start.is_none() {
+            true => self.one_line(e),
+            false => e.to_latex_with(&self.options),
+        }
+    }
+
+    fn push_latex(&mut self, e: String, annotation: Option<&str>) -> &mut Self {
+        let annotation = annotation.map(str::to_string);
+        let previous = self
+            .lines
+            .last()
+            .map(|line| &line.rhs)
+            .or(self.start.as_ref().map(|(e, _)| e));
+        if previous == Some(&e) {
+            return self;
+        }
+        match self.start.take() {
+            Some((lhs, _)) => self.lines.push(Line {
+                lhs,
+                rhs: e,
+                annotation,
+            }),
+            None if self.lines.is_empty() => self.start = Some((e, annotation)),
+            None => self.lines.push(Line {
+                lhs: String::new(),
+                rhs: e,
+                annotation,
+            }),
+        }
+        self
+    }
+
+    fn push_latex_equation(
+        &mut self,
+        lhs: String,
+        rhs: String,
+        annotation: Option<&str>,
+    ) -> &mut Self {
+        self.finish_start();
+        self.lines.push(Line {
+            lhs,
+            rhs,
+            annotation: annotation.map(str::to_string),
+        });
+        self
+    }
+
+    /// Write a chain with only one expression on its own
+    fn finish_start(&mut self) {
+        if let Some((lhs, annotation)) = self.start.take() {
+            self.lines.push(Line {
+                lhs,
+                rhs: String::new(),
+                annotation,
+            });
+        }
+    }
+
+    /// Write the derivation as a latex `align` block, with the `=` signs lined up and the notes
+    /// on the right.
+    pub fn to_latex(&self) -> String {
+        let mut derivation = self.clone();
+        derivation.finish_start();
+        let lines: Vec<String> = derivation
+            .lines
+            .iter()
+            .map(|line| {
+                let mut str = line.lhs.clone();
+                if !line.rhs.is_empty() {
+                    str += &format!(" &= {}", line.rhs);
+                }
+                if let Some(annotation) = &line.annotation {
+                    str += &format!(" && \\text{{{}}}", latex_escape(annotation));
+                }
+                str
+            })
+            .collect();
+        format!(
+            "\\begin{{align}}\n{}\n\\end{{align}}",
+            lines.join(" \\\\\n")
+        )
+    }
+}
+
+/// Escape the characters which mean something in latex (`# $ % & _ { } ~ ^ \`), so that text
+/// can be put in `\text{}`, e.g. `50% of x_1` is `50\% of x\_1`.
+pub fn latex_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped += "\\textbackslash{}",
+            '~' => escaped += "\\textasciitilde{}",
+            '^' => escaped += "\\textasciicircum{}",
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write the name of a variable or function. A single letter is written as it is, in italics
+/// like any other variable. Anything else is written upright as text, escaped with
+/// [`latex_escape`], e.g. `x_1` is `\text{x\_1}`.
+pub fn display_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => name.to_string(),
+        _ => format!("\\text{{{}}}", latex_escape(name)),
+    }
+}
+
+/// The command for the name of a function, e.g. `\sin`. The inverse hyperbolic functions don't
+/// have commands of their own, so they are written with `\operatorname`.
+fn function_command(kind: ExprKind) -> &'static str {
+    match kind {
+        ExprKind::Ln => "\\ln",
+        ExprKind::Sin => "\\sin",
+        ExprKind::Cos => "\\cos",
+        ExprKind::Arcsin => "\\arcsin",
+        ExprKind::Arccos => "\\arccos",
+        ExprKind::Arctan => "\\arctan",
+        ExprKind::Sinh => "\\sinh",
+        ExprKind::Cosh => "\\cosh",
+        ExprKind::Tanh => "\\tanh",
+        ExprKind::Arsinh => "\\operatorname{arsinh}",
+        ExprKind::Arcosh => "\\operatorname{arcosh}",
+        ExprKind::Artanh => "\\operatorname{artanh}",
+        _ => unreachable!("{kind:?} isn't a function"),
+    }
+}
+
+impl Rule {
+    /// The name of the rule, to say how a step was done.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::Constant => "constant",
+            Rule::Variable => "derivative of x",
+            Rule::Sum => "sum rule",
+            Rule::Negation => "negation",
+            Rule::ConstantMultiple => "constant multiple rule",
+            Rule::Product => "product rule",
+            Rule::Power => "power rule",
+            Rule::Root => "square root rule",
+            Rule::Exponential => "exponential rule",
+            Rule::Ln => "derivative of ln",
+            Rule::Sin => "derivative of sin",
+            Rule::Cos => "derivative of cos",
+            Rule::Arcsin => "derivative of arcsin",
+            Rule::Arccos => "derivative of arccos",
+            Rule::Arctan => "derivative of arctan",
+            Rule::Sinh => "derivative of sinh",
+            Rule::Cosh => "derivative of cosh",
+            Rule::Tanh => "derivative of tanh",
+            Rule::Arsinh => "derivative of arsinh",
+            Rule::Arcosh => "derivative of arcosh",
+            Rule::Artanh => "derivative of artanh",
+            Rule::Piecewise => "derivative of each piece",
+            Rule::Chain => "chain rule",
+        }
+    }
+}
+
+/// Write the factors of a product inside another product. They are only nested when something
+/// groups them on purpose, like the coefficients of [`Expr::normal_form`], so they go in brackets,
+/// where a positive whole number in front is written like `(2y)`.
+fn coefficient(v: &[Expr], context: Context) -> String {
+    if let [Expr::Const(n), rest @ ..] = v {
+        let rest = match rest {
+            [e] => e.latex(context),
+            _ => Expr::Prod(rest.to_vec()).latex(context),
+        };
+        // Unless it would run into a number after it
+        if *n > 0 && rest.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return context.number(*n) + &rest;
+        }
+    }
+    Expr::Prod(v.to_vec()).latex(context)
+}
 
 impl Expr {
     /// Write an expression as a latex math equation.
-    // TODO negative indecies as fractions
-    // basically just redo this whole function
     pub fn to_latex(&self) -> String {
+        self.to_latex_with(&LatexOptions::default())
+    }
+
+    /// Write an expression as a latex math equation, with some settings for how it is written.
+    pub fn to_latex_with(&self, options: &LatexOptions) -> String {
+        self.latex(Context {
+            options,
+            outermost: true,
+            depth: 0,
+        })
+    }
+
+    fn latex(&self, context: Context) -> String {
+        let inner = context.inner();
+        if context.options.style != LatexStyle::Plain {
+            if let Some((numerator, denominator)) = self.as_fraction() {
+                return fraction(&numerator, &denominator, context);
+            }
+        }
         match self {
-            Expr::Const(n) => n.to_string(),
+            Expr::Const(n) => context.number(*n),
             Expr::X => "x".to_string(),
-            Expr::Neg(e) => format!("-({})", e.to_latex()),
-            // Expr::Recip(e) => format!("\\frac{{1}}{{{}}}", e.to_latex()),
+            Expr::Var(v) => context.var(*v),
+            Expr::I => "i".to_string(),
+            Expr::E => "e".to_string(),
+            Expr::Hole(id) => format!("\\square_{{{}}}", id.0),
+            Expr::Deriv(v, t) => {
+                let name = |v: VarId| match v {
+                    VarId('x') => "x".to_string(),
+                    v => context.var(v),
+                };
+                format!("\\frac{{d{}}}{{d{}}}", name(*v), name(*t))
+            }
+            Expr::Scaled(1, e) => format!("10^{{{e}}}"),
+            Expr::Scaled(m, e) => format!("{}\\times 10^{{{e}}}", context.number(*m)),
+            Expr::Neg(e) => format!("-{}", context.parens(&e.latex(inner))),
+            // Expr::Recip(e) => format!("\\frac{{1}}{{{}}}", e.latex(inner)),
             Expr::Sum(v) => {
-                let mut str = v[0].to_latex();
+                let mut str = v[0].latex(inner);
+                // Where the line being written starts and how many terms are on it, to know
+                // when to start a new one
+                let (mut line_start, mut terms) = (0, 1);
+                let options = context.options;
                 for e in v.iter().skip(1) {
-                    if let Expr::Neg(e) = e {
-                        str += &format!("-{}", e.to_latex()).to_string();
-                    } else {
-                        str += &format!("+{}", e.to_latex()).to_string();
+                    // The sign is part of the term, so a new line never separates them
+                    let term = match e {
+                        Expr::Neg(e) => format!("-{}", e.latex(inner)),
+                        Expr::Const(n) if *n < 0 => context.number(*n),
+                        _ => format!("+{}", e.latex(inner)),
+                    };
+                    let too_wide = options
+                        .line_width
+                        .is_some_and(|width| str.len() - line_start + term.len() > width);
+                    let too_many = options.max_terms_per_line.is_some_and(|max| terms >= max);
+                    if context.outermost && (too_wide || too_many) {
+                        str += match options.aligned {
+                            true => " \\\\\n&\\quad ",
+                            false => " \\\\\n",
+                        };
+                        line_start = str.len();
+                        terms = 0;
                     }
+                    str += &term;
+                    terms += 1;
                 }
                 str
             }
             Expr::Prod(v) => {
                 let mut str = if v[0] == Expr::Const(1) {
                     "".to_string()
-                } else if matches!(v[0], Expr::Sum(_))
-                    || matches!(v[0], Expr::Const(_))
-                    || matches!(v[0], Expr::Neg(_))
-                {
-                    "(".to_owned() + &v[0].to_latex() + ")"
+                } else if v[0].kind().needs_parens_in_prod() {
+                    context.parens(&v[0].latex(inner))
+                } else if let Expr::Prod(w) = &v[0] {
+                    context.parens(&coefficient(w, inner))
                 } else {
-                    v[0].to_latex()
+                    v[0].latex(inner)
                 };
                 for e in v.iter().skip(1) {
-                    if matches!(e, Expr::Sum(_))
-                        || matches!(e, Expr::Const(_))
-                        || matches!(e, Expr::Neg(_))
-                    {
+                    if e.kind().needs_parens_in_prod() {
                         if let Expr::Const(e) = e {
                             if *e == 1 {
                                 continue;
                             }
                         }
-                        str += &format!("({})", e.to_latex()).to_string();
+                        str += &context.parens(&e.latex(inner));
+                    } else if let Expr::Prod(w) = e {
+                        str += &context.parens(&coefficient(w, inner));
                     } else {
-                        str += &e.to_latex();
+                        let factor = e.latex(inner);
+                        // `\pi x` would otherwise run together into the command `\pix`
+                        if str.ends_with("\\pi") && factor.starts_with(|c: char| c.is_alphabetic())
+                        {
+                            str.push(' ');
+                        }
+                        str += &factor;
                     }
                 }
                 str
             }
+            Expr::Pow(a, b) if b.root_index() == Some(2) => {
+                format!("\\sqrt{{{}}}", a.latex(inner))
+            }
+            Expr::Pow(a, b) if b.root_index().is_some() => {
+                format!("\\sqrt[{}]{{{}}}", b.root_index().unwrap(), a.latex(inner))
+            }
             Expr::Pow(a, b) => {
-                let a_str = if matches!(**a, Expr::Sum(_))
-                    || matches!(**a, Expr::Prod(_))
-                    || matches!(**a, Expr::Neg(_))
-                {
-                    format!("({})", &a.to_latex())
+                let a_str = if a.kind().needs_parens_in_pow_base() {
+                    context.parens(&a.latex(inner))
                 } else {
-                    a.to_latex()
+                    a.latex(inner)
                 };
-                format!("{}^{{{}}}", a_str, &b.to_latex())
+                format!("{}^{{{}}}", a_str, &b.latex(context.nested()))
             }
-            Expr::Ln(x) => {
-                format!("ln({})", &x.to_latex())
+            Expr::Arccos(x) if **x == Expr::Const(-1) => "\\pi".to_string(),
+            Expr::Ln(x)
+            | Expr::Sin(x)
+            | Expr::Cos(x)
+            | Expr::Arcsin(x)
+            | Expr::Arccos(x)
+            | Expr::Arctan(x)
+            | Expr::Sinh(x)
+            | Expr::Cosh(x)
+            | Expr::Tanh(x)
+            | Expr::Arsinh(x)
+            | Expr::Arcosh(x)
+            | Expr::Artanh(x) => {
+                let name = function_command(self.kind());
+                format!("{name}{}", context.parens(&x.latex(inner)))
             }
-            Expr::Sin(x) => {
-                format!("sin({})", &x.to_latex())
+            Expr::SumOver(k, from, to, x) => {
+                let x_str = match x.kind().needs_parens_in_prod() {
+                    true => context.parens(&x.latex(inner)),
+                    false => x.latex(inner),
+                };
+                format!(
+                    "\\sum_{{{}={}}}^{{{}}}{}",
+                    context.var(*k),
+                    from.latex(inner),
+                    to.latex(inner),
+                    x_str
+                )
             }
-            Expr::Cos(x) => {
-                format!("cos({})", &x.to_latex())
+            Expr::Factorial(x) => {
+                if x.kind().is_atom() {
+                    format!("{}!", &x.latex(inner))
+                } else {
+                    format!("{}!", context.parens(&x.latex(inner)))
+                }
             }
-            Expr::Arcsin(x) => {
-                format!("arcsin({})", &x.to_latex())
+            Expr::Piecewise(v) => {
+                let rows: Vec<String> = v
+                    .iter()
+                    .map(|(condition, e)| {
+                        let condition = match condition {
+                            Condition::Less(a, b) => {
+                                format!("{}<{}", a.latex(inner), b.latex(inner))
+                            }
+                            Condition::LessEq(a, b) => {
+                                format!("{}\\le {}", a.latex(inner), b.latex(inner))
+                            }
+                            Condition::Equal(a, b) => {
+                                format!("{}={}", a.latex(inner), b.latex(inner))
+                            }
+                            Condition::Otherwise => "\\text{otherwise}".to_string(),
+                        };
+                        format!("{} & {}", e.latex(inner), condition)
+                    })
+                    .collect();
+                format!("\\begin{{cases}}{}\\end{{cases}}", rows.join(" \\\\ "))
             }
-            Expr::Arccos(x) => {
-                format!("arccos({})", &x.to_latex())
+        }
+    }
+}
+
+impl Expr {
+    /// Split a product (or a single power) into the factors which aren't negative powers, and
+    /// the factors which are, as positive powers. Gives `None` if nothing is a negative power.
+    pub(super) fn as_fraction(&self) -> Option<(Expr, Expr)> {
+        let factors = match self {
+            Expr::Prod(v) => v.as_slice(),
+            Expr::Pow(..) => std::slice::from_ref(self),
+            _ => return None,
+        };
+        let mut numerator = vec![];
+        let mut denominator = vec![];
+        for factor in factors {
+            match factor {
+                Expr::Pow(a, b) => match **b {
+                    Expr::Const(-1) => denominator.push((**a).clone()),
+                    Expr::Const(n) if n < 0 && n != Num::MIN => {
+                        denominator.push(Expr::Pow(a.clone(), Box::new(Expr::Const(-n))))
+                    }
+                    _ => numerator.push(factor.clone()),
+                },
+                _ => numerator.push(factor.clone()),
             }
-            Expr::Arctan(x) => {
-                format!("arctan({})", &x.to_latex())
+        }
+        if denominator.is_empty() {
+            return None;
+        }
+        let join = |mut v: Vec<Expr>| match v.len() {
+            0 => Expr::Const(1),
+            1 => v.pop().unwrap(),
+            _ => Expr::Prod(v),
+        };
+        Some((join(numerator), join(denominator)))
+    }
+}
+
+/// Write a fraction in the style from the options, as `\frac{a}{b}`, `\tfrac{a}{b}` or `a/b`
+fn fraction(numerator: &Expr, denominator: &Expr, context: Context) -> String {
+    let nested = context.nested();
+    let (n, d) = (numerator.latex(nested), denominator.latex(nested));
+    match context.options.style {
+        LatexStyle::Inline if context.depth >= context.options.inline_fraction_depth => {
+            let n = match numerator.kind() {
+                ExprKind::Sum | ExprKind::Neg => nested.parens(&n),
+                _ => n,
+            };
+            let d = match denominator.kind() {
+                ExprKind::Sum | ExprKind::Neg | ExprKind::Prod => nested.parens(&d),
+                _ => d,
+            };
+            format!("{n}/{d}")
+        }
+        LatexStyle::Inline => format!("\\tfrac{{{n}}}{{{d}}}"),
+        LatexStyle::Plain | LatexStyle::Display => format!("\\frac{{{n}}}{{{d}}}"),
+    }
+}
+
+impl Expr {
+    /// Write out the working for the derivative of an expression (see
+    /// [`Expr::derivative_with_steps`]) as a [`Derivation`]. Each rule that was used gets a line,
+    /// and the last lines simplify the derivative.
+    pub fn to_latex_derivation(&self) -> Result<Derivation, DerivativeError> {
+        let options = LatexOptions::default();
+        let (derivative, steps) = self.clone().derivative_with_steps()?;
+        let mut derivation = Derivation::new();
+        for step in &steps {
+            if matches!(step.rule, Rule::Constant | Rule::Variable) {
+                continue;
             }
+            let annotation = match step.chain {
+                true => format!("{}, {}", step.rule.name(), Rule::Chain.name()),
+                false => step.rule.name().to_string(),
+            };
+            derivation.push_latex_equation(
+                d_dx(&step.expr, 1, &options),
+                step.derivative.to_latex(),
+                Some(&annotation),
+            );
         }
+        let mut simplified = derivative.clone();
+        simplified.simplify();
+        derivation.push_latex_equation(d_dx(self, 1, &options), derivative.to_latex(), None);
+        derivation.push_annotated(&simplified, "simplify");
+        Ok(derivation)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::prelude::{
+        arsinh, c, cos, e as exp_e, ln, pow, recip, sin, sum_over, tanh, var, x,
+    };
     use super::*;
     #[test]
     fn latex() {
@@ -94,4 +733,261 @@ mod tests {
         println!("{:?}", e);
         assert_eq!(e.to_latex(), "(x+x(5))x^{-1}");
     }
+
+    #[test]
+    fn coefficients() {
+        // Nested products are grouped, with a bare number in front
+        let e = Expr::Prod(vec![Expr::Prod(vec![c(2), var('y')]), x()]);
+        assert_eq!(e.to_latex(), "(2y)x");
+        let e = Expr::Prod(vec![x(), Expr::Prod(vec![c(2), var('y'), x()])]);
+        assert_eq!(e.to_latex(), "x(2yx)");
+        // Numbers next to each other are kept apart
+        let e = Expr::Prod(vec![Expr::Prod(vec![c(2), pow(c(3), x())]), x()]);
+        assert_eq!(e.to_latex(), "((2)3^{x})x");
+        let e = Expr::Prod(vec![Expr::Prod(vec![c(-2), var('y')]), x()]);
+        assert_eq!(e.to_latex(), "((-2)y)x");
+        // Products which aren't nested are written as before
+        assert_eq!(Expr::Prod(vec![c(2), x()]).to_latex(), "(2)x");
+    }
+
+    #[test]
+    fn derivation() {
+        let mut derivation = Derivation::new();
+        derivation
+            .push(&(Expr::X * 2 + Expr::X))
+            .push_annotated(&(Expr::X * 2 + Expr::X), "nothing happens")
+            .push_annotated(&(Expr::X * 3), "collect $x$ & simplify")
+            .push(&(Expr::X * 3))
+            .push_equation(&Expr::X, &Expr::Const(1), None);
+        assert_eq!(
+            derivation.to_latex(),
+            "\\begin{align}\n\
+             x(2)+x &= x(3) && \\text{collect \\$x\\$ \\& simplify} \\\\\n\
+             x &= 1\n\
+             \\end{align}"
+        );
+
+        let mut derivation = Derivation::new();
+        derivation.push_annotated(&Expr::X, "alone");
+        assert_eq!(
+            derivation.to_latex(),
+            "\\begin{align}\nx && \\text{alone}\n\\end{align}"
+        );
+    }
+
+    #[test]
+    fn derivative_derivation() {
+        let e = Expr::X.pow(Expr::Const(2)) * Expr::X.sin();
+        let latex = e.to_latex_derivation().unwrap().to_latex();
+        assert!(latex.contains("\\frac{d}{dx}\\left(x^{2}\\sin(x)\\right) &= "));
+        assert!(latex.contains("\\text{power rule}"));
+        assert!(latex.contains("\\text{product rule}"));
+        assert!(latex.contains("\\text{derivative of sin}"));
+        assert!(latex.ends_with("\\text{simplify}\n\\end{align}"));
+    }
+
+    #[test]
+    fn statements() {
+        let e = pow(x(), c(2)) * sin(x());
+        let derivative = x() * c(2) * sin(x()) + pow(x(), c(2)) * cos(x());
+        assert_eq!(
+            derivative_statement(&e, &derivative, 1),
+            "\\frac{d}{dx}\\left(x^{2}\\sin(x)\\right) = x(2)\\sin(x)+x^{2}\\cos(x)"
+        );
+        assert_eq!(
+            derivative_statement(&pow(x(), c(4)), &(x() * c(24)), 3),
+            "\\frac{d^{3}}{dx^{3}}\\left(x^{4}\\right) = x(24)"
+        );
+        let body = x() / (x() + 1);
+        assert_eq!(function_definition("f", &body), "f(x) = x(x+1)^{-1}");
+    }
+
+    #[test]
+    fn line_width() {
+        let e = pow(x(), c(3)) + pow(x(), c(2)) * c(3) - x() * c(4) + 1;
+        let options = LatexOptions {
+            line_width: Some(10),
+            ..LatexOptions::default()
+        };
+        assert_eq!(
+            e.to_latex_with(&options),
+            "x^{3} \\\\\n+x^{2}(3) \\\\\n-x(4)+1"
+        );
+        // The expression inside the brackets is never split
+        assert_eq!(
+            derivative_statement_with(&e, &Expr::Const(0), 1, &options),
+            "\\frac{d}{dx}\\left(x^{3}+x^{2}(3)-x(4)+1\\right) = 0"
+        );
+    }
+
+    #[test]
+    fn styles() {
+        // e^(1/(x+1)) / (x + 1/x)
+        let e = pow(exp_e(), recip(x() + 1)) * recip(x() + recip(x()));
+        let style = |style, inline_fraction_depth| LatexOptions {
+            style,
+            inline_fraction_depth,
+            ..LatexOptions::default()
+        };
+        // Fractions in the exponent and the denominator are nested, so they are written with a /
+        assert_eq!(
+            e.to_latex_with(&style(LatexStyle::Inline, 1)),
+            "\\tfrac{e^{1/(x+1)}}{x+1/x}"
+        );
+        assert_eq!(
+            e.to_latex_with(&style(LatexStyle::Inline, 2)),
+            "\\tfrac{e^{1/(x+1)}}{x+\\tfrac{1}{x}}"
+        );
+        assert_eq!(
+            e.to_latex_with(&style(LatexStyle::Display, 1)),
+            "\\frac{e^{\\frac{1}{x+1}}}{x+\\frac{1}{x}}"
+        );
+        // Brackets are only sized when there is a fraction in them
+        assert_eq!(
+            sin(recip(x()) * 2 + 1).to_latex_with(&style(LatexStyle::Display, 1)),
+            "\\sin\\left(\\frac{2}{x}+1\\right)"
+        );
+        assert_eq!(
+            sin(x() + 1).to_latex_with(&style(LatexStyle::Display, 1)),
+            "\\sin(x+1)"
+        );
+        assert_eq!(e.to_latex(), "e^{(x+1)^{-1}}(x+x^{-1})^{-1}");
+    }
+
+    #[test]
+    fn functions() {
+        let e = ln(x()) + sin(x()) * tanh(x() + 1) - arsinh(pow(x(), c(2)));
+        assert_eq!(
+            e.to_latex(),
+            "\\ln(x)+\\sin(x)\\tanh(x+1)-\\operatorname{arsinh}(x^{2})"
+        );
+        assert_eq!(Expr::from_latex(&e.to_latex()).unwrap(), e);
+    }
+
+    #[test]
+    fn pi() {
+        use super::super::prelude::pi;
+        assert_eq!(pi().to_latex(), "\\pi");
+        let e = sin(pi() * recip(c(6))) + pi() * x() + x() * pi() * x() + pow(pi(), c(2));
+        assert_eq!(e.to_latex(), "\\sin(\\pi6^{-1})+\\pi x+x\\pi x+\\pi^{2}");
+        assert_eq!(Expr::from_latex(&e.to_latex()).unwrap(), e);
+        // Other arccosines are written as they are
+        assert_eq!(cos(x()).to_latex(), "\\cos(x)");
+        assert_eq!(Expr::Arccos(Box::new(c(1))).to_latex(), "\\arccos(1)");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(
+            latex_escape("# $ % & _ { } ~ ^ \\"),
+            "\\# \\$ \\% \\& \\_ \\{ \\} \\textasciitilde{} \\textasciicircum{} \\textbackslash{}"
+        );
+        let options = LatexOptions {
+            var_names: [
+                (VarId('a'), "x_1".to_string()),
+                (VarId('n'), "n".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..LatexOptions::default()
+        };
+        let e = sum_over('k', c(1), var('n'), var('k') * var('a')) + var('y');
+        assert_eq!(e.to_latex_with(&options), "\\sum_{k=1}^{n}k\\text{x\\_1}+y");
+        assert_eq!(
+            function_definition("f_2", &var('a')),
+            "\\text{f\\_2}(x) = a"
+        );
+
+        let mut derivation = Derivation::new();
+        derivation
+            .push(&(Expr::X * 2))
+            .push_annotated(&(Expr::X + Expr::X), "50% of x_1 is {half}");
+        assert_eq!(
+            derivation.to_latex(),
+            "\\begin{align}\n\
+             x(2) &= x+x && \\text{50\\% of x\\_1 is \\{half\\}}\n\
+             \\end{align}"
+        );
+    }
+
+    #[test]
+    fn digit_grouping() {
+        let with = |digit_grouping| LatexOptions {
+            digit_grouping,
+            style: LatexStyle::Display,
+            ..LatexOptions::default()
+        };
+        let big = || c(1234567);
+        let cases = [
+            (DigitGrouping::None, "1234567"),
+            (DigitGrouping::ThinSpace, "1\\,234\\,567"),
+            (DigitGrouping::Comma, "1{,}234{,}567"),
+        ];
+        for (grouping, number) in cases {
+            let options = with(grouping);
+            assert_eq!(big().to_latex_with(&options), number);
+            assert_eq!((x() - big()).to_latex_with(&options), format!("x-{number}"));
+            assert_eq!(
+                (pow(x(), big()) / big()).to_latex_with(&options),
+                format!("\\frac{{x^{{{number}}}}}{{{number}}}")
+            );
+        }
+        // Only as many groups as are needed
+        let options = with(DigitGrouping::Comma);
+        assert_eq!(c(999).to_latex_with(&options), "999");
+        assert_eq!(c(-1000).to_latex_with(&options), "-1{,}000");
+        assert_eq!(c(100000).to_latex_with(&options), "100{,}000");
+    }
+
+    #[test]
+    fn terms_per_line() {
+        // x^9 + ... + x + 1, with every other term negative
+        let e = Expr::Sum(
+            (0..10)
+                .map(|n| {
+                    let term = pow(x(), c(9 - n));
+                    if n % 2 == 1 {
+                        -term
+                    } else {
+                        term
+                    }
+                })
+                .collect(),
+        );
+        let options = LatexOptions {
+            max_terms_per_line: Some(3),
+            ..LatexOptions::default()
+        };
+        assert_eq!(
+            e.to_latex_with(&options),
+            "x^{9}-x^{8}+x^{7} \\\\\n\
+             -x^{6}+x^{5}-x^{4} \\\\\n\
+             +x^{3}-x^{2}+x^{1} \\\\\n\
+             -x^{0}"
+        );
+        let aligned = LatexOptions {
+            aligned: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            e.to_latex_with(&aligned),
+            "x^{9}-x^{8}+x^{7} \\\\\n\
+             &\\quad -x^{6}+x^{5}-x^{4} \\\\\n\
+             &\\quad +x^{3}-x^{2}+x^{1} \\\\\n\
+             &\\quad -x^{0}"
+        );
+
+        // In a derivation, the first line has the left hand side and the `=`
+        let mut derivation = Derivation::with_options(options);
+        derivation.push_equation(&var('f'), &e, Some("expanded"));
+        assert_eq!(
+            derivation.to_latex(),
+            "\\begin{align}\n\
+             f &= x^{9}-x^{8}+x^{7} \\\\\n\
+             &\\quad -x^{6}+x^{5}-x^{4} \\\\\n\
+             &\\quad +x^{3}-x^{2}+x^{1} \\\\\n\
+             &\\quad -x^{0} && \\text{expanded}\n\
+             \\end{align}"
+        );
+    }
 }
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use super::substitute::fresh_var;
+use super::{DerivativeError, EvalError, Expr, VarId};
+
+impl Expr {
+    /// Find the partial derivative of the expression with respect to a variable, where x is
+    /// `VarId('x')`. The other variables (including x) are constants.
+    pub fn try_partial_derivative(&self, var: VarId) -> Result<Self, DerivativeError> {
+        if var == VarId('x') {
+            return self.clone().try_derivative();
+        }
+        // Swap the variable with x, differentiate, and swap them back
+        let mut avoid = self.free_vars();
+        avoid.insert(var);
+        let other = fresh_var(&avoid);
+        let swapped = self.substitute_all(&HashMap::from([
+            (VarId('x'), Expr::Var(other)),
+            (var, Expr::X),
+        ]));
+        Ok(swapped.try_derivative()?.substitute_all(&HashMap::from([
+            (VarId('x'), Expr::Var(var)),
+            (other, Expr::X),
+        ])))
+    }
+
+    /// The Hessian of the expression: the matrix of its second partial derivatives, where the
+    /// entry in row i and column j is the derivative with respect to `vars[i]` and then
+    /// `vars[j]` (x is `VarId('x')`).
+    ///
+    /// The matrix is symmetric, so only the entries on and above the diagonal are worked out,
+    /// and the others are copies of them. Each derivative is simplified before it is
+    /// differentiated again, which stops the entries from growing.
+    pub fn hessian(&self, vars: &[VarId]) -> Result<Vec<Vec<Expr>>, DerivativeError> {
+        let gradient: Vec<Expr> = vars
+            .iter()
+            .map(|var| Ok(self.try_partial_derivative(*var)?.simplified()))
+            .collect::<Result<_, DerivativeError>>()?;
+        let mut hessian = vec![vec![Expr::Const(0); vars.len()]; vars.len()];
+        for (i, derivative) in gradient.iter().enumerate() {
+            for j in i..vars.len() {
+                let entry = derivative.try_partial_derivative(vars[j])?.simplified();
+                hessian[j][i] = entry.clone();
+                hessian[i][j] = entry;
+            }
+        }
+        Ok(hessian)
+    }
+}
+
+/// The Jacobian of some expressions: the matrix whose entry in row i and column j is the
+/// partial derivative of `fs[i]` with respect to `vars[j]` (x is `VarId('x')`), simplified.
+pub fn jacobian(fs: &[Expr], vars: &[VarId]) -> Result<Vec<Vec<Expr>>, DerivativeError> {
+    fs.iter()
+        .map(|f| {
+            vars.iter()
+                .map(|var| Ok(f.try_partial_derivative(*var)?.simplified()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Evaluate each entry of a matrix (e.g. from [`jacobian`] or [`Expr::hessian`]) with the
+/// variables set to the given values, where x is `VarId('x')`. It is an error for an entry to
+/// use a variable without a value.
+pub fn eval_matrix(
+    matrix: &[Vec<Expr>],
+    bindings: &[(VarId, f64)],
+) -> Result<Vec<Vec<f64>>, EvalError> {
+    let x = bindings
+        .iter()
+        .find(|(var, _)| *var == VarId('x'))
+        .map(|(_, value)| *value);
+    let eval = |e: &Expr| {
+        let e = e.specialize(bindings);
+        match x {
+            Some(x) => e.eval(x),
+            None if e.uses_x() => Err(EvalError::UnknownVariable(VarId('x'))),
+            None => e.eval(0.0),
+        }
+    };
+    matrix
+        .iter()
+        .map(|row| row.iter().map(eval).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, pow, sin, var, x};
+    use super::*;
+
+    fn y() -> Expr {
+        var('y')
+    }
+
+    fn z() -> Expr {
+        var('z')
+    }
+
+    const XYZ: [VarId; 3] = [VarId('x'), VarId('y'), VarId('z')];
+
+    #[test]
+    fn partial_derivatives() {
+        let e = pow(x(), c(2)) * y() + sin(y() * z());
+        let at = |e: Expr| {
+            e.specialize(&[(VarId('y'), 2.0), (VarId('z'), 3.0)])
+                .eval(1.5)
+        };
+        let partial = |var| at(e.try_partial_derivative(var).unwrap());
+        assert_eq!(partial(VarId('x')), Ok(2.0 * 1.5 * 2.0));
+        assert_eq!(partial(VarId('y')), Ok(1.5 * 1.5 + 3.0 * 6f64.cos()));
+        assert_eq!(partial(VarId('z')), Ok(2.0 * 6f64.cos()));
+        assert_eq!(
+            e.try_partial_derivative(VarId('w')).unwrap().simplified(),
+            c(0)
+        );
+    }
+
+    #[test]
+    fn quadratic_form() {
+        // The Hessian of a quadratic form is constant
+        let e =
+            pow(x(), c(2)) * 3 + x() * y() * 2 + pow(y(), c(2)) * 5 - x() * z() + pow(z(), c(2));
+        let expected = [[6, 2, -1], [2, 10, 0], [-1, 0, 2]];
+        let hessian = e.hessian(&XYZ).unwrap();
+        assert_eq!(hessian, expected.map(|row| row.map(c).to_vec()).to_vec());
+        let values = eval_matrix(&hessian, &[]).unwrap();
+        assert_eq!(
+            values,
+            expected.map(|row| row.map(|n| n as f64).to_vec()).to_vec()
+        );
+    }
+
+    #[test]
+    fn trig() {
+        let fs = [sin(x() * y()) + z(), cos(y()) * pow(z(), c(2)) - x()];
+        let point = [1.2, -0.7, 0.4];
+        let bindings: Vec<(VarId, f64)> = XYZ.into_iter().zip(point).collect();
+        let values = eval_matrix(&jacobian(&fs, &XYZ).unwrap(), &bindings).unwrap();
+        // Compare with central differences
+        let f = |i: usize, p: [f64; 3]| {
+            let bindings: Vec<(VarId, f64)> = XYZ.into_iter().zip(p).collect();
+            fs[i].specialize(&bindings).eval(0.0).unwrap()
+        };
+        let h = 1e-6;
+        for (i, row) in values.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let (mut above, mut below) = (point, point);
+                above[j] += h;
+                below[j] -= h;
+                let estimate = (f(i, above) - f(i, below)) / (2.0 * h);
+                assert!(
+                    (value - estimate).abs() < 1e-6,
+                    "{i} {j}: {value} {estimate}"
+                );
+            }
+        }
+
+        let e = sin(x() * y()) * z();
+        let hessian = e.hessian(&XYZ).unwrap();
+        for (i, row) in hessian.iter().enumerate() {
+            for (j, entry) in row.iter().enumerate() {
+                assert_eq!(*entry, hessian[j][i]);
+            }
+        }
+        let values = eval_matrix(&hessian, &bindings).unwrap();
+        let (x, y, z) = (point[0], point[1], point[2]);
+        assert!((values[0][0] + y * y * z * (x * y).sin()).abs() < 1e-12);
+        assert!((values[1][2] - x * (x * y).cos()).abs() < 1e-12);
+        assert_eq!(values[2][2], 0.0);
+        // x has to have a value
+        assert_eq!(
+            eval_matrix(&hessian, &bindings[1..]),
+            Err(EvalError::UnknownVariable(VarId('x')))
+        );
+    }
+}
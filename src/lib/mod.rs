@@ -4,16 +4,137 @@
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_doc_code_examples)]
 
+mod analysis;
+mod assumptions;
+mod batch;
+mod bytes;
+mod cache;
+mod canonical;
+mod codegen;
+mod combinatorics;
+mod complex;
+mod composition;
+#[cfg(any(test, feature = "test-suite"))]
+pub mod conformance;
+mod continuity;
+mod debug;
 mod derivative;
-mod latex;
+mod diff;
+mod diffeq;
+mod domain;
+mod dot;
+mod error;
+mod eval;
+mod factor;
+mod fit;
+mod fold;
+mod fractions;
+#[cfg(feature = "num-traits")]
+mod generic;
+mod hashed;
+mod holes;
+mod hyperbolic;
+mod integrate;
+mod interval;
+mod isolate;
+mod kind;
+pub mod latex;
+mod matrix;
+mod monomial;
+mod newton;
+mod normal;
 mod operations;
+mod parse;
+mod piecewise;
+mod polynomial;
+pub mod prelude;
+mod problems;
+mod python;
+mod quadrature;
+mod radicals;
+mod rates;
+mod ratio;
+mod scaled;
+mod sexpr;
 mod simplify;
+mod special_angles;
+mod specialize;
+mod stable;
+mod substitute;
+mod summation;
+mod symmetry;
+mod system;
+mod tagged;
+pub mod testing;
+mod traverse;
+mod trig;
+mod unicode;
 
-type Num = isize;
+pub use analysis::{AnalysisOptions, CriticalPoint, CriticalPointKind, CurveReport, Interval};
+pub use assumptions::Assumptions;
+pub use batch::EvalFailure;
+pub use bytes::FORMAT_VERSION;
+pub use cache::{CacheStats, SimplifyCache};
+pub use codegen::CodegenError;
+pub use combinatorics::ExpandError;
+pub use continuity::{ContinuityResult, Discontinuity};
+pub use derivative::{DerivativeError, Rule, Step};
+pub use diff::ExprDiff;
+pub use diffeq::{solve_linear_first_order, solve_separable, OdeSolution, CONSTANT};
+pub use dot::DotOptions;
+pub use error::MathError;
+pub use eval::EvalError;
+pub use fit::FitError;
+pub use fold::ConstFoldMode;
+pub use hashed::HashedExpr;
+pub use interval::IntervalOptions;
+pub use kind::ExprKind;
+pub use latex::{Derivation, DigitGrouping, LatexOptions, LatexStyle};
+pub use matrix::{eval_matrix, jacobian};
+pub use newton::{solve_newton_system, NewtonOptions, RootError};
+pub use normal::NormalForm;
+pub use parse::{ParseError, ParseOptions};
+pub use piecewise::Condition;
+pub use problems::{
+    generate_derivative_problem, generate_problem, Difficulty, GenConfig, GenError, Problem,
+    Rejection, Rng,
+};
+pub use python::{PyDialect, PythonOptions};
+pub use quadrature::{area_between, IntegrationMethod, Volume};
+pub use simplify::{
+    merge_like_terms, Budget, SimplifyOptions, SimplifyReport, SimplifyWarning, Strictness,
+};
+pub use substitute::MatchMode;
+pub use symmetry::{Symmetry, SymmetryEvidence};
+pub use system::{solve_for_param, Equation, LinearSystem, NotLinear, Solution};
+pub use tagged::{Meta, Tagged};
+pub use unicode::UnicodeOptions;
+
+/// The type of the whole number constants in expressions. This is `i64`, or `i128` with the
+/// `num-i128` feature, which allows bigger constants before arithmetic on them overflows. Every
+/// simplification uses checked arithmetic, and leaves the expression alone when it would
+/// overflow or give a polynomial of degree more than 1000.
+#[cfg(not(feature = "num-i128"))]
+pub type Num = i64;
+/// The type of the whole number constants in expressions. This is `i64`, or `i128` with the
+/// `num-i128` feature, which allows bigger constants before arithmetic on them overflows. Every
+/// simplification uses checked arithmetic, and leaves the expression alone when it would
+/// overflow or give a polynomial of degree more than 1000.
+#[cfg(feature = "num-i128")]
+pub type Num = i128;
+
+/// The name of a variable other than x (see [`Expr::Var`]), e.g. `VarId('y')`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct VarId(pub char);
+
+/// The label of a blank in an expression which is still to be filled in (see [`Expr::Hole`]),
+/// e.g. `HoleId(0)`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct HoleId(pub u32);
 
 /// An expression type! All mathematical expressions should be able to be expressed with this type.
 /// This type is essentially an AST (abstract syntax tree).
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub enum Expr {
     /// A constant value (e.g. 1, 6, 15)
     Const(Num),
@@ -21,6 +142,25 @@ pub enum Expr {
     /// This might be changed to an id based variable or something (because you will often want
     /// more variables than just x in expressions).
     X,
+    /// A variable other than x. Derivatives and integrals are always with respect to x, so these
+    /// are treated like constants by them.
+    Var(VarId),
+    /// The imaginary unit i, where i^2 = -1
+    I,
+    /// Euler's number e, the base of ln
+    E,
+    /// A constant multiplied by a power of ten (e.g. `Scaled(6, 23)` is `6 * 10^23`), for
+    /// numbers too big or small to be a [`Expr::Const`]
+    Scaled(Num, i32),
+    /// A blank which is still to be filled in, e.g. in a step of a derivation a student is
+    /// completing. It can't be evaluated or differentiated, and is only ever equal to a hole with
+    /// the same id. See [`Expr::fill_hole`] and [`Expr::match_template`].
+    Hole(HoleId),
+    /// The rate of change of a variable with respect to another, e.g. `Deriv(VarId('x'),
+    /// VarId('t'))` is `dx/dt`, where x is `VarId('x')`, as it appears in a total derivative (see
+    /// [`Expr::total_derivative`]). It is an opaque value, like a [`Expr::Var`], which only has a
+    /// value when one is given for it with [`Expr::bind_rates`].
+    Deriv(VarId, VarId),
     /// The sum of each expression in the vector.
     Sum(Vec<Expr>),
     /// The product of each expression in the vector.
@@ -42,9 +182,50 @@ pub enum Expr {
     Arccos(Box<Expr>),
     /// Arctan of an expression
     Arctan(Box<Expr>),
+    /// Sinh (the hyperbolic sine) of an expression
+    Sinh(Box<Expr>),
+    /// Cosh (the hyperbolic cosine) of an expression
+    Cosh(Box<Expr>),
+    /// Tanh (the hyperbolic tangent) of an expression
+    Tanh(Box<Expr>),
+    /// Arsinh (the inverse hyperbolic sine) of an expression
+    Arsinh(Box<Expr>),
+    /// Arcosh (the inverse hyperbolic cosine) of an expression, which is only defined from 1 up
+    Arcosh(Box<Expr>),
+    /// Artanh (the inverse hyperbolic tangent) of an expression, which is only defined between
+    /// -1 and 1
+    Artanh(Box<Expr>),
+    /// The factorial of an expression (n!), which is only defined for non-negative integers
+    Factorial(Box<Expr>),
+    /// The sum of the last expression for each whole number value of the variable from the first
+    /// expression to the second, e.g. `SumOver(k, 1, n, k^2)` is `1^2 + 2^2 + ... + n^2`. The
+    /// variable is bound inside the summed expression, so it isn't the same as a `k` outside.
+    SumOver(VarId, Box<Expr>, Box<Expr>, Box<Expr>),
+    /// A function defined in pieces, which is the expression of the first branch whose condition
+    /// holds, e.g. `|x|` is `Piecewise([(x < 0, -x), (Otherwise, x)])`
+    Piecewise(Vec<(Condition, Expr)>),
 }
 
+/// The default expression is 0, which is cheap to make. Passes use it with [`std::mem::take`] to
+/// move a subexpression out of a tree without a copy, and then replace the whole node, so a
+/// half-rewritten tree never has a made-up subexpression (like an extra x) in it.
+impl Default for Expr {
+    fn default() -> Self {
+        Expr::Const(0)
+    }
+}
+
+// Every node of a tree is an `Expr`, so one big variant makes every expression bigger. The
+// biggest now are the vectors of Sum, Prod and Piecewise, SumOver's three boxes, and Scaled when
+// `Num` is an i128. Anything bigger should be boxed.
+const _: () = assert!(std::mem::size_of::<Expr>() <= 32);
+
 impl Expr {
+    /// Get a constant, the same as [`Expr::Const`]
+    pub fn constant(n: Num) -> Self {
+        Expr::Const(n)
+    }
+
     /// Get the reciprocal of an expression (i.e. 1/x)
     pub fn recip(self) -> Self {
         match self {
@@ -53,24 +234,73 @@ impl Expr {
         }
     }
 
-    /// Raise an expression to a power
-    pub fn pow(self, b: Expr) -> Self {
-        Expr::Pow(Box::new(self), Box::new(b))
+    /// Raise an expression to a power, which can be an expression or a [`Num`], e.g.
+    /// `x.pow(3)` is `x.pow(Expr::Const(3))`
+    pub fn pow(self, b: impl Into<Expr>) -> Self {
+        Expr::Pow(
+            Box::new(self.normalized_sign()),
+            Box::new(b.into().normalized_sign()),
+        )
+    }
+
+    /// Raise an expression to a whole number power, the same as [`Expr::pow`] with a constant
+    pub fn powi(self, n: i32) -> Self {
+        self.pow(Num::from(n))
+    }
+
+    /// Get the square root of an expression
+    pub fn sqrt(self) -> Self {
+        self.pow(Expr::Const(2).recip())
+    }
+
+    /// Raise e to the power of an expression
+    pub fn exp(self) -> Self {
+        Expr::E.pow(self)
     }
 
     /// Get the ln of an expression
     pub fn ln(self) -> Self {
-        Expr::Ln(Box::new(self))
+        Expr::Ln(Box::new(self.normalized_sign()))
     }
 
     /// Get the sin of an expression
     pub fn sin(self) -> Self {
-        Expr::Sin(Box::new(self))
+        Expr::Sin(Box::new(self.normalized_sign()))
     }
 
     /// Get the cos of an expression
     pub fn cos(self) -> Self {
-        Expr::Cos(Box::new(self))
+        Expr::Cos(Box::new(self.normalized_sign()))
+    }
+
+    /// Get the sinh of an expression
+    pub fn sinh(self) -> Self {
+        Expr::Sinh(Box::new(self.normalized_sign()))
+    }
+
+    /// Get the cosh of an expression
+    pub fn cosh(self) -> Self {
+        Expr::Cosh(Box::new(self.normalized_sign()))
+    }
+
+    /// Get the tanh of an expression
+    pub fn tanh(self) -> Self {
+        Expr::Tanh(Box::new(self.normalized_sign()))
+    }
+
+    /// Get the factorial of an expression
+    pub fn factorial(self) -> Self {
+        Expr::Factorial(Box::new(self.normalized_sign()))
+    }
+
+    /// Sum an expression for each whole number value of `index` from `from` to `to`
+    pub fn sum_over(self, index: VarId, from: Expr, to: Expr) -> Self {
+        Expr::SumOver(
+            index,
+            Box::new(from.normalized_sign()),
+            Box::new(to.normalized_sign()),
+            Box::new(self.normalized_sign()),
+        )
     }
 }
 
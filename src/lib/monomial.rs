@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{Expr, Num, VarId};
+
+/// A term of a sum without its constant factor, as the power of each variable in it and the
+/// factors which aren't powers of variables, so that terms which only differ in the order of
+/// their factors have the same monomial, e.g. `xy^2` and `y^2x`. `x^2y` and `xy^2` are
+/// different.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub(super) struct Monomial {
+    /// The power of each variable, where x is `VarId('x')`. None of them are 0.
+    powers: BTreeMap<VarId, Num>,
+    /// The other factors (e.g. `sin(y)`), which have to be the same in like terms, in order
+    others: Vec<Expr>,
+}
+
+impl Monomial {
+    /// The monomial of a term, or `None` if a variable in it has a power which isn't a whole
+    /// number, or has both positive and negative powers (like `x \cdot x^{-1}`, which isn't 1
+    /// at 0). Such a term is only like terms which are written in the same way.
+    pub(super) fn new(term: &Expr) -> Option<Self> {
+        let mut powers: BTreeMap<VarId, Num> = BTreeMap::new();
+        let mut others = vec![];
+        for factor in term.factors() {
+            let (var, n) = match factor {
+                Expr::X => (VarId('x'), 1),
+                Expr::Var(v) => (*v, 1),
+                Expr::Pow(a, b) => match (a.as_ref(), b.as_ref()) {
+                    (Expr::X, Expr::Const(n)) if *n != 0 => (VarId('x'), *n),
+                    (Expr::Var(v), Expr::Const(n)) if *n != 0 => (*v, *n),
+                    (Expr::X | Expr::Var(_), _) => return None,
+                    _ => {
+                        others.push(factor.clone());
+                        continue;
+                    }
+                },
+                _ => {
+                    others.push(factor.clone());
+                    continue;
+                }
+            };
+            let power = powers.entry(var).or_insert(0);
+            if power.signum() * n.signum() < 0 {
+                return None;
+            }
+            *power = power.checked_add(n)?;
+        }
+        others.sort();
+        Some(Monomial { powers, others })
+    }
+
+    /// The total degree, which is the sum of the powers of the variables
+    pub(super) fn degree(&self) -> Num {
+        self.powers
+            .values()
+            .fold(0, |total: Num, n| total.saturating_add(*n))
+    }
+
+    /// Compare monomials in graded lexicographic order: the higher total degree first, then the
+    /// higher power of the first variable in alphabetical order which they have different
+    /// powers of, e.g. `x^2y > xy^2 > y^2 > x`. Monomials with the same powers are ordered by
+    /// their other factors.
+    pub(super) fn graded_lex(&self, other: &Monomial) -> Ordering {
+        let vars: BTreeSet<&VarId> = self.powers.keys().chain(other.powers.keys()).collect();
+        let exponents = |m: &Monomial| -> Vec<Num> {
+            vars.iter()
+                .map(|v| m.powers.get(v).copied().unwrap_or(0))
+                .collect()
+        };
+        // Higher degrees and powers come first
+        other
+            .degree()
+            .cmp(&self.degree())
+            .then_with(|| exponents(other).cmp(&exponents(self)))
+            .then_with(|| self.others.cmp(&other.others))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::*;
+
+    fn y() -> Expr {
+        var('y')
+    }
+
+    fn z() -> Expr {
+        var('z')
+    }
+
+    #[test]
+    fn keys() {
+        let monomial = |e: Expr| Monomial::new(&e);
+        assert_eq!(
+            monomial(x() * pow(y(), c(2))),
+            monomial(pow(y(), c(2)) * x())
+        );
+        assert_eq!(monomial(x() * y() * x()), monomial(pow(x(), c(2)) * y()));
+        assert_ne!(
+            monomial(pow(x(), c(2)) * y()),
+            monomial(x() * pow(y(), c(2)))
+        );
+        assert_eq!(
+            monomial(sin(y()) * x() * z()),
+            monomial(z() * x() * sin(y()))
+        );
+        assert_ne!(monomial(sin(y()) * x()), monomial(sin(x()) * y()));
+        // Powers which aren't whole numbers, or don't just add up
+        assert_eq!(monomial(pow(x(), y())), None);
+        assert_eq!(monomial(x() * pow(x(), c(-1))), None);
+    }
+
+    #[test]
+    fn ordering() {
+        let mut terms = vec![
+            x(),
+            pow(y(), c(2)),
+            x() * pow(y(), c(2)),
+            c(1),
+            pow(x(), c(2)) * y(),
+            z() * y() * x(),
+        ];
+        terms.sort_by(|a, b| {
+            Monomial::new(a)
+                .unwrap()
+                .graded_lex(&Monomial::new(b).unwrap())
+        });
+        assert_eq!(
+            terms,
+            vec![
+                pow(x(), c(2)) * y(),
+                x() * pow(y(), c(2)),
+                z() * y() * x(),
+                pow(y(), c(2)),
+                x(),
+                c(1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+
+use super::fit::solve;
+use super::{eval_matrix, jacobian, DerivativeError, EvalError, Expr, MathError, VarId};
+
+/// Settings for [`solve_newton_system`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewtonOptions {
+    /// The point is a solution once the size of the residuals (the values of the expressions) is
+    /// at most this
+    pub tolerance: f64,
+    /// The most steps which are taken before giving up
+    pub max_iterations: usize,
+    /// The most times a step is halved when it doesn't make the residuals smaller
+    pub max_halvings: usize,
+}
+
+impl Default for NewtonOptions {
+    fn default() -> Self {
+        NewtonOptions {
+            tolerance: 1e-12,
+            max_iterations: 50,
+            max_halvings: 30,
+        }
+    }
+}
+
+/// An error from finding a root of a system with [`solve_newton_system`]
+#[derive(Debug, PartialEq, Clone)]
+pub enum RootError {
+    /// There isn't one expression and one starting value for each variable
+    WrongSize {
+        /// How many expressions there are
+        equations: usize,
+        /// How many variables there are
+        vars: usize,
+        /// How many starting values there are
+        guess: usize,
+    },
+    /// An expression couldn't be differentiated
+    Derivative(DerivativeError),
+    /// An expression or its Jacobian couldn't be evaluated at a point
+    Eval(EvalError),
+    /// The Jacobian at a point can't be inverted, so there is no step to take from it
+    SingularJacobian {
+        /// The point
+        point: Vec<f64>,
+    },
+    /// No step from a point, however small, makes the residuals smaller
+    Diverged {
+        /// The point
+        point: Vec<f64>,
+    },
+    /// The residuals still weren't small enough after the most steps allowed
+    NoConvergence {
+        /// Where the last step got to
+        point: Vec<f64>,
+    },
+}
+
+impl fmt::Display for RootError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RootError::WrongSize {
+                equations,
+                vars,
+                guess,
+            } => write!(
+                f,
+                "there are {equations} equations, {vars} variables and {guess} starting values"
+            ),
+            RootError::Derivative(error) => write!(f, "{}", MathError::from(*error)),
+            RootError::Eval(error) => write!(f, "{}", MathError::from(*error)),
+            RootError::SingularJacobian { point } => {
+                write!(f, "the jacobian at {point:?} is singular")
+            }
+            RootError::Diverged { point } => write!(f, "no step from {point:?} gets closer"),
+            RootError::NoConvergence { point } => {
+                write!(f, "the steps hadn't converged by {point:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RootError {}
+
+impl From<DerivativeError> for RootError {
+    fn from(error: DerivativeError) -> Self {
+        RootError::Derivative(error)
+    }
+}
+
+impl From<EvalError> for RootError {
+    fn from(error: EvalError) -> Self {
+        RootError::Eval(error)
+    }
+}
+
+/// Find a point where all of the expressions are 0, starting from a guess, with Newton's method.
+/// `vars[i]` is the variable whose value is `guess[i]` (x is `VarId('x')`), and there has to be
+/// one expression for each variable.
+///
+/// Each step solves `J \Delta = -F` for the step `\Delta`, where F are the values of the
+/// expressions and J is their [`jacobian`] at the point, by Gaussian elimination. A step which
+/// doesn't make the residuals smaller is halved until it does, so a guess which isn't close to
+/// a root can still get to one. Newton's method only finds the root it is led to, so a
+/// different guess can give a different root.
+pub fn solve_newton_system(
+    fs: &[Expr],
+    vars: &[VarId],
+    guess: &[f64],
+    options: &NewtonOptions,
+) -> Result<Vec<f64>, RootError> {
+    if fs.len() != vars.len() || guess.len() != vars.len() {
+        return Err(RootError::WrongSize {
+            equations: fs.len(),
+            vars: vars.len(),
+            guess: guess.len(),
+        });
+    }
+    let jacobian = jacobian(fs, vars)?;
+    let column: Vec<Vec<Expr>> = fs.iter().map(|f| vec![f.clone()]).collect();
+    let eval = |matrix: &[Vec<Expr>], point: &[f64]| {
+        let bindings: Vec<(VarId, f64)> = vars.iter().copied().zip(point.iter().copied()).collect();
+        eval_matrix(matrix, &bindings)
+    };
+    let residuals = |point: &[f64]| -> Result<Vec<f64>, EvalError> {
+        Ok(eval(&column, point)?.into_iter().flatten().collect())
+    };
+    let size = |r: &[f64]| r.iter().map(|r| r * r).sum::<f64>().sqrt();
+
+    let mut point = guess.to_vec();
+    let mut r = residuals(&point)?;
+    for _ in 0..options.max_iterations {
+        if size(&r) <= options.tolerance {
+            return Ok(point);
+        }
+        // Each row of J with -F on the end
+        let rows = eval(&jacobian, &point)?
+            .into_iter()
+            .zip(&r)
+            .map(|(mut row, r)| {
+                row.push(-r);
+                row
+            })
+            .collect();
+        let step = solve(rows)
+            .filter(|step| step.iter().all(|s| s.is_finite()))
+            .ok_or_else(|| RootError::SingularJacobian {
+                point: point.clone(),
+            })?;
+        let mut scale = 1.0;
+        let next = (0..=options.max_halvings).find_map(|_| {
+            let next: Vec<f64> = point
+                .iter()
+                .zip(&step)
+                .map(|(p, s)| p + scale * s)
+                .collect();
+            scale /= 2.0;
+            let next_r = residuals(&next).ok()?;
+            (size(&next_r) < size(&r)).then_some((next, next_r))
+        });
+        let Some((next, next_r)) = next else {
+            return Err(RootError::Diverged { point });
+        };
+        (point, r) = (next, next_r);
+    }
+    match size(&r) <= options.tolerance {
+        true => Ok(point),
+        false => Err(RootError::NoConvergence { point }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, pow, var, x};
+    use super::*;
+
+    fn y() -> Expr {
+        var('y')
+    }
+
+    const XY: [VarId; 2] = [VarId('x'), VarId('y')];
+
+    #[test]
+    fn intersection() {
+        // The circle x^2 + y^2 = 4 and the line y = x
+        let fs = [pow(x(), c(2)) + pow(y(), c(2)) - 4, y() - x()];
+        let options = NewtonOptions::default();
+        let root = solve_newton_system(&fs, &XY, &[1.0, 1.5], &options).unwrap();
+        let expected = 2f64.sqrt();
+        assert!(
+            root.iter().all(|v| (v - expected).abs() < 1e-12),
+            "{root:?}"
+        );
+        // The other intersection, from a guess near it
+        let root = solve_newton_system(&fs, &XY, &[-3.0, -0.5], &options).unwrap();
+        assert!(
+            root.iter().all(|v| (v + expected).abs() < 1e-12),
+            "{root:?}"
+        );
+    }
+
+    #[test]
+    fn damping() {
+        // x = cos(y) and y = cos(x), from a guess far enough away that full steps overshoot
+        let fs = [x() - cos(y()), y() - cos(x())];
+        let root = solve_newton_system(&fs, &XY, &[10.0, -7.0], &NewtonOptions::default()).unwrap();
+        assert!((root[0] - root[1]).abs() < 1e-12);
+        assert!((root[0] - root[0].cos()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn errors() {
+        let fs = [pow(x(), c(2)) + pow(y(), c(2)) - 4, y() - x()];
+        let options = NewtonOptions::default();
+        // The Jacobian is singular at the origin
+        assert_eq!(
+            solve_newton_system(&fs, &XY, &[0.0, 0.0], &options),
+            Err(RootError::SingularJacobian {
+                point: vec![0.0, 0.0]
+            })
+        );
+        // A circle and a line which don't meet
+        let fs = [pow(x(), c(2)) + pow(y(), c(2)) - 4, y() - x() - 10];
+        assert!(matches!(
+            solve_newton_system(&fs, &XY, &[1.0, 2.0], &options),
+            Err(RootError::Diverged { .. } | RootError::SingularJacobian { .. })
+        ));
+        assert_eq!(
+            solve_newton_system(&fs, &XY, &[1.0], &options),
+            Err(RootError::WrongSize {
+                equations: 2,
+                vars: 2,
+                guess: 1
+            })
+        );
+    }
+}
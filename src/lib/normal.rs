@@ -0,0 +1,284 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use super::{Expr, Num, VarId};
+
+/// A normal form for [`Expr::normal_form`] to write a polynomial in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NormalForm {
+    /// A sum of monomials with their like terms collected, e.g. `x^2y + x^2 + 2xy + 3`
+    Expanded,
+    /// A polynomial in one variable whose coefficients are expanded polynomials in the others,
+    /// e.g. `(y+1)x^2 + (2y)x + 3` in x. x is `VarId('x')`.
+    RecursiveIn(VarId),
+}
+
+/// The powers of the variables in a term, where anything which isn't a sum, product or power
+/// with a whole number exponent counts as a variable
+type Monomial = BTreeMap<Expr, Num>;
+
+/// A polynomial, with the coefficient of each monomial
+type Poly = BTreeMap<Monomial, Num>;
+
+impl Expr {
+    /// Write the expression in a normal form, so that polynomials which are equal are written in
+    /// the same way. Products and powers are multiplied out, and terms with the same powers of
+    /// the variables are collected, with the highest degree terms first.
+    ///
+    /// Subexpressions which aren't polynomials (e.g. `sin(x)`) are treated like variables, after
+    /// their own arguments are put in the normal form, so `sin(x)(y+1)` expands to
+    /// `sin(x)y + sin(x)`. The expression is given back unchanged if a coefficient overflows.
+    pub fn normal_form(&self, form: NormalForm) -> Expr {
+        let Some(poly) = self.to_poly(form) else {
+            return self.clone();
+        };
+        match form {
+            NormalForm::Expanded => expanded(&poly),
+            NormalForm::RecursiveIn(var) => recursive(poly, var),
+        }
+    }
+
+    /// The expression as a polynomial, or `None` if a coefficient overflows
+    fn to_poly(&self, form: NormalForm) -> Option<Poly> {
+        match self {
+            Expr::Const(0) => Some(Poly::new()),
+            Expr::Const(n) => Some(Poly::from([(Monomial::new(), *n)])),
+            Expr::Sum(v) => v
+                .iter()
+                .try_fold(Poly::new(), |acc, e| add(acc, e.to_poly(form)?)),
+            Expr::Prod(v) => v
+                .iter()
+                .try_fold(one(), |acc, e| mul(&acc, &e.to_poly(form)?)),
+            Expr::Neg(e) => e
+                .to_poly(form)?
+                .into_iter()
+                .map(|(m, c)| Some((m, c.checked_neg()?)))
+                .collect(),
+            Expr::Pow(a, b) => match **b {
+                Expr::Const(n) if n >= 0 => pow(a.to_poly(form)?, n),
+                _ => Some(atom(self, form)),
+            },
+            _ => Some(atom(self, form)),
+        }
+    }
+}
+
+/// The polynomial 1
+fn one() -> Poly {
+    Poly::from([(Monomial::new(), 1)])
+}
+
+/// A subexpression which counts as a variable, with its arguments in the normal form
+fn atom(e: &Expr, form: NormalForm) -> Poly {
+    let mut e = e.clone();
+    for child in e.children_mut() {
+        *child = child.normal_form(form);
+    }
+    Poly::from([(Monomial::from([(e, 1)]), 1)])
+}
+
+fn add(mut a: Poly, b: Poly) -> Option<Poly> {
+    for (m, c) in b {
+        let sum = a.get(&m).map_or(Some(c), |d| d.checked_add(c))?;
+        if sum == 0 {
+            a.remove(&m);
+        } else {
+            a.insert(m, sum);
+        }
+    }
+    Some(a)
+}
+
+fn mul(a: &Poly, b: &Poly) -> Option<Poly> {
+    let mut product = Poly::new();
+    for (m, c) in a {
+        for (n, d) in b {
+            let mut monomial = m.clone();
+            for (var, k) in n {
+                let power = monomial.entry(var.clone()).or_insert(0);
+                *power = power.checked_add(*k)?;
+            }
+            product = add(product, Poly::from([(monomial, c.checked_mul(*d)?)]))?;
+        }
+    }
+    Some(product)
+}
+
+/// A polynomial to a power, by repeated squaring
+fn pow(mut base: Poly, mut n: Num) -> Option<Poly> {
+    let mut result = one();
+    while n > 0 {
+        if n % 2 == 1 {
+            result = mul(&result, &base)?;
+        }
+        n /= 2;
+        if n > 0 {
+            base = mul(&base, &base)?;
+        }
+    }
+    Some(result)
+}
+
+/// The monomials of a polynomial, with the highest total degree first
+fn sorted_terms(poly: &Poly) -> Vec<(&Monomial, Num)> {
+    let mut terms: Vec<_> = poly.iter().map(|(m, c)| (m, *c)).collect();
+    terms.sort_by_key(|(m, _)| {
+        let degree = m.values().fold(0, |total: Num, k| total.saturating_add(*k));
+        (Reverse(degree), Reverse(*m))
+    });
+    terms
+}
+
+fn power(var: &Expr, n: Num) -> Expr {
+    match n {
+        1 => var.clone(),
+        n => Expr::Pow(Box::new(var.clone()), Box::new(Expr::Const(n))),
+    }
+}
+
+/// Write a term as a product with the coefficient first, which is left out when it is 1
+fn term(coefficient: Expr, mut factors: Vec<Expr>) -> Expr {
+    if coefficient != Expr::Const(1) || factors.is_empty() {
+        factors.insert(0, coefficient);
+    }
+    match factors.len() {
+        1 => factors.pop().unwrap(),
+        _ => Expr::Prod(factors),
+    }
+}
+
+fn sum(mut terms: Vec<Expr>) -> Expr {
+    match terms.len() {
+        0 => Expr::Const(0),
+        1 => terms.pop().unwrap(),
+        _ => Expr::Sum(terms),
+    }
+}
+
+fn expanded(poly: &Poly) -> Expr {
+    sum(sorted_terms(poly)
+        .into_iter()
+        .map(|(m, c)| {
+            let factors = m.iter().map(|(var, n)| power(var, *n)).collect();
+            term(Expr::Const(c), factors)
+        })
+        .collect())
+}
+
+/// Group the terms of a polynomial by their power of a variable, with each coefficient as a
+/// factor of the power. The term without the variable is written as it is.
+fn recursive(poly: Poly, var: VarId) -> Expr {
+    let var = match var {
+        VarId('x') => Expr::X,
+        var => Expr::Var(var),
+    };
+    let mut coefficients: BTreeMap<Reverse<Num>, Poly> = BTreeMap::new();
+    for (mut m, c) in poly {
+        let n = m.remove(&var).unwrap_or(0);
+        coefficients.entry(Reverse(n)).or_default().insert(m, c);
+    }
+    sum(coefficients
+        .into_iter()
+        .map(|(Reverse(n), coefficient)| match n {
+            0 => expanded(&coefficient),
+            n => term(expanded(&coefficient), vec![power(&var, n)]),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::*;
+
+    /// (x + y)^2 - y^2 + x^2y + 3, which is (y+1)x^2 + (2y)x + 3 in x
+    fn bivariate() -> Expr {
+        pow(x() + var('y'), c(2)) - pow(var('y'), c(2)) + pow(x(), c(2)) * var('y') + 3
+    }
+
+    #[test]
+    fn expanded() {
+        let e = bivariate().normal_form(NormalForm::Expanded);
+        let y = || var('y');
+        assert_eq!(
+            e,
+            Expr::Sum(vec![
+                Expr::Prod(vec![pow(x(), c(2)), y()]),
+                pow(x(), c(2)),
+                Expr::Prod(vec![c(2), x(), y()]),
+                c(3),
+            ])
+        );
+        assert_eq!(e.to_latex(), "x^{2}y+x^{2}+(2)xy+3");
+        // Writing it in the normal form again doesn't change it
+        assert_eq!(e.normal_form(NormalForm::Expanded), e);
+        assert_eq!(
+            pow(x() - 1, c(3)).normal_form(NormalForm::Expanded),
+            Expr::from_coefficients(&[-1, 3, -3, 1]).normal_form(NormalForm::Expanded)
+        );
+        assert_eq!((x() - x()).normal_form(NormalForm::Expanded), c(0));
+    }
+
+    #[test]
+    fn recursive() {
+        let y = || var('y');
+        let e = bivariate().normal_form(NormalForm::RecursiveIn(VarId('x')));
+        assert_eq!(
+            e,
+            Expr::Sum(vec![
+                Expr::Prod(vec![y() + 1, pow(x(), c(2))]),
+                Expr::Prod(vec![Expr::Prod(vec![c(2), y()]), x()]),
+                c(3),
+            ])
+        );
+        assert_eq!(e.to_latex(), "(y+1)x^{2}+(2y)x+3");
+
+        // In y, the coefficients are polynomials in x
+        let in_y = bivariate().normal_form(NormalForm::RecursiveIn(VarId('y')));
+        assert_eq!(
+            in_y,
+            Expr::Sum(vec![
+                Expr::Prod(vec![pow(x(), c(2)) + Expr::Prod(vec![c(2), x()]), y()]),
+                pow(x(), c(2)) + 3,
+            ])
+        );
+
+        // Both forms are the same polynomial, and go back to the same expanded form
+        let expanded = bivariate().normal_form(NormalForm::Expanded);
+        for e in [e, in_y] {
+            assert_eq!(e.normal_form(NormalForm::Expanded), expanded);
+            for (x, y) in [(0.5, -2), (-1.5, 3)] {
+                let at = |e: &Expr| e.substitute_var(VarId('y'), &c(y)).eval(x).unwrap();
+                assert!((at(&e) - at(&bivariate())).abs() < 1e-9);
+            }
+        }
+        // And the recursive form in x doesn't change when it is regrouped
+        let regrouped = expanded.normal_form(NormalForm::RecursiveIn(VarId('x')));
+        assert_eq!(
+            regrouped.normal_form(NormalForm::RecursiveIn(VarId('x'))),
+            regrouped
+        );
+    }
+
+    #[test]
+    fn non_polynomial() {
+        let y = || var('y');
+        // sin is treated like a variable, and its argument is put in the normal form too
+        let e = sin(x() * (x() + 1)) * (y() + 1);
+        let inner = pow(x(), c(2)) + x();
+        assert_eq!(
+            e.normal_form(NormalForm::Expanded),
+            Expr::Sum(vec![
+                Expr::Prod(vec![y(), sin(inner.clone())]),
+                sin(inner.clone()),
+            ])
+        );
+        assert_eq!(
+            e.normal_form(NormalForm::RecursiveIn(VarId('y'))),
+            Expr::Sum(vec![Expr::Prod(vec![sin(inner.clone()), y()]), sin(inner)])
+        );
+        // Overflowing coefficients leave the expression alone
+        let big = pow(x() * Num::MAX + 1, c(2));
+        assert_eq!(big.normal_form(NormalForm::Expanded), big);
+    }
+}
@@ -1,18 +1,55 @@
+//! The operators for building expressions. They only build the tree, without simplifying it,
+//! apart from folding signs (see [`Expr::normalized_sign`]). With a [`Num`] on either side, an
+//! operator builds the same shape of tree, with the number as a [`Expr::Const`]:
+//!
+//! | Expression      | Tree                                        |
+//! |-----------------|---------------------------------------------|
+//! | `e + n`         | `Sum([..terms of e, n])`                    |
+//! | `n + e`         | `Sum([n, ..terms of e])`                    |
+//! | `e - n`         | `Sum([..terms of e, -n])`                   |
+//! | `n - e`         | `Sum([n, ..terms of -e])`                   |
+//! | `e * n`         | `Prod([..factors of e, n])`                 |
+//! | `n * e`         | `Prod([n, ..factors of e])`                 |
+//! | `e / n`         | `Prod([..factors of e, Pow(n, -1)])`        |
+//! | `n / e`         | `Prod([n, e^-1])`                           |
+//! | `e.pow(n)`      | `Pow(e, n)`                                 |
+//!
+//! where the terms of e are only spread out when e is a sum, and its factors when it is a
+//! product, on whichever side the number is. `-e` and `e^-1` are [`Neg`] and [`Expr::recip`],
+//! so `n - e` is `n + -e` and `n / e` is `n * e^-1`. The assigning operators
+//! (`e += n`) and the operators on references (`&e + n`) give the same trees as the operators
+//! on owned expressions.
+
 use super::{Expr, Num};
+use std::iter::{Product, Sum};
 use std::ops::*;
 
+impl Expr {
+    /// Fold a negative of a constant into the constant, and a double negative into the
+    /// expression, so that `-3` is always `Const(-3)` in trees built with the operators and
+    /// constructors. Expressions built from the variants directly can still have these forms,
+    /// so [`Expr::simplify`] still handles them.
+    pub(super) fn normalized_sign(self) -> Self {
+        match self {
+            Expr::Neg(e) if matches!(*e, Expr::Const(_) | Expr::Neg(_)) => -*e,
+            _ => self,
+        }
+    }
+}
+
 impl Add for Expr {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
-        Expr::Sum(match self {
+        let rhs = rhs.normalized_sign();
+        Expr::Sum(match self.normalized_sign() {
             Expr::Sum(v) => {
                 let mut v = v;
                 v.push(rhs);
                 v
             }
-            _ => {
-                vec![self, rhs]
+            lhs => {
+                vec![lhs, rhs]
             }
         })
     }
@@ -22,14 +59,15 @@ impl Mul for Expr {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
-        Expr::Prod(match self {
+        let rhs = rhs.normalized_sign();
+        Expr::Prod(match self.normalized_sign() {
             Expr::Prod(v) => {
                 let mut v = v;
                 v.push(rhs);
                 v
             }
-            _ => {
-                vec![self, rhs]
+            lhs => {
+                vec![lhs, rhs]
             }
         })
     }
@@ -41,6 +79,10 @@ impl Neg for Expr {
     fn neg(self) -> Self {
         match self {
             Expr::Neg(e) => *e,
+            Expr::Const(n) => match n.checked_neg() {
+                Some(n) => Expr::Const(n),
+                None => Expr::Neg(Box::new(self)),
+            },
             _ => Expr::Neg(Box::new(self)),
         }
     }
@@ -63,11 +105,13 @@ impl Div for Expr {
     }
 }
 
+// The left side is taken out instead of cloned, so a Sum or Prod keeps its Vec and `e *= x` in a
+// loop doesn't copy the whole tree each time
 macro_rules! assigning_operator {
     ($trait_name:ty, $func_name:ident, $token:tt) => {
         impl $trait_name for Expr {
             fn $func_name(&mut self, rhs: Self) {
-                *self = self.clone() $token rhs;
+                *self = std::mem::take(self) $token rhs;
             }
         }
     }
@@ -78,17 +122,62 @@ assigning_operator!(MulAssign, mul_assign, *);
 assigning_operator!(SubAssign, sub_assign, -);
 assigning_operator!(DivAssign, div_assign, /);
 
+macro_rules! reference_operator {
+    ($trait_name:ident, $assign_trait:ident, $func_name:ident, $assign_func:ident, $token:tt) => {
+        // The owned side is passed straight through, so a Sum or Prod on the left keeps its Vec
+        impl $trait_name<&Expr> for Expr {
+            type Output = Expr;
+            fn $func_name(self, rhs: &Expr) -> Expr {
+                self $token rhs.clone()
+            }
+        }
+        impl $trait_name<Expr> for &Expr {
+            type Output = Expr;
+            fn $func_name(self, rhs: Expr) -> Expr {
+                self.clone() $token rhs
+            }
+        }
+        impl $trait_name<&Expr> for &Expr {
+            type Output = Expr;
+            fn $func_name(self, rhs: &Expr) -> Expr {
+                self.clone() $token rhs.clone()
+            }
+        }
+        impl $assign_trait<&Expr> for Expr {
+            fn $assign_func(&mut self, rhs: &Expr) {
+                *self = std::mem::take(self) $token rhs;
+            }
+        }
+    }
+}
+
+reference_operator!(Add, AddAssign, add, add_assign, +);
+reference_operator!(Mul, MulAssign, mul, mul_assign, *);
+reference_operator!(Sub, SubAssign, sub, sub_assign, -);
+reference_operator!(Div, DivAssign, div, div_assign, /);
+
+impl Neg for &Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        match self {
+            Expr::Neg(e) => *e.clone(),
+            _ => -self.clone(),
+        }
+    }
+}
+
 macro_rules! apply_num {
     ($trait_name:ty, $assign_trait:ty, $func_name:ident, $assign_func:ident, $token:tt) => {
         impl $trait_name for Expr {
             type Output = Self;
             fn $func_name(self, rhs: Num) -> Self::Output {
-                self.clone() $token Expr::Const(rhs)
+                self $token Expr::Const(rhs)
             }
         }
         impl $assign_trait for Expr {
             fn $assign_func(&mut self, rhs: Num) {
-                *self = self.clone() $token rhs;
+                *self = std::mem::take(self) $token rhs;
             }
         }
     }
@@ -99,18 +188,379 @@ apply_num!(Mul<Num>, MulAssign<Num>, mul, mul_assign, *);
 apply_num!(Sub<Num>, SubAssign<Num>, sub, sub_assign, -);
 apply_num!(Div<Num>, DivAssign<Num>, div, div_assign, /);
 
+// A number on the left goes in front of the terms of a sum or the factors of a product, so that
+// they are spread out like they are with the number on the right
+impl Add<Expr> for Num {
+    type Output = Expr;
+    fn add(self, rhs: Expr) -> Expr {
+        match rhs.normalized_sign() {
+            Expr::Sum(mut v) => {
+                v.insert(0, Expr::Const(self));
+                Expr::Sum(v)
+            }
+            rhs => Expr::Const(self) + rhs,
+        }
+    }
+}
+
+impl Mul<Expr> for Num {
+    type Output = Expr;
+    fn mul(self, rhs: Expr) -> Expr {
+        match rhs.normalized_sign() {
+            Expr::Prod(mut v) => {
+                v.insert(0, Expr::Const(self));
+                Expr::Prod(v)
+            }
+            rhs => Expr::Const(self) * rhs,
+        }
+    }
+}
+
+impl Sub<Expr> for Num {
+    type Output = Expr;
+    fn sub(self, rhs: Expr) -> Expr {
+        self + -rhs
+    }
+}
+
+impl Div<Expr> for Num {
+    type Output = Expr;
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: Expr) -> Expr {
+        self * rhs.recip()
+    }
+}
+
 macro_rules! apply_to_num {
-    ($trait_name:ty, $func_name:ident, $token:tt) => {
-        impl $trait_name for Num {
+    ($trait_name:ident, $func_name:ident, $token:tt) => {
+        impl $trait_name<&Expr> for Num {
             type Output = Expr;
-            fn $func_name(self, rhs: Expr) -> Self::Output {
-                Expr::Const(self) $token rhs
+            fn $func_name(self, rhs: &Expr) -> Self::Output {
+                self $token rhs.clone()
+            }
+        }
+        impl $trait_name<Num> for &Expr {
+            type Output = Expr;
+            fn $func_name(self, rhs: Num) -> Self::Output {
+                self.clone() $token Expr::Const(rhs)
             }
         }
     }
 }
 
-apply_to_num!(Add<Expr>, add, +);
-apply_to_num!(Mul<Expr>, mul, *);
-apply_to_num!(Sub<Expr>, sub, -);
-apply_to_num!(Div<Expr>, div, /);
+apply_to_num!(Add, add, +);
+apply_to_num!(Mul, mul, *);
+apply_to_num!(Sub, sub, -);
+apply_to_num!(Div, div, /);
+
+impl From<Num> for Expr {
+    fn from(n: Num) -> Self {
+        Expr::Const(n)
+    }
+}
+
+/// The sum of some expressions, as one [`Expr::Sum`] built with `+`. An empty sum is 0, and the
+/// sum of one expression is that expression.
+impl Sum for Expr {
+    fn sum<I: Iterator<Item = Expr>>(mut iter: I) -> Self {
+        let Some(first) = iter.next() else {
+            return Expr::Const(0);
+        };
+        iter.fold(first.normalized_sign(), |sum, e| sum + e)
+    }
+}
+
+impl<'a> Sum<&'a Expr> for Expr {
+    fn sum<I: Iterator<Item = &'a Expr>>(iter: I) -> Self {
+        iter.cloned().sum()
+    }
+}
+
+/// The sum of some numbers as constants, without adding them up
+impl Sum<Num> for Expr {
+    fn sum<I: Iterator<Item = Num>>(iter: I) -> Self {
+        iter.map(Expr::Const).sum()
+    }
+}
+
+impl<'a> Sum<&'a Num> for Expr {
+    fn sum<I: Iterator<Item = &'a Num>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// The product of some expressions, as one [`Expr::Prod`] built with `*`. An empty product is
+/// 1, and the product of one expression is that expression.
+impl Product for Expr {
+    fn product<I: Iterator<Item = Expr>>(mut iter: I) -> Self {
+        let Some(first) = iter.next() else {
+            return Expr::Const(1);
+        };
+        iter.fold(first.normalized_sign(), |product, e| product * e)
+    }
+}
+
+impl<'a> Product<&'a Expr> for Expr {
+    fn product<I: Iterator<Item = &'a Expr>>(iter: I) -> Self {
+        iter.cloned().product()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references() {
+        let a = Expr::X.sin() + 1;
+        let b = Expr::X.pow(Expr::Const(2));
+        assert_eq!(&a + &b, a.clone() + b.clone());
+        assert_eq!(&a * b.clone(), a.clone() * b.clone());
+        assert_eq!(a.clone() - &b, a.clone() - b.clone());
+        assert_eq!(&a / &b, a.clone() / b.clone());
+        assert_eq!(-&a, -a.clone());
+        assert_eq!(-&-a.clone(), a);
+
+        let mut c = a.clone();
+        c *= &b;
+        assert_eq!(c, a.clone() * b.clone());
+    }
+
+    #[test]
+    fn reference_reuses_vec() {
+        let mut v = Vec::with_capacity(8);
+        v.push(Expr::X);
+        v.push(Expr::Const(1));
+        let ptr = v.as_ptr();
+        let sum = Expr::Sum(v) + &Expr::X.sin();
+        let Expr::Sum(v) = sum else {
+            panic!("expected a sum");
+        };
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v.len(), 3);
+    }
+
+    #[test]
+    fn assigning_reuses_vec() {
+        let mut e = Expr::Prod(Vec::with_capacity(64));
+        e *= Expr::X;
+        let Expr::Prod(v) = &e else {
+            panic!("expected a product");
+        };
+        let ptr = v.as_ptr();
+        for _ in 0..10 {
+            e *= Expr::X.sin();
+            e *= &Expr::X;
+            e *= 2;
+        }
+        let Expr::Prod(v) = &e else {
+            panic!("expected a product");
+        };
+        assert_eq!(v.as_ptr(), ptr);
+        assert_eq!(v.len(), 31);
+    }
+
+    #[test]
+    fn assigning_matches_operators() {
+        let a = Expr::X.sin() + 1;
+        let b = Expr::X.pow(Expr::Const(2)) * 3;
+        for (lhs, rhs) in [(&a, &b), (&b, &a), (&Expr::X, &Expr::Const(-2))] {
+            let mut e = lhs.clone();
+            e += rhs.clone();
+            assert_eq!(e, lhs.clone() + rhs.clone());
+            let mut e = lhs.clone();
+            e -= rhs;
+            assert_eq!(e, lhs.clone() - rhs.clone());
+            let mut e = lhs.clone();
+            e *= rhs.clone();
+            assert_eq!(e, lhs.clone() * rhs.clone());
+            let mut e = lhs.clone();
+            e /= rhs;
+            assert_eq!(e, lhs.clone() / rhs.clone());
+            let mut e = lhs.clone();
+            e -= 4;
+            assert_eq!(e, lhs.clone() - Expr::Const(4));
+        }
+    }
+
+    #[test]
+    fn repeated_squaring() {
+        // Squaring 20 times would have 2^20 factors without simplifying in between
+        let mut e = Expr::X + 1;
+        for _ in 0..20 {
+            e *= e.clone();
+            e.simplify();
+        }
+        assert_eq!(e, (Expr::X + 1).pow(Expr::Const(1 << 20)));
+    }
+
+    /// The raw forms which the operators treat differently: atoms, negatives (including the raw
+    /// `Neg(Const)` form), sums, products and powers
+    fn operands() -> Vec<Expr> {
+        let neg = |e| Expr::Neg(Box::new(e));
+        vec![
+            Expr::X,
+            Expr::Const(3),
+            Expr::Const(-3),
+            neg(Expr::Const(2)),
+            neg(Expr::X),
+            Expr::Sum(vec![Expr::X, Expr::Const(1)]),
+            Expr::Prod(vec![Expr::Const(2), Expr::X]),
+            Expr::Pow(Box::new(Expr::X), Box::new(Expr::Const(2))),
+        ]
+    }
+
+    /// The terms of e as the operators spread them out on the left, after folding its sign
+    fn terms(e: &Expr) -> Vec<Expr> {
+        match e.clone().normalized_sign() {
+            Expr::Sum(v) => v,
+            e => vec![e],
+        }
+    }
+
+    /// The factors of e as the operators spread them out on the left, after folding its sign
+    fn factors(e: &Expr) -> Vec<Expr> {
+        match e.clone().normalized_sign() {
+            Expr::Prod(v) => v,
+            e => vec![e],
+        }
+    }
+
+    /// Every operator with an expression and a number, on both sides and with owned, borrowed
+    /// and assigning forms, against the table at the top of this module
+    #[test]
+    fn num_operators() {
+        type Case = (&'static str, fn(Expr, Num) -> Expr, fn(&Expr, Num) -> Expr);
+        fn k(n: Num) -> Expr {
+            Expr::Const(n)
+        }
+        let table: [Case; 9] = [
+            (
+                "e + n",
+                |e, n| e + n,
+                |e, n| Expr::Sum([terms(e), vec![k(n)]].concat()),
+            ),
+            (
+                "n + e",
+                |e, n| n + e,
+                |e, n| Expr::Sum([vec![k(n)], terms(e)].concat()),
+            ),
+            (
+                "e - n",
+                |e, n| e - n,
+                |e, n| Expr::Sum([terms(e), vec![-k(n)]].concat()),
+            ),
+            (
+                "n - e",
+                |e, n| n - e,
+                |e, n| Expr::Sum([vec![k(n)], terms(&-e.clone())].concat()),
+            ),
+            (
+                "e * n",
+                |e, n| e * n,
+                |e, n| Expr::Prod([factors(e), vec![k(n)]].concat()),
+            ),
+            (
+                "n * e",
+                |e, n| n * e,
+                |e, n| Expr::Prod([vec![k(n)], factors(e)].concat()),
+            ),
+            (
+                "e / n",
+                |e, n| e / n,
+                |e, n| {
+                    let recip = Expr::Pow(Box::new(k(n)), Box::new(k(-1)));
+                    Expr::Prod([factors(e), vec![recip]].concat())
+                },
+            ),
+            (
+                "n / e",
+                |e, n| n / e,
+                |e, n| Expr::Prod([vec![k(n)], factors(&e.clone().recip())].concat()),
+            ),
+            (
+                "e.pow(n)",
+                |e, n| e.pow(n),
+                |e, n| Expr::Pow(Box::new(e.clone().normalized_sign()), Box::new(k(n))),
+            ),
+        ];
+        for e in operands() {
+            for n in [0, 1, -2, 7] {
+                for (name, op, expected) in &table {
+                    assert_eq!(op(e.clone(), n), expected(&e, n), "{name} with {e:?}, {n}");
+                }
+                // The borrowed and assigning forms match
+                assert_eq!(&e + n, e.clone() + n);
+                assert_eq!(&e - n, e.clone() - n);
+                assert_eq!(&e * n, e.clone() * n);
+                assert_eq!(&e / n, e.clone() / n);
+                assert_eq!(n + &e, n + e.clone());
+                assert_eq!(n - &e, n - e.clone());
+                assert_eq!(n * &e, n * e.clone());
+                assert_eq!(n / &e, n / e.clone());
+                let mut assigned = e.clone();
+                assigned -= n;
+                assert_eq!(assigned, e.clone() - n);
+                assert_eq!(e.clone().powi(n as i32), e.clone().pow(n));
+            }
+        }
+    }
+
+    #[test]
+    fn iterators() {
+        let terms = vec![Expr::X, Expr::X.powi(2), Expr::Const(3)];
+        assert_eq!(
+            terms.iter().sum::<Expr>(),
+            Expr::Sum(vec![Expr::X, Expr::X.pow(2), Expr::Const(3)])
+        );
+        assert_eq!(
+            terms.clone().into_iter().product::<Expr>(),
+            Expr::X * Expr::X.pow(Expr::Const(2)) * 3
+        );
+        assert_eq!(Vec::<Expr>::new().into_iter().sum::<Expr>(), Expr::Const(0));
+        assert_eq!([Expr::X].iter().product::<Expr>(), Expr::X);
+        let coeffs: [Num; 3] = [1, -2, 5];
+        let poly: Expr = coeffs
+            .iter()
+            .enumerate()
+            .map(|(n, c)| *c * Expr::X.powi(n as i32))
+            .sum();
+        assert_eq!(poly.eval(2.0), Ok(17.0));
+        assert_eq!(
+            coeffs.iter().sum::<Expr>(),
+            Expr::Sum(vec![Expr::Const(1), Expr::Const(-2), Expr::Const(5)])
+        );
+    }
+
+    #[test]
+    fn normalized_signs() {
+        assert_eq!(
+            Expr::X - Expr::Const(3),
+            Expr::Sum(vec![Expr::X, Expr::Const(-3)])
+        );
+        assert_eq!(Expr::X - 3, Expr::Sum(vec![Expr::X, Expr::Const(-3)]));
+        assert_eq!(
+            -Expr::Const(3) * Expr::X,
+            Expr::Prod(vec![Expr::Const(-3), Expr::X])
+        );
+        assert_eq!(-&Expr::Const(3), Expr::Const(-3));
+
+        // Raw forms are folded when they are put into a tree by an operator or a constructor
+        let raw = || Expr::Neg(Box::new(Expr::Const(2)));
+        let double = Expr::Neg(Box::new(Expr::Neg(Box::new(Expr::X))));
+        assert_eq!(Expr::X + raw(), Expr::Sum(vec![Expr::X, Expr::Const(-2)]));
+        assert_eq!(double.clone() * Expr::X, Expr::Prod(vec![Expr::X, Expr::X]));
+        assert_eq!(raw().sin(), Expr::Sin(Box::new(Expr::Const(-2))));
+        assert_eq!(double.clone().ln(), Expr::Ln(Box::new(Expr::X)));
+        assert_eq!(
+            Expr::X.pow(raw()),
+            Expr::Pow(Box::new(Expr::X), Box::new(Expr::Const(-2)))
+        );
+
+        // The most negative number has no positive, so it stays a negative
+        assert_eq!(
+            -Expr::Const(Num::MIN),
+            Expr::Neg(Box::new(Expr::Const(Num::MIN)))
+        );
+    }
+}
@@ -0,0 +1,775 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{Expr, HoleId, Num, VarId};
+
+/// An error from reading an expression with [`Expr::from_latex`] or [`Expr::from_sexpr`].
+/// Positions are byte offsets into the text.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    /// A latex command which has no expression, e.g. `\int`
+    UnsupportedCommand {
+        /// The name of the command, without the backslash
+        command: String,
+        /// Where the backslash is
+        position: usize,
+    },
+    /// Something which doesn't make sense where it is, e.g. a `)` without a `(`
+    Unexpected {
+        /// The character or command that was found
+        found: String,
+        /// Where it is
+        position: usize,
+    },
+    /// The latex ended in the middle of an expression, e.g. `x+`
+    UnexpectedEnd,
+    /// An S-expression list starting with a symbol which isn't the head of any kind of
+    /// expression
+    UnknownHead {
+        /// The symbol at the start of the list
+        head: String,
+        /// Where the symbol is
+        position: usize,
+    },
+    /// An S-expression list with the wrong number of expressions for its head, e.g. `(^ x)`
+    WrongArity {
+        /// The symbol at the start of the list
+        head: String,
+        /// How many expressions should be in the list
+        expected: usize,
+        /// How many expressions are in the list
+        found: usize,
+        /// Where the head symbol is
+        position: usize,
+    },
+    /// A number which is too big to be a constant
+    NumberTooLarge {
+        /// Where the number starts
+        position: usize,
+    },
+    /// A number whose digits are split up with commas (see [`ParseOptions::digit_groups`]), but
+    /// not into groups of three, e.g. `1,00`
+    DigitGroups {
+        /// Where the number starts
+        position: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnsupportedCommand { command, position } => {
+                write!(f, "unsupported command \\{command} at {position}")
+            }
+            ParseError::Unexpected { found, position } => {
+                write!(f, "unexpected {found} at {position}")
+            }
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ParseError::UnknownHead { head, position } => {
+                write!(f, "unknown head symbol {head} at {position}")
+            }
+            ParseError::WrongArity {
+                head,
+                expected,
+                found,
+                position,
+            } => write!(
+                f,
+                "{head} at {position} needs {expected} expressions but has {found}"
+            ),
+            ParseError::NumberTooLarge { position } => {
+                write!(f, "number at {position} is too large")
+            }
+            ParseError::DigitGroups { position } => {
+                write!(
+                    f,
+                    "the digits of the number at {position} aren't in groups of three"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+type Function = fn(Expr) -> Expr;
+
+/// The functions which can be applied to an argument, as commands (`\sin`, as
+/// [`Expr::to_latex`] writes them), with `\operatorname{arsinh}`, or written without the
+/// backslash (`sin`). Longer names come first, so that `arcsin` isn't read as `arc` and then
+/// `sin`.
+const FUNCTIONS: &[(&str, Function)] = &[
+    ("arcsin", |e| Expr::Arcsin(Box::new(e))),
+    ("arccos", |e| Expr::Arccos(Box::new(e))),
+    ("arctan", |e| Expr::Arctan(Box::new(e))),
+    ("arsinh", |e| Expr::Arsinh(Box::new(e))),
+    ("arcosh", |e| Expr::Arcosh(Box::new(e))),
+    ("artanh", |e| Expr::Artanh(Box::new(e))),
+    ("sinh", Expr::sinh),
+    ("cosh", Expr::cosh),
+    ("tanh", Expr::tanh),
+    ("sin", Expr::sin),
+    ("cos", Expr::cos),
+    ("ln", Expr::ln),
+];
+
+/// The functions whose inverses are read from `\f^{-1}`, e.g. `\sin^{-1}` is arcsin
+const INVERSES: &[(&str, &str)] = &[
+    ("sin", "arcsin"),
+    ("cos", "arccos"),
+    ("sinh", "arsinh"),
+    ("cosh", "arcosh"),
+    ("tanh", "artanh"),
+];
+
+/// Latex for a small space, which is skipped like whitespace
+const SPACES: &[&str] = &["\\,", "\\;", "\\:", "\\!", "\\ "];
+
+/// What can be between groups of digits with [`ParseOptions::digit_groups`], as
+/// [`super::LatexOptions::digit_grouping`] writes them or as they are usually written
+const DIGIT_SEPARATORS: &[&str] = &["{,}", ",", "\\,"];
+
+/// Settings for how latex is read by [`Expr::from_latex_with`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Read numbers with their digits split into groups of three by commas or thin spaces, e.g.
+    /// `12,345` or `1\,000\,000`. Groups which aren't three digits long (like `1,00`) are an
+    /// error, since they are probably a list or a decimal. Off by default, when a comma is
+    /// unexpected and a thin space is a space between two numbers.
+    pub digit_groups: bool,
+    /// Names which stand for expressions, e.g. `f` for `x^2 + 1`, so that `2f` is read as
+    /// `2(x^2 + 1)`. A name is only read where it is a whole word, so `f` isn't read in `fx`,
+    /// and longer names are tried first. Names are read before functions and single letter
+    /// variables, so a name like `sin` or `x` hides them.
+    pub names: HashMap<String, Expr>,
+}
+
+impl Expr {
+    /// Read an expression from latex. Everything written by [`Expr::to_latex`] can be read, along
+    /// with the latex usually written by hand or by equation editors:
+    /// - `\frac{a}{b}`, `\sqrt{a}` and `\sqrt[n]{a}`
+    /// - powers (`x^{2}` or `x^2`) and factorials
+    /// - `\sin`, `\cos`, `\ln`, `\arcsin`, `\arccos`, `\arctan`, `\exp`, the hyperbolic
+    ///   functions `\sinh`, `\cosh`, `\tanh` and their inverses `\arsinh`, `\arcosh`, `\artanh`,
+    ///   with or without the backslash and brackets (`\sin x`, `\sin^2(x)`, `\sinh^{-1} x`), or
+    ///   as `\operatorname{arsinh}`
+    /// - sums over a variable, `\sum_{k=1}^{n} k^2`
+    /// - brackets, including `\left( \right)` and `{}`
+    /// - multiplication with `\cdot`, `\times`, `*` or by writing things next to each other
+    /// - x, e, i and other single letter variables, and `\pi` (see [`prelude::pi`](super::prelude::pi))
+    /// - holes, `\square_{n}` for [`Expr::Hole`] n, where holes written as just `\square` (or
+    ///   `\Box`) are numbered from 0 in the order they are written
+    ///
+    /// Anything else is an error rather than being left out, e.g. `\int` gives
+    /// [`ParseError::UnsupportedCommand`]. The digits of a number can be split up with
+    /// underscores, like `1_000_000`.
+    pub fn from_latex(latex: &str) -> Result<Expr, ParseError> {
+        Expr::from_latex_with(latex, &ParseOptions::default())
+    }
+
+    /// Read an expression from latex like [`Expr::from_latex`], with some settings
+    pub fn from_latex_with(latex: &str, options: &ParseOptions) -> Result<Expr, ParseError> {
+        let mut parser = Parser {
+            latex,
+            position: 0,
+            holes: 0,
+            digit_groups: options.digit_groups,
+            names: &options.names,
+        };
+        let e = parser.sum()?;
+        match parser.peek() {
+            None => Ok(e),
+            Some(_) => Err(parser.unexpected()),
+        }
+    }
+}
+
+struct Parser<'a> {
+    latex: &'a str,
+    position: usize,
+    /// How many holes without a label have been read, to number the next one
+    holes: u32,
+    /// See [`ParseOptions::digit_groups`]
+    digit_groups: bool,
+    /// See [`ParseOptions::names`]
+    names: &'a HashMap<String, Expr>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.latex[self.position..]
+    }
+
+    /// Skip whitespace and get the next character
+    fn peek(&mut self) -> Option<char> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with(char::is_whitespace) {
+                self.position += rest.chars().next().unwrap().len_utf8();
+            } else if SPACES.iter().any(|space| rest.starts_with(space)) {
+                self.position += 2;
+            } else {
+                return rest.chars().next();
+            }
+        }
+    }
+
+    /// Skip the next character if it is `c`
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += c.len_utf8();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.unexpected()),
+        }
+    }
+
+    /// Get the name of the command which is next, without skipping it
+    fn peek_command(&mut self) -> Option<&'a str> {
+        if self.peek() != Some('\\') {
+            return None;
+        }
+        let name = &self.rest()[1..];
+        let len = name
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(name.len());
+        Some(&name[..len])
+    }
+
+    /// Skip the next command if it is called `name`
+    fn eat_command(&mut self, name: &str) -> bool {
+        let found = self.peek_command() == Some(name);
+        if found {
+            self.position += name.len() + 1;
+        }
+        found
+    }
+
+    /// The error for whatever is next
+    fn unexpected(&mut self) -> ParseError {
+        let found = match (self.peek_command(), self.peek()) {
+            (Some(command), _) => format!("\\{command}"),
+            (None, Some(c)) => format!("'{c}'"),
+            (None, None) => return ParseError::UnexpectedEnd,
+        };
+        ParseError::Unexpected {
+            found,
+            position: self.position,
+        }
+    }
+
+    /// The function which is next, written either as a command or as a word
+    fn peek_function(&mut self) -> Option<(&'static str, Function)> {
+        let command = self.peek_command();
+        let rest = self.rest();
+        FUNCTIONS.iter().copied().find(|(name, _)| match command {
+            Some("operatorname") => rest["\\operatorname".len()..]
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_prefix(name))
+                .is_some_and(|rest| rest.starts_with('}')),
+            Some(command) => command == *name,
+            None => rest.starts_with(name),
+        })
+    }
+
+    /// The longest name in [`ParseOptions::names`] which is the whole of the word next
+    fn peek_name(&mut self) -> Option<(&'a str, &'a Expr)> {
+        if !self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        // A name can't start part of the way through a word either
+        let before = self.latex[..self.position].chars().next_back();
+        if before.is_some_and(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        self.names
+            .get_key_value(&rest[..len])
+            .map(|(name, e)| (name.as_str(), e))
+    }
+
+    /// Whether a factor of a product starts next, so that it is multiplied by the previous one
+    fn starts_factor(&mut self) -> bool {
+        match self.peek_command() {
+            Some(command) => !matches!(command, "right" | "cdot" | "times"),
+            None => {
+                matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "([{.".contains(c))
+            }
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseError> {
+        let mut terms = vec![self.term()?];
+        loop {
+            if self.eat('+') {
+                terms.push(self.term()?);
+            } else if self.eat('-') {
+                terms.push(-self.term()?);
+            } else {
+                break;
+            }
+        }
+        Ok(match terms.len() {
+            1 => terms.pop().unwrap(),
+            _ => Expr::Sum(terms),
+        })
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let first = match self.peek() {
+            // A negative number is a constant, otherwise the whole term is negated
+            Some('-')
+                if self.rest()[1..]
+                    .trim_start()
+                    .starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                self.position += 1;
+                match self.power()? {
+                    Expr::Const(n) => Expr::Const(-n),
+                    e => -e,
+                }
+            }
+            Some('-') => {
+                self.position += 1;
+                return Ok(-self.term()?);
+            }
+            _ => self.power()?,
+        };
+        let mut factors = vec![first];
+        loop {
+            if self.eat_command("cdot") || self.eat_command("times") || self.eat('*') {
+                factors.push(match self.eat('-') {
+                    true => -self.power()?,
+                    false => self.power()?,
+                });
+            } else if self.starts_factor() {
+                factors.push(self.power()?);
+            } else {
+                break;
+            }
+        }
+        Ok(match factors.len() {
+            1 => factors.pop().unwrap(),
+            _ => Expr::Prod(factors),
+        })
+    }
+
+    /// Something which might have a power or a factorial after it
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let mut e = self.primary()?;
+        loop {
+            if self.eat('!') {
+                e = e.factorial();
+            } else if self.eat('^') {
+                e = e.pow(self.argument()?);
+            } else {
+                return Ok(e);
+            }
+        }
+    }
+
+    /// The argument of a power or a command, which is either in braces or a single character
+    /// (e.g. the 2 in `x^2` or `\frac12`)
+    fn argument(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                self.position += 1;
+                Ok(Expr::Const(c.to_digit(10).unwrap() as Num))
+            }
+            Some('-') => {
+                self.position += 1;
+                Ok(-self.argument()?)
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        if let Some((name, e)) = self.peek_name() {
+            self.position += name.len();
+            return Ok(e.clone());
+        }
+        if let Some((name, function)) = self.peek_function() {
+            return self.function(name, function);
+        }
+        let start = self.position;
+        match self.peek() {
+            None => Err(ParseError::UnexpectedEnd),
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c @ ('(' | '[' | '{')) => {
+                self.position += 1;
+                let e = self.sum()?;
+                self.expect(match c {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                })?;
+                Ok(e)
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.position += 1;
+                Ok(match c {
+                    'x' => Expr::X,
+                    'e' => Expr::E,
+                    'i' => Expr::I,
+                    c => Expr::Var(VarId(c)),
+                })
+            }
+            Some('\\') => {
+                let command = self.peek_command().unwrap_or_default().to_string();
+                match command.as_str() {
+                    "left" => {
+                        self.position += command.len() + 1;
+                        let close = match self.peek() {
+                            Some('(') => ')',
+                            Some('[') => ']',
+                            _ => return Err(self.unexpected()),
+                        };
+                        self.position += 1;
+                        let e = self.sum()?;
+                        if !self.eat_command("right") {
+                            return Err(self.unexpected());
+                        }
+                        self.expect(close)?;
+                        Ok(e)
+                    }
+                    "frac" | "dfrac" | "tfrac" => {
+                        self.position += command.len() + 1;
+                        let numerator = self.argument()?;
+                        Ok(numerator / self.argument()?)
+                    }
+                    "sqrt" => {
+                        self.position += command.len() + 1;
+                        let index = match self.eat('[') {
+                            true => {
+                                let index = self.sum()?;
+                                self.expect(']')?;
+                                Some(index)
+                            }
+                            false => None,
+                        };
+                        let e = self.argument()?;
+                        Ok(match index {
+                            Some(index) => e.pow(index.recip()),
+                            None => e.sqrt(),
+                        })
+                    }
+                    "exp" => self.function("exp", Expr::exp),
+                    "pi" => {
+                        self.position += command.len() + 1;
+                        Ok(super::prelude::pi())
+                    }
+                    "sum" => {
+                        self.position += command.len() + 1;
+                        self.expect('_')?;
+                        self.expect('{')?;
+                        let index = match self.primary()? {
+                            Expr::Var(k) => k,
+                            _ => {
+                                return Err(ParseError::Unexpected {
+                                    found: "\\sum".to_string(),
+                                    position: start,
+                                })
+                            }
+                        };
+                        self.expect('=')?;
+                        let from = self.sum()?;
+                        self.expect('}')?;
+                        self.expect('^')?;
+                        let to = self.argument()?;
+                        Ok(self.term()?.sum_over(index, from, to))
+                    }
+                    "square" | "Box" => {
+                        self.position += command.len() + 1;
+                        let id = match self.eat('_') {
+                            true => match self.argument()? {
+                                Expr::Const(n) => u32::try_from(n).ok(),
+                                _ => None,
+                            }
+                            .ok_or(ParseError::Unexpected {
+                                found: format!("\\{command}"),
+                                position: start,
+                            })?,
+                            false => {
+                                self.holes += 1;
+                                self.holes - 1
+                            }
+                        };
+                        Ok(Expr::Hole(HoleId(id)))
+                    }
+                    "right" | "cdot" | "times" | "" => Err(self.unexpected()),
+                    _ => Err(ParseError::UnsupportedCommand {
+                        command,
+                        position: start,
+                    }),
+                }
+            }
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    /// A function applied to the argument after it. `\sin^{n}` is a power of sin, and
+    /// `\sin^{-1}` is arcsin. Without brackets, the argument is everything up to the next
+    /// function or operator, e.g. `\sin 2x \cos x` is `sin(2x)cos(x)`.
+    fn function(&mut self, name: &str, function: Function) -> Result<Expr, ParseError> {
+        if self.eat_command("operatorname") {
+            // The name and the braces around it
+            self.position += name.len() + 2;
+        } else {
+            if self.peek_command() == Some(name) {
+                self.position += 1;
+            }
+            self.position += name.len();
+        }
+        let (function, power) = match self.eat('^') {
+            true => {
+                let power = self.argument()?;
+                match INVERSES.iter().find(|(f, _)| *f == name) {
+                    Some((_, inverse)) if power == Expr::Const(-1) => {
+                        let (_, inverse) = FUNCTIONS.iter().find(|(f, _)| f == inverse).unwrap();
+                        (*inverse, None)
+                    }
+                    _ => (function, Some(power)),
+                }
+            }
+            false => (function, None),
+        };
+        let argument = match self.peek() {
+            Some('(' | '[' | '{') => self.primary()?,
+            Some('\\') if self.peek_command() == Some("left") => self.primary()?,
+            _ => {
+                let mut factors = vec![self.power()?];
+                while self.starts_factor() && self.peek_function().is_none() {
+                    factors.push(self.power()?);
+                }
+                match factors.len() {
+                    1 => factors.pop().unwrap(),
+                    _ => Expr::Prod(factors),
+                }
+            }
+        };
+        let e = function(argument);
+        Ok(match power {
+            Some(power) => e.pow(power),
+            None => e,
+        })
+    }
+
+    /// A whole number, or a decimal which is written as a fraction
+    fn number(&mut self) -> Result<Expr, ParseError> {
+        let start = self.position;
+        let starts_digit = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let mut digits = String::new();
+        // Where the groups of digits start, as indices into `digits`
+        let mut groups = vec![];
+        loop {
+            let rest = self.rest();
+            let after_digit = digits.ends_with(|c: char| c.is_ascii_digit());
+            let separator = match rest.chars().next() {
+                Some(c) if c.is_ascii_digit() || c == '.' => {
+                    digits.push(c);
+                    self.position += 1;
+                    continue;
+                }
+                Some('_') => Some("_"),
+                _ if self.digit_groups => DIGIT_SEPARATORS
+                    .iter()
+                    .copied()
+                    .find(|s| rest.starts_with(s)),
+                _ => None,
+            };
+            match separator {
+                Some(s) if after_digit && starts_digit(&rest[s.len()..]) => {
+                    if s != "_" {
+                        groups.push(digits.len());
+                    }
+                    self.position += s.len();
+                }
+                _ => break,
+            }
+        }
+        let digits = digits.as_str();
+        let too_large = ParseError::NumberTooLarge { position: start };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if fraction.contains('.') || whole.len() + fraction.len() == 0 {
+            return Err(ParseError::Unexpected {
+                found: digits.to_string(),
+                position: start,
+            });
+        }
+        // The first group has up to three digits, and the others (before the point) have three
+        if let (Some(first), Some(last)) = (groups.first(), groups.last()) {
+            let three_apart = groups.windows(2).all(|w| w[1] - w[0] == 3);
+            if *first > 3 || !three_apart || whole.len() != last + 3 {
+                return Err(ParseError::DigitGroups { position: start });
+            }
+        }
+        let n: Num = format!("{whole}{fraction}")
+            .parse()
+            .map_err(|_| too_large.clone())?;
+        Ok(match fraction.len() {
+            0 => Expr::Const(n),
+            len => {
+                let denominator = (10 as Num).checked_pow(len as u32).ok_or(too_large)?;
+                Expr::Const(n) / Expr::Const(denominator)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{generate_derivative_problem, Difficulty, Rng};
+    use super::*;
+
+    fn parsed(latex: &str) -> Expr {
+        Expr::from_latex(latex).unwrap_or_else(|e| panic!("{latex}: {e}"))
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng::new(383);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            for _ in 0..100 {
+                let problem = generate_derivative_problem(&mut rng, difficulty).unwrap();
+                assert!(parsed(&problem.question).equivalent(&problem.expr));
+                assert!(parsed(&problem.answer).equivalent(&problem.derivative));
+            }
+        }
+        let e = (Expr::X + 1).sqrt() * Expr::X.pow(Expr::Const(3).recip())
+            - Expr::Const(-3) * Expr::X.exp()
+            + Expr::X.factorial()
+            + Expr::Arctan(Box::new(Expr::X * Expr::I))
+            + (Expr::Var(VarId('k')) * Expr::X).sum_over(VarId('k'), Expr::Const(1), Expr::X);
+        assert_eq!(parsed(&e.to_latex()), e);
+    }
+
+    #[test]
+    fn written_by_hand() {
+        let x = || Expr::X;
+        let square = |e: Expr| e.pow(Expr::Const(2));
+        let cases = [
+            ("\\frac{1}{2}x^{2}", Expr::Const(1) / 2 * square(x())),
+            (
+                "2x\\cdot\\cos\\left(x^{2}\\right)",
+                Expr::Const(2) * x() * square(x()).cos(),
+            ),
+            ("-\\sin\\left(3x+1\\right)", -(x() * 3 + 1).sin()),
+            ("\\frac{2x}{x^2+1}", x() * 2 / (square(x()) + 1)),
+            ("3x^2 - 4x + 7", square(x()) * 3 - x() * 4 + 7),
+            (
+                "\\frac{1}{\\sqrt{1-x^{2}}}",
+                (Expr::Const(1) - square(x())).sqrt().recip(),
+            ),
+            (
+                "e^{2x}\\left(2\\sin x+\\cos x\\right)",
+                (x() * 2).exp() * (x().sin() * 2 + x().cos()),
+            ),
+            ("\\ln\\left(x\\right)+1", x().ln() + 1),
+            (
+                "\\sin^2 x + \\cos^{2}(x)",
+                square(x().sin()) + square(x().cos()),
+            ),
+            (
+                "0.5x \\times \\sqrt[3]{x}",
+                x() / 2 * x().pow(Expr::Const(3).recip()),
+            ),
+            ("\\sin^{-1}(x)", Expr::Arcsin(Box::new(x()))),
+            ("y\\,\\exp(x)", Expr::Var(VarId('y')) * x().exp()),
+        ];
+        for (latex, expected) in cases {
+            assert!(parsed(latex).equivalent(&expected), "{latex}");
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Expr::from_latex("2\\theta x"),
+            Err(ParseError::UnsupportedCommand {
+                command: "theta".to_string(),
+                position: 1
+            })
+        );
+        assert_eq!(
+            Expr::from_latex("\\int x").unwrap_err().to_string(),
+            "unsupported command \\int at 0"
+        );
+        assert_eq!(Expr::from_latex("x+"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(Expr::from_latex("(x"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            Expr::from_latex("x)").unwrap_err().to_string(),
+            "unexpected ')' at 1"
+        );
+        assert_eq!(
+            Expr::from_latex("\\left(x\\right]"),
+            Err(ParseError::Unexpected {
+                found: "']'".to_string(),
+                position: 13
+            })
+        );
+    }
+
+    #[test]
+    fn names() {
+        let mut options = ParseOptions::default();
+        options.names.insert("f".to_string(), Expr::X + 1);
+        options.names.insert("ans".to_string(), Expr::X.sin());
+        let read = |latex| Expr::from_latex_with(latex, &options).unwrap();
+        assert_eq!(read("2f"), Expr::Const(2) * (Expr::X + 1));
+        assert_eq!(read("f^2"), (Expr::X + 1).pow(Expr::Const(2)));
+        assert_eq!(read("ans\\cdot f"), Expr::X.sin() * (Expr::X + 1));
+        // Only whole words are names
+        let (a, n, s) = (VarId('a'), VarId('n'), VarId('s'));
+        assert_eq!(
+            read("ansx"),
+            Expr::Prod(vec![Expr::Var(a), Expr::Var(n), Expr::Var(s), Expr::X])
+        );
+        assert_eq!(read("fx"), Expr::Var(VarId('f')) * Expr::X);
+        assert_eq!(read("xf"), Expr::X * Expr::Var(VarId('f')));
+        assert_eq!(
+            read("xans"),
+            Expr::Prod(vec![Expr::X, Expr::Var(a), Expr::Var(n), Expr::Var(s)])
+        );
+    }
+
+    #[test]
+    fn digit_groups() {
+        assert_eq!(parsed("1_000"), Expr::Const(1000));
+        assert_eq!(parsed("1_000_000x"), Expr::Const(1000000) * Expr::X);
+        let grouped = ParseOptions {
+            digit_groups: true,
+            ..Default::default()
+        };
+        let read = |latex| Expr::from_latex_with(latex, &grouped);
+        assert_eq!(read("12,345"), Ok(Expr::Const(12345)));
+        assert_eq!(read("1{,}234{,}567"), Ok(Expr::Const(1234567)));
+        assert_eq!(read("1\\,000\\,000"), Ok(Expr::Const(1000000)));
+        assert!(read("1,234.5")
+            .unwrap()
+            .equivalent(&(Expr::Const(12345) / 10)));
+        for latex in ["1,00", "1234,567", "1,2345", "12,345.6,7"] {
+            assert!(read(latex).is_err(), "{latex}");
+        }
+        assert_eq!(read("1,00"), Err(ParseError::DigitGroups { position: 0 }));
+        assert_eq!(
+            read("x+1,00").unwrap_err().to_string(),
+            "the digits of the number at 2 aren't in groups of three"
+        );
+        // Without the option, commas aren't expected and thin spaces are spaces
+        assert!(Expr::from_latex("12,345").is_err());
+        assert_eq!(
+            parsed("2\\,3"),
+            Expr::Prod(vec![Expr::Const(2), Expr::Const(3)])
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use super::{EvalError, Expr};
+
+/// The condition of a branch of a [`Expr::Piecewise`]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub enum Condition {
+    /// The first expression is less than the second
+    Less(Expr, Expr),
+    /// The first expression is less than or equal to the second
+    LessEq(Expr, Expr),
+    /// The expressions are equal
+    Equal(Expr, Expr),
+    /// Always holds, for the last branch
+    Otherwise,
+}
+
+impl Condition {
+    /// The two expressions being compared, or nothing for [`Condition::Otherwise`]
+    pub fn sides(&self) -> Vec<&Expr> {
+        match self {
+            Condition::Less(a, b) | Condition::LessEq(a, b) | Condition::Equal(a, b) => vec![a, b],
+            Condition::Otherwise => vec![],
+        }
+    }
+
+    /// Mutable references to the two expressions being compared, see [`Condition::sides`]
+    pub fn sides_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Condition::Less(a, b) | Condition::LessEq(a, b) | Condition::Equal(a, b) => vec![a, b],
+            Condition::Otherwise => vec![],
+        }
+    }
+
+    /// Whether the condition holds, using `eval` to evaluate each side. Sides are compared
+    /// exactly, so `Equal` is only useful when both sides are exact at the point.
+    pub(super) fn holds(
+        &self,
+        eval: &dyn Fn(&Expr) -> Result<f64, EvalError>,
+    ) -> Result<bool, EvalError> {
+        Ok(match self {
+            Condition::Less(a, b) => eval(a)? < eval(b)?,
+            Condition::LessEq(a, b) => eval(a)? <= eval(b)?,
+            Condition::Equal(a, b) => eval(a)? == eval(b)?,
+            Condition::Otherwise => true,
+        })
+    }
+}
+
+impl Expr {
+    /// This function removes the branches of a piecewise expression which can never be taken,
+    /// because their condition compares two constants and is false
+    /// e.g. the `1 < 0` branch of `{x if 1 < 0, 2 otherwise}`
+    pub fn simplify_impossible_branches(&mut self) {
+        if let Expr::Piecewise(v) = self {
+            v.retain(|(condition, _)| condition.is_possible());
+        }
+    }
+}
+
+impl Condition {
+    /// Whether the condition might hold, which is false when it compares two constants and
+    /// doesn't hold for them
+    fn is_possible(&self) -> bool {
+        match self {
+            Condition::Less(Expr::Const(a), Expr::Const(b)) => a < b,
+            Condition::LessEq(Expr::Const(a), Expr::Const(b)) => a <= b,
+            Condition::Equal(Expr::Const(a), Expr::Const(b)) => a == b,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, x};
+    use super::*;
+
+    /// |x|, as `-x` when x < 0 and x otherwise
+    fn abs() -> Expr {
+        Expr::Piecewise(vec![
+            (Condition::Less(x(), c(0)), -x()),
+            (Condition::Otherwise, x()),
+        ])
+    }
+
+    #[test]
+    fn eval() {
+        assert_eq!(abs().eval(-2.0), Ok(2.0));
+        assert_eq!(abs().eval(2.0), Ok(2.0));
+        let e = Expr::Piecewise(vec![(Condition::LessEq(x(), c(1)), x())]);
+        assert_eq!(e.eval(1.0), Ok(1.0));
+        assert_eq!(e.eval(1.5), Err(EvalError::NoBranch));
+    }
+
+    #[test]
+    fn latex() {
+        assert_eq!(
+            abs().to_latex(),
+            "\\begin{cases}-(x) & x<0 \\\\ x & \\text{otherwise}\\end{cases}"
+        );
+        let e = Expr::Piecewise(vec![
+            (Condition::LessEq(x(), c(1)), c(2)),
+            (Condition::Equal(x(), c(2)), c(3)),
+        ]);
+        assert_eq!(
+            e.to_latex(),
+            "\\begin{cases}2 & x\\le 1 \\\\ 3 & x=2\\end{cases}"
+        );
+    }
+
+    #[test]
+    fn derivative() {
+        // x^2 up to 1, and then 2x - 1
+        let e = Expr::Piecewise(vec![
+            (Condition::LessEq(x(), c(1)), pow(x(), c(2))),
+            (Condition::Otherwise, x() * 2 - 1),
+        ]);
+        assert_eq!(
+            e.derivative().simplified(),
+            Expr::Piecewise(vec![
+                (Condition::LessEq(x(), c(1)), x() * c(2)),
+                (Condition::Otherwise, c(2)),
+            ])
+        );
+    }
+
+    #[test]
+    fn simplify() {
+        let e = Expr::Piecewise(vec![
+            (Condition::Less(c(1), c(0)), x()),
+            (Condition::Equal(c(2), c(1) + 1), x() + x()),
+            (Condition::Otherwise, c(0)),
+        ]);
+        assert_eq!(
+            e.simplified(),
+            Expr::Piecewise(vec![
+                (Condition::Equal(c(2), c(2)), x() * c(2)),
+                (Condition::Otherwise, c(0)),
+            ])
+        );
+    }
+}
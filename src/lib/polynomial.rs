@@ -0,0 +1,484 @@
+use super::combinatorics::MAX_EXPANDED_TERMS;
+use super::factor::square_root;
+use super::{Expr, Num};
+
+impl Expr {
+    /// Build a polynomial in x from its coefficients, where `coeffs[n]` is the coefficient of
+    /// `x^n`. Zero terms are skipped, so `[1, 0, -3]` gives `1 + (-3)x^2`.
+    pub fn from_coefficients(coeffs: &[Num]) -> Self {
+        let mut terms: Vec<Expr> = coeffs
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c != 0)
+            .map(|(n, c)| {
+                let power = match n {
+                    0 => return Expr::Const(*c),
+                    1 => Expr::X,
+                    _ => Expr::X.pow(Expr::Const(n as Num)),
+                };
+                if *c == 1 {
+                    power
+                } else {
+                    Expr::Prod(vec![Expr::Const(*c), power])
+                }
+            })
+            .collect();
+
+        match terms.len() {
+            0 => Expr::Const(0),
+            1 => terms.pop().unwrap(),
+            _ => Expr::Sum(terms),
+        }
+    }
+
+    /// Get the coefficients of a polynomial in x, where the nth element is the coefficient of
+    /// `x^n`. Trailing zero coefficients are removed, so the zero polynomial gives an empty vector.
+    ///
+    /// Returns `None` if the expression isn't a polynomial with integer coefficients, or if a
+    /// coefficient overflows.
+    pub fn to_coefficients(&self) -> Option<Vec<Num>> {
+        let mut coeffs = self.coefficients()?;
+        while coeffs.last() == Some(&0) {
+            coeffs.pop();
+        }
+        Some(coeffs)
+    }
+
+    /// This function multiplies out a product or power of polynomials
+    /// e.g. `x(x+1) = x^2 + x` and `(x+1)^2 = x^2 + 2x + 1`
+    ///
+    /// Powers of sums which aren't polynomials in x (e.g. `(x+y+1)^4`) are expanded with
+    /// [`Expr::expand_pow_multinomial`], unless they would have more than 1000 terms.
+    ///
+    /// This is the opposite of [`Expr::simplify_factor_common_terms`], and isn't done by
+    /// [`Expr::simplify`]. It is enabled with [`super::SimplifyOptions::expand_polynomials`].
+    pub fn simplify_expand_polynomial(&mut self) {
+        if !matches!(self, Expr::Prod(_) | Expr::Pow(_, _))
+            || !self.children().iter().any(|e| matches!(e, Expr::Sum(_)))
+        {
+            return;
+        }
+        if let Some(coeffs) = self.to_coefficients() {
+            *self = Expr::from_coefficients(&coeffs);
+        } else if let Ok(expanded) = self.expand_pow_multinomial(MAX_EXPANDED_TERMS) {
+            *self = expanded;
+        }
+    }
+
+    /// Find the exact roots of a linear or quadratic polynomial in x, using the quadratic
+    /// formula. A repeated root is only given once.
+    ///
+    /// When the roots aren't real they are only given if `complex` is true, in terms of
+    /// [`Expr::I`]. Otherwise there are no roots.
+    ///
+    /// Returns `None` if the expression isn't a polynomial of degree 1 or 2, or if working out
+    /// the roots overflows.
+    pub fn roots(&self, complex: bool) -> Option<Vec<Expr>> {
+        let coeffs = self.to_coefficients()?;
+        let mut roots = match coeffs.as_slice() {
+            [b, a] => vec![Expr::Const(b.checked_neg()?) / Expr::Const(*a)],
+            [c, b, a] => {
+                let discriminant = b
+                    .checked_mul(*b)?
+                    .checked_sub(a.checked_mul(*c)?.checked_mul(4)?)?;
+                let minus_b = b.checked_neg()?;
+                let two_a = a.checked_mul(2)?;
+                let real = Expr::Const(minus_b) / Expr::Const(two_a);
+                let size = discriminant.checked_abs()?;
+                // Perfect squares are taken out of the root so the roots can be whole numbers
+                let root = square_root(size);
+                let imaginary = match root {
+                    Some(n) => Expr::Const(n),
+                    None => Expr::Const(size).sqrt(),
+                } / Expr::Const(two_a);
+                match (discriminant, root) {
+                    (0, _) => vec![real],
+                    (d, Some(n)) if d > 0 => vec![
+                        Expr::Const(minus_b.checked_add(n)?) / Expr::Const(two_a),
+                        Expr::Const(minus_b.checked_sub(n)?) / Expr::Const(two_a),
+                    ],
+                    (d, None) if d > 0 => vec![&real + &imaginary, real - imaginary],
+                    _ if complex => vec![&real + &imaginary * Expr::I, real - imaginary * Expr::I],
+                    _ => vec![],
+                }
+            }
+            _ => return None,
+        };
+        for root in roots.iter_mut() {
+            root.simplify();
+        }
+        Some(roots)
+    }
+
+    /// Get the coefficients of a polynomial in x like [`Expr::to_coefficients`], where the
+    /// coefficients can be any expressions which don't use x, e.g. `[4, k, 1]` for
+    /// `x^2 + kx + 4`. Each coefficient is simplified, and trailing zero coefficients are
+    /// removed.
+    ///
+    /// Returns `None` if the expression isn't a polynomial in x, or if its degree is more than
+    /// 1000.
+    pub fn coefficients_in_x(&self) -> Option<Vec<Expr>> {
+        self.coefficients_in_x_up_to(MAX_EXPANDED_TERMS)
+    }
+
+    /// [`Expr::coefficients_in_x`], giving `None` as soon as the degree is more than
+    /// `max_degree`, so that e.g. the discriminant of `(x+1)^20` doesn't expand the power
+    fn coefficients_in_x_up_to(&self, max_degree: usize) -> Option<Vec<Expr>> {
+        let mut coeffs = self.expr_coefficients(max_degree)?;
+        for c in coeffs.iter_mut() {
+            c.simplify();
+        }
+        trim_zeros(&mut coeffs);
+        (coeffs.len() <= max_degree + 1).then_some(coeffs)
+    }
+
+    /// The discriminant of a quadratic or cubic in x, which is 0 exactly when it has a repeated
+    /// root: `b^2 - 4ac` for `ax^2 + bx + c`, and `b^2c^2 - 4ac^3 - 4b^3d - 27a^2d^2 + 18abcd`
+    /// for `ax^3 + bx^2 + cx + d`. The coefficients can use other variables (see
+    /// [`Expr::coefficients_in_x`]), so the discriminant can be handed to
+    /// [`super::solve_for_param`] to find when there is a repeated root.
+    ///
+    /// Returns `None` if the expression isn't a polynomial in x of degree 2 or 3.
+    pub fn discriminant(&self) -> Option<Expr> {
+        let coeffs = self.coefficients_in_x_up_to(3)?;
+        let c = |n: usize| coeffs[n].clone();
+        let mut discriminant = match coeffs.len() {
+            3 => {
+                let (a, b, c) = (c(2), c(1), c(0));
+                b.pow(Expr::Const(2)) - Expr::Const(4) * a * c
+            }
+            4 => {
+                let (a, b, c, d) = (c(3), c(2), c(1), c(0));
+                let square = |e: &Expr| e.clone().pow(Expr::Const(2));
+                let cube = |e: &Expr| e.clone().pow(Expr::Const(3));
+                square(&b) * square(&c)
+                    - Expr::Const(4) * &a * cube(&c)
+                    - Expr::Const(4) * cube(&b) * &d
+                    - Expr::Const(27) * square(&a) * square(&d)
+                    + Expr::Prod(vec![Expr::Const(18), a, b, c, d])
+            }
+            _ => return None,
+        };
+        discriminant.simplify();
+        Some(discriminant)
+    }
+
+    /// The coefficients of a polynomial in x as expressions, with zeros left in, or `None` if a
+    /// product in it has a degree more than `max_degree`
+    fn expr_coefficients(&self, max_degree: usize) -> Option<Vec<Expr>> {
+        if !self.uses_x() {
+            return Some(vec![self.clone()]);
+        }
+        match self {
+            Expr::X => Some(vec![Expr::Const(0), Expr::Const(1)]),
+            Expr::Neg(e) => Some(
+                e.expr_coefficients(max_degree)?
+                    .into_iter()
+                    .map(|c| -c)
+                    .collect(),
+            ),
+            Expr::Sum(v) => v.iter().try_fold(vec![], |acc, e| {
+                Some(add_exprs(acc, e.expr_coefficients(max_degree)?))
+            }),
+            Expr::Prod(v) => v.iter().try_fold(vec![Expr::Const(1)], |acc, e| {
+                mul_exprs(&acc, &e.expr_coefficients(max_degree)?, max_degree)
+            }),
+            Expr::Pow(a, b) => match **b {
+                Expr::Const(n) if n >= 0 => {
+                    pow_exprs(&a.expr_coefficients(max_degree)?, n, max_degree)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn coefficients(&self) -> Option<Vec<Num>> {
+        match self {
+            Expr::Const(c) => Some(vec![*c]),
+            Expr::X => Some(vec![0, 1]),
+            Expr::Neg(e) => e
+                .coefficients()?
+                .into_iter()
+                .map(|c| c.checked_neg())
+                .collect(),
+            Expr::Sum(v) => v
+                .iter()
+                .try_fold(vec![], |acc, e| add(&acc, &e.coefficients()?)),
+            Expr::Prod(v) => v
+                .iter()
+                .try_fold(vec![1], |acc, e| mul(&acc, &e.coefficients()?)),
+            Expr::Pow(a, b) => match **b {
+                Expr::Const(n) if n >= 0 => pow(&a.coefficients()?, n),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Add two coefficient vectors
+fn add(a: &[Num], b: &[Num]) -> Option<Vec<Num>> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut result = long.to_vec();
+    for (r, c) in result.iter_mut().zip(short) {
+        *r = r.checked_add(*c)?;
+    }
+    Some(result)
+}
+
+/// Multiply two coefficient vectors, or give `None` if the degree of the product is more than
+/// [`MAX_EXPANDED_TERMS`]
+fn mul(a: &[Num], b: &[Num]) -> Option<Vec<Num>> {
+    if a.is_empty() || b.is_empty() {
+        return Some(vec![]);
+    }
+    let len = a.len() + b.len() - 1;
+    if len > MAX_EXPANDED_TERMS + 1 {
+        return None;
+    }
+    let mut result: Vec<Num> = vec![0; len];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] = result[i + j].checked_add(x.checked_mul(*y)?)?;
+        }
+    }
+    Some(result)
+}
+
+/// Raise a coefficient vector to a power by repeated squaring, so huge powers of constants like
+/// `1^(10^18)` don't take forever
+fn pow(a: &[Num], mut n: Num) -> Option<Vec<Num>> {
+    let mut result = vec![1];
+    let mut square = a.to_vec();
+    while n > 0 {
+        if n % 2 == 1 {
+            result = mul(&result, &square)?;
+        }
+        n /= 2;
+        if n > 0 {
+            square = mul(&square, &square)?;
+        }
+    }
+    Some(result)
+}
+
+/// Add two vectors of coefficients which are expressions
+fn add_exprs(a: Vec<Expr>, b: Vec<Expr>) -> Vec<Expr> {
+    let (mut long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    for (r, c) in long.iter_mut().zip(short) {
+        *r = std::mem::take(r) + c;
+    }
+    long
+}
+
+/// Multiply two vectors of coefficients which are expressions, or give `None` if the degree of
+/// the product is more than `max_degree`. The coefficients of the product are simplified, so
+/// that they don't grow with each multiplication, and trailing zeros are removed.
+fn mul_exprs(a: &[Expr], b: &[Expr], max_degree: usize) -> Option<Vec<Expr>> {
+    if a.is_empty() || b.is_empty() {
+        return Some(vec![]);
+    }
+    let len = a.len() + b.len() - 1;
+    if len > max_degree + 1 {
+        return None;
+    }
+    let mut result = vec![Expr::Const(0); len];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] = std::mem::take(&mut result[i + j]) + x * y;
+        }
+    }
+    for c in result.iter_mut() {
+        c.simplify();
+    }
+    trim_zeros(&mut result);
+    Some(result)
+}
+
+/// Raise a vector of coefficients which are expressions to a power, or give `None` if the
+/// degree of the result is more than `max_degree`, which is checked before multiplying
+fn pow_exprs(a: &[Expr], n: Num, max_degree: usize) -> Option<Vec<Expr>> {
+    match a {
+        [] if n == 0 => Some(vec![Expr::Const(1)]),
+        [] => Some(vec![]),
+        [c] => Some(vec![c.clone().pow(Expr::Const(n)).simplified()]),
+        _ => {
+            let degree = (a.len() - 1).checked_mul(usize::try_from(n).ok()?)?;
+            if degree > max_degree {
+                return None;
+            }
+            (0..n).try_fold(vec![Expr::Const(1)], |acc, _| {
+                mul_exprs(&acc, a, max_degree)
+            })
+        }
+    }
+}
+
+/// Remove the zero coefficients at the end of a vector of coefficients
+fn trim_zeros(coeffs: &mut Vec<Expr>) {
+    while coeffs.last() == Some(&Expr::Const(0)) {
+        coeffs.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(coeffs: &[Num]) {
+        let mut e = Expr::from_coefficients(coeffs);
+        assert_eq!(e.to_coefficients().as_deref(), Some(trimmed(coeffs)));
+        e.simplify();
+        assert_eq!(e.to_coefficients().as_deref(), Some(trimmed(coeffs)));
+    }
+
+    fn trimmed(coeffs: &[Num]) -> &[Num] {
+        let len = coeffs.iter().rposition(|c| *c != 0).map_or(0, |i| i + 1);
+        &coeffs[..len]
+    }
+
+    #[test]
+    fn from_coefficients() {
+        assert_eq!(Expr::from_coefficients(&[]), Expr::Const(0));
+        assert_eq!(Expr::from_coefficients(&[0, 1]), Expr::X);
+        assert_eq!(
+            Expr::from_coefficients(&[1, 0, -3]),
+            Expr::Sum(vec![
+                Expr::Const(1),
+                Expr::Prod(vec![Expr::Const(-3), Expr::X.pow(Expr::Const(2))])
+            ])
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(&[]);
+        round_trip(&[5]);
+        round_trip(&[1, 2, 3, 4, 5]);
+        round_trip(&[-1, -2, 0, 7]);
+        round_trip(&[0, 0, 0, 0, 0, 1]);
+        round_trip(&[3, 0, 0, -9, 0, 0, 0, 2]);
+        round_trip(&[1, 2, 0, 0]);
+        round_trip(&[0, -1, 0]);
+    }
+
+    #[test]
+    fn non_polynomials() {
+        assert_eq!(Expr::X.sin().to_coefficients(), None);
+        assert_eq!(Expr::X.pow(Expr::Const(-1)).to_coefficients(), None);
+        assert_eq!(Expr::X.pow(Expr::X).to_coefficients(), None);
+    }
+
+    #[test]
+    fn huge_degrees() {
+        let huge = Expr::X.pow(Expr::Const(Num::MAX));
+        assert_eq!(huge.to_coefficients(), None);
+        assert_eq!(Expr::X.pow(Expr::Const(1001)).to_coefficients(), None);
+        assert_eq!(
+            Expr::Const(1).pow(Expr::Const(Num::MAX)).to_coefficients(),
+            Some(vec![1])
+        );
+        // This used to hang working out the coefficients of x^(3 Num::MAX)
+        let e = Expr::Prod(vec![Expr::Const(7), huge.pow(Expr::Const(3))]);
+        assert_ne!(e.canonical(), Expr::Const(0));
+    }
+
+    #[test]
+    fn expanded() {
+        // (x+1)^2 - x = x^2 + x + 1
+        let e = (Expr::X + 1).pow(Expr::Const(2)) - Expr::X;
+        assert_eq!(e.to_coefficients(), Some(vec![1, 1, 1]));
+    }
+
+    #[test]
+    fn expand() {
+        let mut e = Expr::X * (Expr::X + 1);
+        e.simplify_expand_polynomial();
+        assert_eq!(e, Expr::from_coefficients(&[0, 1, 1]));
+        let mut e = (Expr::X + 1).pow(Expr::Const(2));
+        e.simplify_expand_polynomial();
+        assert_eq!(e, Expr::from_coefficients(&[1, 2, 1]));
+        // Only products and powers with sums in them are multiplied out
+        let mut e = Expr::X * 2;
+        e.simplify_expand_polynomial();
+        assert_eq!(e, Expr::X * 2);
+    }
+
+    #[test]
+    fn roots() {
+        // x^2 - 5x + 6 = (x-2)(x-3)
+        let e = Expr::from_coefficients(&[6, -5, 1]);
+        assert_eq!(e.roots(false), Some(vec![Expr::Const(3), Expr::Const(2)]));
+        assert_eq!(
+            Expr::from_coefficients(&[1, 2, 1]).roots(false),
+            Some(vec![Expr::Const(-1)])
+        );
+        assert_eq!(
+            Expr::from_coefficients(&[-3, 2]).roots(false),
+            Some(vec![Expr::Const(3) / 2])
+        );
+        assert_eq!(Expr::from_coefficients(&[5]).roots(false), None);
+        assert_eq!(Expr::X.sin().roots(false), None);
+        // Big perfect squares are still found
+        let big = 1 << 30;
+        assert_eq!(
+            Expr::from_coefficients(&[-big * big, 0, 1]).roots(false),
+            Some(vec![Expr::Const(big), Expr::Const(-big)])
+        );
+        // -b and 2a don't fit
+        assert_eq!(Expr::from_coefficients(&[Num::MIN, 1]).roots(false), None);
+        assert_eq!(
+            Expr::from_coefficients(&[0, 1, Num::MAX]).roots(false),
+            None
+        );
+    }
+
+    #[test]
+    fn discriminants() {
+        use super::super::prelude::{c, pow, sin, var, x};
+        use super::super::{solve_for_param, Equation, VarId};
+        let k = || var('k');
+        let e = pow(x(), c(2)) + k() * x() + 4;
+        assert_eq!(e.coefficients_in_x(), Some(vec![c(4), k(), c(1)]));
+        let discriminant = e.discriminant().unwrap();
+        assert!(
+            discriminant.equivalent(&(pow(k(), c(2)) - 16)),
+            "{discriminant:?}"
+        );
+        let mut solutions = solve_for_param(&Equation::new(discriminant, c(0)), VarId('k'));
+        solutions.sort();
+        assert_eq!(solutions, vec![c(-4), c(4)]);
+
+        // (x - 1)^2 (x + 2) has a repeated root, and x^3 - x doesn't
+        let repeated = pow(x() - 1, c(2)) * (x() + 2);
+        assert_eq!(repeated.discriminant().unwrap().eval(0.0), Ok(0.0));
+        assert_eq!((pow(x(), c(3)) - x()).discriminant(), Some(c(4)));
+        // x^3 + px + q has the discriminant -4p^3 - 27q^2
+        let depressed = pow(x(), c(3)) + var('p') * x() + var('q');
+        let discriminant = depressed.discriminant().unwrap();
+        for (p, q) in [(2, 3), (-3, 2), (5, -1)] {
+            let value = discriminant
+                .substitute_var(VarId('p'), &c(p))
+                .substitute_var(VarId('q'), &c(q));
+            assert_eq!(value.eval(0.0), Ok((-4 * p * p * p - 27 * q * q) as f64));
+        }
+
+        for e in [x() + 1, pow(x(), c(4)), sin(x()) * x(), c(3)] {
+            assert_eq!(e.discriminant(), None, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn high_degrees() {
+        use super::super::prelude::{c, pow, var, x};
+        // Too high a degree is noticed before the power is multiplied out
+        assert_eq!(pow(x() + 1, c(20)).discriminant(), None);
+        assert_eq!(pow(x() + var('k'), c(Num::MAX)).discriminant(), None);
+        assert_eq!(pow(x() + 1, c(1001)).coefficients_in_x(), None);
+        // The coefficients are collected as the power is multiplied out
+        let coeffs = pow(x() + var('k'), c(30)).coefficients_in_x().unwrap();
+        assert_eq!(coeffs.len(), 31);
+        assert_eq!(coeffs[29], var('k') * 30);
+    }
+}
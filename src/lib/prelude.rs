@@ -0,0 +1,183 @@
+//! Short functions for building expressions, so that `sin(x()) * c(3) + pow(x(), c(2))` can be
+//! written instead of `Expr::X.sin() * Expr::Const(3) + Expr::X.pow(Expr::Const(2))`.
+//!
+//! Import everything with `use prelude::*`, which also brings in [`Expr`] and [`VarId`].
+
+use super::Num;
+pub use super::{Expr, VarId};
+
+/// x
+pub fn x() -> Expr {
+    Expr::X
+}
+
+/// A constant, see [`Expr::Const`]
+pub fn c(n: Num) -> Expr {
+    Expr::Const(n)
+}
+
+/// A variable other than x, see [`Expr::Var`]
+pub fn var(name: char) -> Expr {
+    Expr::Var(VarId(name))
+}
+
+/// The imaginary unit i
+pub fn i() -> Expr {
+    Expr::I
+}
+
+/// Euler's number e
+pub fn e() -> Expr {
+    Expr::E
+}
+
+/// π, as `\arccos(-1)` since there isn't a constant for it. It is still written as `\pi` by
+/// [`Expr::to_latex`], and multiples of π/6 and π/4 in sin and cos are worked out exactly by
+/// [`Expr::fold_constants`].
+pub fn pi() -> Expr {
+    Expr::Arccos(Box::new(Expr::Const(-1)))
+}
+
+/// A constant multiplied by a power of ten, see [`Expr::Scaled`]
+pub fn scaled(mantissa: Num, exp10: i32) -> Expr {
+    Expr::Scaled(mantissa, exp10)
+}
+
+/// The sum of some expressions
+pub fn sum(terms: Vec<Expr>) -> Expr {
+    Expr::Sum(terms.into_iter().map(Expr::normalized_sign).collect())
+}
+
+/// The product of some expressions
+pub fn prod(factors: Vec<Expr>) -> Expr {
+    Expr::Prod(factors.into_iter().map(Expr::normalized_sign).collect())
+}
+
+/// The negative of an expression
+pub fn neg(e: Expr) -> Expr {
+    -e
+}
+
+/// One expression to the power of another
+pub fn pow(a: Expr, b: Expr) -> Expr {
+    a.pow(b)
+}
+
+/// The reciprocal of an expression, see [`Expr::recip`]
+pub fn recip(e: Expr) -> Expr {
+    e.recip()
+}
+
+/// The square root of an expression
+pub fn sqrt(e: Expr) -> Expr {
+    e.sqrt()
+}
+
+/// e to the power of an expression
+pub fn exp(e: Expr) -> Expr {
+    e.exp()
+}
+
+/// The ln of an expression
+pub fn ln(e: Expr) -> Expr {
+    e.ln()
+}
+
+/// The sin of an expression
+pub fn sin(e: Expr) -> Expr {
+    e.sin()
+}
+
+/// The cos of an expression
+pub fn cos(e: Expr) -> Expr {
+    e.cos()
+}
+
+/// The arcsin of an expression
+pub fn arcsin(e: Expr) -> Expr {
+    Expr::Arcsin(Box::new(e.normalized_sign()))
+}
+
+/// The arccos of an expression
+pub fn arccos(e: Expr) -> Expr {
+    Expr::Arccos(Box::new(e.normalized_sign()))
+}
+
+/// The arctan of an expression
+pub fn arctan(e: Expr) -> Expr {
+    Expr::Arctan(Box::new(e.normalized_sign()))
+}
+
+/// The sinh of an expression
+pub fn sinh(e: Expr) -> Expr {
+    e.sinh()
+}
+
+/// The cosh of an expression
+pub fn cosh(e: Expr) -> Expr {
+    e.cosh()
+}
+
+/// The tanh of an expression
+pub fn tanh(e: Expr) -> Expr {
+    e.tanh()
+}
+
+/// The arsinh of an expression
+pub fn arsinh(e: Expr) -> Expr {
+    Expr::Arsinh(Box::new(e.normalized_sign()))
+}
+
+/// The arcosh of an expression
+pub fn arcosh(e: Expr) -> Expr {
+    Expr::Arcosh(Box::new(e.normalized_sign()))
+}
+
+/// The artanh of an expression
+pub fn artanh(e: Expr) -> Expr {
+    Expr::Artanh(Box::new(e.normalized_sign()))
+}
+
+/// The factorial of an expression
+pub fn factorial(e: Expr) -> Expr {
+    e.factorial()
+}
+
+/// The sum of `body` for each whole number value of `index` from `from` to `to`, see
+/// [`Expr::SumOver`]
+pub fn sum_over(index: char, from: Expr, to: Expr, body: Expr) -> Expr {
+    body.sum_over(VarId(index), from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constructors() {
+        assert_eq!(
+            sin(x()) * c(3) + pow(x(), c(2)),
+            Expr::X.sin() * Expr::Const(3) + Expr::X.pow(Expr::Const(2))
+        );
+        assert_eq!(
+            sum(vec![neg(var('y')), prod(vec![i(), e()])]),
+            Expr::Sum(vec![
+                Expr::Neg(Box::new(Expr::Var(VarId('y')))),
+                Expr::Prod(vec![Expr::I, Expr::E])
+            ])
+        );
+        assert_eq!(sqrt(x()), x().pow(recip(c(2))));
+        assert_eq!(exp(ln(x())), Expr::E.pow(Expr::X.ln()));
+        assert_eq!(arctan(x()).kind(), super::super::ExprKind::Arctan);
+        assert_eq!(
+            sum_over('k', c(1), x(), var('k')),
+            Expr::SumOver(
+                VarId('k'),
+                Box::new(Expr::Const(1)),
+                Box::new(Expr::X),
+                Box::new(Expr::Var(VarId('k')))
+            )
+        );
+        assert_eq!(Expr::constant(5), c(5));
+    }
+}
@@ -0,0 +1,435 @@
+use std::fmt;
+
+use super::{Expr, ExprKind, Num, Rule, Step};
+
+/// The most nodes the simplified answer to a generated problem is allowed to have, so that the
+/// answers stay something a student could write out.
+const MAX_ANSWER_NODES: usize = 40;
+
+/// How many times to try generating a problem with a reasonable answer before falling back to an
+/// easier one.
+const MAX_ATTEMPTS: usize = 100;
+
+/// A small deterministic random number generator (splitmix64). Problems generated from the same
+/// seed are always the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Get the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Get a random number in `0..n`.
+    ///
+    /// # Panics
+    /// Panics if n is 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "the range should not be empty");
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Get a random number in `lo..=hi`.
+    pub fn range(&mut self, lo: Num, hi: Num) -> Num {
+        lo + self.below((hi - lo) as usize + 1) as Num
+    }
+
+    /// Get true with a probability of 1/2.
+    pub fn coin(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+}
+
+/// How hard a generated problem is, which decides the rules needed to solve it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum Difficulty {
+    /// Polynomials, which only need the power rule
+    Easy,
+    /// Products of polynomials, which also need the product rule
+    Medium,
+    /// Trig and hyperbolic functions and ln of polynomials, which also need the chain rule
+    Hard,
+}
+
+impl Difficulty {
+    /// The rules which can be needed by a problem of this difficulty.
+    pub fn rules(self) -> &'static [Rule] {
+        const EASY: &[Rule] = &[
+            Rule::Constant,
+            Rule::Variable,
+            Rule::Sum,
+            Rule::Negation,
+            Rule::ConstantMultiple,
+            Rule::Power,
+        ];
+        const MEDIUM: &[Rule] = &[
+            Rule::Constant,
+            Rule::Variable,
+            Rule::Sum,
+            Rule::Negation,
+            Rule::ConstantMultiple,
+            Rule::Power,
+            Rule::Product,
+        ];
+        const HARD: &[Rule] = &[
+            Rule::Constant,
+            Rule::Variable,
+            Rule::Sum,
+            Rule::Negation,
+            Rule::ConstantMultiple,
+            Rule::Power,
+            Rule::Product,
+            Rule::Chain,
+            Rule::Sin,
+            Rule::Cos,
+            Rule::Ln,
+            Rule::Sinh,
+            Rule::Cosh,
+            Rule::Tanh,
+        ];
+        match self {
+            Difficulty::Easy => EASY,
+            Difficulty::Medium => MEDIUM,
+            Difficulty::Hard => HARD,
+        }
+    }
+}
+
+/// A generated "differentiate this" problem with its worked answer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Problem {
+    /// The expression to differentiate
+    pub expr: Expr,
+    /// The simplified derivative
+    pub derivative: Expr,
+    /// The expression to differentiate as latex
+    pub question: String,
+    /// The simplified derivative as latex
+    pub answer: String,
+    /// The working, from [`Expr::derivative_with_steps`]
+    pub steps: Vec<Step>,
+}
+
+/// Generate a random expression to differentiate, along with its answer and working. The same
+/// state of `rng` always gives the same problem.
+///
+/// Medium problems need the product rule, and hard problems need the chain rule. If no problem
+/// with a short enough answer is found after [`MAX_ATTEMPTS`] tries, an error is given rather
+/// than a problem of another difficulty.
+pub fn generate_derivative_problem(
+    rng: &mut Rng,
+    difficulty: Difficulty,
+) -> Result<Problem, GenError> {
+    let config = GenConfig {
+        difficulty,
+        ..GenConfig::default()
+    };
+    generate_problem(rng, &config)
+}
+
+/// A closure which says whether to throw away a generated expression, see
+/// [`GenConfig::reject_if`]
+pub type Rejection = Box<dyn Fn(&Expr) -> bool>;
+
+/// Settings for [`generate_problem`], which say what a generated problem has to be like so that
+/// it makes a good question. Problems always have an answer of at most [`MAX_ANSWER_NODES`]
+/// nodes.
+pub struct GenConfig {
+    /// How hard the problem is
+    pub difficulty: Difficulty,
+    /// The fewest nodes the simplified expression can have, so that it doesn't simplify to
+    /// something trivial like a constant
+    pub min_simplified_nodes: usize,
+    /// The most nodes the simplified expression can have
+    pub max_simplified_nodes: Option<usize>,
+    /// Kinds of expression which have to appear somewhere in the expression, e.g.
+    /// [`ExprKind::Sin`]
+    pub must_contain_kinds: Vec<ExprKind>,
+    /// Rules which the working for the derivative has to use (see [`Step::uses`])
+    pub derivative_must_need: Vec<Rule>,
+    /// Expressions which any of these return true for are thrown away, see
+    /// [`GenConfig::reject_if`]
+    pub rejections: Vec<Rejection>,
+    /// How many expressions are generated before giving up
+    pub max_attempts: usize,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        GenConfig {
+            difficulty: Difficulty::Easy,
+            min_simplified_nodes: 0,
+            max_simplified_nodes: None,
+            must_contain_kinds: vec![],
+            derivative_must_need: vec![],
+            rejections: vec![],
+            max_attempts: MAX_ATTEMPTS,
+        }
+    }
+}
+
+impl GenConfig {
+    /// Throw away generated expressions which `reject` returns true for
+    pub fn reject_if(mut self, reject: impl Fn(&Expr) -> bool + 'static) -> Self {
+        self.rejections.push(Box::new(reject));
+        self
+    }
+
+    /// Whether a problem meets all of the constraints
+    fn accepts(&self, problem: &Problem) -> bool {
+        if problem.derivative.node_count() > MAX_ANSWER_NODES {
+            return false;
+        }
+        let uses = |rule: &Rule| problem.steps.iter().any(|s| s.uses(*rule));
+        if !self.derivative_must_need.iter().all(uses) {
+            return false;
+        }
+        let contains = |kind: &ExprKind| contains_kind(&problem.expr, *kind);
+        if !self.must_contain_kinds.iter().all(contains) {
+            return false;
+        }
+        if self.min_simplified_nodes > 0 || self.max_simplified_nodes.is_some() {
+            let nodes = problem.expr.clone().simplified().node_count();
+            let max = self.max_simplified_nodes.unwrap_or(usize::MAX);
+            if !(self.min_simplified_nodes..=max).contains(&nodes) {
+                return false;
+            }
+        }
+        !self.rejections.iter().any(|reject| reject(&problem.expr))
+    }
+}
+
+/// An error from [`generate_problem`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GenError {
+    /// None of the expressions generated met the constraints, which might be impossible to meet
+    Unsatisfiable {
+        /// How many expressions were generated
+        attempts: usize,
+    },
+}
+
+impl fmt::Display for GenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenError::Unsatisfiable { attempts } => {
+                write!(
+                    f,
+                    "none of {attempts} generated problems met the constraints"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GenError {}
+
+/// Generate a random expression to differentiate which meets the constraints in `config`, along
+/// with its answer and working. Expressions are generated until one meets them, up to
+/// [`GenConfig::max_attempts`] times. The same state of `rng` always gives the same problem.
+pub fn generate_problem(rng: &mut Rng, config: &GenConfig) -> Result<Problem, GenError> {
+    for _ in 0..config.max_attempts {
+        let problem = make_problem(random_expr(rng, config.difficulty));
+        if config.accepts(&problem) {
+            return Ok(problem);
+        }
+    }
+    Err(GenError::Unsatisfiable {
+        attempts: config.max_attempts,
+    })
+}
+
+/// Whether an expression of a kind appears anywhere in `e`
+fn contains_kind(e: &Expr, kind: ExprKind) -> bool {
+    e.kind() == kind || e.children().into_iter().any(|e| contains_kind(e, kind))
+}
+
+fn make_problem(expr: Expr) -> Problem {
+    let (mut derivative, steps) = expr
+        .clone()
+        .derivative_with_steps()
+        .expect("generated expressions should be differentiable");
+    derivative.simplify();
+    Problem {
+        question: expr.to_latex(),
+        answer: derivative.to_latex(),
+        expr,
+        derivative,
+        steps,
+    }
+}
+
+fn random_expr(rng: &mut Rng, difficulty: Difficulty) -> Expr {
+    match difficulty {
+        Difficulty::Easy => random_polynomial(rng),
+        Difficulty::Medium => {
+            let product = random_factor(rng) * random_factor(rng);
+            if rng.coin() {
+                product + random_term(rng)
+            } else {
+                product
+            }
+        }
+        Difficulty::Hard => {
+            // Something other than x inside, so that the chain rule is needed
+            let inner = match rng.coin() {
+                true => random_term(rng),
+                false => random_polynomial(rng),
+            };
+            let inner = match inner {
+                Expr::X => Expr::X + rng.range(1, 9),
+                inner => inner,
+            };
+            let function = match rng.below(6) {
+                0 => inner.sin(),
+                1 => inner.cos(),
+                2 => inner.sinh(),
+                3 => inner.cosh(),
+                4 => inner.tanh(),
+                _ => inner.ln(),
+            };
+            match rng.below(3) {
+                0 => function,
+                1 => random_factor(rng) * function,
+                _ => function + random_term(rng),
+            }
+        }
+    }
+}
+
+/// A sum of one to three terms, which always uses x
+fn random_polynomial(rng: &mut Rng) -> Expr {
+    let mut terms = vec![random_term(rng)];
+    for _ in 0..rng.below(3) {
+        terms.push(match rng.below(4) {
+            0 => Expr::Const(rng.range(1, 9)),
+            _ => random_term(rng),
+        });
+    }
+    if terms.len() == 1 {
+        terms.pop().unwrap()
+    } else {
+        Expr::Sum(terms)
+    }
+}
+
+/// Either `x^n` or `cx^n`
+fn random_term(rng: &mut Rng) -> Expr {
+    let power = match rng.range(1, 5) {
+        1 => Expr::X,
+        n => Expr::X.pow(Expr::Const(n)),
+    };
+    match rng.range(1, 9) {
+        1 => power,
+        c => Expr::Const(c) * power,
+    }
+}
+
+/// A factor of a product which uses x, either a term or `x + c`
+fn random_factor(rng: &mut Rng) -> Expr {
+    match rng.coin() {
+        true => random_term(rng),
+        false => Expr::X + rng.range(1, 9),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_by_difficulty() {
+        let mut rng = Rng::new(371);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            for _ in 0..50 {
+                let problem = generate_derivative_problem(&mut rng, difficulty).unwrap();
+                assert!(problem.derivative.node_count() <= MAX_ANSWER_NODES);
+                for step in &problem.steps {
+                    assert!(difficulty.rules().contains(&step.rule), "{step:?}");
+                    assert!(!step.chain || difficulty.rules().contains(&Rule::Chain));
+                }
+                let uses = |rule| problem.steps.iter().any(|s| s.uses(rule));
+                assert!(uses(Rule::Power) || uses(Rule::Variable));
+                match difficulty {
+                    Difficulty::Easy => (),
+                    Difficulty::Medium => assert!(uses(Rule::Product)),
+                    Difficulty::Hard => assert!(uses(Rule::Chain)),
+                }
+                let question = Expr::from_latex(&problem.question).unwrap();
+                assert!(question.equivalent(&problem.expr));
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let problems = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..10)
+                .map(|_| generate_derivative_problem(&mut rng, Difficulty::Hard).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(problems(1), problems(1));
+        assert_ne!(problems(1), problems(2));
+    }
+
+    #[test]
+    fn constraints() {
+        let config = GenConfig {
+            difficulty: Difficulty::Hard,
+            max_simplified_nodes: Some(25),
+            must_contain_kinds: vec![ExprKind::Sin],
+            derivative_must_need: vec![Rule::Chain],
+            ..GenConfig::default()
+        }
+        .reject_if(|e| matches!(e, Expr::Sum(_)));
+        let mut rng = Rng::new(438);
+        let mut problems: Vec<Problem> = vec![];
+        for _ in 0..20 {
+            let problem = generate_problem(&mut rng, &config).unwrap();
+            assert!(problem.expr.clone().simplified().node_count() <= 25);
+            assert!(contains_kind(&problem.expr, ExprKind::Sin));
+            assert!(problem.steps.iter().any(|s| s.uses(Rule::Chain)));
+            assert!(!matches!(problem.expr, Expr::Sum(_)));
+            assert!(
+                !problems.iter().any(|p| p.expr == problem.expr),
+                "{problem:?}"
+            );
+            problems.push(problem);
+        }
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        // Polynomials never need the chain rule
+        let config = GenConfig {
+            derivative_must_need: vec![Rule::Chain],
+            max_attempts: 20,
+            ..GenConfig::default()
+        };
+        assert_eq!(
+            generate_problem(&mut Rng::new(1), &config).map(|p| p.expr),
+            Err(GenError::Unsatisfiable { attempts: 20 })
+        );
+        let config = GenConfig::default().reject_if(|_| true);
+        assert!(generate_problem(&mut Rng::new(1), &config).is_err());
+        // Nothing generated is this big
+        let config = GenConfig {
+            min_simplified_nodes: 1000,
+            ..GenConfig::default()
+        };
+        assert!(generate_problem(&mut Rng::new(1), &config).is_err());
+    }
+}
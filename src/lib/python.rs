@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+
+use super::{CodegenError, Condition, Expr, Num, VarId};
+
+/// Which Python library the code written by [`Expr::to_python`] is for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PyDialect {
+    /// SymPy, with the functions imported with `from sympy import *`, e.g. `sin(x)`, and exact
+    /// rationals like `Rational(1, 2)`, so the expression can be compared symbolically
+    #[default]
+    SymPy,
+    /// NumPy, imported as `np`, e.g. `np.sin(x)`, with rationals written as floats, so the
+    /// expression can be evaluated on arrays
+    NumPy,
+}
+
+/// Settings for how an expression is written by [`Expr::to_python_with`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonOptions {
+    /// The library the code is for
+    pub dialect: PyDialect,
+    /// The Python name of x
+    pub x: String,
+    /// The Python names of the other variables. A variable which isn't in here is written as
+    /// its letter.
+    pub vars: HashMap<VarId, String>,
+}
+
+impl Default for PythonOptions {
+    fn default() -> Self {
+        PythonOptions {
+            dialect: PyDialect::default(),
+            x: "x".to_string(),
+            vars: HashMap::new(),
+        }
+    }
+}
+
+// How tightly each kind of Python expression binds, from loosest to tightest
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const NEG: u8 = 3;
+const POW: u8 = 4;
+const ATOM: u8 = 5;
+
+impl Expr {
+    /// Write the expression as Python code for a dialect, with the default names (see
+    /// [`Expr::to_python_with`]).
+    pub fn to_python(&self, dialect: PyDialect) -> Result<String, CodegenError> {
+        self.to_python_with(&PythonOptions {
+            dialect,
+            ..PythonOptions::default()
+        })
+    }
+
+    /// Write the expression as a Python expression, e.g. `3*x**2 + sin(x)` for SymPy or
+    /// `3*x**2 + np.sin(x)` for NumPy, which expects the variables to be defined already (as
+    /// symbols or arrays).
+    ///
+    /// Brackets are only added where Python needs them. Negative powers in a product are written
+    /// as a division, and a quotient of whole numbers is written as a `Rational` for SymPy,
+    /// since dividing Python integers would give a float, and as a float for NumPy.
+    /// `arccos(-1)` is written as π.
+    ///
+    /// Returns an error if the expression contains a hole or a rate of change, which Python
+    /// has no value for, or, for NumPy, a factorial or a sum over a range.
+    pub fn to_python_with(&self, options: &PythonOptions) -> Result<String, CodegenError> {
+        Ok(self.python(options)?.0)
+    }
+
+    /// The Python code for the expression, along with how tightly it binds
+    fn python(&self, options: &PythonOptions) -> Result<(String, u8), CodegenError> {
+        let sympy = options.dialect == PyDialect::SymPy;
+        let function = |name: &str, numpy: &str, e: &Expr| -> Result<(String, u8), _> {
+            let code = e.python(options)?.0;
+            Ok(match sympy {
+                true => (format!("{name}({code})"), ATOM),
+                false => (format!("np.{numpy}({code})"), ATOM),
+            })
+        };
+        let unsupported = || Err(CodegenError::Unsupported(self.kind()));
+        if let Some((numerator, denominator)) = self.as_fraction() {
+            return fraction(&numerator, &denominator, options);
+        }
+        Ok(match self {
+            Expr::Const(n) if *n < 0 => (n.to_string(), NEG),
+            Expr::Const(n) => (n.to_string(), ATOM),
+            Expr::X => (options.x.clone(), ATOM),
+            Expr::Var(v) => match options.vars.get(v) {
+                Some(name) => (name.clone(), ATOM),
+                None => (v.0.to_string(), ATOM),
+            },
+            Expr::I if sympy => ("I".to_string(), ATOM),
+            Expr::I => ("1j".to_string(), ATOM),
+            Expr::E if sympy => ("E".to_string(), ATOM),
+            Expr::E => ("np.e".to_string(), ATOM),
+            Expr::Scaled(m, e) if !sympy => {
+                let code = format!("{m}e{e}");
+                (code, if *m < 0 { NEG } else { ATOM })
+            }
+            Expr::Scaled(m, e) if *e >= 0 => (format!("{m}*10**{e}"), PRODUCT),
+            Expr::Scaled(m, e) => (format!("Rational({m}, 10**{})", -(*e as i64)), ATOM),
+            Expr::Sum(v) => match v.as_slice() {
+                [] => ("0".to_string(), ATOM),
+                [e] => e.python(options)?,
+                [first, rest @ ..] => {
+                    let mut code = first.python_operand(SUM, options)?;
+                    for term in rest {
+                        match term {
+                            Expr::Neg(e) => {
+                                code += &format!(" - {}", e.python_operand(PRODUCT, options)?)
+                            }
+                            Expr::Const(n) if *n < 0 && *n != Num::MIN => {
+                                code += &format!(" - {}", -n)
+                            }
+                            _ => code += &format!(" + {}", term.python_operand(SUM, options)?),
+                        }
+                    }
+                    (code, SUM)
+                }
+            },
+            Expr::Prod(v) => match v.as_slice() {
+                [] => ("1".to_string(), ATOM),
+                [e] => e.python(options)?,
+                _ => {
+                    let factors = v
+                        .iter()
+                        .enumerate()
+                        // `x*-y` is valid Python, but hard to read
+                        .map(|(i, e)| e.python_operand(if i == 0 { NEG } else { POW }, options))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    (factors.join("*"), PRODUCT)
+                }
+            },
+            Expr::Neg(e) => (format!("-{}", e.python_operand(POW, options)?), NEG),
+            Expr::Pow(a, b) if **a == Expr::E => function("exp", "exp", b)?,
+            Expr::Pow(a, b) => {
+                let base = a.python_operand(ATOM, options)?;
+                (format!("{base}**{}", b.python_operand(POW, options)?), POW)
+            }
+            Expr::Ln(e) => function("log", "log", e)?,
+            Expr::Sin(e) => function("sin", "sin", e)?,
+            Expr::Cos(e) => function("cos", "cos", e)?,
+            Expr::Arcsin(e) => function("asin", "arcsin", e)?,
+            Expr::Arccos(e) if **e == Expr::Const(-1) => match sympy {
+                true => ("pi".to_string(), ATOM),
+                false => ("np.pi".to_string(), ATOM),
+            },
+            Expr::Arccos(e) => function("acos", "arccos", e)?,
+            Expr::Arctan(e) => function("atan", "arctan", e)?,
+            Expr::Sinh(e) => function("sinh", "sinh", e)?,
+            Expr::Cosh(e) => function("cosh", "cosh", e)?,
+            Expr::Tanh(e) => function("tanh", "tanh", e)?,
+            Expr::Arsinh(e) => function("asinh", "arcsinh", e)?,
+            Expr::Arcosh(e) => function("acosh", "arccosh", e)?,
+            Expr::Artanh(e) => function("atanh", "arctanh", e)?,
+            Expr::Factorial(e) if sympy => function("factorial", "", e)?,
+            Expr::SumOver(k, from, to, body) if sympy => {
+                let code = format!(
+                    "Sum({}, ({}, {}, {}))",
+                    body.python(options)?.0,
+                    Expr::Var(*k).python(options)?.0,
+                    from.python(options)?.0,
+                    to.python(options)?.0
+                );
+                (code, ATOM)
+            }
+            Expr::Piecewise(branches) => piecewise(branches, options)?,
+            Expr::Hole(_) | Expr::Deriv(..) | Expr::Factorial(_) | Expr::SumOver(..) => {
+                return unsupported()
+            }
+        })
+    }
+
+    /// The Python code for the expression, with parentheses around it unless it binds at least
+    /// as tightly as `precedence`
+    fn python_operand(
+        &self,
+        precedence: u8,
+        options: &PythonOptions,
+    ) -> Result<String, CodegenError> {
+        let (code, binds) = self.python(options)?;
+        Ok(match binds >= precedence {
+            true => code,
+            false => format!("({code})"),
+        })
+    }
+}
+
+/// Write a quotient. A quotient of whole numbers is a `Rational` or a float, since Python would
+/// divide them as floats. For NumPy a zero denominator gives `np.inf` or `np.nan`, as dividing
+/// by zero does in an array.
+fn fraction(
+    numerator: &Expr,
+    denominator: &Expr,
+    options: &PythonOptions,
+) -> Result<(String, u8), CodegenError> {
+    match (options.dialect, numerator, denominator) {
+        (PyDialect::NumPy, Expr::Const(p), Expr::Const(q)) => {
+            let value = *p as f64 / *q as f64;
+            let code = match value {
+                _ if value.is_nan() => "np.nan".to_string(),
+                f64::INFINITY => "np.inf".to_string(),
+                f64::NEG_INFINITY => "-np.inf".to_string(),
+                _ => format!("{value:?}"),
+            };
+            return Ok((code, if value < 0.0 { NEG } else { ATOM }));
+        }
+        (PyDialect::SymPy, _, _) if is_integer(numerator) && is_integer(denominator) => {
+            let (p, q) = (numerator.python(options)?.0, denominator.python(options)?.0);
+            return Ok((format!("Rational({p}, {q})"), ATOM));
+        }
+        _ => (),
+    }
+    let code = format!(
+        "{}/{}",
+        numerator.python_operand(PRODUCT, options)?,
+        denominator.python_operand(POW, options)?
+    );
+    Ok((code, PRODUCT))
+}
+
+/// Whether an expression is written as Python integers, so dividing by it would give a float
+fn is_integer(e: &Expr) -> bool {
+    match e {
+        Expr::Const(_) => true,
+        Expr::Sum(v) | Expr::Prod(v) => v.iter().all(is_integer),
+        Expr::Neg(e) => is_integer(e),
+        Expr::Pow(a, b) => is_integer(a) && matches!(**b, Expr::Const(n) if n >= 0),
+        _ => false,
+    }
+}
+
+/// Write a piecewise function, as `Piecewise` for SymPy and `np.select` for NumPy, which is NaN
+/// where no condition holds
+fn piecewise(
+    branches: &[(Condition, Expr)],
+    options: &PythonOptions,
+) -> Result<(String, u8), CodegenError> {
+    let condition = |condition: &Condition| -> Result<String, CodegenError> {
+        let side = |e: &Expr| e.python_operand(SUM, options);
+        Ok(match condition {
+            Condition::Less(a, b) => format!("{} < {}", side(a)?, side(b)?),
+            Condition::LessEq(a, b) => format!("{} <= {}", side(a)?, side(b)?),
+            Condition::Equal(a, b) if options.dialect == PyDialect::SymPy => {
+                format!("Eq({}, {})", a.python(options)?.0, b.python(options)?.0)
+            }
+            Condition::Equal(a, b) => format!("{} == {}", side(a)?, side(b)?),
+            Condition::Otherwise => "True".to_string(),
+        })
+    };
+    let code = match options.dialect {
+        PyDialect::SymPy => {
+            let branches = branches
+                .iter()
+                .map(|(c, e)| Ok(format!("({}, {})", e.python(options)?.0, condition(c)?)))
+                .collect::<Result<Vec<_>, CodegenError>>()?;
+            format!("Piecewise({})", branches.join(", "))
+        }
+        PyDialect::NumPy => {
+            let (mut conditions, mut values) = (vec![], vec![]);
+            let mut default = "np.nan".to_string();
+            for (c, e) in branches {
+                let value = e.python(options)?.0;
+                if *c == Condition::Otherwise {
+                    default = value;
+                    break;
+                }
+                conditions.push(condition(c)?);
+                values.push(value);
+            }
+            format!(
+                "np.select([{}], [{}], default={default})",
+                conditions.join(", "),
+                values.join(", ")
+            )
+        }
+    };
+    Ok((code, ATOM))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::super::prelude::{c, e, ln, pi, pow, sin, var, x};
+    use super::super::{ExprKind, HoleId};
+    use super::*;
+
+    /// 3x^2 - sin(x)/(x + 1) + e^{-x}/2 + ln(y) + π
+    fn representative() -> Expr {
+        c(3) * pow(x(), c(2)) - sin(x()) / (x() + 1)
+            + pow(e(), -x()) * (c(1) / 2)
+            + ln(var('y'))
+            + pi()
+    }
+
+    #[test]
+    fn golden() {
+        let e = representative();
+        assert_eq!(
+            e.to_python(PyDialect::SymPy).unwrap(),
+            "3*x**2 - sin(x)/(x + 1) + exp(-x)*Rational(1, 2) + log(y) + pi"
+        );
+        assert_eq!(
+            e.to_python(PyDialect::NumPy).unwrap(),
+            "3*x**2 - np.sin(x)/(x + 1) + np.exp(-x)*0.5 + np.log(y) + np.pi"
+        );
+        let options = PythonOptions {
+            x: "t".to_string(),
+            vars: HashMap::from([(VarId('y'), "y_0".to_string())]),
+            ..PythonOptions::default()
+        };
+        assert_eq!(
+            (x() * var('y') + var('z'))
+                .to_python_with(&options)
+                .unwrap(),
+            "t*y_0 + z"
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        let python = |e: Expr| e.to_python(PyDialect::SymPy).unwrap();
+        assert_eq!(python(-pow(x(), c(2))), "-x**2");
+        assert_eq!(python(pow(-x(), c(2))), "(-x)**2");
+        assert_eq!(python(pow(c(-2), x())), "(-2)**x");
+        assert_eq!(python(pow(pow(x(), c(2)), c(3))), "(x**2)**3");
+        assert_eq!(python(pow(x(), -x())), "x**(-x)");
+        assert_eq!(python(pow(x(), c(1) / 2)), "x**Rational(1, 2)");
+        assert_eq!(python(x() * (x() + 1) * -x()), "x*(x + 1)*(-x)");
+        assert_eq!(python(x() - (x() - 1)), "x - (x - 1)");
+        assert_eq!(python(x() / (x() * 2)), "x/(x*2)");
+        assert_eq!(python(x().recip()), "1/x");
+        assert_eq!(python(pow(x(), c(-2))), "1/x**2");
+        // Whole numbers are divided exactly
+        assert_eq!(python(c(1) / (c(2) + 3)), "Rational(1, 2 + 3)");
+        assert_eq!(python(pow(c(2), c(-3))), "Rational(1, 2**3)");
+        assert_eq!(python(Expr::Scaled(15, -1)), "Rational(15, 10**1)");
+        assert_eq!(
+            Expr::Scaled(-15, -1).to_python(PyDialect::NumPy).unwrap(),
+            "-15e-1"
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        assert_eq!(
+            (x() + Expr::Hole(HoleId(0))).to_python(PyDialect::SymPy),
+            Err(CodegenError::Unsupported(ExprKind::Hole))
+        );
+        assert_eq!(
+            Expr::Deriv(VarId('x'), VarId('t')).to_python(PyDialect::SymPy),
+            Err(CodegenError::Unsupported(ExprKind::Deriv))
+        );
+        let factorial = Expr::Factorial(Box::new(x()));
+        assert_eq!(
+            factorial.to_python(PyDialect::SymPy).unwrap(),
+            "factorial(x)"
+        );
+        assert_eq!(
+            factorial.to_python(PyDialect::NumPy),
+            Err(CodegenError::Unsupported(ExprKind::Factorial))
+        );
+        let abs = Expr::Piecewise(vec![
+            (Condition::Less(x(), c(0)), -x()),
+            (Condition::Otherwise, x()),
+        ]);
+        assert_eq!(
+            abs.to_python(PyDialect::SymPy).unwrap(),
+            "Piecewise((-x, x < 0), (x, True))"
+        );
+        assert_eq!(
+            abs.to_python(PyDialect::NumPy).unwrap(),
+            "np.select([x < 0], [-x], default=x)"
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let quotient = |p, q| Expr::Prod(vec![c(p), pow(c(q), c(-1))]);
+        let numpy = |e: Expr| e.to_python(PyDialect::NumPy).unwrap();
+        assert_eq!(numpy(quotient(1, 0)), "np.inf");
+        assert_eq!(numpy(quotient(-3, 0)), "-np.inf");
+        assert_eq!(numpy(quotient(0, 0)), "np.nan");
+        assert_eq!(numpy(quotient(3, 4)), "0.75");
+        assert_eq!(numpy(x() * quotient(-1, 0)), "x*(-np.inf)");
+    }
+
+    /// Check that SymPy reads the code, and gives the same values as eval
+    #[test]
+    #[ignore = "needs python with sympy"]
+    fn sympy() {
+        let exprs = [
+            representative(),
+            pow(x() + 3, c(1) / 2) - c(1) / (c(2) + 3),
+            Expr::Scaled(-15, -1) * x() / (x().cos() + 3),
+        ];
+        let mut script = "from sympy import *\nx, y = symbols('x y')\n".to_string();
+        for e in &exprs {
+            let code = e.to_python(PyDialect::SymPy).unwrap();
+            script += &format!("print(N(({code}).subs({{x: 0.7, y: 2}})))\n");
+        }
+        let output = Command::new("python3")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        let values: Vec<f64> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        assert_eq!(values.len(), exprs.len());
+        for (value, e) in values.iter().zip(&exprs) {
+            let expected = e.substitute_var(VarId('y'), &c(2)).eval(0.7).unwrap();
+            assert!((value - expected).abs() <= 1e-9 * expected.abs().max(1.0));
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use super::fold::float_const;
+use super::prelude::pi;
+use super::{Expr, ExprKind, Interval, MathError, Num};
+
+/// How far the value of a numeric integral can be from the true value
+const TOLERANCE: f64 = 1e-10;
+
+/// The most times an interval is halved by [`Expr::integrate_numeric`]
+const MAX_DEPTH: usize = 40;
+
+/// The most times [`Expr::integrate_numeric`] evaluates the expression before giving up
+const MAX_EVALUATIONS: usize = 1_000_000;
+
+/// The number of pieces [`area_between`] splits an interval into to look for crossings of
+/// curves which aren't polynomials
+const CROSSING_SAMPLES: usize = 200;
+
+/// A volume of a solid of revolution, from [`Expr::volume_disk`] or [`Expr::volume_shell`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    /// The volume
+    pub value: Expr,
+    /// How the integral in it was found
+    pub method: IntegrationMethod,
+}
+
+/// How an integral was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrationMethod {
+    /// With an antiderivative, so the value is exact
+    Symbolic,
+    /// With [`Expr::integrate_numeric`], so the value is a float constant
+    Numeric,
+}
+
+impl Expr {
+    /// Work out the integral of an expression in x from `a` to `b` numerically, with adaptive
+    /// Simpson's rule: each piece of the interval is halved until Simpson's rule on the halves
+    /// agrees with Simpson's rule on the whole piece.
+    ///
+    /// Returns an error if the expression can't be evaluated somewhere it is sampled, e.g. when
+    /// it has a pole in the interval, or if a value is too big for a float. It is also an error
+    /// if the pieces still don't agree after [`MAX_EVALUATIONS`] samples.
+    pub fn integrate_numeric(&self, a: f64, b: f64) -> Result<f64, MathError> {
+        let f = |x: f64| -> Result<f64, MathError> {
+            match self.eval(x)? {
+                y if y.is_finite() => Ok(y),
+                _ => Err(MathError::Overflow { op: self.kind() }),
+            }
+        };
+        let (fa, fm, fb) = (f(a)?, f(a + (b - a) / 2.0)?, f(b)?);
+        let mut evaluations = MAX_EVALUATIONS;
+        adaptive_simpson(
+            &f,
+            a,
+            b,
+            (fa, fm, fb),
+            TOLERANCE,
+            MAX_DEPTH,
+            &mut evaluations,
+        )
+    }
+
+    /// The length of the curve `y = f(x)` from `a` to `b`, which is `\int_a^b \sqrt{1 + f'(x)^2}
+    /// dx`. The derivative is found symbolically, and the integral numerically (see
+    /// [`Expr::integrate_numeric`]), since it rarely has an antiderivative which can be written
+    /// down.
+    pub fn arc_length(&self, a: f64, b: f64) -> Result<f64, MathError> {
+        let derivative = self.clone().try_derivative()?;
+        let integrand = (derivative.pow(Expr::Const(2)) + 1).sqrt().simplified();
+        integrand.integrate_numeric(a, b)
+    }
+
+    /// The volume of the solid made by turning the curve `y = f(x)` from `a` to `b` around the
+    /// x axis, which is `\pi \int_a^b f(x)^2 dx` (the disk method).
+    ///
+    /// See [`Expr::volume_shell`] for how the integral is found.
+    pub fn volume_disk(&self, a: f64, b: f64) -> Result<Volume, MathError> {
+        pi_times_integral(self.clone().pow(Expr::Const(2)), a, b)
+    }
+
+    /// The volume of the solid made by turning the curve `y = f(x)` from `a` to `b` around the
+    /// y axis, which is `2\pi \int_a^b xf(x) dx` (the shell method).
+    ///
+    /// The integrand is multiplied out first, so it is a polynomial when f is. It is integrated
+    /// exactly when `a` and `b` are whole numbers and it has an antiderivative (see
+    /// [`Expr::integrate_definite`]), and numerically otherwise. There isn't a constant for π, so
+    /// an exact volume has `\arccos(-1)` in it, which is written as `\pi`.
+    pub fn volume_shell(&self, a: f64, b: f64) -> Result<Volume, MathError> {
+        let volume = pi_times_integral(Expr::X * self.clone(), a, b)?;
+        Ok(Volume {
+            value: (volume.value * 2).simplified(),
+            ..volume
+        })
+    }
+}
+
+/// π times the integral of an expression from `a` to `b`, exactly if possible
+fn pi_times_integral(integrand: Expr, a: f64, b: f64) -> Result<Volume, MathError> {
+    let mut integrand = integrand.simplified();
+    integrand.simplify_expand_polynomial();
+    let exact = match (whole(a), whole(b)) {
+        (Some(a), Some(b)) => integrand.integrate_definite(a, b),
+        _ => None,
+    };
+    if let Some(integral) = exact {
+        return Ok(Volume {
+            value: (pi() * integral).simplified(),
+            method: IntegrationMethod::Symbolic,
+        });
+    }
+    let value = std::f64::consts::PI * integrand.integrate_numeric(a, b)?;
+    Ok(Volume {
+        // The integral is only infinite if the integrand is too big to add up somewhere
+        value: float_const(value).ok_or(MathError::Overflow {
+            op: integrand.kind(),
+        })?,
+        method: IntegrationMethod::Numeric,
+    })
+}
+
+/// A float as a [`Num`], if it is a whole number which fits exactly
+fn whole(v: f64) -> Option<Num> {
+    match float_const(v)? {
+        Expr::Const(n) => Some(n),
+        _ => None,
+    }
+}
+
+/// The area between the curves `y = f(x)` and `y = g(x)` from `a` to `b`.
+///
+/// The interval is split at the points where the curves cross, and the size of the integral of
+/// `f - g` over each piece is added up, so that pieces where g is above f don't cancel out
+/// pieces where it is below. The crossings are found exactly with [`Expr::isolate_roots`] when
+/// `f - g` is a polynomial, and otherwise by looking for sign changes between some samples.
+pub fn area_between(f: &Expr, g: &Expr, a: f64, b: f64) -> Result<f64, MathError> {
+    let (a, b) = (a.min(b), a.max(b));
+    let difference = (f.clone() - g.clone()).simplified();
+    let crossings = match difference.polynomial_roots(Interval::new(a, b), 1e-12) {
+        Some(roots) => roots,
+        None => sign_changes(&difference, a, b),
+    };
+    let mut points = vec![a];
+    points.extend(crossings.into_iter().filter(|x| a < *x && *x < b));
+    points.push(b);
+    points.windows(2).try_fold(0.0, |area, piece| {
+        Ok(area + difference.integrate_numeric(piece[0], piece[1])?.abs())
+    })
+}
+
+/// The points between `a` and `b` where an expression changes sign, found by bisecting between
+/// samples with different signs. Samples where it can't be evaluated are skipped.
+fn sign_changes(e: &Expr, a: f64, b: f64) -> Vec<f64> {
+    let f = |x: f64| e.eval(x).ok().filter(|y| y.is_finite());
+    let step = (b - a) / CROSSING_SAMPLES as f64;
+    let samples: Vec<(f64, Option<f64>)> = (0..=CROSSING_SAMPLES)
+        .map(|i| a + i as f64 * step)
+        .map(|x| (x, f(x)))
+        .collect();
+    let mut changes = vec![];
+    for pair in samples.windows(2) {
+        let [(mut lo, Some(f_lo)), (mut hi, Some(f_hi))] = *pair else {
+            continue;
+        };
+        if f_lo == 0.0 {
+            changes.push(lo);
+            continue;
+        }
+        if f_lo * f_hi >= 0.0 {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = lo + (hi - lo) / 2.0;
+            match f(mid) {
+                Some(y) if (y > 0.0) == (f_lo > 0.0) => lo = mid,
+                Some(_) => hi = mid,
+                None => break,
+            }
+        }
+        changes.push(lo + (hi - lo) / 2.0);
+    }
+    changes
+}
+
+/// Simpson's rule from `a` to `b`, given the values at a, the middle and b
+fn simpson(a: f64, b: f64, (fa, fm, fb): (f64, f64, f64)) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+/// Simpson's rule on `a` to `b`, halving the interval until the halves agree with the whole.
+/// `evaluations` is how many more times `f` can be called.
+fn adaptive_simpson(
+    f: &impl Fn(f64) -> Result<f64, MathError>,
+    a: f64,
+    b: f64,
+    (fa, fm, fb): (f64, f64, f64),
+    tolerance: f64,
+    depth: usize,
+    evaluations: &mut usize,
+) -> Result<f64, MathError> {
+    *evaluations = evaluations.checked_sub(2).ok_or(MathError::NotConverged)?;
+    let m = a + (b - a) / 2.0;
+    let (left_mid, right_mid) = (a + (m - a) / 2.0, m + (b - m) / 2.0);
+    let (fl, fr) = (f(left_mid)?, f(right_mid)?);
+    let whole = simpson(a, b, (fa, fm, fb));
+    let left = simpson(a, m, (fa, fl, fm));
+    let right = simpson(m, b, (fm, fr, fb));
+    let error = left + right - whole;
+    // The samples are finite, so this can only be from adding them up
+    if !error.is_finite() {
+        return Err(MathError::Overflow { op: ExprKind::Sum });
+    }
+    if depth == 0 || error.abs() <= 15.0 * tolerance {
+        // Richardson extrapolation, which cancels out the leading error term
+        return Ok(left + right + error / 15.0);
+    }
+    let tolerance = tolerance / 2.0;
+    let left = adaptive_simpson(f, a, m, (fa, fl, fm), tolerance, depth - 1, evaluations)?;
+    let right = adaptive_simpson(f, m, b, (fm, fr, fb), tolerance, depth - 1, evaluations)?;
+    Ok(left + right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{arccos, c, pow, sin, x};
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-8
+    }
+
+    #[test]
+    fn integrals() {
+        assert!(close(
+            pow(x(), c(2)).integrate_numeric(0.0, 3.0).unwrap(),
+            9.0
+        ));
+        let pi = std::f64::consts::PI;
+        assert!(close(sin(x()).integrate_numeric(0.0, pi).unwrap(), 2.0));
+        assert!(close(
+            x().recip().integrate_numeric(1.0, 5.0).unwrap(),
+            5f64.ln()
+        ));
+        // The pole at 0 is sampled
+        assert!(x().recip().integrate_numeric(-1.0, 1.0).is_err());
+        // Too big for a float
+        assert_eq!(
+            pow(x(), c(400)).integrate_numeric(1.0, 10.0),
+            Err(MathError::Overflow { op: ExprKind::Pow })
+        );
+        assert_eq!(
+            x().integrate_numeric(f64::MAX / 2.0, f64::MAX),
+            Err(MathError::Overflow { op: ExprKind::Sum })
+        );
+        // Never settles down, since it is different at every scale
+        let e = sin(x().recip()) * x().recip();
+        assert_eq!(
+            e.integrate_numeric(1e-12, 1.0),
+            Err(MathError::NotConverged)
+        );
+    }
+
+    #[test]
+    fn arc_lengths() {
+        assert!(close(x().arc_length(0.0, 1.0).unwrap(), 2f64.sqrt()));
+        // A semicircle of radius 1, without the ends where the derivative is infinite
+        let circle = (c(1) - pow(x(), c(2))).sqrt();
+        let (a, b) = (-0.5f64, 0.5f64);
+        assert!(close(circle.arc_length(a, b).unwrap(), b.asin() - a.asin()));
+        // y = x^{3/2} has a length which can be worked out exactly
+        let e = pow(x(), c(3) / 2);
+        let exact = (13f64.powf(1.5) - 8.0) / 27.0;
+        assert!(close(e.arc_length(0.0, 1.0).unwrap(), exact));
+    }
+
+    #[test]
+    fn areas() {
+        let square = pow(x(), c(2));
+        // The curves cross at 1, and both pieces count
+        assert!(close(area_between(&square, &x(), 0.0, 2.0).unwrap(), 1.0));
+        assert!(close(area_between(&x(), &square, 2.0, 0.0).unwrap(), 1.0));
+        // sin x crosses 0 at π, which is found by sampling
+        let pi = std::f64::consts::PI;
+        assert!(close(
+            area_between(&sin(x()), &c(0), 0.0, 2.0 * pi).unwrap(),
+            4.0
+        ));
+    }
+
+    #[test]
+    fn huge_volume() {
+        // The integral is about 10^308, which is too big once it is multiplied by π
+        let e = Expr::Scaled(1, 150);
+        assert_eq!(
+            e.volume_disk(0.5, 1e8 + 0.5),
+            Err(MathError::Overflow { op: ExprKind::Pow })
+        );
+    }
+
+    #[test]
+    fn volumes() {
+        // A cone of radius 1 and height 1
+        let cone = x().volume_disk(0.0, 1.0).unwrap();
+        assert_eq!(cone.method, IntegrationMethod::Symbolic);
+        assert!(
+            cone.value.equivalent(&(arccos(c(-1)) / 3)),
+            "{:?}",
+            cone.value
+        );
+        assert!(close(cone.value.eval(0.0).unwrap(), PI / 3.0));
+        // sin^2 is integrated numerically, since π isn't a whole number
+        let e = sin(x()).volume_disk(0.0, PI).unwrap();
+        assert_eq!(e.method, IntegrationMethod::Numeric);
+        assert!(close(e.value.eval(0.0).unwrap(), PI * PI / 2.0));
+        // Turning y = x + 1 on [0, 2] around the y axis gives 2π(8/3 + 2)
+        let shell = (x() + 1).volume_shell(0.0, 2.0).unwrap();
+        assert_eq!(shell.method, IntegrationMethod::Symbolic);
+        assert!(close(shell.value.eval(0.0).unwrap(), 2.0 * PI * 14.0 / 3.0));
+        let shell = (x() + 1).volume_shell(0.0, 2.5).unwrap();
+        assert_eq!(shell.method, IntegrationMethod::Numeric);
+        assert!(close(
+            shell.value.eval(0.0).unwrap(),
+            2.0 * PI * (2.5f64.powi(3) / 3.0 + 3.125)
+        ));
+    }
+}
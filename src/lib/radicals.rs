@@ -0,0 +1,194 @@
+use super::assumptions::Allowance;
+use super::{Assumptions, Expr, Num, Strictness};
+
+/// Only factors up to this size are looked for when taking perfect powers out of a root, so that
+/// roots of huge primes don't take forever.
+const MAX_FACTOR: Num = 100_000;
+
+impl Expr {
+    /// If this expression is `1/n` for a whole number `n >= 2` (written as `n^-1` or `1 * n^-1`),
+    /// get n. An expression to this power is an nth root.
+    pub fn root_index(&self) -> Option<Num> {
+        match self {
+            Expr::Pow(a, b) => match (a.as_ref(), b.as_ref()) {
+                (Expr::Const(n), Expr::Const(-1)) if *n >= 2 => Some(*n),
+                _ => None,
+            },
+            Expr::Prod(v) => match v.as_slice() {
+                [Expr::Const(1), e] | [e, Expr::Const(1)] => e.root_index(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether this is a root of a constant, e.g. `sqrt(3)`
+    pub(super) fn is_root_of_const(&self) -> bool {
+        matches!(self, Expr::Pow(a, b) if matches!(**a, Expr::Const(_)) && b.root_index().is_some())
+    }
+
+    /// This function takes perfect powers out of roots of constants
+    /// e.g. `sqrt(12) = 2sqrt(3)` and `sqrt(16) = 4`
+    pub fn simplify_root_of_const(&mut self) {
+        let Expr::Pow(a, b) = self else {
+            return;
+        };
+        let (Expr::Const(n), Some(k)) = (a.as_ref(), b.root_index()) else {
+            return;
+        };
+        let (Some(k), true) = (u32::try_from(k).ok(), *n > 0) else {
+            return;
+        };
+
+        let mut outside: Num = 1;
+        let mut inside = *n;
+        let mut factor: Num = 2;
+        while factor <= MAX_FACTOR {
+            let Some(power) = factor.checked_pow(k) else {
+                break;
+            };
+            if power > inside {
+                break;
+            }
+            while inside % power == 0 {
+                inside /= power;
+                outside *= factor;
+            }
+            factor += 1;
+        }
+
+        if outside != 1 {
+            *self = if inside == 1 {
+                Expr::Const(outside)
+            } else {
+                Expr::Const(outside) * Expr::Const(inside).pow(std::mem::take(&mut **b))
+            };
+        }
+    }
+
+    /// This function cancels the nth root of something to the nth power, when n is odd
+    /// e.g. `cbrt(x^3) = x`
+    ///
+    /// For even n this isn't true when the base is negative (`sqrt(x^2) = |x|`), see
+    /// [`Expr::simplify_root_of_even_pow`].
+    pub fn simplify_root_of_pow(&mut self) {
+        if matches!(self.root_of_pow(), Some((_, n)) if n % 2 == 1) {
+            self.cancel_root_of_pow();
+        }
+    }
+
+    /// This function cancels the nth root of something to the nth power, for any n, when the
+    /// base is non-negative
+    /// e.g. `sqrt(x^2) = x` when x >= 0
+    pub fn simplify_root_of_even_pow(&mut self, assumptions: &Assumptions) {
+        self.root_of_even_pow(&mut Allowance::new(assumptions, Strictness::Refuse));
+    }
+
+    pub(super) fn root_of_even_pow(&mut self, allowance: &mut Allowance) {
+        let Some((base, _)) = self.root_of_pow() else {
+            return;
+        };
+        let justified = allowance.assumptions.is_nonnegative(base);
+        let nonnegative = || format!("{} \\geq 0", base.to_latex());
+        if allowance.allows(justified, "root of even power", nonnegative, self) {
+            self.cancel_root_of_pow();
+        }
+    }
+
+    /// If this is the nth root of something to the nth power, get the something and n
+    fn root_of_pow(&self) -> Option<(&Expr, Num)> {
+        let Expr::Pow(a, b) = self else {
+            return None;
+        };
+        let Expr::Pow(base, exponent) = a.as_ref() else {
+            return None;
+        };
+        let n = b.root_index()?;
+        (**exponent == Expr::Const(n)).then_some((base, n))
+    }
+
+    fn cancel_root_of_pow(&mut self) {
+        if let Expr::Pow(a, _) = self {
+            if let Expr::Pow(base, _) = a.as_mut() {
+                *self = std::mem::take(base.as_mut());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::SimplifyOptions;
+    use super::*;
+
+    fn root(n: Num) -> Expr {
+        Expr::Const(n).recip()
+    }
+
+    #[test]
+    fn root_index() {
+        assert_eq!(root(2).root_index(), Some(2));
+        assert_eq!((Expr::Const(1) / 3).root_index(), Some(3));
+        assert_eq!(Expr::Const(2).root_index(), None);
+        assert_eq!(Expr::X.sqrt(), Expr::X.pow(root(2)));
+    }
+
+    #[test]
+    fn perfect_powers() {
+        let mut e = Expr::Const(12).sqrt();
+        e.simplify();
+        assert_eq!(e, Expr::Const(2) * Expr::Const(3).sqrt());
+
+        let mut e = Expr::Const(16).sqrt();
+        e.simplify();
+        assert_eq!(e, Expr::Const(4));
+
+        let mut e = Expr::Const(54).pow(root(3));
+        e.simplify();
+        assert_eq!(e, Expr::Const(3) * Expr::Const(2).pow(root(3)));
+
+        let mut e = Expr::Const(7).sqrt();
+        e.simplify();
+        assert_eq!(e, Expr::Const(7).sqrt());
+    }
+
+    #[test]
+    fn roots_of_powers() {
+        let square = Expr::X.pow(Expr::Const(2)).sqrt();
+        let mut e = square.clone();
+        e.simplify();
+        assert_eq!(e, square);
+
+        let options = SimplifyOptions {
+            assumptions: Assumptions::positive(),
+            ..Default::default()
+        };
+        e.simplify_with(&options);
+        assert_eq!(e, Expr::X);
+
+        let mut e = Expr::X.pow(Expr::Const(3)).pow(root(3));
+        e.simplify();
+        assert_eq!(e, Expr::X);
+    }
+
+    #[test]
+    fn latex() {
+        assert_eq!((Expr::X + 1).sqrt().to_latex(), "\\sqrt{x+1}");
+        assert_eq!(Expr::X.pow(root(3)).to_latex(), "\\sqrt[3]{x}");
+    }
+
+    #[test]
+    fn derivative() {
+        // (sqrt(x^2+1))' = 2x/(2sqrt(x^2+1))
+        let inner = Expr::X.pow(Expr::Const(2)) + 1;
+        let derivative = inner.clone().sqrt().derivative();
+        assert_eq!(
+            derivative,
+            (Expr::Const(2) * inner.clone().sqrt()).recip() * inner.derivative()
+        );
+        for x in [-2.0, 0.0, 0.5, 3.0] {
+            let expected = x / (x * x + 1.0f64).sqrt();
+            assert!((derivative.eval(x).unwrap() - expected).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use super::fold::float_const;
+use super::{DerivativeError, Expr, VarId};
+
+impl Expr {
+    /// Find the total derivative of the expression with respect to `t`, where the variables in
+    /// `dependents` are functions of t (x is `VarId('x')`), as in a related rates problem.
+    ///
+    /// # Panics
+    /// Panics if the expression can't be differentiated. Use [`Expr::try_total_derivative`] if
+    /// it might contain something like a factorial.
+    pub fn total_derivative(&self, t: VarId, dependents: &[VarId]) -> Expr {
+        self.try_total_derivative(t, dependents)
+            .expect("expression should be differentiable")
+    }
+
+    /// Find the total derivative of the expression with respect to `t`, where the variables in
+    /// `dependents` are functions of t (x is `VarId('x')`), or an error if it contains something
+    /// which can't be differentiated.
+    ///
+    /// By the chain rule this is the partial derivative with respect to each dependent variable
+    /// v, times the rate `dv/dt` (an [`Expr::Deriv`]), added up along with the partial
+    /// derivative with respect to t itself. E.g. for `x^2 y` it is `2xy dx/dt + x^2 dy/dt`. The
+    /// result is simplified, and the rates can be given values with [`Expr::bind_rates`].
+    /// Rates which are already in the expression are treated as constants, so this can't be
+    /// used to find second derivatives.
+    pub fn try_total_derivative(
+        &self,
+        t: VarId,
+        dependents: &[VarId],
+    ) -> Result<Expr, DerivativeError> {
+        let mut terms = vec![self.try_partial_derivative(t)?];
+        for v in dependents.iter().filter(|v| **v != t) {
+            let rate = Expr::Deriv(*v, t);
+            terms.push(self.try_partial_derivative(*v)? * rate);
+        }
+        Ok(Expr::Sum(terms).simplified())
+    }
+
+    /// Put values in for some of the rates of change in the expression (see [`Expr::Deriv`]),
+    /// given as the variable, the variable it changes with respect to, and the value. The rest
+    /// of the expression isn't changed, so other variables can be given values with
+    /// [`Expr::specialize`].
+    pub fn bind_rates(&self, rates: &[(VarId, VarId, f64)]) -> Expr {
+        let mut e = self.clone();
+        e.transform_bottom_up(&mut |e| {
+            let Expr::Deriv(v, t) = e else {
+                return;
+            };
+            let value = rates
+                .iter()
+                .find(|(var, wrt, _)| var == v && wrt == t)
+                .and_then(|(_, _, value)| float_const(*value));
+            if let Some(value) = value {
+                *e = value;
+            }
+        });
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, pow, sin, var, x};
+    use super::super::{EvalError, MathError};
+    use super::*;
+
+    const T: VarId = VarId('t');
+    const X: VarId = VarId('x');
+    const Y: VarId = VarId('y');
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn product() {
+        let e = pow(x(), c(2)) * var('y');
+        let derivative = e.total_derivative(T, &[X, Y]);
+        // 2xy dx/dt + x^2 dy/dt
+        let expected =
+            c(2) * x() * var('y') * Expr::Deriv(X, T) + pow(x(), c(2)) * Expr::Deriv(Y, T);
+        for (x, y, dx, dy) in [(1.0, 2.0, 3.0, 4.0), (-0.5, 1.5, 2.0, -3.0)] {
+            let value = |e: &Expr| {
+                e.bind_rates(&[(X, T, dx), (Y, T, dy)])
+                    .specialize(&[(Y, y)])
+                    .eval(x)
+                    .unwrap()
+            };
+            assert!(
+                close(value(&derivative), value(&expected)),
+                "{derivative:?}"
+            );
+            assert!(close(value(&derivative), 2.0 * x * y * dx + x * x * dy));
+        }
+        assert_eq!(Expr::Deriv(X, T).to_latex(), "\\frac{dx}{dt}");
+        assert!(derivative.to_latex().contains("x^{2}\\frac{dy}{dt}"));
+        // The rates which haven't been given values can't be evaluated
+        assert_eq!(
+            derivative.specialize(&[(Y, 1.0)]).eval(1.0),
+            Err(EvalError::UnknownRate(X, T))
+        );
+        assert_eq!(
+            MathError::from(EvalError::UnknownRate(X, T)).to_string(),
+            "dx/dt has no value"
+        );
+    }
+
+    #[test]
+    fn related_rates() {
+        // A ladder of length 5 whose foot slides away from a wall: the angle θ (as y) between it
+        // and the ground changes as the foot moves, so the height of the top is 5 sin θ and the
+        // distance of the foot is 5 cos θ
+        let height = c(5) * sin(var('y'));
+        let foot = c(5) * cos(var('y'));
+        let (angle, rate) = (0.6f64, -0.25);
+        let value = |e: &Expr| {
+            e.total_derivative(T, &[Y])
+                .bind_rates(&[(Y, T, rate)])
+                .specialize(&[(Y, angle)])
+                .eval(0.0)
+                .unwrap()
+        };
+        assert!(close(value(&height), 5.0 * angle.cos() * rate));
+        assert!(close(value(&foot), -5.0 * angle.sin() * rate));
+        // The area under the ladder also depends on t directly, with x and t in it
+        let e = foot * height * x() + sin(var('t'));
+        let derivative = e
+            .total_derivative(T, &[X, Y])
+            .bind_rates(&[(X, T, 2.0), (Y, T, rate)]);
+        let t = 0.4;
+        let actual = derivative
+            .specialize(&[(Y, angle), (T, t)])
+            .eval(1.5)
+            .unwrap();
+        let (s, k) = (angle.sin(), angle.cos());
+        let expected = 25.0 * (s * k * 2.0 + 1.5 * (k * k - s * s) * rate) + t.cos();
+        assert!(close(actual, expected), "{actual} {expected}");
+    }
+
+    #[test]
+    fn round_trip() {
+        let e = Expr::Deriv(Y, T) * x();
+        assert_eq!(Expr::from_sexpr(&e.to_sexpr()), Ok(e.clone()));
+        assert_eq!(e.to_sexpr(), "(* (deriv y t) x)");
+        assert_eq!(e.clone().simplified(), e);
+        assert_eq!(e.clone().derivative().simplified(), Expr::Deriv(Y, T));
+    }
+}
@@ -0,0 +1,91 @@
+use super::simplify::gcd;
+use super::{Expr, Num};
+
+/// A fraction in lowest terms, with a positive denominator
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) struct Ratio {
+    pub(super) num: Num,
+    pub(super) den: Num,
+}
+
+impl Ratio {
+    pub(super) const ZERO: Ratio = Ratio { num: 0, den: 1 };
+    pub(super) const ONE: Ratio = Ratio { num: 1, den: 1 };
+
+    pub(super) fn new(num: Num, den: Num) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den)?;
+        let (num, den) = (num / g, den / g);
+        if den < 0 {
+            Some(Ratio {
+                num: num.checked_neg()?,
+                den: den.checked_neg()?,
+            })
+        } else {
+            Some(Ratio { num, den })
+        }
+    }
+
+    pub(super) fn whole(n: Num) -> Self {
+        Ratio { num: n, den: 1 }
+    }
+
+    pub(super) fn neg(self) -> Option<Self> {
+        Some(Ratio {
+            num: self.num.checked_neg()?,
+            den: self.den,
+        })
+    }
+
+    pub(super) fn recip(self) -> Option<Self> {
+        Ratio::new(self.den, self.num)
+    }
+
+    pub(super) fn add(self, other: Ratio) -> Option<Self> {
+        let num = self
+            .num
+            .checked_mul(other.den)?
+            .checked_add(other.num.checked_mul(self.den)?)?;
+        Ratio::new(num, self.den.checked_mul(other.den)?)
+    }
+
+    pub(super) fn sub(self, other: Ratio) -> Option<Self> {
+        self.add(other.neg()?)
+    }
+
+    pub(super) fn mul(self, other: Ratio) -> Option<Self> {
+        Ratio::new(
+            self.num.checked_mul(other.num)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    /// Raise to a power by repeated squaring, so that big powers overflow quickly rather than
+    /// after as many multiplications as the power
+    pub(super) fn pow(self, n: Num) -> Option<Self> {
+        let mut square = if n < 0 { self.recip()? } else { self };
+        let mut n = n.unsigned_abs();
+        let mut acc = Ratio::ONE;
+        while n > 0 {
+            if n % 2 == 1 {
+                acc = acc.mul(square)?;
+            }
+            n /= 2;
+            // The last square isn't needed, and might overflow when the result doesn't
+            if n > 0 {
+                square = square.mul(square)?;
+            }
+        }
+        Some(acc)
+    }
+
+    pub(super) fn to_expr(self) -> Expr {
+        if self.den == 1 {
+            Expr::Const(self.num)
+        } else {
+            (Expr::Const(self.num) / Expr::Const(self.den)).simplified()
+        }
+    }
+}
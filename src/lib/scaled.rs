@@ -0,0 +1,152 @@
+use super::{Expr, Num};
+
+impl Expr {
+    /// This function takes trailing zeros out of the mantissa of a scaled constant, and turns it
+    /// into a plain constant when there is no power of ten left
+    /// e.g. `60 * 10^8 = 6 * 10^9` and `50 * 10^-1 = 5`
+    pub fn simplify_scaled(&mut self) {
+        if let Expr::Scaled(m, e) = self {
+            *self = scaled(*m, *e);
+        }
+    }
+
+    /// This function multiplies scaled constants in a product together, along with any plain
+    /// constants, by multiplying the mantissas and adding the exponents. If the mantissa would
+    /// overflow the product is left alone.
+    /// e.g. `3*10^4 * 2*10^5 = 6*10^9`
+    pub fn simplify_multiply_scaled(&mut self) {
+        let Expr::Prod(v) = self else {
+            return;
+        };
+        if !v.iter().any(|e| matches!(e, Expr::Scaled(..))) {
+            return;
+        }
+        let mut mantissa: Num = 1;
+        let mut exponent: i32 = 0;
+        for (m, e) in v.iter().filter_map(as_scaled) {
+            match (mantissa.checked_mul(m), exponent.checked_add(e)) {
+                (Some(m), Some(e)) => (mantissa, exponent) = (m, e),
+                _ => return,
+            }
+        }
+        v.retain(|e| as_scaled(e).is_none());
+        v.push(scaled(mantissa, exponent));
+        if v.len() == 1 {
+            *self = v.pop().unwrap();
+        }
+    }
+
+    /// This function adds scaled constants in a sum, along with any plain constants, when they
+    /// can all be written exactly with the smallest of their exponents
+    /// e.g. `3*10^4 + 2*10^5 = 23*10^4`, but `10^50 + 1` is left alone
+    pub fn simplify_add_scaled(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        if !v.iter().any(|e| matches!(e, Expr::Scaled(..))) {
+            return;
+        }
+        // Zeros don't need to be aligned with the other terms
+        let terms: Vec<(Num, i32)> = v
+            .iter()
+            .filter_map(as_scaled)
+            .filter(|(m, _)| *m != 0)
+            .collect();
+        let exponent = terms.iter().map(|(_, e)| *e).min().unwrap_or(0);
+        let mut mantissa: Num = 0;
+        for (m, e) in terms {
+            let aligned = e
+                .checked_sub(exponent)
+                .and_then(|shift| u32::try_from(shift).ok())
+                .and_then(|shift| (10 as Num).checked_pow(shift))
+                .and_then(|power| m.checked_mul(power));
+            match aligned.and_then(|m| mantissa.checked_add(m)) {
+                Some(m) => mantissa = m,
+                None => return,
+            }
+        }
+        v.retain(|e| as_scaled(e).is_none());
+        v.push(scaled(mantissa, exponent));
+        if v.len() == 1 {
+            *self = v.pop().unwrap();
+        }
+    }
+}
+
+/// Get a constant or scaled constant as a mantissa and a power of ten
+fn as_scaled(e: &Expr) -> Option<(Num, i32)> {
+    match e {
+        Expr::Const(n) => Some((*n, 0)),
+        Expr::Scaled(m, e) => Some((*m, *e)),
+        _ => None,
+    }
+}
+
+/// A scaled constant without trailing zeros in the mantissa, which is a plain constant if it
+/// doesn't need a power of ten. Zeros aren't taken out of a mantissa with no power of ten, so
+/// that e.g. 100 stays a plain constant.
+pub(super) fn scaled(mut mantissa: Num, mut exponent: i32) -> Expr {
+    while mantissa != 0 && mantissa % 10 == 0 && exponent != 0 && exponent < i32::MAX {
+        mantissa /= 10;
+        exponent += 1;
+    }
+    match (mantissa, exponent) {
+        (0, _) => Expr::Const(0),
+        (m, 0) => Expr::Const(m),
+        (m, e) => Expr::Scaled(m, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{scaled as sc, x};
+    use super::*;
+
+    #[test]
+    fn multiply() {
+        let e = Expr::Scaled(3, 4) * Expr::Scaled(2, 5);
+        assert_eq!(e.simplified(), Expr::Scaled(6, 9));
+        let e = sc(5, -3) * x() * 4;
+        assert_eq!(e.simplified(), x() * sc(2, -2));
+        let e = Expr::Scaled(25, 2) * Expr::Scaled(4, -2);
+        assert_eq!(e.simplified(), Expr::Const(100));
+
+        // The mantissa would overflow
+        let e = Expr::Scaled(Num::MAX, 3) * Expr::Scaled(3, 1);
+        assert_eq!(e.clone().simplified(), e);
+    }
+
+    #[test]
+    fn add() {
+        let e = Expr::Scaled(3, 4) + Expr::Scaled(2, 5);
+        assert_eq!(e.simplified(), Expr::Scaled(23, 4));
+        let e = sc(5, -1) + 1 + x();
+        assert_eq!(e.simplified(), x() + sc(15, -1));
+        let e = Expr::Scaled(6, 23) - Expr::Scaled(6, 23);
+        assert_eq!(e.simplified(), Expr::Const(0));
+
+        // 10^50 + 1 can't be written with a power of ten of 0
+        let e = Expr::Scaled(1, 50) + 1;
+        assert_eq!(e.clone().simplified(), e);
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(Expr::Scaled(600, 21).simplified(), Expr::Scaled(6, 23));
+        assert_eq!(Expr::Scaled(7, 0).simplified(), Expr::Const(7));
+        assert_eq!(Expr::Scaled(50, -1).simplified(), Expr::Const(5));
+        assert_eq!((-Expr::Scaled(2, 3)).simplified(), Expr::Scaled(-2, 3));
+    }
+
+    #[test]
+    fn latex_and_eval() {
+        assert_eq!(Expr::Scaled(6, 23).to_latex(), "6\\times 10^{23}");
+        assert_eq!(Expr::Scaled(1, -9).to_latex(), "10^{-9}");
+        assert_eq!(
+            (Expr::Scaled(6, 23) * Expr::X).to_latex(),
+            "(6\\times 10^{23})x"
+        );
+        assert!((Expr::Scaled(6, 23).eval(0.0).unwrap() - 6e23).abs() < 1e8);
+        assert_eq!(Expr::Scaled(-25, -1).eval(0.0), Ok(-2.5));
+    }
+}
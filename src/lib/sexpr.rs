@@ -0,0 +1,450 @@
+use std::str::FromStr;
+
+use super::{Condition, Expr, ExprKind, HoleId, ParseError, VarId};
+
+/// The head symbol of a list for each kind of expression with children
+pub(super) fn head(kind: ExprKind) -> &'static str {
+    match kind {
+        ExprKind::Sum => "+",
+        ExprKind::Prod => "*",
+        ExprKind::Neg => "-",
+        ExprKind::Pow => "^",
+        ExprKind::Ln => "ln",
+        ExprKind::Sin => "sin",
+        ExprKind::Cos => "cos",
+        ExprKind::Arcsin => "arcsin",
+        ExprKind::Arccos => "arccos",
+        ExprKind::Arctan => "arctan",
+        ExprKind::Sinh => "sinh",
+        ExprKind::Cosh => "cosh",
+        ExprKind::Tanh => "tanh",
+        ExprKind::Arsinh => "arsinh",
+        ExprKind::Arcosh => "arcosh",
+        ExprKind::Artanh => "artanh",
+        ExprKind::Factorial => "factorial",
+        ExprKind::Var => "var",
+        ExprKind::Scaled => "scaled",
+        ExprKind::Hole => "hole",
+        ExprKind::Deriv => "deriv",
+        ExprKind::SumOver => "sum",
+        ExprKind::Piecewise => "piecewise",
+        ExprKind::Const | ExprKind::X | ExprKind::I | ExprKind::E => {
+            unreachable!("atoms are written without a list")
+        }
+    }
+}
+
+/// The kinds which are written as a list, to find the kind of a head symbol
+const LIST_KINDS: &[ExprKind] = &[
+    ExprKind::Sum,
+    ExprKind::Prod,
+    ExprKind::Neg,
+    ExprKind::Pow,
+    ExprKind::Ln,
+    ExprKind::Sin,
+    ExprKind::Cos,
+    ExprKind::Arcsin,
+    ExprKind::Arccos,
+    ExprKind::Arctan,
+    ExprKind::Sinh,
+    ExprKind::Cosh,
+    ExprKind::Tanh,
+    ExprKind::Arsinh,
+    ExprKind::Arcosh,
+    ExprKind::Artanh,
+    ExprKind::Factorial,
+    ExprKind::SumOver,
+];
+
+impl Expr {
+    /// Write the expression as an S-expression, e.g. `(+ (* 3 (^ x 2)) (sin x))`. Nothing is
+    /// simplified or rewritten, so [`Expr::from_sexpr`] gives back exactly the same expression.
+    ///
+    /// Constants are written as numbers, x, i and e as `x`, `i` and `e`, and other variables as
+    /// `(var y)`. Holes are written as `(hole 0)`, rates of change as `(deriv x t)`, scaled
+    /// constants as `(scaled 6 23)`, and a [`Expr::SumOver`] as `(sum k from to e)`. Everything
+    /// else is a list starting with `+`, `*`, `-` (negation), `^` or the name of the function.
+    ///
+    /// A [`Expr::Piecewise`] is written as `(piecewise (< x 0) (- x) otherwise x)`, with each
+    /// condition before its branch. Conditions are `(< a b)`, `(<= a b)`, `(= a b)` or
+    /// `otherwise`.
+    pub fn to_sexpr(&self) -> String {
+        match self {
+            Expr::Const(n) => n.to_string(),
+            Expr::X => "x".to_string(),
+            Expr::I => "i".to_string(),
+            Expr::E => "e".to_string(),
+            Expr::Var(v) => format!("(var {})", v.0),
+            Expr::Hole(id) => format!("(hole {})", id.0),
+            Expr::Deriv(v, t) => format!("(deriv {} {})", v.0, t.0),
+            Expr::Scaled(m, e) => format!("(scaled {m} {e})"),
+            Expr::Piecewise(v) => {
+                let mut str = "(piecewise".to_string();
+                for (condition, e) in v {
+                    str += " ";
+                    str += &match condition {
+                        Condition::Less(a, b) => format!("(< {} {})", a.to_sexpr(), b.to_sexpr()),
+                        Condition::LessEq(a, b) => {
+                            format!("(<= {} {})", a.to_sexpr(), b.to_sexpr())
+                        }
+                        Condition::Equal(a, b) => format!("(= {} {})", a.to_sexpr(), b.to_sexpr()),
+                        Condition::Otherwise => "otherwise".to_string(),
+                    };
+                    str += " ";
+                    str += &e.to_sexpr();
+                }
+                str + ")"
+            }
+            _ => {
+                let mut str = format!("({}", head(self.kind()));
+                if let Expr::SumOver(k, ..) = self {
+                    str += &format!(" {}", k.0);
+                }
+                for e in self.children() {
+                    str += " ";
+                    str += &e.to_sexpr();
+                }
+                str + ")"
+            }
+        }
+    }
+
+    /// Read an expression written by [`Expr::to_sexpr`]. A list with a head symbol that isn't
+    /// known is an error ([`ParseError::UnknownHead`]), as is a list with the wrong number of
+    /// expressions in it.
+    pub fn from_sexpr(sexpr: &str) -> Result<Expr, ParseError> {
+        let mut parser = Parser { sexpr, position: 0 };
+        let e = parser.expr()?;
+        match parser.token() {
+            None => Ok(e),
+            Some((token, position)) => Err(ParseError::Unexpected {
+                found: format!("'{token}'"),
+                position,
+            }),
+        }
+    }
+}
+
+/// Read one expression from `sexpr`, starting at `position`, and get where it ends. This is for
+/// formats which have an S-expression inside them, so that the positions in errors are still
+/// offsets into the whole text.
+pub(super) fn read_at(sexpr: &str, position: usize) -> Result<(Expr, usize), ParseError> {
+    let mut parser = Parser { sexpr, position };
+    let e = parser.expr()?;
+    Ok((e, parser.position))
+}
+
+struct Parser<'a> {
+    sexpr: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Get the next token and where it starts, which is a bracket or a symbol
+    fn token(&mut self) -> Option<(&'a str, usize)> {
+        let rest = &self.sexpr[self.position..];
+        let trimmed = rest.trim_start();
+        let start = self.position + rest.len() - trimmed.len();
+        let len = match trimmed.chars().next()? {
+            '(' | ')' => 1,
+            _ => trimmed
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(trimmed.len()),
+        };
+        self.position = start + len;
+        Some((&trimmed[..len], start))
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let (token, position) = self.token().ok_or(ParseError::UnexpectedEnd)?;
+        match token {
+            "(" => self.list(),
+            "x" => Ok(Expr::X),
+            "i" => Ok(Expr::I),
+            "e" => Ok(Expr::E),
+            _ if token.starts_with(|c: char| c == '-' || c.is_ascii_digit()) && token != "-" => {
+                number(token, position).map(Expr::Const)
+            }
+            _ => Err(ParseError::Unexpected {
+                found: format!("'{token}'"),
+                position,
+            }),
+        }
+    }
+
+    /// The rest of a list, after the `(`
+    fn list(&mut self) -> Result<Expr, ParseError> {
+        let (head_symbol, position) = self.token().ok_or(ParseError::UnexpectedEnd)?;
+        if head_symbol == "var" {
+            let variable = self.variable()?;
+            self.close()?;
+            return Ok(Expr::Var(variable));
+        }
+        if head_symbol == "hole" {
+            let id = self.integer()?;
+            self.close()?;
+            return Ok(Expr::Hole(HoleId(id)));
+        }
+        if head_symbol == "deriv" {
+            let variable = self.variable()?;
+            let wrt = self.variable()?;
+            self.close()?;
+            return Ok(Expr::Deriv(variable, wrt));
+        }
+        if head_symbol == "scaled" {
+            let mantissa = self.integer()?;
+            let exponent = self.integer()?;
+            self.close()?;
+            return Ok(Expr::Scaled(mantissa, exponent));
+        }
+        if head_symbol == "piecewise" {
+            let mut branches = vec![];
+            loop {
+                let condition = match self.token() {
+                    Some((")", _)) => break,
+                    Some(("otherwise", _)) => Condition::Otherwise,
+                    Some(("(", _)) => self.condition()?,
+                    Some((token, position)) => {
+                        return Err(ParseError::Unexpected {
+                            found: format!("'{token}'"),
+                            position,
+                        })
+                    }
+                    None => return Err(ParseError::UnexpectedEnd),
+                };
+                branches.push((condition, self.expr()?));
+            }
+            return Ok(Expr::Piecewise(branches));
+        }
+        let Some(kind) = LIST_KINDS.iter().copied().find(|k| head(*k) == head_symbol) else {
+            return Err(ParseError::UnknownHead {
+                head: head_symbol.to_string(),
+                position,
+            });
+        };
+
+        let index = match kind {
+            ExprKind::SumOver => Some(self.variable()?),
+            _ => None,
+        };
+        let mut args = vec![];
+        loop {
+            let before = self.position;
+            match self.token() {
+                Some((")", _)) => break,
+                Some(_) => {
+                    self.position = before;
+                    args.push(self.expr()?);
+                }
+                None => return Err(ParseError::UnexpectedEnd),
+            }
+        }
+        if let Some(arity) = kind.arity() {
+            if args.len() != arity {
+                return Err(ParseError::WrongArity {
+                    head: head_symbol.to_string(),
+                    expected: arity,
+                    found: args.len(),
+                    position,
+                });
+            }
+        }
+
+        let mut args = args.into_iter();
+        let mut arg = || Box::new(args.next().unwrap());
+        Ok(match kind {
+            ExprKind::Sum => Expr::Sum(args.collect()),
+            ExprKind::Prod => Expr::Prod(args.collect()),
+            ExprKind::Neg => Expr::Neg(arg()),
+            ExprKind::Pow => Expr::Pow(arg(), arg()),
+            ExprKind::Ln => Expr::Ln(arg()),
+            ExprKind::Sin => Expr::Sin(arg()),
+            ExprKind::Cos => Expr::Cos(arg()),
+            ExprKind::Arcsin => Expr::Arcsin(arg()),
+            ExprKind::Arccos => Expr::Arccos(arg()),
+            ExprKind::Arctan => Expr::Arctan(arg()),
+            ExprKind::Sinh => Expr::Sinh(arg()),
+            ExprKind::Cosh => Expr::Cosh(arg()),
+            ExprKind::Tanh => Expr::Tanh(arg()),
+            ExprKind::Arsinh => Expr::Arsinh(arg()),
+            ExprKind::Arcosh => Expr::Arcosh(arg()),
+            ExprKind::Artanh => Expr::Artanh(arg()),
+            ExprKind::Factorial => Expr::Factorial(arg()),
+            ExprKind::SumOver => Expr::SumOver(index.unwrap(), arg(), arg(), arg()),
+            _ => unreachable!("only kinds written as lists are found"),
+        })
+    }
+
+    /// The rest of a condition of a piecewise expression, after the `(`
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        let (symbol, position) = self.token().ok_or(ParseError::UnexpectedEnd)?;
+        let comparison = match symbol {
+            "<" => Condition::Less,
+            "<=" => Condition::LessEq,
+            "=" => Condition::Equal,
+            _ => {
+                return Err(ParseError::UnknownHead {
+                    head: symbol.to_string(),
+                    position,
+                })
+            }
+        };
+        let condition = comparison(self.expr()?, self.expr()?);
+        self.close()?;
+        Ok(condition)
+    }
+
+    /// A whole number, e.g. the exponent of a scaled constant
+    fn integer<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let (token, position) = self.token().ok_or(ParseError::UnexpectedEnd)?;
+        number(token, position)
+    }
+
+    /// A variable name, which is a single character
+    fn variable(&mut self) -> Result<VarId, ParseError> {
+        match self.token() {
+            Some((token, _)) if token.chars().count() == 1 && token != "(" && token != ")" => {
+                Ok(VarId(token.chars().next().unwrap()))
+            }
+            Some((token, position)) => Err(ParseError::Unexpected {
+                found: format!("'{token}'"),
+                position,
+            }),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), ParseError> {
+        match self.token() {
+            Some((")", _)) => Ok(()),
+            Some((token, position)) => Err(ParseError::Unexpected {
+                found: format!("'{token}'"),
+                position,
+            }),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+}
+
+/// Read a whole number token
+fn number<T: FromStr>(token: &str, position: usize) -> Result<T, ParseError> {
+    match token.parse() {
+        Ok(n) => Ok(n),
+        Err(_) if token.len() > 1 && token[1..].chars().all(|c| c.is_ascii_digit()) => {
+            Err(ParseError::NumberTooLarge { position })
+        }
+        Err(_) => Err(ParseError::Unexpected {
+            found: format!("'{token}'"),
+            position,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::super::{generate_derivative_problem, Difficulty, Rng};
+    use super::*;
+
+    fn round_trip(e: &Expr) {
+        assert_eq!(
+            Expr::from_sexpr(&e.to_sexpr()).as_ref(),
+            Ok(e),
+            "{}",
+            e.to_sexpr()
+        );
+    }
+
+    #[test]
+    fn format() {
+        let e = c(3) * pow(x(), c(2)) + sin(x());
+        assert_eq!(e.to_sexpr(), "(+ (* 3 (^ x 2)) (sin x))");
+        let e = -var('y').recip() + c(-4);
+        assert_eq!(e.to_sexpr(), "(+ (- (^ (var y) -1)) -4)");
+    }
+
+    #[test]
+    fn every_variant() {
+        let arg = || Box::new(Expr::X);
+        let variants = vec![
+            Expr::Const(-7),
+            Expr::X,
+            Expr::Var(VarId('y')),
+            Expr::I,
+            Expr::E,
+            Expr::Scaled(-6, 23),
+            Expr::Hole(HoleId(4)),
+            Expr::Sum(vec![]),
+            Expr::Prod(vec![Expr::Const(1)]),
+            Expr::Neg(Box::new(Expr::Neg(arg()))),
+            Expr::Pow(arg(), Box::new(Expr::Const(-1))),
+            Expr::Ln(arg()),
+            Expr::Sin(arg()),
+            Expr::Cos(arg()),
+            Expr::Arcsin(arg()),
+            Expr::Arccos(arg()),
+            Expr::Arctan(arg()),
+            Expr::Sinh(arg()),
+            Expr::Cosh(arg()),
+            Expr::Tanh(arg()),
+            Expr::Arsinh(arg()),
+            Expr::Arcosh(arg()),
+            Expr::Artanh(arg()),
+            Expr::Factorial(arg()),
+            Expr::SumOver(VarId('k'), arg(), arg(), arg()),
+            Expr::Piecewise(vec![
+                (Condition::Less(Expr::X, Expr::Const(0)), Expr::Neg(arg())),
+                (Condition::LessEq(Expr::X, Expr::E), Expr::Const(1)),
+                (Condition::Equal(Expr::X, Expr::I), Expr::X),
+                (Condition::Otherwise, Expr::X),
+            ]),
+        ];
+        for e in &variants {
+            round_trip(e);
+        }
+        round_trip(&Expr::Sum(variants));
+    }
+
+    #[test]
+    fn generated() {
+        let mut rng = Rng::new(384);
+        for difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard] {
+            for _ in 0..50 {
+                let problem = generate_derivative_problem(&mut rng, difficulty).unwrap();
+                round_trip(&problem.expr);
+                round_trip(&problem.derivative);
+                for step in &problem.steps {
+                    round_trip(&step.derivative);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Expr::from_sexpr("(+ x (tan x))"),
+            Err(ParseError::UnknownHead {
+                head: "tan".to_string(),
+                position: 6
+            })
+        );
+        assert_eq!(
+            Expr::from_sexpr("(^ x)").unwrap_err().to_string(),
+            "^ at 1 needs 2 expressions but has 1"
+        );
+        assert_eq!(Expr::from_sexpr("(sin x"), Err(ParseError::UnexpectedEnd));
+        assert_eq!(
+            Expr::from_sexpr("(sin x))").unwrap_err().to_string(),
+            "unexpected ')' at 7"
+        );
+        assert_eq!(
+            Expr::from_sexpr("(* 2 y)").unwrap_err().to_string(),
+            "unexpected 'y' at 5"
+        );
+        assert_eq!(
+            Expr::from_sexpr("9999999999999999999999999999999999999999999"),
+            Err(ParseError::NumberTooLarge { position: 0 })
+        );
+    }
+}
@@ -1,6 +1,497 @@
-use super::Expr;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use super::assumptions::Allowance;
+use super::monomial::Monomial;
+use super::{Assumptions, Expr, ExprKind, HashedExpr, Num};
+
+/// The most times [`Expr::simplify_with`] applies the simplifications before giving up on the
+/// expression stopping changing
+const MAX_ITERATIONS: usize = 100;
+
+/// How many of the most recent states of an expression [`Expr::simplify_with`] remembers, to
+/// notice when it goes back to one of them
+const RECENT_STATES: usize = 8;
+
+/// Options for [`Expr::simplify_with`], for simplifications which aren't always wanted and so
+/// aren't done by [`Expr::simplify`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimplifyOptions {
+    /// Write sums of fractions over a common denominator with
+    /// [`Expr::simplify_combine_fractions`]. Without this, fractions are left as separate terms.
+    pub combine_fractions: bool,
+    /// Take factors which every term of a sum has in common out of the sum with
+    /// [`Expr::simplify_factor_common_terms`], e.g. `2x + 2y = 2(x + y)`, and factor perfect
+    /// squares and differences of squares with [`Expr::simplify_factor_special_forms`].
+    pub factor_common_terms: bool,
+    /// Multiply out products and powers of polynomials with
+    /// [`Expr::simplify_expand_polynomial`], e.g. `x(x+1) = x^2 + x`. This undoes
+    /// `factor_common_terms`, so turning both on makes [`Expr::simplify_with`] stop at whichever
+    /// form is smaller.
+    pub expand_polynomials: bool,
+    /// Expand sin and cos of sums with the angle addition formulas with
+    /// [`Expr::simplify_expand_trig`], e.g. `sin(x+y) = sin(x)cos(y) + cos(x)sin(y)`.
+    pub expand_trig: bool,
+    /// Facts about x which allow more simplifications with [`Expr::simplify_assuming`] (e.g.
+    /// `sqrt(x^2) = x` when x is positive). By default nothing is assumed, and these
+    /// simplifications are only done when they are true for every x.
+    pub assumptions: Assumptions,
+    /// Limits on how much work [`Expr::simplify_with`] does, after which it stops early. By
+    /// default there are none.
+    pub budget: Option<Budget>,
+    /// Leave `0^0` as it is, since it is indeterminate. By default it is 1, the usual
+    /// convention (so that e.g. `x^0 = 1` holds for every x), as it is with [`Expr::simplify`].
+    pub keep_zero_pow_zero: bool,
+    /// What to do with the simplifications which are only true under some assumption (e.g.
+    /// `x/x = 1` needs `x != 0`) when [`SimplifyOptions::assumptions`] don't justify them. By
+    /// default they aren't done.
+    pub strictness: Strictness,
+}
+
+/// Whether [`Expr::simplify_with`] does the simplifications which are only true under some
+/// assumption, when the assumptions it is given don't justify them (see
+/// [`SimplifyOptions::strictness`])
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Do them anyway
+    Silent,
+    /// Do them anyway, with a warning in [`SimplifyReport::warnings`] saying what was assumed
+    Warn,
+    /// Don't do them
+    #[default]
+    Refuse,
+}
+
+/// Limits on how much work [`Expr::simplify_with`] does, for expressions which are too big to
+/// simplify fully in time (see [`SimplifyOptions::budget`]). When a limit is reached the
+/// simplification stops between two rewrites, so the expression is always valid and has the
+/// same value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The most subexpressions [`Expr::simplify`] goes to, counting a subexpression each time it
+    /// is gone to again
+    pub max_node_visits: Option<usize>,
+    /// The most milliseconds to spend. The time is only checked every so often and between the
+    /// optional simplifications, which aren't stopped part of the way through, so this can be
+    /// gone over a little.
+    pub max_millis: Option<u64>,
+}
+
+/// How many nodes are gone to between checks of the time, since getting it is slow
+const VISITS_PER_TIME_CHECK: usize = 64;
+
+/// A warning about a rewrite [`Expr::simplify_with_report`] did or didn't do, e.g. because it
+/// relied on something which isn't known to be true or the expression is undefined, found in
+/// [`SimplifyReport::warnings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimplifyWarning {
+    /// What the warning is about, e.g. "cancel powers" or "zero to a negative power"
+    pub rule: &'static str,
+    /// What the rewrite assumed, as LaTeX (e.g. `x \neq 0`), if it was done with
+    /// [`Strictness::Warn`] without the assumptions justifying it
+    pub assumption: Option<String>,
+    /// The subexpression it is about, as LaTeX, before it was rewritten
+    pub subexpr_latex: String,
+}
+
+/// How much of a [`Budget`] has been used, and the other state of one run of
+/// [`Expr::simplify_with`] which the passes on each subexpression need
+struct Meter {
+    budget: Budget,
+    visits: usize,
+    start: Instant,
+    /// Whether the budget has run out, which stays true once it has
+    spent: bool,
+    /// See [`SimplifyOptions::keep_zero_pow_zero`]
+    keep_zero_pow_zero: bool,
+    warnings: Vec<SimplifyWarning>,
+    /// The fingerprints (see [`fingerprints`]) of the subexpressions which [`Expr::simplify`]
+    /// has been seen to leave as they are, so they can be skipped when they come up again in a
+    /// later iteration. The passes only look at the subexpression they are given, so this
+    /// holds for the whole run. `None` goes to every subexpression every time.
+    clean: Option<HashSet<u64>>,
+    /// How many subexpressions have been gone to, counting the clean ones which are skipped
+    #[cfg(test)]
+    traversed: usize,
+}
+
+impl Meter {
+    fn new(options: &SimplifyOptions) -> Self {
+        Meter {
+            budget: options.budget.unwrap_or_default(),
+            visits: 0,
+            start: Instant::now(),
+            spent: false,
+            keep_zero_pow_zero: options.keep_zero_pow_zero,
+            warnings: vec![],
+            clean: Some(HashSet::new()),
+            #[cfg(test)]
+            traversed: 0,
+        }
+    }
+
+    /// A meter with no budget, which goes to every subexpression, for [`Expr::simplify`]
+    fn unlimited() -> Self {
+        Meter {
+            clean: None,
+            ..Meter::new(&SimplifyOptions::default())
+        }
+    }
+
+    /// Add a warning, unless the same one has already been given (since the passes are
+    /// applied to each subexpression many times)
+    fn warn(&mut self, warning: SimplifyWarning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Whether the budget has run out, checking the time if it is due
+    fn check(&mut self, check_time: bool) -> bool {
+        if self
+            .budget
+            .max_node_visits
+            .is_some_and(|max| self.visits > max)
+        {
+            self.spent = true;
+        }
+        if let (Some(max), true) = (self.budget.max_millis, check_time) {
+            if self.start.elapsed() >= Duration::from_millis(max) {
+                self.spent = true;
+            }
+        }
+        self.spent
+    }
+
+    /// Count a visit to a node, and get whether the budget has run out
+    fn visit(&mut self) -> bool {
+        self.visits += 1;
+        self.check(self.visits.is_multiple_of(VISITS_PER_TIME_CHECK))
+    }
+}
+
+impl SimplifyOptions {
+    /// Options with every optional simplification turned on, apart from
+    /// [`SimplifyOptions::expand_polynomials`], which undoes
+    /// [`SimplifyOptions::factor_common_terms`], and [`SimplifyOptions::expand_trig`], which
+    /// makes expressions bigger.
+    pub fn aggressive() -> Self {
+        SimplifyOptions {
+            combine_fractions: true,
+            factor_common_terms: true,
+            expand_polynomials: false,
+            expand_trig: false,
+            assumptions: Assumptions::default(),
+            budget: None,
+            keep_zero_pow_zero: false,
+            strictness: Strictness::Refuse,
+        }
+    }
+}
+
+/// What happened when an expression was simplified by [`Expr::simplify_with_report`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimplifyReport {
+    /// How many times the simplifications were applied to the whole expression
+    pub iterations: usize,
+    /// Whether the expression stopped changing, so simplifying it again won't do anything
+    pub converged: bool,
+    /// Whether the simplifications went back to a state the expression was in before (because
+    /// one undoes another), so they would have gone on forever. The smallest of the states is
+    /// kept.
+    pub oscillation_detected: bool,
+    /// Whether the simplification was stopped early because it ran out of
+    /// [`SimplifyOptions::budget`]. The state with the fewest nodes seen is kept.
+    pub budget_exhausted: bool,
+    /// Things which were noticed along the way, like subexpressions which are undefined (e.g.
+    /// `0^{-1}`) and so were left as they are, each given once
+    pub warnings: Vec<SimplifyWarning>,
+}
+
+/// One of the steps of [`Expr::simplify_with`], which is applied to the whole expression. The
+/// simplifications which go through every subexpression stop when the meter runs out.
+type Step = fn(&mut Expr, &SimplifyOptions, &mut Meter);
+
+/// The steps [`Expr::simplify_with`] applies in each iteration, for the simplifications turned
+/// on in `options`
+fn steps(options: &SimplifyOptions) -> Vec<Step> {
+    let mut steps: Vec<Step> = vec![|e, _, meter| e.simplify_metered(meter)];
+    if options.combine_fractions {
+        steps.push(|e, _, meter| {
+            e.transform_metered(&mut Expr::simplify_combine_fractions, meter);
+            e.simplify_metered(meter);
+        });
+    }
+    if options.factor_common_terms {
+        steps.push(|e, _, meter| {
+            let factor = &mut |e: &mut Expr| {
+                e.simplify_factor_special_forms();
+                e.simplify_factor_common_terms();
+            };
+            e.transform_metered(factor, meter);
+            e.simplify_metered(meter);
+        });
+    }
+    if options.expand_polynomials {
+        steps.push(|e, _, meter| {
+            e.transform_metered(&mut Expr::simplify_expand_polynomial, meter);
+            e.simplify_metered(meter);
+        });
+    }
+    if options.expand_trig {
+        steps.push(|e, _, meter| {
+            e.transform_metered(&mut Expr::simplify_expand_trig, meter);
+            e.simplify_metered(meter);
+        });
+    }
+    steps.push(|e, options, meter| {
+        let mut allowance = Allowance::new(&options.assumptions, options.strictness);
+        e.transform_metered(&mut |e| e.simplify_assuming_with(&mut allowance), meter);
+        for warning in allowance.warnings {
+            meter.warn(warning);
+        }
+        e.simplify_metered(meter);
+    });
+    steps
+}
+
+/// A state of an expression with the terms of its sums and products sorted, so that states
+/// which only differ in the order of terms are equal
+fn sorted_state(e: &Expr) -> HashedExpr {
+    let mut sorted = e.clone();
+    sorted.transform_bottom_up(&mut Expr::sort_terms);
+    HashedExpr::new(sorted)
+}
+
+/// Start the hash of a node with the parts of it which aren't its children, including how many
+/// children it has
+fn hash_node(e: &Expr, children: usize) -> DefaultHasher {
+    let mut hasher = DefaultHasher::new();
+    std::mem::discriminant(e).hash(&mut hasher);
+    match e {
+        Expr::SumOver(k, ..) => k.hash(&mut hasher),
+        Expr::Piecewise(v) => {
+            for (condition, _) in v {
+                std::mem::discriminant(condition).hash(&mut hasher);
+            }
+        }
+        e if children == 0 => e.hash(&mut hasher),
+        _ => (),
+    }
+    children.hash(&mut hasher);
+    hasher
+}
+
+/// A hash of a subexpression, made from the hashes of its children, and so the same as the one
+/// [`fingerprints`] gives for it. Equal fingerprints can be a collision, in the same way as
+/// the hashes which notice oscillations in [`Expr::simplify_with`].
+fn fingerprint(e: &Expr) -> u64 {
+    let children = e.children();
+    let mut hasher = hash_node(e, children.len());
+    for child in children {
+        hasher.write_u64(fingerprint(child));
+    }
+    hasher.finish()
+}
+
+/// The fingerprint of every subexpression, along with how many nodes it has, in pre-order
+/// (each node followed by each of its children in turn), which is worked out with one walk of
+/// the tree.
+fn fingerprints(e: &Expr, out: &mut Vec<(u64, usize)>) -> u64 {
+    let index = out.len();
+    out.push((0, 0));
+    let children = e.children();
+    let mut hasher = hash_node(e, children.len());
+    for child in children {
+        hasher.write_u64(fingerprints(child, out));
+    }
+    let hash = hasher.finish();
+    out[index] = (hash, out.len() - index);
+    hash
+}
+
+/// A simplification, which rewrites an expression when it has some shape and otherwise leaves it
+/// alone.
+type Pass = fn(&mut Expr);
+
+/// The passes [`Expr::simplify`] applies to each kind of expression, in order. Passes which
+/// collapse an expression into something else (like [`Expr::simplify_singleton`]) come last, so
+/// that the passes before them have already been applied.
+fn passes(kind: ExprKind) -> &'static [Pass] {
+    match kind {
+        ExprKind::Scaled => &[Expr::simplify_scaled],
+        ExprKind::Sum => &[
+            Expr::simplify_singleton,
+            Expr::simplify_sum_in_sum,
+            Expr::simplify_hyperbolic_identity,
+            Expr::simplify_add_consts,
+            Expr::simplify_add_scaled,
+            Expr::simplify_collect_like_terms,
+            Expr::simplify_plus_zero,
+        ],
+        ExprKind::Prod => &[
+            Expr::simplify_singleton,
+            Expr::simplify_prod_in_prod,
+            Expr::simplify_times_zero,
+            Expr::simplify_imaginary_units,
+            Expr::simplify_multiply_consts,
+            Expr::simplify_multiply_scaled,
+            Expr::simplify_collect_powers,
+            Expr::simplify_singleton,
+        ],
+        ExprKind::Neg => &[
+            Expr::simplify_negative_consts,
+            Expr::simplify_double_negative,
+            Expr::simplify_distribute_negative_in_sum,
+        ],
+        ExprKind::Pow => &[
+            Expr::simplify_zero_pow,
+            Expr::simplify_pow_of_zero,
+            Expr::simplify_one_pow,
+            Expr::simplify_const_pow,
+            Expr::simplify_imaginary_pow,
+            Expr::simplify_root_of_const,
+            Expr::simplify_root_of_pow,
+        ],
+        ExprKind::Ln => &[Expr::simplify_ln_of_exp],
+        ExprKind::Factorial => &[Expr::simplify_factorial],
+        ExprKind::Sinh | ExprKind::Cosh | ExprKind::Tanh => &[Expr::simplify_hyperbolic_parity],
+        ExprKind::Piecewise => &[Expr::simplify_impossible_branches],
+        ExprKind::SumOver => &[Expr::simplify_sum_of_polynomial],
+        ExprKind::Const
+        | ExprKind::X
+        | ExprKind::Var
+        | ExprKind::I
+        | ExprKind::E
+        | ExprKind::Hole
+        | ExprKind::Deriv
+        | ExprKind::Sin
+        | ExprKind::Cos
+        | ExprKind::Arcsin
+        | ExprKind::Arccos
+        | ExprKind::Arctan
+        | ExprKind::Arsinh
+        | ExprKind::Arcosh
+        | ExprKind::Artanh => &[],
+    }
+}
 
 impl Expr {
+    /// Simplify an expression with [`Expr::simplify`], and then apply the optional
+    /// simplifications turned on in `options` to every subexpression, along with the
+    /// simplifications which depend on the assumptions in `options`. This is repeated until the
+    /// expression stops changing (see [`Expr::simplify_with_report`]).
+    pub fn simplify_with(&mut self, options: &SimplifyOptions) {
+        self.simplify_with_report(options);
+    }
+
+    /// Simplify an expression like [`Expr::simplify_with`], and report how it went.
+    ///
+    /// The simplifications are applied until the expression stops changing. If they go back to
+    /// a recent state instead, because one simplification undoes another (like
+    /// `factor_common_terms` and `expand_polynomials`), they are stopped and the state with the
+    /// fewest nodes is kept. A step which only reorders terms doesn't count as a change. They
+    /// are also stopped after [`MAX_ITERATIONS`] iterations, in which case the report says that
+    /// the expression didn't converge.
+    pub fn simplify_with_report(&mut self, options: &SimplifyOptions) -> SimplifyReport {
+        let mut meter = Meter::new(options);
+        let mut report = self.simplify_iterations(options, &mut meter);
+        report.warnings = meter.warnings;
+        report
+    }
+
+    /// Apply the steps of [`Expr::simplify_with`] until the expression stops changing, goes
+    /// back to a recent state or the meter runs out
+    fn simplify_iterations(
+        &mut self,
+        options: &SimplifyOptions,
+        meter: &mut Meter,
+    ) -> SimplifyReport {
+        let steps = steps(options);
+        let mut report = SimplifyReport::default();
+        // The smallest state seen, which is only needed if the budget runs out
+        let mut smallest = options.budget.map(|_| self.clone());
+        // A ring buffer of the most recent states, sorted and as they were
+        let mut recent: VecDeque<(HashedExpr, Expr)> =
+            VecDeque::from([(sorted_state(self), self.clone())]);
+        while report.iterations < MAX_ITERATIONS {
+            report.iterations += 1;
+            let mut changed = false;
+            for step in &steps {
+                if meter.check(true) {
+                    report.budget_exhausted = true;
+                    if let Some(smallest) = smallest.filter(|e| e.node_count() < self.node_count())
+                    {
+                        *self = smallest;
+                    }
+                    return report;
+                }
+                let before = self.clone();
+                step(self, options, meter);
+                if *self == before {
+                    continue;
+                }
+                if let Some(smallest) = &mut smallest {
+                    if self.node_count() < smallest.node_count() {
+                        *smallest = self.clone();
+                    }
+                }
+                let sorted = sorted_state(self);
+                // Only reordering the terms isn't a change of state, and isn't going back to it
+                if recent.back().is_some_and(|(state, _)| *state == sorted) {
+                    continue;
+                }
+                changed = true;
+                // The hashes are compared first, and the trees only when they match
+                if recent.iter().any(|(state, _)| *state == sorted) {
+                    report.oscillation_detected = true;
+                    *self = recent
+                        .into_iter()
+                        .map(|(_, e)| e)
+                        .chain([self.clone()])
+                        .min_by_key(Expr::node_count)
+                        .unwrap();
+                    return report;
+                }
+                if recent.len() == RECENT_STATES {
+                    recent.pop_front();
+                }
+                recent.push_back((sorted, self.clone()));
+            }
+            if !changed && !meter.spent {
+                report.converged = true;
+                return report;
+            }
+        }
+        report
+    }
+
+    /// Get the simplified expression (see [`Expr::simplify`]), reusing this one.
+    pub fn simplified(mut self) -> Self {
+        self.simplify();
+        self
+    }
+
+    /// Get the simplified expression (see [`Expr::simplify`]) without changing this one. Use
+    /// [`Expr::simplified`] when this expression isn't needed, to save a copy.
+    pub fn to_simplified(&self) -> Self {
+        self.clone().simplified()
+    }
+
+    /// Get the expression simplified with options (see [`Expr::simplify_with`]), reusing this
+    /// one.
+    pub fn simplified_with(mut self, options: &SimplifyOptions) -> Self {
+        self.simplify_with(options);
+        self
+    }
+
+    /// Get the expression simplified with options (see [`Expr::simplify_with`]) without changing
+    /// this one.
+    pub fn to_simplified_with(&self, options: &SimplifyOptions) -> Self {
+        self.clone().simplified_with(options)
+    }
+
     /// Apply all simplification techniques to an expression (INCOMPLETE!)
     ///
     /// List of applied simplifications:
@@ -8,45 +499,145 @@ impl Expr {
     /// [`Expr::simplify_singleton`]
     /// [`Expr::simplify_zero_pow`]
     /// [`Expr::simplify_one_pow`]
+    /// [`Expr::simplify_const_pow`]
+    /// [`Expr::simplify_imaginary_pow`]
+    /// [`Expr::simplify_imaginary_units`]
+    /// [`Expr::simplify_root_of_const`]
+    /// [`Expr::simplify_root_of_pow`]
+    /// [`Expr::simplify_scaled`]
+    /// [`Expr::simplify_add_scaled`]
+    /// [`Expr::simplify_multiply_scaled`]
     /// [`Expr::simplify_negative_consts`]
     /// [`Expr::simplify_double_negative`]
     /// [`Expr::simplify_distribute_negative_in_sum`]
     /// [`Expr::simplify_times_zero`]
     /// [`Expr::simplify_plus_zero`]
+    /// [`Expr::simplify_collect_like_terms`]
+    /// [`Expr::simplify_collect_powers`]
+    /// [`Expr::simplify_factorial`]
+    /// [`Expr::simplify_ln_of_exp`]
+    /// [`Expr::simplify_sum_of_polynomial`]
+    ///
+    /// The children of an expression are simplified first, and then the simplifications for its
+    /// kind are applied in order. If a pass turns the expression into a different kind of
+    /// expression (e.g. a product with one term becomes the term), the passes for the new kind
+    /// are applied from the start instead, so that the new expression is simplified fully.
     pub fn simplify(&mut self) {
-        // Simplify all subterms before simplifying the current term
-        self.simplify_terms();
-        match self {
-            Expr::Const(_) => (),
-            Expr::X => (),
-            Expr::Sum(_) => {
-                self.simplify_singleton();
-                self.simplify_sum_in_sum();
-                self.simplify_add_consts();
-                self.simplify_plus_zero();
-            }
-            Expr::Prod(_) => {
-                self.simplify_singleton();
-                self.simplify_prod_in_prod();
-                self.simplify_times_zero();
-                self.simplify_multiply_consts();
-            }
-            Expr::Neg(_) => {
-                self.simplify_negative_consts();
-                self.simplify_double_negative();
-                self.simplify_distribute_negative_in_sum();
-            }
-            Expr::Pow(_, _) => {
-                self.simplify_zero_pow();
-                self.simplify_one_pow();
-            }
-            Expr::Ln(_) => (),
-            Expr::Sin(_) => (),
-            Expr::Cos(_) => (),
-            Expr::Arcsin(_) => (),
-            Expr::Arccos(_) => (),
-            Expr::Arctan(_) => (),
-        };
+        self.simplify_metered(&mut Meter::unlimited());
+    }
+
+    /// [`Expr::simplify`], stopping between two passes when the meter runs out. Nothing is left
+    /// half rewritten, since each pass is done completely or not at all.
+    ///
+    /// Subexpressions which an earlier iteration left as they are (see [`Meter::clean`]) aren't
+    /// gone into, so after the first iteration only the parts of the tree which the other steps
+    /// changed are simplified again.
+    fn simplify_metered(&mut self, meter: &mut Meter) {
+        if meter.clean.is_none() {
+            return self.simplify_every_node(meter);
+        }
+        let mut before = vec![];
+        fingerprints(self, &mut before);
+        self.simplify_dirty(&before, meter);
+    }
+
+    /// [`Expr::simplify_metered`], going to every subexpression
+    fn simplify_every_node(&mut self, meter: &mut Meter) {
+        #[cfg(test)]
+        {
+            meter.traversed += 1;
+        }
+        for child in self.children_mut() {
+            if meter.spent {
+                return;
+            }
+            child.simplify_every_node(meter);
+        }
+        self.apply_passes_metered(meter);
+    }
+
+    /// [`Expr::simplify_metered`], skipping clean subexpressions, given the [`fingerprints`] of
+    /// this one from before it is changed. Gives whether it was left as it is.
+    fn simplify_dirty(&mut self, before: &[(u64, usize)], meter: &mut Meter) -> bool {
+        #[cfg(test)]
+        {
+            meter.traversed += 1;
+        }
+        let hash = before[0].0;
+        if meter
+            .clean
+            .as_ref()
+            .is_some_and(|clean| clean.contains(&hash))
+        {
+            return true;
+        }
+        let mut unchanged = true;
+        let mut rest = &before[1..];
+        for child in self.children_mut() {
+            if meter.spent {
+                return false;
+            }
+            let (child_before, after) = rest.split_at(rest[0].1);
+            unchanged &= child.simplify_dirty(child_before, meter);
+            rest = after;
+        }
+        let kind = self.kind();
+        self.apply_passes_metered(meter);
+        if meter.spent {
+            return false;
+        }
+        // A pass could have changed the children as well, so the whole subexpression is hashed
+        // again. This is only needed where nothing below has changed, and kinds with no passes
+        // can't have changed at all.
+        unchanged &= passes(kind).is_empty() || fingerprint(self) == hash;
+        if let (true, Some(clean)) = (unchanged, &mut meter.clean) {
+            clean.insert(hash);
+        }
+        unchanged
+    }
+
+    fn apply_passes_metered(&mut self, meter: &mut Meter) {
+        if let Expr::Pow(a, b) = self {
+            if a.as_constant() == Some(0) {
+                match b.as_constant() {
+                    Some(0) if meter.keep_zero_pow_zero => return,
+                    Some(n) if n < 0 => meter.warn(SimplifyWarning {
+                        rule: "zero to a negative power",
+                        assumption: None,
+                        subexpr_latex: self.to_latex(),
+                    }),
+                    _ => (),
+                }
+            }
+        }
+        let kind = self.kind();
+        for pass in passes(kind) {
+            if meter.visit() {
+                return;
+            }
+            pass(self);
+            if self.kind() != kind {
+                return self.apply_passes_metered(meter);
+            }
+        }
+    }
+
+    fn apply_passes(&mut self) {
+        self.apply_passes_metered(&mut Meter::unlimited());
+    }
+
+    /// [`Expr::transform_bottom_up`] for the optional simplifications, counting each
+    /// subexpression it goes to and stopping when the meter runs out
+    fn transform_metered(&mut self, f: &mut impl FnMut(&mut Expr), meter: &mut Meter) {
+        for child in self.children_mut() {
+            if meter.spent {
+                return;
+            }
+            child.transform_metered(f, meter);
+        }
+        if !meter.visit() {
+            f(self);
+        }
     }
 
     /// This function simplifies all of the terms in an expression. For example, it may simplify
@@ -55,6 +646,12 @@ impl Expr {
         match self {
             Expr::Const(_) => (),
             Expr::X => (),
+            Expr::Var(_) => (),
+            Expr::I => (),
+            Expr::E => (),
+            Expr::Scaled(..) => (),
+            Expr::Hole(_) => (),
+            Expr::Deriv(..) => (),
             Expr::Sum(v) => {
                 for e in v.iter_mut() {
                     e.simplify();
@@ -90,6 +687,37 @@ impl Expr {
             Expr::Arctan(x) => {
                 x.simplify();
             }
+            Expr::Sinh(x) => {
+                x.simplify();
+            }
+            Expr::Cosh(x) => {
+                x.simplify();
+            }
+            Expr::Tanh(x) => {
+                x.simplify();
+            }
+            Expr::Arsinh(x) => {
+                x.simplify();
+            }
+            Expr::Arcosh(x) => {
+                x.simplify();
+            }
+            Expr::Artanh(x) => {
+                x.simplify();
+            }
+            Expr::Factorial(x) => {
+                x.simplify();
+            }
+            Expr::SumOver(_, from, to, x) => {
+                from.simplify();
+                to.simplify();
+                x.simplify();
+            }
+            Expr::Piecewise(_) => {
+                for e in self.children_mut() {
+                    e.simplify();
+                }
+            }
         }
     }
 
@@ -100,15 +728,15 @@ impl Expr {
                 if v.is_empty() {
                     *self = Expr::Const(0);
                 } else if v.len() == 1 {
-                    // I feel like I shouldn't use an unwrap but len == 1
-                    *self = v.first().unwrap().clone();
+                    // The term is moved out rather than cloned, so this doesn't depend on its size
+                    *self = v.pop().unwrap();
                 }
             }
             Expr::Prod(v) => {
                 if v.is_empty() {
                     *self = Expr::Const(0);
                 } else if v.len() == 1 {
-                    *self = v.first().unwrap().clone();
+                    *self = v.pop().unwrap();
                 }
             }
             _ => (),
@@ -116,21 +744,74 @@ impl Expr {
     }
 
     /// This function turns expressions to the power of 0 to 1
-    /// e.g. `x^0 = 1`
+    /// e.g. `x^0 = 1` and `x^sin(0) = 1`. This includes `0^0`, which is indeterminate but is 1
+    /// by convention (see [`SimplifyOptions::keep_zero_pow_zero`] to leave it).
     pub fn simplify_zero_pow(&mut self) {
         if let Expr::Pow(_, b) = self {
-            if **b == Expr::Const(0) {
+            if b.as_constant() == Some(0) {
                 *self = Expr::Const(1);
             }
         }
     }
 
+    /// This function turns zero to a positive power into 0
+    /// e.g. `0^3 = 0` and `sin(0)^2 = 0`. Zero to a negative power is undefined, so it is left
+    /// as it is (and [`Expr::simplify_with_report`] warns about it).
+    pub fn simplify_pow_of_zero(&mut self) {
+        if let Expr::Pow(a, b) = self {
+            if a.as_constant() == Some(0) && b.as_constant().is_some_and(|n| n > 0) {
+                *self = Expr::Const(0);
+            }
+        }
+    }
+
     /// This function turns expressions to the power of 1 to x
-    /// e.g. `x^1 = x`
+    /// e.g. `x^1 = x` and `x^ln(e) = x`
     pub fn simplify_one_pow(&mut self) {
         if let Expr::Pow(a, b) = self {
-            if **b == Expr::Const(1) {
-                *self = *a.clone();
+            if b.as_constant() == Some(1) {
+                *self = std::mem::take(&mut **a);
+            }
+        }
+    }
+
+    /// This function raises constants to non-negative constant powers
+    /// e.g. `2^3 = 8`
+    pub fn simplify_const_pow(&mut self) {
+        if let Expr::Pow(a, b) = self {
+            if let (Expr::Const(a), Expr::Const(b)) = (a.as_ref(), b.as_ref()) {
+                if let Some(n) = u32::try_from(*b).ok().and_then(|b| a.checked_pow(b)) {
+                    *self = Expr::Const(n);
+                }
+            }
+        }
+    }
+
+    /// This function works out factorials of constants, as long as the result doesn't overflow
+    /// e.g. `5! = 120`
+    pub fn simplify_factorial(&mut self) {
+        if let Expr::Factorial(x) = self {
+            if let Expr::Const(n) = **x {
+                if let Some(n) = u32::try_from(n)
+                    .ok()
+                    .and_then(super::combinatorics::factorial)
+                {
+                    *self = Expr::Const(n);
+                }
+            }
+        }
+    }
+
+    /// This function cancels ln with powers of e
+    /// e.g. `ln(e) = 1` and `ln(e^x) = x`
+    pub fn simplify_ln_of_exp(&mut self) {
+        if let Expr::Ln(x) = self {
+            match x.as_mut() {
+                Expr::E => *self = Expr::Const(1),
+                Expr::Pow(a, b) if **a == Expr::E => {
+                    *self = std::mem::take(b.as_mut());
+                }
+                _ => (),
             }
         }
     }
@@ -138,46 +819,62 @@ impl Expr {
     /// This function turns expressions of the form `Neg(Const(x))` into Const(-x).
     pub fn simplify_negative_consts(&mut self) {
         if let Expr::Neg(x) = self {
-            if let Expr::Const(c) = **x {
-                *self = Expr::Const(-c);
+            match **x {
+                // The negative of the smallest constant doesn't fit, so it is left as it is
+                Expr::Const(c) => {
+                    if let Some(c) = c.checked_neg() {
+                        *self = Expr::Const(c)
+                    }
+                }
+                Expr::Scaled(m, e) => {
+                    if let Some(m) = m.checked_neg() {
+                        *self = Expr::Scaled(m, e)
+                    }
+                }
+                _ => (),
             }
         }
     }
 
     /// This function turns a negative of a negative of an expression into just the expression
     pub fn simplify_double_negative(&mut self) {
-        if let Expr::Neg(x) = self {
-            if let Expr::Neg(x) = x.as_mut() {
-                // use unsafe std::ptr::read() ? faster but unsafe
-                let a = std::mem::replace(x, Box::new(Expr::X));
-                *self = *a;
-                // In case it is a quadruple+ negative
-                self.simplify_double_negative();
+        // Loop in case it is a quadruple+ negative
+        while matches!(self, Expr::Neg(x) if matches!(**x, Expr::Neg(_))) {
+            if let Expr::Neg(x) = std::mem::take(self) {
+                if let Expr::Neg(x) = *x {
+                    *self = *x;
+                }
             }
         }
     }
 
     /// This function distributes the negative sign in a sum to all of it's elements
     pub fn simplify_distribute_negative_in_sum(&mut self) {
-        if let Expr::Neg(x) = self {
-            if let Expr::Sum(v) = x.as_mut() {
-                // I hope this is fine
-                let v = v
-                    .iter_mut()
-                    .map(|x| -std::mem::replace(x, Expr::X))
-                    .collect();
-                *self = Expr::Sum(v);
-                // Simplify the new stuff
-                // This may not be a good idea
-                self.simplify_terms();
+        // Simplify the new stuff
+        // This may not be a good idea
+        self.distribute_negative_in_sum_then(Expr::simplify_terms);
+    }
+
+    /// Distribute the negative sign in a sum, and then apply `then` to the new sum. The sum is
+    /// only put back into the tree once it is complete, so if `then` panics the tree has the
+    /// new sum in it.
+    fn distribute_negative_in_sum_then(&mut self, then: impl FnOnce(&mut Expr)) {
+        if !matches!(self, Expr::Neg(x) if matches!(**x, Expr::Sum(_))) {
+            return;
+        }
+        if let Expr::Neg(x) = std::mem::take(self) {
+            if let Expr::Sum(v) = *x {
+                *self = Expr::Sum(v.into_iter().map(|x| -x).collect());
+                then(self);
             }
         }
     }
 
     /// This function turns expressions multiplied by zero into just zero
+    /// e.g. `0x = 0` and `ln(1)sin(x) = 0`
     pub fn simplify_times_zero(&mut self) {
         if let Expr::Prod(v) = self {
-            if v.contains(&Expr::Const(0)) {
+            if v.iter().any(|e| e.as_constant() == Some(0)) {
                 *self = Expr::Const(0);
             }
         }
@@ -191,35 +888,252 @@ impl Expr {
         }
     }
 
+    /// This function adds up terms of a sum which are the same apart from a constant factor
+    /// e.g. `x + x = 2x`, `3sin(x) - sin(x) = 2sin(x)` and `xy^2 + 2y^2x = 3xy^2`
+    ///
+    /// Terms are compared by their monomials (see [`Monomial`]), so the order of their factors
+    /// doesn't matter, but `x^2y` and `xy^2` aren't like terms. A negated term counts as having a
+    /// negative factor, so terms which cancel are removed, and a sum where everything cancels
+    /// becomes 0 (e.g. `x^2 - x^2`).
+    pub fn simplify_collect_like_terms(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        let mut like_terms: Vec<LikeTerm> = Vec::with_capacity(v.len());
+        // The like terms with each key, which are the only ones a term can be like
+        let mut by_key: HashMap<u64, Vec<usize>> = HashMap::with_capacity(v.len());
+        let mut merged = false;
+        for e in v.drain(..) {
+            let like = LikeTerm::new(e);
+            let same_key = by_key.entry(like.key()).or_default();
+            let other = same_key
+                .iter()
+                .find(|i| like_terms[**i].is_like(&like))
+                .map(|i| &mut like_terms[*i]);
+            // A term which would overflow the total is left on its own
+            if other.is_some_and(|other| other.merge(&like)) {
+                merged = true;
+                continue;
+            }
+            same_key.push(like_terms.len());
+            like_terms.push(like);
+        }
+        *v = match merged {
+            false => like_terms
+                .into_iter()
+                .map(|like| like.original.unwrap())
+                .collect(),
+            true => like_terms
+                .into_iter()
+                .filter(|like| like.total != 0)
+                .map(LikeTerm::into_merged)
+                .collect(),
+        };
+    }
+
+    /// This function multiplies the factors of a product which are powers of the same base
+    /// together, by adding up their exponents
+    /// e.g. `x^2 * x * x^4 = x^7` and `(x^2+1)^y * (x^2+1)^-y = 1`
+    ///
+    /// All the powers of a base are collected at once into one sum of exponents, which is
+    /// simplified. Constant bases are left to [`Expr::simplify_multiply_consts`]. Powers whose
+    /// exponents might have different signs (like `x/x` or `x^y * x^-y`) aren't collected unless
+    /// the base is non-zero, since they only cancel when the base isn't 0 (see
+    /// [`Expr::simplify_cancel_powers`]).
+    pub fn simplify_collect_powers(&mut self) {
+        let Expr::Prod(v) = self else {
+            return;
+        };
+        // The bases which are in more than one factor, and can be collected
+        let mut bases: Vec<&Expr> = vec![];
+        for (i, base) in v.iter().enumerate() {
+            let Some(base) = base.power_base() else {
+                continue;
+            };
+            let exponents: Vec<&Expr> = v
+                .iter()
+                .filter(|e| e.power_base() == Some(base))
+                .map(|e| match e {
+                    Expr::Pow(_, b) => b,
+                    _ => &Expr::Const(1),
+                })
+                .collect();
+            let first = v[..i].iter().all(|e| e.power_base() != Some(base));
+            if first
+                && exponents.len() > 1
+                && Assumptions::default().can_collect_powers(base, &exponents)
+            {
+                bases.push(base);
+            }
+        }
+        if bases.is_empty() {
+            return;
+        }
+        let bases: Vec<Expr> = bases.into_iter().cloned().collect();
+
+        // The collected bases with their exponents, and the factors which aren't collected
+        let mut groups: Vec<(Expr, Vec<Expr>)> = vec![];
+        for e in std::mem::take(v) {
+            if !e.power_base().is_some_and(|base| bases.contains(base)) {
+                groups.push((e, vec![]));
+                continue;
+            }
+            let (base, exponent) = match e {
+                Expr::Pow(a, b) => (*a, *b),
+                e => (e, Expr::Const(1)),
+            };
+            match groups
+                .iter_mut()
+                .find(|(b, exponents)| !exponents.is_empty() && *b == base)
+            {
+                Some((_, exponents)) => exponents.push(exponent),
+                None => groups.push((base, vec![exponent])),
+            }
+        }
+        *v = groups
+            .into_iter()
+            .filter_map(|(base, exponents)| {
+                if exponents.is_empty() {
+                    return Some(base);
+                }
+                match Expr::Sum(exponents).simplified() {
+                    Expr::Const(0) => None,
+                    Expr::Const(1) => Some(base),
+                    exponent => {
+                        let mut power = Expr::Pow(Box::new(base), Box::new(exponent));
+                        power.apply_passes();
+                        Some(power)
+                    }
+                }
+            })
+            .collect();
+        if v.is_empty() {
+            v.push(Expr::Const(1));
+        }
+    }
+
+    /// The base of a factor of a product which [`Expr::simplify_collect_powers`] collects, which
+    /// is the factor itself if it isn't a power
+    fn power_base(&self) -> Option<&Expr> {
+        let base = match self {
+            Expr::Pow(a, _) => a,
+            e => e,
+        };
+        match base {
+            Expr::Const(_) | Expr::Scaled(..) => None,
+            base => Some(base),
+        }
+    }
+
+    /// Split a term of a sum into a constant factor and the rest of the term
+    pub(super) fn split_const_factor(&self) -> (Num, Expr) {
+        match self {
+            Expr::Neg(e) => {
+                let (c, term) = e.split_const_factor();
+                match c.checked_neg() {
+                    Some(c) => (c, term),
+                    None => (1, self.clone()),
+                }
+            }
+            Expr::Prod(v) => {
+                let consts: Vec<Num> = v
+                    .iter()
+                    .filter_map(|e| match e {
+                        Expr::Const(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect();
+                let rest: Vec<Expr> = v
+                    .iter()
+                    .filter(|e| !matches!(e, Expr::Const(_)))
+                    .cloned()
+                    .collect();
+                match (consts.as_slice(), rest.len()) {
+                    ([c], 1) => (*c, rest.into_iter().next().unwrap()),
+                    ([c], _) => (*c, Expr::Prod(rest)),
+                    _ => (1, self.clone()),
+                }
+            }
+            _ => (1, self.clone()),
+        }
+    }
+
     /// This function adds constants in a sum together
     pub fn simplify_add_consts(&mut self) {
         if let Expr::Sum(v) = self {
-            let mut total = 0;
-            v.retain(|e| {
+            let mut total: Num = 0;
+            for e in v.iter() {
                 if let Expr::Const(val) = e {
-                    total += val;
-                    false
-                } else {
-                    true
+                    match total.checked_add(*val) {
+                        Some(sum) => total = sum,
+                        // Leave the sum alone instead of overflowing
+                        None => return,
+                    }
                 }
-            });
+            }
+            v.retain(|e| !matches!(e, Expr::Const(_)));
             *self += Expr::Const(total);
         }
     }
 
-    /// This function multiplies constants in a sum together
+    /// This function multiplies constants in a product together. Reciprocals of constants are
+    /// multiplied into a denominator, and the resulting fraction is cancelled
+    /// e.g. `6 * x * 4^-1 = x * 3 * 2^-1`
     pub fn simplify_multiply_consts(&mut self) {
         if let Expr::Prod(v) = self {
-            let mut total = 1;
-            v.retain(|e| {
-                if let Expr::Const(val) = e {
-                    total *= val;
-                    false
-                } else {
-                    true
+            // A whole number in front of a root of a constant (e.g. `2sqrt(3)`) is already how
+            // `simplify_root_of_const` leaves it
+            if let [Expr::Const(n), root] = v.as_slice() {
+                if *n != 1 && root.is_root_of_const() {
+                    return;
                 }
+            }
+            let mut numerator: Num = 1;
+            let mut denominator: Num = 1;
+            for e in v.iter() {
+                let folded = match e.as_fraction_factor() {
+                    Some((n, 1)) => numerator.checked_mul(n).map(|n| numerator = n),
+                    Some((1, d)) => denominator.checked_mul(d).map(|d| denominator = d),
+                    _ => Some(()),
+                };
+                // Leave the product alone instead of overflowing
+                if folded.is_none() {
+                    return;
+                }
+            }
+            // The denominator is made positive, which doesn't fit for the smallest constant
+            let sign = denominator.signum();
+            let cancelled = gcd(numerator, denominator).and_then(|gcd| {
+                Some((
+                    (numerator / gcd).checked_mul(sign)?,
+                    (denominator / gcd).checked_mul(sign)?,
+                ))
             });
-            *self *= Expr::Const(total);
+            let Some((numerator, denominator)) = cancelled else {
+                return;
+            };
+            v.retain(|e| e.as_fraction_factor().is_none());
+
+            // A factor of 1 is only needed when there's nothing else in the product
+            if numerator != 1 || (v.is_empty() && denominator == 1) {
+                v.push(Expr::Const(numerator));
+            }
+            if denominator != 1 {
+                v.push(Expr::Const(denominator).recip());
+            }
+        }
+    }
+
+    /// Get the value of a constant factor of a product as `(numerator, denominator)`, where one
+    /// of the two is 1. This is for constants and reciprocals of non-zero constants.
+    fn as_fraction_factor(&self) -> Option<(Num, Num)> {
+        match self {
+            Expr::Const(n) => Some((*n, 1)),
+            Expr::Pow(a, b) => match (a.as_ref(), b.as_ref()) {
+                (Expr::Const(d), Expr::Const(-1)) if *d != 0 => Some((1, *d)),
+                _ => None,
+            },
+            _ => None,
         }
     }
 
@@ -255,3 +1169,679 @@ impl Expr {
         }
     }
 }
+
+/// The greatest common divisor of two numbers, which is always positive (unless both are 0).
+/// Gives `None` if it doesn't fit in a [`Num`], which is only when both are 0 or `Num::MIN`.
+pub(super) fn gcd(a: Num, b: Num) -> Option<Num> {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    Num::try_from(a).ok()
+}
+
+/// A term of a sum, split into its constant factor and the rest, for
+/// [`Expr::simplify_collect_like_terms`]
+struct LikeTerm {
+    /// The term as it was, until another term is added to it
+    original: Option<Expr>,
+    /// The term without its constant factor
+    term: HashedExpr,
+    monomial: Option<Monomial>,
+    /// The total of the constant factors of the terms added to this one
+    total: Num,
+}
+
+impl LikeTerm {
+    fn new(e: Expr) -> Self {
+        let (total, term) = e.split_const_factor();
+        LikeTerm {
+            original: Some(e),
+            monomial: Monomial::new(&term),
+            term: HashedExpr::new(term),
+            total,
+        }
+    }
+
+    /// A hash which is the same for like terms
+    fn key(&self) -> u64 {
+        match &self.monomial {
+            Some(monomial) => {
+                let mut hasher = DefaultHasher::new();
+                monomial.hash(&mut hasher);
+                hasher.finish()
+            }
+            None => self.term.hash_value(),
+        }
+    }
+
+    /// Whether the terms are the same apart from their constant factors
+    fn is_like(&self, other: &LikeTerm) -> bool {
+        match (&self.monomial, &other.monomial) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.term == other.term,
+        }
+    }
+
+    /// Add a like term to this one, unless they aren't like terms or the total would overflow
+    fn merge(&mut self, other: &LikeTerm) -> bool {
+        if !self.is_like(other) {
+            return false;
+        }
+        let Some(total) = self.total.checked_add(other.total) else {
+            return false;
+        };
+        (self.total, self.original) = (total, None);
+        true
+    }
+
+    /// The term multiplied by the total of the constant factors
+    fn into_merged(self) -> Expr {
+        match self.total {
+            0 => Expr::Const(0),
+            total => with_const_factor(self.term.into_inner(), total),
+        }
+    }
+}
+
+/// A term multiplied by a constant, without the constant if it is 1
+fn with_const_factor(term: Expr, c: Num) -> Expr {
+    match (term, c) {
+        (term, 1) => term,
+        (Expr::Prod(mut v), c) => {
+            v.push(Expr::Const(c));
+            Expr::Prod(v)
+        }
+        (term, c) => Expr::Prod(vec![term, Expr::Const(c)]),
+    }
+}
+
+/// Add two terms of a sum if they are the same apart from their constant factors (see
+/// [`Expr::simplify_collect_like_terms`]), e.g. `3sin(x)` and `-sin(x)` give `2sin(x)`, and
+/// terms which cancel give 0. The terms are given back unchanged if they aren't like terms, or
+/// if adding their constants would overflow.
+pub fn merge_like_terms(a: Expr, b: Expr) -> Result<Expr, (Expr, Expr)> {
+    let (mut a, b) = (LikeTerm::new(a), LikeTerm::new(b));
+    match a.merge(&b) {
+        true => Ok(a.into_merged()),
+        false => Err((a.original.unwrap(), b.original.unwrap())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{conformance, generate_derivative_problem, Difficulty, Rng, VarId};
+    use super::*;
+
+    #[test]
+    fn invariants() {
+        let corpus = conformance::corpus(387, 150);
+        conformance::check_rewrite(Expr::simplify, &corpus).unwrap();
+        let options = SimplifyOptions::aggressive();
+        conformance::check_rewrite(|e| e.simplify_with(&options), &corpus).unwrap();
+        let options = SimplifyOptions {
+            expand_polynomials: true,
+            ..Default::default()
+        };
+        conformance::check_rewrite(|e| e.simplify_with(&options), &corpus).unwrap();
+    }
+
+    #[test]
+    fn simplified() {
+        let mut rng = Rng::new(387);
+        let options = SimplifyOptions::aggressive();
+        for _ in 0..50 {
+            let problem = generate_derivative_problem(&mut rng, Difficulty::Hard).unwrap();
+            let e = problem.expr * problem.derivative;
+            let original = e.clone();
+            let simplified = e.to_simplified();
+            conformance::check_value_preserving(&e, &simplified, conformance::SAMPLES).unwrap();
+
+            let mut in_place = e.clone();
+            in_place.simplify();
+            assert_eq!(e.to_simplified(), in_place);
+            assert_eq!(e, original);
+            assert_eq!(e.clone().simplified(), in_place);
+
+            let mut in_place = e.clone();
+            in_place.simplify_with(&options);
+            assert_eq!(e.to_simplified_with(&options), in_place);
+            assert_eq!(e, original);
+            assert_eq!(e.simplified_with(&options), in_place);
+        }
+    }
+
+    #[test]
+    fn pass_order() {
+        let y = || Expr::Var(super::super::VarId('y'));
+        // The sum left by collapsing the product still gets the sum passes
+        let e = Expr::Prod(vec![Expr::Sum(vec![Expr::X, Expr::X])]);
+        crate::assert_expr_eq!(e.simplified(), Expr::X * 2);
+
+        let e = Expr::Sum(vec![Expr::Prod(vec![Expr::Const(0), Expr::X]), y()]);
+        crate::assert_expr_eq!(e.simplified(), y());
+
+        let e = -Expr::Prod(vec![Expr::Const(1), Expr::Const(-1)]);
+        crate::assert_expr_eq!(e.simplified(), Expr::Const(1));
+
+        // The sum in the negative collects into one term, so there is nothing to distribute
+        let e = -(Expr::X.sin() + Expr::X.sin() * 3);
+        crate::assert_expr_eq!(e.simplified(), -(Expr::X.sin() * 4));
+    }
+
+    #[test]
+    fn like_terms() {
+        let e = Expr::X.sin() * 3 - Expr::X.sin() + Expr::X + 1;
+        crate::assert_expr_eq!(e.simplified(), Expr::X.sin() * 2 + Expr::X + 1);
+        // The order of the factors doesn't matter
+        let e = Expr::X * Expr::X.cos() - Expr::X.cos() * Expr::X;
+        assert_eq!(e.simplified(), Expr::Const(0));
+        let (y, z) = (|| Expr::Var(VarId('y')), || Expr::Var(VarId('z')));
+        let e = Expr::X * y() * y() + y() * y() * Expr::X * 2 + Expr::X * Expr::X * y();
+        crate::assert_expr_eq!(
+            e.simplified(),
+            Expr::X * y().pow(Expr::Const(2)) * 3 + Expr::X.pow(Expr::Const(2)) * y()
+        );
+        let e = y().sin() * Expr::X * z() * 4 - z() * y().sin() * Expr::X + Expr::X * z();
+        crate::assert_expr_eq!(
+            e.simplified(),
+            y().sin() * Expr::X * z() * 3 + Expr::X * z()
+        );
+        let e = Expr::X * 2 - Expr::X * 2;
+        crate::assert_expr_eq!(e.simplified(), Expr::Const(0));
+    }
+
+    /// [`Expr::simplify_collect_like_terms`] as it was before it took the terms out of the sum,
+    /// to check that it does the same thing
+    fn collect_like_terms_reference(e: &mut Expr) {
+        let Expr::Sum(v) = e else {
+            return;
+        };
+        let mut like_terms: Vec<(Expr, Option<Monomial>, Num, usize)> = vec![];
+        for e in v.iter() {
+            let (c, term) = e.split_const_factor();
+            let monomial = Monomial::new(&term);
+            let like = like_terms.iter_mut().find(|(other, other_monomial, _, _)| {
+                match (&monomial, other_monomial) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => *other == term,
+                }
+            });
+            match like {
+                Some((_, _, total, count)) => match total.checked_add(c) {
+                    Some(sum) => (*total, *count) = (sum, *count + 1),
+                    None => return,
+                },
+                None => like_terms.push((term, monomial, c, 1)),
+            }
+        }
+        if like_terms.iter().all(|(_, _, _, count)| *count == 1) {
+            return;
+        }
+        *v = like_terms
+            .into_iter()
+            .filter(|(_, _, c, _)| *c != 0)
+            .map(|(term, _, c, _)| with_const_factor(term, c))
+            .collect();
+    }
+
+    #[test]
+    fn like_terms_unchanged() {
+        fn check(e: &Expr) {
+            let (mut new, mut old) = (e.clone(), e.clone());
+            new.simplify_collect_like_terms();
+            collect_like_terms_reference(&mut old);
+            assert_eq!(new, old, "{e:?}");
+            e.children().into_iter().for_each(check);
+        }
+        let corpus = conformance::corpus(447, 150);
+        for (e, other) in corpus.iter().zip(corpus.iter().skip(1)) {
+            check(e);
+            // Sums which have like terms in them, in different shapes
+            let terms = vec![
+                e.clone(),
+                other.clone(),
+                e.clone() * 3,
+                -e.clone(),
+                -other.clone(),
+            ];
+            check(&Expr::Sum(terms));
+            check(&Expr::Sum(vec![
+                e.clone() * 2,
+                -(e.clone() * 2),
+                other.clone(),
+            ]));
+        }
+    }
+
+    #[test]
+    fn merging() {
+        let sin = || Expr::X.sin();
+        crate::assert_expr_eq!(merge_like_terms(sin() * 3, -sin()).unwrap(), sin() * 2);
+        assert_eq!(merge_like_terms(sin(), -sin()), Ok(Expr::Const(0)));
+        assert_eq!(merge_like_terms(sin(), Expr::X), Err((sin(), Expr::X)));
+        let (big, also_big) = (Expr::X * Num::MAX, Expr::X * 2);
+        assert_eq!(
+            merge_like_terms(big.clone(), also_big.clone()),
+            Err((big, also_big))
+        );
+
+        // A term which would overflow is left on its own, and the others are still collected
+        let mut e = Expr::Sum(vec![
+            Expr::X * Num::MAX,
+            sin(),
+            Expr::X * 2,
+            sin(),
+            Expr::X * -1,
+        ]);
+        e.simplify_collect_like_terms();
+        assert_eq!(
+            e,
+            Expr::Sum(vec![
+                Expr::Prod(vec![Expr::X, Expr::Const(Num::MAX - 1)]),
+                Expr::Prod(vec![sin(), Expr::Const(2)]),
+                Expr::Prod(vec![Expr::X, Expr::Const(2)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn cancellation() {
+        let square = || Expr::X.pow(Expr::Const(2));
+        let cancelling = [
+            (
+                Expr::X * 2 - Expr::X * 2,
+                Expr::Sum(vec![
+                    Expr::Prod(vec![Expr::Const(2), Expr::X]),
+                    Expr::Prod(vec![Expr::Const(-2), Expr::X]),
+                ]),
+            ),
+            (
+                Expr::X.sin() - Expr::X.sin(),
+                Expr::Sum(vec![Expr::X.sin(), Expr::Neg(Box::new(Expr::X.sin()))]),
+            ),
+            (
+                square() - square(),
+                Expr::Sum(vec![square(), Expr::Prod(vec![Expr::Const(-1), square()])]),
+            ),
+        ];
+        for (operators, raw) in cancelling {
+            for mut e in [operators, raw] {
+                e.simplify();
+                assert_eq!(e, Expr::Const(0));
+            }
+        }
+
+        // Terms cancel when they are in nested sums, or negated more than once
+        let mut e = Expr::Sum(vec![
+            Expr::X.sin(),
+            Expr::Sum(vec![
+                Expr::Const(1),
+                Expr::Neg(Box::new(Expr::Prod(vec![Expr::X.sin(), Expr::Const(3)]))),
+            ]),
+            Expr::Neg(Box::new(Expr::Neg(Box::new(Expr::X.sin() * 2)))),
+            Expr::Const(-1),
+        ]);
+        e.simplify();
+        assert_eq!(e, Expr::Const(0));
+    }
+
+    #[test]
+    fn report() {
+        let options = SimplifyOptions::aggressive();
+        let mut e = Expr::X * 2 + Expr::X * 3 + Expr::Var(super::super::VarId('y')) * 5;
+        let report = e.simplify_with_report(&options);
+        assert!(report.converged);
+        assert!(!report.oscillation_detected);
+        assert_eq!(report.iterations, 2);
+
+        // Factoring and expanding undo each other, so x^2 + x would go back and forth with x(x+1)
+        let options = SimplifyOptions {
+            factor_common_terms: true,
+            expand_polynomials: true,
+            ..Default::default()
+        };
+        let mut e = Expr::X.pow(Expr::Const(2)) + Expr::X;
+        let report = e.simplify_with_report(&options);
+        assert!(report.oscillation_detected);
+        assert!(!report.converged);
+        assert_eq!(report.iterations, 1);
+        assert_eq!(e, Expr::X.pow(Expr::Const(2)) + Expr::X);
+
+        let mut e = Expr::X * (Expr::X + 1);
+        let report = e.simplify_with_report(&options);
+        assert!(report.oscillation_detected);
+        assert!(e.equivalent(&(Expr::X.pow(Expr::Const(2)) + Expr::X)));
+
+        // Simplifying only reorders x^2/2x at first, which isn't going back to where it started
+        let options = SimplifyOptions {
+            assumptions: Assumptions::nonzero(),
+            ..Default::default()
+        };
+        let mut e = Expr::Prod(vec![
+            Expr::X.pow(Expr::Const(2)),
+            Expr::Const(2).recip(),
+            Expr::X.recip(),
+        ]);
+        let report = e.simplify_with_report(&options);
+        assert!(!report.oscillation_detected);
+        assert_eq!(e, Expr::X * Expr::Const(2).recip());
+    }
+
+    #[test]
+    fn zero_powers() {
+        let zero = || Expr::Const(0);
+        let keep = SimplifyOptions {
+            keep_zero_pow_zero: true,
+            ..Default::default()
+        };
+        for options in [SimplifyOptions::default(), keep.clone()] {
+            let simplify = |e: Expr| {
+                let mut e = e * Expr::X;
+                let report = e.simplify_with_report(&options);
+                (e, report.warnings)
+            };
+            // 0^0 is 1 by convention, unless it is kept
+            let (e, warnings) = simplify(zero().pow(Expr::X - Expr::X));
+            match options.keep_zero_pow_zero {
+                true => assert_eq!(e, zero().pow(zero()) * Expr::X),
+                false => assert_eq!(e, Expr::X),
+            }
+            assert!(warnings.is_empty());
+            // 0 to a positive power is 0, even when the base isn't written as 0
+            let (e, warnings) = simplify(Expr::Const(0).sin().pow(Expr::Const(3)));
+            assert_eq!(e, zero());
+            assert!(warnings.is_empty());
+            // 0 to a negative power is undefined, so it is left and warned about once
+            let (e, warnings) = simplify(zero().pow(Expr::Const(-2)) + zero().pow(Expr::Const(-2)));
+            let undefined = zero().pow(Expr::Const(-2));
+            assert_eq!(e.replace_counted(&undefined, &undefined).1, 1, "{e:?}");
+            assert_eq!(
+                warnings,
+                vec![SimplifyWarning {
+                    rule: "zero to a negative power",
+                    assumption: None,
+                    subexpr_latex: "0^{-2}".to_string(),
+                }]
+            );
+            // Anything else to the power of 0 is 1
+            let (e, warnings) = simplify((Expr::X + 1).pow(zero()));
+            assert_eq!(e, Expr::X);
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn budget() {
+        let mut rng = Rng::new(441);
+        for max_node_visits in [0, 1, 5, 20] {
+            let problem = generate_derivative_problem(&mut rng, Difficulty::Hard).unwrap();
+            let original = problem.expr * problem.derivative;
+            let options = SimplifyOptions {
+                budget: Some(Budget {
+                    max_node_visits: Some(max_node_visits),
+                    max_millis: None,
+                }),
+                ..SimplifyOptions::aggressive()
+            };
+            let mut e = original.clone();
+            let report = e.simplify_with_report(&options);
+            assert!(report.budget_exhausted);
+            assert!(!report.converged);
+            assert!(e.node_count() <= original.node_count());
+            conformance::check_value_preserving(&original, &e, conformance::SAMPLES).unwrap();
+        }
+
+        // Time runs out before anything is done
+        let mut e = Expr::X * 2 + Expr::X * 3;
+        let options = SimplifyOptions {
+            budget: Some(Budget {
+                max_node_visits: None,
+                max_millis: Some(0),
+            }),
+            ..Default::default()
+        };
+        let report = e.simplify_with_report(&options);
+        assert!(report.budget_exhausted);
+        assert_eq!(e, Expr::X * 2 + Expr::X * 3);
+
+        // The optional simplifications count against the budget too
+        let mut meter = Meter::new(&SimplifyOptions {
+            budget: Some(Budget {
+                max_node_visits: Some(2),
+                max_millis: None,
+            }),
+            ..Default::default()
+        });
+        let mut e = (Expr::X + 1) * (Expr::X + 2);
+        let mut expanded = 0;
+        e.transform_metered(&mut |_| expanded += 1, &mut meter);
+        assert_eq!(expanded, 2);
+        assert!(meter.spent);
+
+        // A budget which is big enough changes nothing
+        let options = SimplifyOptions {
+            budget: Some(Budget {
+                max_node_visits: Some(1_000_000),
+                max_millis: Some(60_000),
+            }),
+            ..SimplifyOptions::aggressive()
+        };
+        for _ in 0..20 {
+            let problem = generate_derivative_problem(&mut rng, Difficulty::Hard).unwrap();
+            let e = problem.expr * problem.derivative;
+            let mut budgeted = e.clone();
+            let report = budgeted.simplify_with_report(&options);
+            assert!(!report.budget_exhausted);
+            assert_eq!(budgeted, e.simplified_with(&SimplifyOptions::aggressive()));
+        }
+    }
+
+    #[test]
+    fn constant_shapes() {
+        let x = || Expr::X;
+        // Exponents and factors which are constants but aren't written as one
+        let zero = Expr::Const(1).ln();
+        let one = Expr::E.ln();
+        assert_eq!(x().pow(zero.clone()).simplified(), Expr::Const(1));
+        assert_eq!(x().sin().pow(one).simplified(), x().sin());
+        assert_eq!((zero * x().cos()).simplified(), Expr::Const(0));
+        let neg_zero = Expr::Neg(Box::new(Expr::Const(0).sin()));
+        assert_eq!((x() * neg_zero).simplified(), Expr::Const(0));
+    }
+
+    #[test]
+    fn no_placeholders() {
+        let y = || Expr::Var(super::super::VarId('y'));
+        let mut e = -(-(-(-y())));
+        e.simplify_double_negative();
+        assert_eq!(e, y());
+
+        // A panic while the new sum is being simplified leaves the whole new sum in the tree,
+        // rather than a sum of made-up terms
+        let mut e = -(y() + 2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            e.distribute_negative_in_sum_then(|_| panic!("simplify failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(e, Expr::Sum(vec![-y(), -Expr::Const(2)]));
+        assert!(!e.uses_x());
+
+        let mut e = -(y() + 2);
+        e.simplify_distribute_negative_in_sum();
+        assert_eq!(e, -y() + Expr::Const(-2));
+    }
+
+    /// Promoting a child to replace its parent moves it, so it gives the same child and keeps
+    /// its storage, however big it is
+    #[test]
+    fn promotion() {
+        let corpus = conformance::corpus(459, 100);
+        for e in &corpus {
+            let one = || Box::new(Expr::Const(1));
+            let cases: [(Expr, Pass); 3] = [
+                (
+                    Expr::Pow(Box::new(e.clone()), one()),
+                    Expr::simplify_one_pow,
+                ),
+                (Expr::Sum(vec![e.clone()]), Expr::simplify_singleton),
+                (Expr::Prod(vec![e.clone()]), Expr::simplify_singleton),
+            ];
+            for (mut wrapped, promote) in cases {
+                promote(&mut wrapped);
+                assert_eq!(&wrapped, e);
+            }
+        }
+
+        let terms: Vec<Expr> = (0..1000).map(|n| Expr::X.pow(Expr::Const(n))).collect();
+        let ptr = terms.as_ptr();
+        let mut e = Expr::Pow(Box::new(Expr::Sum(terms)), Box::new(Expr::Const(1)));
+        e.simplify_one_pow();
+        let Expr::Sum(v) = &e else { panic!("{e:?}") };
+        assert_eq!(v.as_ptr(), ptr);
+        let mut e = Expr::Prod(vec![e]);
+        e.simplify_singleton();
+        assert!(matches!(&e, Expr::Sum(v) if v.as_ptr() == ptr));
+    }
+
+    #[test]
+    fn collect_powers() {
+        use super::super::prelude::{c, cos, pow, recip, sin, var, x};
+
+        let e = pow(x(), c(2)) * x() * pow(x(), c(4));
+        assert_eq!(e.simplified(), pow(x(), c(7)));
+
+        let e = recip(x()) * pow(x(), c(-2)) * pow(x(), c(3)).recip();
+        assert_eq!(e.simplified(), pow(x(), c(-6)));
+
+        // The exponents might have different signs, so these need x != 0
+        let nonzero = |e: Expr| {
+            let mut e = e;
+            e.simplify_with(&SimplifyOptions {
+                assumptions: Assumptions::nonzero(),
+                ..Default::default()
+            });
+            e
+        };
+        // x^n * x^2 * sin(x) * x = x^(n+3) sin(x)
+        let e = pow(x(), var('n')) * pow(x(), c(2)) * sin(x()) * x();
+        assert_eq!(e.clone().simplified(), e);
+        assert_eq!(nonzero(e), pow(x(), var('n') + c(3)) * sin(x()));
+
+        let e = pow(x(), sin(x())) * pow(x(), cos(x())) * pow(x(), c(1));
+        assert_eq!(nonzero(e), pow(x(), sin(x()) + cos(x()) + c(1)));
+
+        // The exponents add up to 0, so the factor goes
+        let e = pow(x(), var('n')) * c(3) * pow(x(), -var('n'));
+        assert_eq!(nonzero(e), c(3));
+        let e = pow(x(), var('n') + c(1)) * pow(x(), -var('n') - c(1));
+        assert_eq!(nonzero(e), c(1));
+        for e in [
+            pow(x(), var('y')) * pow(x(), -var('y')),
+            pow(x(), c(1) / c(2)) * pow(x(), c(-1) / c(2)),
+        ] {
+            assert_ne!(e.clone().simplified(), c(1));
+            assert_eq!(nonzero(e), c(1));
+        }
+
+        // A base which is never 0 is fine
+        let base = pow(x(), c(2)) + c(1);
+        let e = pow(base.clone(), var('y')) * pow(base, -var('y'));
+        assert_eq!(e.simplified(), c(1));
+
+        // Cancelling x/x needs x != 0, and constants are multiplied by another pass
+        let e = x() * c(2) / x();
+        assert_eq!(e.simplified(), x() / x() * c(2));
+    }
+
+    #[test]
+    fn smallest_constant() {
+        // The negative of the smallest constant doesn't fit, so it stays a negative
+        let e = Expr::Neg(Box::new(Expr::Const(Num::MIN)));
+        assert_eq!(e.clone().simplified(), e);
+        let e = Expr::Neg(Box::new(Expr::Scaled(Num::MIN, 3)));
+        assert_eq!(e.clone().simplified(), e);
+        assert_eq!(gcd(Num::MIN, 6), Some(2));
+        assert_eq!(gcd(Num::MIN, 0), None);
+        assert_eq!(gcd(Num::MIN, Num::MIN), None);
+        assert_eq!(gcd(-4, 6), Some(2));
+        // A product with the smallest constant over a negative is left alone
+        let e = Expr::Prod(vec![
+            Expr::X,
+            Expr::Const(Num::MIN),
+            Expr::Const(-3).recip(),
+        ]);
+        assert_eq!(e.clone().simplified(), e);
+    }
+
+    /// Simplify with the steps of [`Expr::simplify_with`], either skipping clean subexpressions
+    /// or going to every one, and get how many subexpressions were gone to
+    fn simplify_counting(
+        e: &Expr,
+        options: &SimplifyOptions,
+        skip_clean: bool,
+    ) -> (Expr, SimplifyReport, usize) {
+        let mut meter = Meter::new(options);
+        if !skip_clean {
+            meter.clean = None;
+        }
+        let mut e = e.clone();
+        let mut report = e.simplify_iterations(options, &mut meter);
+        report.warnings = meter.warnings;
+        (e, report, meter.traversed)
+    }
+
+    #[test]
+    fn clean_subtrees() {
+        let corpus = conformance::corpus(461, 100);
+        let all_options = [
+            SimplifyOptions::default(),
+            SimplifyOptions::aggressive(),
+            SimplifyOptions {
+                expand_polynomials: true,
+                expand_trig: true,
+                ..Default::default()
+            },
+        ];
+        for options in &all_options {
+            for e in &corpus {
+                let (skipping, skipping_report, _) = simplify_counting(e, options, true);
+                let (every, every_report, _) = simplify_counting(e, options, false);
+                assert_eq!(skipping, every, "{e:?}");
+                assert_eq!(skipping_report, every_report, "{e:?}");
+            }
+        }
+    }
+
+    /// A deep expression which is already simplified apart from one part at the bottom, which
+    /// takes a few iterations, is only simplified again along the path to that part
+    #[test]
+    fn frontier() {
+        use super::super::prelude::{c, cos, pow, sin, x};
+
+        let options = SimplifyOptions::aggressive();
+        let mut e = Expr::Hole(super::super::HoleId(0));
+        for k in 1..=40 {
+            // A polynomial in x, which is most of each level
+            let side: Expr = (1..=5).map(|j| pow(x(), c(j)) * (k + j)).sum();
+            e = sin(e.ln() + cos(side)) * cos(x() + c(k));
+        }
+        let e = e
+            .simplified_with(&options)
+            .fill_hole(super::super::HoleId(0), &(x() * 6 + (x() + 6) * 6));
+
+        let (skipping, report, skipping_count) = simplify_counting(&e, &options, true);
+        let (every, _, every_count) = simplify_counting(&e, &options, false);
+        assert_eq!(skipping, every);
+        assert!(report.converged && report.iterations >= 3, "{report:?}");
+        // Without skipping, every iteration goes to the whole tree
+        assert!(
+            every_count >= report.iterations * e.node_count(),
+            "{every_count}"
+        );
+        assert!(
+            skipping_count * 5 < every_count,
+            "{skipping_count} {every_count}"
+        );
+    }
+}
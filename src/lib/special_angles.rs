@@ -0,0 +1,168 @@
+use super::ratio::Ratio;
+use super::{Expr, Num};
+
+/// The values of sin at the angles `kπ/12` for k from 0 to 6 which are in the table, as the
+/// number the square root is taken of and a denominator, e.g. `(3, 2)` is `\sqrt{3}/2`
+const SIN_TWELFTHS: [Option<(Num, Num)>; 7] = [
+    Some((0, 1)),
+    None,
+    Some((1, 2)),
+    Some((2, 2)),
+    Some((3, 2)),
+    None,
+    Some((1, 1)),
+];
+
+impl Expr {
+    /// The exact value of a sin or cos of a multiple of π/6 or π/4 (see [`pi_multiple`]), e.g.
+    /// `\frac{1}{2}` for `\sin(π/6)` and `-\frac{\sqrt{2}}{2}` for `\cos(3π/4)`. `None` for any
+    /// other expression, or an angle which isn't in the table.
+    pub(super) fn special_trig_value(&self) -> Option<Expr> {
+        let (negative, (radicand, den)) = self.special_trig()?;
+        let value = match radicand {
+            0 | 1 => return self.special_trig_ratio().map(Ratio::to_expr),
+            n => Expr::Const(n).sqrt() / Expr::Const(den),
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    /// [`Expr::special_trig_value`], if it is a fraction
+    fn special_trig_ratio(&self) -> Option<Ratio> {
+        match self.special_trig()? {
+            (negative, (n @ (0 | 1), den)) => Ratio::new(if negative { -n } else { n }, den),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is negative, and its size from [`SIN_TWELFTHS`]
+    fn special_trig(&self) -> Option<(bool, (Num, Num))> {
+        let (angle, shift) = match self {
+            Expr::Sin(angle) => (angle, 0),
+            // cos(θ) = sin(θ + π/2)
+            Expr::Cos(angle) => (angle, 6),
+            _ => return None,
+        };
+        let angle = pi_multiple(angle)?;
+        if 12 % angle.den != 0 {
+            return None;
+        }
+        let twelfths = angle.num.checked_mul(12 / angle.den)?;
+        // Reduce the angle into [0, 2π), and then into [0, π) with sin(θ + π) = -sin(θ)
+        let k = (twelfths.rem_euclid(24) + shift) % 24;
+        let (negative, k) = if k >= 12 { (true, k - 12) } else { (false, k) };
+        // sin(π - θ) = sin(θ)
+        let k = if k > 6 { 12 - k } else { k };
+        let value = SIN_TWELFTHS[k as usize]?;
+        Some((negative && value.0 != 0, value))
+    }
+}
+
+/// An expression as a fraction times π, if it is one, where π is `\arccos(-1)` (see
+/// [`prelude::pi`](super::prelude::pi)), e.g. `3π/4` written as `Prod([Const(3), π,
+/// Const(4)^{-1}])`. A constant which is 0 is 0 times π.
+pub(super) fn pi_multiple(e: &Expr) -> Option<Ratio> {
+    match e {
+        Expr::Arccos(a) if a.exact_value() == Some(Ratio::whole(-1)) => Some(Ratio::ONE),
+        Expr::Neg(e) => pi_multiple(e)?.neg(),
+        Expr::Prod(v) => {
+            let mut multiple = Ratio::ONE;
+            let mut has_pi = false;
+            for factor in v {
+                let value = match factor.exact_value() {
+                    Some(value) => value,
+                    None if !has_pi => {
+                        has_pi = true;
+                        pi_multiple(factor)?
+                    }
+                    None => return None,
+                };
+                multiple = multiple.mul(value)?;
+            }
+            (has_pi || multiple == Ratio::ZERO).then_some(multiple)
+        }
+        e => (e.exact_value()? == Ratio::ZERO).then_some(Ratio::ZERO),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, pi, sin, sqrt, x};
+    use super::super::ConstFoldMode;
+    use super::*;
+
+    /// The angles of the unit circle table, as a fraction of π
+    const TABLE: [(Num, Num); 16] = [
+        (0, 1),
+        (1, 6),
+        (1, 4),
+        (1, 3),
+        (1, 2),
+        (2, 3),
+        (3, 4),
+        (5, 6),
+        (1, 1),
+        (7, 6),
+        (5, 4),
+        (4, 3),
+        (3, 2),
+        (5, 3),
+        (7, 4),
+        (11, 6),
+    ];
+
+    fn has_trig(e: &Expr) -> bool {
+        e.kind().is_trig() || e.children().into_iter().any(has_trig)
+    }
+
+    #[test]
+    fn unit_circle() {
+        for (p, q) in TABLE {
+            // The same angle, and after going round once or more either way
+            for turns in [-2, -1, 0, 1, 3] {
+                let angle = pi() * (p + 2 * q * turns) / q;
+                let radians = std::f64::consts::PI * (p + 2 * q * turns) as f64 / q as f64;
+                for (f, expected) in [
+                    (sin as fn(Expr) -> Expr, radians.sin()),
+                    (cos, radians.cos()),
+                ] {
+                    let mut e = f(angle.clone());
+                    e.fold_constants(ConstFoldMode::Exact);
+                    assert!(!has_trig(&e), "{p}π/{q} + {turns} turns: {e:?}");
+                    assert!((e.eval(0.0).unwrap() - expected).abs() < 1e-12, "{e:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_values() {
+        let fold = |mut e: Expr| {
+            e.fold_constants(ConstFoldMode::Exact);
+            e
+        };
+        assert_eq!(fold(sin(pi() / 6)), (c(1) / 2).simplified());
+        assert_eq!(fold(cos(-pi() * 2 / 3)), (c(-1) / 2).simplified());
+        let three_quarters = Expr::Prod(vec![c(3), pi(), c(4).recip()]);
+        assert_eq!(fold(cos(three_quarters)), -(sqrt(c(2)) / 2));
+        assert_eq!(fold(sin(pi() * 4 / 3)), -(sqrt(c(3)) / 2));
+        assert_eq!(fold(sin(pi() * 13)), c(0));
+        // Sums of them are worked out when they are fractions
+        assert_eq!(fold(sin(pi() / 6) + cos(pi() / 3)), c(1));
+        assert_eq!((sin(x()) * 2 + cos(x())).eval_symbolic(&(pi() / 2)), c(2));
+    }
+
+    #[test]
+    fn untouched() {
+        for e in [
+            sin(pi() / 5),
+            cos(pi() / 12),
+            sin(pi() * pi()),
+            cos(c(1)),
+            sin(pi() + 1),
+        ] {
+            let mut folded = e.clone();
+            folded.fold_constants(ConstFoldMode::Exact);
+            assert_eq!(folded, e);
+        }
+    }
+}
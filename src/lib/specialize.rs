@@ -0,0 +1,127 @@
+use super::fold::float_const;
+use super::{Expr, VarId};
+
+impl Expr {
+    /// Whether the only variables the expression uses are in `vars`, where x is `VarId('x')`.
+    /// The index of a [`Expr::SumOver`] doesn't count inside the sum, since it is bound there.
+    /// The expression is only gone through once.
+    pub fn depends_only_on(&self, vars: &[VarId]) -> bool {
+        self.only_uses(vars, &mut vec![])
+    }
+
+    /// [`Expr::depends_only_on`], where `bound` are the indices of the sums this is inside of
+    fn only_uses(&self, vars: &[VarId], bound: &mut Vec<VarId>) -> bool {
+        match self {
+            Expr::X => vars.contains(&VarId('x')),
+            Expr::Var(v) => vars.contains(v) || bound.contains(v),
+            // A rate only has a value when one is bound with Expr::bind_rates
+            Expr::Deriv(..) => false,
+            Expr::SumOver(k, from, to, e) => {
+                if !from.only_uses(vars, bound) || !to.only_uses(vars, bound) {
+                    return false;
+                }
+                bound.push(*k);
+                let only = e.only_uses(vars, bound);
+                bound.pop();
+                only
+            }
+            e => e.children().iter().all(|e| e.only_uses(vars, bound)),
+        }
+    }
+
+    /// Put values in for some of the variables (x is `VarId('x')`), and replace each part of
+    /// the expression which is now constant with its value, so that the result is as quick as
+    /// possible to evaluate many times, e.g. for many values of x with the other variables
+    /// fixed.
+    ///
+    /// The rest of the expression isn't rewritten, so evaluating the result gives exactly the
+    /// same floats as evaluating the expression with the values put in. Parts which can't be
+    /// evaluated (like `ln(-1)`) are left as they are, so they still give an error when the
+    /// result is evaluated. A value which isn't finite can't be put in an expression, so its
+    /// variable is left alone.
+    pub fn specialize(&self, bindings: &[(VarId, f64)]) -> Expr {
+        let mut e = self.clone();
+        for (var, value) in bindings {
+            let Some(value) = float_const(*value) else {
+                continue;
+            };
+            e = match var {
+                VarId('x') => e.substitute(&value),
+                var => e.substitute_var(*var, &value),
+            };
+        }
+        e.hoist_constants();
+        e
+    }
+
+    /// Replace the biggest subexpressions which don't use any variables with their values
+    fn hoist_constants(&mut self) {
+        if self.kind().is_atom() {
+            return;
+        }
+        if self.depends_only_on(&[]) {
+            if let Some(value) = self.eval(0.0).ok().and_then(float_const) {
+                *self = value;
+                return;
+            }
+        }
+        for e in self.children_mut() {
+            e.hoist_constants();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, exp, ln, pow, sin, sqrt, sum_over, var, x};
+    use super::*;
+
+    fn t() -> Expr {
+        var('t')
+    }
+
+    #[test]
+    fn dependencies() {
+        let e = sin(x() * t()) + sum_over('k', c(1), var('n'), var('k') * x());
+        assert!(e.depends_only_on(&[VarId('x'), VarId('t'), VarId('n')]));
+        assert!(!e.depends_only_on(&[VarId('x'), VarId('t')]));
+        assert!(!e.depends_only_on(&[VarId('t'), VarId('n')]));
+        assert!(c(3).depends_only_on(&[]));
+        // The index of a sum is only bound inside it
+        let e = sum_over('k', c(1), var('k'), var('k'));
+        assert!(!e.depends_only_on(&[]));
+    }
+
+    #[test]
+    fn specialization() {
+        // A damped wave in x and t, with parts which only depend on t
+        let e = exp(-t() * 3) * sin(x() * cos(t()) + sqrt(t() + 2))
+            + pow(x(), c(2)) * ln(t() + 1) / (t() * t() + 1);
+        let t_value = 0.37;
+        let specialized = e.specialize(&[(VarId('t'), t_value)]);
+        assert!(specialized.depends_only_on(&[VarId('x')]));
+        assert!(
+            specialized.node_count() < e.node_count() - 10,
+            "{specialized:?}"
+        );
+        let t_const = float_const(t_value).unwrap();
+        for i in 0..1000 {
+            let x = i as f64 * 0.013 - 6.0;
+            let expected = e.substitute_var(VarId('t'), &t_const).eval(x);
+            assert_eq!(specialized.eval(x), expected);
+        }
+        // Putting in x too leaves a single number
+        let value = e.specialize(&[(VarId('t'), t_value), (VarId('x'), 1.5)]);
+        assert!(matches!(value, Expr::Const(_) | Expr::Scaled(..)));
+        assert_eq!(value.eval(0.0), specialized.eval(1.5));
+    }
+
+    #[test]
+    fn errors_stay() {
+        let e = ln(t()) * x();
+        let specialized = e.specialize(&[(VarId('t'), -1.0)]);
+        assert_eq!(specialized, ln(c(-1)) * x());
+        assert!(specialized.eval(2.0).is_err());
+        assert_eq!(e.specialize(&[(VarId('t'), f64::NAN)]), e);
+    }
+}
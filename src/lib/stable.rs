@@ -0,0 +1,193 @@
+use super::{EvalError, Expr, ExprKind};
+
+impl Expr {
+    /// Evaluate the expression as a float like [`Expr::eval`], but with more accurate formulas
+    /// for expressions which lose most of their digits when evaluated directly:
+    /// - `ln(1 + u)` for small u, with [`f64::ln_1p`]
+    /// - `e^u - 1` for small u, with [`f64::exp_m1`]
+    /// - `1 - cos(u)` for small u, as `2sin(u/2)^2`
+    /// - `sqrt(a) - b` when a is close to b^2, as `(a - b^2)/(sqrt(a) + b)` with `a - b^2` worked
+    ///   out exactly (this needs it to be a polynomial)
+    pub fn eval_stable(&self, x: f64) -> Result<f64, EvalError> {
+        match self.eval_stable_shape(x) {
+            Some(result) => result,
+            None => self.eval_node(x, &|e| e.eval_stable(x)),
+        }
+    }
+
+    /// Evaluate the expression with a stable formula, if it has one of the shapes listed in
+    /// [`Expr::eval_stable`].
+    fn eval_stable_shape(&self, x: f64) -> Option<Result<f64, EvalError>> {
+        match self {
+            Expr::Ln(e) => {
+                let u = one_plus(e)?;
+                Some(u.eval_stable(x).and_then(|u| {
+                    if u <= -1.0 {
+                        return Err(EvalError::Domain {
+                            kind: ExprKind::Ln,
+                            value: 1.0 + u,
+                        });
+                    }
+                    Ok(u.ln_1p())
+                }))
+            }
+            Expr::Sum(v) if v.len() == 2 => {
+                let (a, b) = (&v[0], &v[1]);
+                exp_minus_one(a, b)
+                    .or_else(|| exp_minus_one(b, a))
+                    .map(|u| u.eval_stable(x).map(f64::exp_m1))
+                    .or_else(|| {
+                        let u = one_minus_cos(a, b).or_else(|| one_minus_cos(b, a))?;
+                        Some(u.eval_stable(x).map(|u| 2.0 * (u / 2.0).sin().powi(2)))
+                    })
+                    .or_else(|| {
+                        let Expr::Neg(b) = b else {
+                            return None;
+                        };
+                        // sqrt(a) - b, or b - sqrt(a)
+                        root_minus(a, b, x).or_else(|| root_minus(b, a, x).map(|r| r.map(|r| -r)))
+                    })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn is_one(e: &Expr) -> bool {
+    *e == Expr::Const(1)
+}
+
+fn is_minus_one(e: &Expr) -> bool {
+    matches!(e, Expr::Const(-1)) || matches!(e, Expr::Neg(e) if is_one(e))
+}
+
+/// If the expression is `1 + u`, get u
+fn one_plus(e: &Expr) -> Option<&Expr> {
+    match e {
+        Expr::Sum(v) => match v.as_slice() {
+            [a, u] | [u, a] if is_one(a) => Some(u),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If the terms are `e^u` and `-1`, get u
+fn exp_minus_one<'a>(a: &'a Expr, b: &Expr) -> Option<&'a Expr> {
+    match a {
+        Expr::Pow(base, u) if **base == Expr::E && is_minus_one(b) => Some(u),
+        _ => None,
+    }
+}
+
+/// If the terms are `1` and `-cos(u)`, get u
+fn one_minus_cos<'a>(a: &Expr, b: &'a Expr) -> Option<&'a Expr> {
+    match b {
+        Expr::Neg(cos) if is_one(a) => match cos.as_ref() {
+            Expr::Cos(u) => Some(u),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// If `root` is `sqrt(a)` where `a - b^2` is a polynomial, evaluate `sqrt(a) - b` with the
+/// conjugate
+fn root_minus(root: &Expr, b: &Expr, x: f64) -> Option<Result<f64, EvalError>> {
+    let Expr::Pow(a, half) = root else {
+        return None;
+    };
+    if half.root_index() != Some(2) {
+        return None;
+    }
+    let difference = &**a - b.clone().pow(Expr::Const(2));
+    let difference = Expr::from_coefficients(&difference.to_coefficients()?);
+    let b_value = match b.eval_stable(x) {
+        Ok(b) => b,
+        Err(e) => return Some(Err(e)),
+    };
+    // There is only cancellation when b is positive
+    if b_value <= 0.0 {
+        return None;
+    }
+    Some((|| {
+        let a = a.eval_stable(x)?;
+        if a < 0.0 {
+            return Err(EvalError::Domain {
+                kind: ExprKind::Pow,
+                value: a,
+            });
+        }
+        Ok(difference.eval_stable(x)? / (a.sqrt() + b_value))
+    })())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `eval_stable` gets at least 14 digits right, where `eval` gets fewer than
+    /// `naive_digits`
+    fn check(e: Expr, x: f64, reference: f64, naive_digits: i32) {
+        let error = |value: f64| ((value - reference) / reference).abs();
+        let stable = e.eval_stable(x).unwrap();
+        assert!(error(stable) < 1e-14, "{stable} vs {reference}");
+        let naive = e.eval(x).unwrap();
+        assert!(
+            error(naive) > 10f64.powi(-naive_digits),
+            "{naive} vs {reference}"
+        );
+    }
+
+    #[test]
+    fn ln_1p() {
+        let x = 1e-12;
+        // ln(1 + x) = x - x^2/2 + x^3/3 - ...
+        let reference = x - x * x / 2.0 + x * x * x / 3.0;
+        check((Expr::Const(1) + Expr::X).ln(), x, reference, 8);
+        check((Expr::X + 1).ln(), x, reference, 8);
+    }
+
+    #[test]
+    fn exp_m1() {
+        let x = 1e-10;
+        // e^x - 1 = x + x^2/2 + x^3/6 + ...
+        let reference = x + x * x / 2.0 + x * x * x / 6.0;
+        check(Expr::X.exp() - 1, x, reference, 9);
+    }
+
+    #[test]
+    fn one_minus_cos() {
+        let x: f64 = 1e-5;
+        // 1 - cos(x) = x^2/2 - x^4/24 + ...
+        let reference = x * x / 2.0 - x.powi(4) / 24.0;
+        check(Expr::Const(1) - Expr::X.cos(), x, reference, 8);
+    }
+
+    #[test]
+    fn conjugate() {
+        let x = 1e8;
+        // sqrt(x^2 + 1) - x = 1/(sqrt(x^2 + 1) + x) = 1/(2x) - 1/(8x^3) + ...
+        let reference = 1.0 / (2.0 * x) - 1.0 / (8.0 * x * x * x);
+        let e = (Expr::X.pow(Expr::Const(2)) + 1).sqrt() - Expr::X;
+        check(e, x, reference, 1);
+        let e = Expr::X - (Expr::X.pow(Expr::Const(2)) + 1).sqrt();
+        assert!((e.eval_stable(x).unwrap() + reference).abs() / reference < 1e-14);
+    }
+
+    #[test]
+    fn other_shapes() {
+        // Nothing special, and inside of other functions
+        let e = Expr::X.sin() * 3 + (Expr::Const(1) + Expr::X).ln().sin();
+        for x in [0.5, 2.0, 10.0] {
+            assert!((e.eval_stable(x).unwrap() - e.eval(x).unwrap()).abs() < 1e-12);
+        }
+        assert_eq!(
+            (Expr::Const(1) + Expr::X).ln().eval_stable(-2.0),
+            Err(EvalError::Domain {
+                kind: ExprKind::Ln,
+                value: -1.0
+            })
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{Expr, VarId};
+
+/// The names tried in order when a bound variable has to be renamed, see [`fresh_var`]
+const FRESH_VARS: &str = "jklmnpqrstuvwyzabcdfgh";
+
+/// How [`Expr::replace_with`] decides whether a subexpression matches.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MatchMode {
+    /// Only subexpressions which are exactly the same match
+    #[default]
+    Exact,
+    /// Some of the terms of a sum or product also match a sum or product, in any order, e.g.
+    /// `x^2 + 1` matches in `1 + sin(x) + x^2`
+    Subset,
+}
+
+impl Expr {
+    /// Replace every x in the expression with another expression.
+    pub fn substitute(&self, value: &Expr) -> Self {
+        match self {
+            Expr::Const(_)
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Hole(_)
+            | Expr::Deriv(..) => self.clone(),
+            Expr::X => value.clone(),
+            Expr::Sum(v) => Expr::Sum(v.iter().map(|e| e.substitute(value)).collect()),
+            Expr::Prod(v) => Expr::Prod(v.iter().map(|e| e.substitute(value)).collect()),
+            Expr::Neg(e) => Expr::Neg(Box::new(e.substitute(value))),
+            Expr::Pow(a, b) => a.substitute(value).pow(b.substitute(value)),
+            Expr::Ln(e) => e.substitute(value).ln(),
+            Expr::Sin(e) => e.substitute(value).sin(),
+            Expr::Cos(e) => e.substitute(value).cos(),
+            Expr::Arcsin(e) => Expr::Arcsin(Box::new(e.substitute(value))),
+            Expr::Arccos(e) => Expr::Arccos(Box::new(e.substitute(value))),
+            Expr::Arctan(e) => Expr::Arctan(Box::new(e.substitute(value))),
+            Expr::Sinh(e) => e.substitute(value).sinh(),
+            Expr::Cosh(e) => e.substitute(value).cosh(),
+            Expr::Tanh(e) => e.substitute(value).tanh(),
+            Expr::Arsinh(e) => Expr::Arsinh(Box::new(e.substitute(value))),
+            Expr::Arcosh(e) => Expr::Arcosh(Box::new(e.substitute(value))),
+            Expr::Artanh(e) => Expr::Artanh(Box::new(e.substitute(value))),
+            Expr::Factorial(e) => e.substitute(value).factorial(),
+            Expr::SumOver(k, from, to, e) => {
+                let (k, e) = bind_avoiding(*k, e, value);
+                e.substitute(value)
+                    .sum_over(k, from.substitute(value), to.substitute(value))
+            }
+            Expr::Piecewise(_) => {
+                let mut e = self.clone();
+                for child in e.children_mut() {
+                    *child = child.substitute(value);
+                }
+                e
+            }
+        }
+    }
+
+    /// Replace every free occurrence of a variable with another expression. A variable bound by
+    /// a [`Expr::SumOver`] is a different variable, so it isn't replaced inside it.
+    ///
+    /// Bound variables are renamed when `value` uses them, so that they don't capture it, e.g.
+    /// putting `k` for y in `SumOver(k, 1, n, yk)` gives `SumOver(j, 1, n, kj)`.
+    pub fn substitute_var(&self, var: VarId, value: &Expr) -> Self {
+        match self {
+            Expr::Var(v) if *v == var => value.clone(),
+            Expr::SumOver(k, from, to, e) => {
+                let from = from.substitute_var(var, value);
+                let to = to.substitute_var(var, value);
+                if *k == var {
+                    return e.as_ref().clone().sum_over(*k, from, to);
+                }
+                let (k, e) = bind_avoiding(*k, e, value);
+                e.substitute_var(var, value).sum_over(k, from, to)
+            }
+            _ => {
+                let mut e = self.clone();
+                for child in e.children_mut() {
+                    *child = child.substitute_var(var, value);
+                }
+                e
+            }
+        }
+    }
+
+    /// Replace every free occurrence of each variable in `map` with its value at the same time,
+    /// where x is `VarId('x')`. The values aren't searched for more variables to replace, so
+    /// `{x → y, y → x}` swaps x and y. Bound variables are renamed so that they don't capture
+    /// the values, as in [`Expr::substitute_var`].
+    ///
+    /// See [`Expr::substitute_sequential`] for replacing the variables one after another.
+    pub fn substitute_all(&self, map: &HashMap<VarId, Expr>) -> Self {
+        match self {
+            Expr::X => map.get(&VarId('x')).unwrap_or(self).clone(),
+            Expr::Var(v) => map.get(v).unwrap_or(self).clone(),
+            Expr::SumOver(k, from, to, e) => {
+                let (from, to) = (from.substitute_all(map), to.substitute_all(map));
+                let mut inner = map.clone();
+                inner.remove(k);
+                let values: BTreeSet<VarId> = inner.values().flat_map(Expr::free_vars).collect();
+                let (mut k, mut e) = (*k, e.as_ref().clone());
+                if values.contains(&k) {
+                    let mut avoid = e.free_vars();
+                    avoid.extend(values);
+                    avoid.insert(k);
+                    let fresh = fresh_var(&avoid);
+                    e.rename_var(k, fresh);
+                    k = fresh;
+                }
+                e.substitute_all(&inner).sum_over(k, from, to)
+            }
+            _ => {
+                let mut e = self.clone();
+                for child in e.children_mut() {
+                    *child = child.substitute_all(map);
+                }
+                e
+            }
+        }
+    }
+
+    /// Replace each variable with its value in order, where x is `VarId('x')`, so that a later
+    /// substitution also replaces the variable in the values put in before it. E.g. `[(x, y),
+    /// (y, z)]` replaces both x and y with z. This is the same as calling
+    /// [`Expr::substitute_var`] (or [`Expr::substitute`] for x) for each one.
+    ///
+    /// See [`Expr::substitute_all`] for replacing them all at the same time.
+    pub fn substitute_sequential(&self, substitutions: &[(VarId, Expr)]) -> Self {
+        substitutions
+            .iter()
+            .fold(self.clone(), |e, (var, value)| match var {
+                VarId('x') => e.substitute(value),
+                var => e.substitute_var(*var, value),
+            })
+    }
+
+    /// The variables other than x which appear in the expression without being bound by a
+    /// [`Expr::SumOver`]. Use [`Expr::uses_x`] to check for x.
+    pub fn free_vars(&self) -> BTreeSet<VarId> {
+        match self {
+            Expr::Var(v) => BTreeSet::from([*v]),
+            Expr::SumOver(k, from, to, e) => {
+                let mut vars = e.free_vars();
+                vars.remove(k);
+                vars.extend(from.free_vars());
+                vars.extend(to.free_vars());
+                vars
+            }
+            e => e.children().iter().flat_map(|e| e.free_vars()).collect(),
+        }
+    }
+
+    /// Rename every free occurrence of a variable (see [`Expr::free_vars`]). A bound variable
+    /// with the new name is renamed first if the variable is used inside it, so that the
+    /// variable doesn't get captured.
+    pub fn rename_var(&mut self, from: VarId, to: VarId) {
+        match self {
+            Expr::Var(v) if *v == from => *v = to,
+            Expr::SumOver(k, lower, upper, e) => {
+                lower.rename_var(from, to);
+                upper.rename_var(from, to);
+                if *k == from {
+                    return;
+                }
+                if *k == to && e.free_vars().contains(&from) {
+                    let mut avoid = e.free_vars();
+                    avoid.extend([from, to]);
+                    let fresh = fresh_var(&avoid);
+                    e.rename_var(*k, fresh);
+                    *k = fresh;
+                }
+                e.rename_var(from, to);
+            }
+            _ => {
+                for child in self.children_mut() {
+                    child.rename_var(from, to);
+                }
+            }
+        }
+    }
+
+    /// Replace every occurrence of a subexpression with another expression. Larger matches are
+    /// replaced first, and a replaced subexpression isn't searched for more matches.
+    pub fn replace(&self, from: &Expr, to: &Expr) -> Self {
+        self.replace_counted(from, to).0
+    }
+
+    /// Like [`Expr::replace`], but also get the number of replacements made.
+    pub fn replace_counted(&self, from: &Expr, to: &Expr) -> (Self, usize) {
+        self.replace_with(from, to, MatchMode::Exact)
+    }
+
+    /// Replace every match of a subexpression with another expression, along with the number of
+    /// replacements made. With [`MatchMode::Subset`], matching terms of a sum or product are
+    /// removed and `to` is added as a new term, as many times as the terms are found, e.g.
+    /// `a + b` in `a + b + c + a + b` gives `c + to + to`.
+    pub fn replace_with(&self, from: &Expr, to: &Expr, mode: MatchMode) -> (Self, usize) {
+        let mut count = 0;
+        let e = self.replace_matches(from, to, mode, &mut count);
+        (e, count)
+    }
+
+    fn replace_matches(&self, from: &Expr, to: &Expr, mode: MatchMode, count: &mut usize) -> Self {
+        if self == from {
+            *count += 1;
+            return to.clone();
+        }
+        if let (MatchMode::Subset, Expr::Sum(terms), Expr::Sum(subset))
+        | (MatchMode::Subset, Expr::Prod(terms), Expr::Prod(subset)) = (mode, self, from)
+        {
+            let mut rest = terms.clone();
+            let mut matches = 0;
+            while let Some(fewer) = remove_terms(&rest, subset) {
+                rest = fewer;
+                matches += 1;
+            }
+            if matches > 0 {
+                *count += matches;
+                for e in rest.iter_mut() {
+                    *e = e.replace_matches(from, to, mode, count);
+                }
+                rest.extend(std::iter::repeat_n(to.clone(), matches));
+                if rest.len() == 1 {
+                    return rest.pop().unwrap();
+                }
+                return match self {
+                    Expr::Sum(_) => Expr::Sum(rest),
+                    _ => Expr::Prod(rest),
+                };
+            }
+        }
+        let mut e = self.clone();
+        for child in e.children_mut() {
+            *child = child.replace_matches(from, to, mode, count);
+        }
+        e
+    }
+
+    /// Whether a variable other than x appears anywhere in the expression.
+    pub fn uses_var(&self, var: VarId) -> bool {
+        match self {
+            Expr::Var(v) => *v == var,
+            e => e.children().iter().any(|e| e.uses_var(var)),
+        }
+    }
+
+    /// Whether x appears anywhere in the expression.
+    pub fn uses_x(&self) -> bool {
+        match self {
+            Expr::Const(_)
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Hole(_)
+            | Expr::Deriv(..) => false,
+            Expr::X => true,
+            Expr::Sum(v) | Expr::Prod(v) => v.iter().any(|e| e.uses_x()),
+            Expr::Pow(a, b) => a.uses_x() || b.uses_x(),
+            Expr::Neg(e)
+            | Expr::Ln(e)
+            | Expr::Sin(e)
+            | Expr::Cos(e)
+            | Expr::Arcsin(e)
+            | Expr::Arccos(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Cosh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Arcosh(e)
+            | Expr::Artanh(e)
+            | Expr::Factorial(e) => e.uses_x(),
+            Expr::SumOver(_, from, to, e) => from.uses_x() || to.uses_x() || e.uses_x(),
+            Expr::Piecewise(_) => self.children().iter().any(|e| e.uses_x()),
+        }
+    }
+}
+
+/// The variable bound over `e`, renamed if `value` uses it so that putting `value` into `e`
+/// doesn't capture it
+fn bind_avoiding(k: VarId, e: &Expr, value: &Expr) -> (VarId, Expr) {
+    let mut e = e.clone();
+    if !value.free_vars().contains(&k) {
+        return (k, e);
+    }
+    let mut avoid = e.free_vars();
+    avoid.extend(value.free_vars());
+    avoid.insert(k);
+    let fresh = fresh_var(&avoid);
+    e.rename_var(k, fresh);
+    (fresh, e)
+}
+
+/// A variable name which isn't in `avoid`, and isn't x, e or i
+pub(super) fn fresh_var(avoid: &BTreeSet<VarId>) -> VarId {
+    FRESH_VARS
+        .chars()
+        .chain('α'..='ω')
+        .map(VarId)
+        .find(|v| !avoid.contains(v))
+        .expect("an expression shouldn't use every variable name")
+}
+
+/// If `terms` has every term of `subset`, get the terms which are left after removing them. An
+/// empty subset is never found, so that removing it can't be repeated forever.
+fn remove_terms(terms: &[Expr], subset: &[Expr]) -> Option<Vec<Expr>> {
+    if subset.is_empty() {
+        return None;
+    }
+    let mut rest = terms.to_vec();
+    for e in subset {
+        let i = rest.iter().position(|t| t == e)?;
+        rest.remove(i);
+    }
+    Some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute() {
+        let e = Expr::X.pow(Expr::Const(2)) + Expr::X.sin();
+        let sub = e.substitute(&(Expr::X + 1));
+        assert_eq!(sub, (Expr::X + 1).pow(Expr::Const(2)) + (Expr::X + 1).sin());
+        assert!(!e.substitute(&Expr::Const(3)).uses_x());
+    }
+
+    #[test]
+    fn uses_var() {
+        let e = Expr::X.sin() * Expr::Var(VarId('y'));
+        assert!(e.uses_var(VarId('y')));
+        assert!(!e.uses_var(VarId('z')));
+        assert!(e.uses_x());
+        assert!(!Expr::Var(VarId('y')).uses_x());
+    }
+
+    #[test]
+    fn replace_exact() {
+        let u = Expr::X.pow(Expr::Const(2)) + 1;
+        let e = u.clone().sin() * u.clone().pow(Expr::Const(3));
+        let (replaced, count) = e.replace_counted(&u, &Expr::X);
+        assert_eq!(replaced, Expr::X.sin() * Expr::X.pow(Expr::Const(3)));
+        assert_eq!(count, 2);
+
+        let (replaced, count) = e.replace_counted(&Expr::X.cos(), &Expr::X);
+        assert_eq!((replaced, count), (e, 0));
+    }
+
+    #[test]
+    fn replace_nested() {
+        // sin(x) is replaced inside ln(sin(x) + 1), and the x in sin(x) isn't touched
+        let e = (Expr::X.sin() + 1).ln() + Expr::X;
+        let replaced = e.replace(&Expr::X.sin(), &Expr::Const(0));
+        assert_eq!(replaced, (Expr::Const(0) + 1).ln() + Expr::X);
+    }
+
+    #[test]
+    fn replace_overlapping() {
+        // Only the outer sin(sin(x)) is replaced, since the inner one is inside of it
+        let sin2 = Expr::X.sin().sin();
+        let (replaced, count) = sin2.clone().sin().replace_counted(&sin2, &Expr::X);
+        assert_eq!((replaced, count), (Expr::X.sin(), 1));
+
+        let e = (Expr::X + 1).pow(Expr::X + 1);
+        let (replaced, count) = e.replace_counted(&(Expr::X + 1), &Expr::Const(2));
+        assert_eq!((replaced, count), (Expr::Const(2).pow(Expr::Const(2)), 2));
+    }
+
+    #[test]
+    fn replace_subset() {
+        let u = Expr::X.pow(Expr::Const(2)) + 1;
+        let e = Expr::Sum(vec![
+            Expr::Const(1),
+            Expr::X.sin(),
+            Expr::X.pow(Expr::Const(2)),
+        ]);
+        assert_eq!(e.replace_counted(&u, &Expr::X).1, 0);
+
+        let (replaced, count) = e.replace_with(&u, &Expr::X, MatchMode::Subset);
+        assert_eq!(replaced, Expr::Sum(vec![Expr::X.sin(), Expr::X]));
+        assert_eq!(count, 1);
+
+        // All of the terms in a different order
+        let reordered = Expr::Const(1) + Expr::X.pow(Expr::Const(2));
+        let (replaced, _) = reordered.replace_with(&u, &Expr::X, MatchMode::Subset);
+        assert_eq!(replaced, Expr::X);
+
+        let e = Expr::Prod(vec![Expr::X, Expr::Const(2), Expr::X, Expr::X]);
+        let square = Expr::Prod(vec![Expr::X, Expr::X]);
+        let (replaced, count) = e.replace_with(&square, &Expr::I, MatchMode::Subset);
+        assert_eq!(replaced, Expr::Prod(vec![Expr::Const(2), Expr::X, Expr::I]));
+        assert_eq!(count, 1);
+
+        // Both of two separate occurrences
+        let e = Expr::Sum(vec![
+            Expr::X.pow(Expr::Const(2)),
+            Expr::Const(1),
+            Expr::X.sin(),
+            Expr::Const(1),
+            Expr::X.pow(Expr::Const(2)),
+        ]);
+        let (replaced, count) = e.replace_with(&u, &Expr::X, MatchMode::Subset);
+        assert_eq!(replaced, Expr::Sum(vec![Expr::X.sin(), Expr::X, Expr::X]));
+        assert_eq!(count, 2);
+        let e = Expr::Prod(vec![Expr::X, Expr::X, Expr::X, Expr::X]);
+        let (replaced, count) = e.replace_with(&square, &Expr::I, MatchMode::Subset);
+        assert_eq!(replaced, Expr::Prod(vec![Expr::I, Expr::I]));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn free_vars() {
+        let (k, n, y) = (VarId('k'), VarId('n'), VarId('y'));
+        let e = (Expr::X * Expr::Var(k) + Expr::Var(y)).sum_over(k, Expr::Const(0), Expr::Var(n));
+        assert_eq!(e.free_vars(), BTreeSet::from([n, y]));
+        assert_eq!((e * Expr::Var(k)).free_vars(), BTreeSet::from([k, n, y]));
+    }
+
+    #[test]
+    fn rename_var() {
+        let (k, n, y) = (VarId('k'), VarId('n'), VarId('y'));
+        let mut e = Expr::Var(y) * Expr::Var(n).sin();
+        e.rename_var(y, k);
+        assert_eq!(e, Expr::Var(k) * Expr::Var(n).sin());
+
+        // The bound k isn't the k being renamed
+        let sum = (Expr::Var(k) * Expr::Var(y)).sum_over(k, Expr::Const(0), Expr::Var(k));
+        let mut e = sum.clone();
+        e.rename_var(k, n);
+        assert_eq!(
+            e,
+            (Expr::Var(k) * Expr::Var(y)).sum_over(k, Expr::Const(0), Expr::Var(n))
+        );
+
+        // Renaming y to k would be captured by the bound k, so that is renamed first
+        let mut e = sum;
+        e.rename_var(y, k);
+        assert_eq!(
+            e,
+            (Expr::Var(VarId('j')) * Expr::Var(k)).sum_over(
+                VarId('j'),
+                Expr::Const(0),
+                Expr::Var(k)
+            )
+        );
+    }
+
+    #[test]
+    fn capture_avoiding() {
+        let (j, k, n) = (VarId('j'), VarId('k'), VarId('n'));
+        // Substituting k + 1 for x in the sum over k of xk renames the bound k first
+        let e = (Expr::X * Expr::Var(k)).sum_over(k, Expr::Const(0), Expr::Var(n));
+        let value = Expr::Var(k) + 1;
+        assert_eq!(
+            e.substitute(&value),
+            (value.clone() * Expr::Var(j)).sum_over(j, Expr::Const(0), Expr::Var(n))
+        );
+        assert_eq!(
+            e.substitute(&Expr::Var(n)),
+            (Expr::Var(n) * Expr::Var(k)).sum_over(k, Expr::Const(0), Expr::Var(n))
+        );
+
+        // Bound variables aren't substituted, but the bounds are
+        let e = Expr::Var(k).sum_over(k, Expr::Const(1), Expr::Var(k));
+        assert_eq!(
+            e.substitute_var(k, &Expr::Const(3)),
+            Expr::Var(k).sum_over(k, Expr::Const(1), Expr::Const(3))
+        );
+        let e = (Expr::Var(n) * Expr::Var(k)).sum_over(k, Expr::Const(1), Expr::Var(n));
+        assert_eq!(
+            e.substitute_var(n, &value),
+            (value.clone() * Expr::Var(j)).sum_over(j, Expr::Const(1), value)
+        );
+    }
+
+    #[test]
+    fn simultaneous() {
+        let (y, z) = (Expr::Var(VarId('y')), Expr::Var(VarId('z')));
+        let e = Expr::X.pow(Expr::Const(2)) * y.clone() + Expr::X.sin();
+        // Swapping x and y
+        let swap = [(VarId('x'), y.clone()), (VarId('y'), Expr::X)];
+        assert_eq!(
+            e.substitute_all(&HashMap::from(swap.clone())),
+            y.clone().pow(Expr::Const(2)) * Expr::X + y.clone().sin()
+        );
+        // One after another, x becomes y and then every y becomes x again
+        assert_eq!(
+            e.substitute_sequential(&swap),
+            Expr::X.pow(Expr::Const(2)) * Expr::X + Expr::X.sin()
+        );
+
+        // A chain x → y → z: all at once, the y put in for x isn't replaced
+        let chain = [(VarId('x'), y.clone()), (VarId('y'), z.clone())];
+        let e = Expr::X + y.clone();
+        assert_eq!(
+            e.substitute_all(&HashMap::from(chain.clone())),
+            y + z.clone()
+        );
+        assert_eq!(e.substitute_sequential(&chain), z.clone() + z);
+    }
+
+    #[test]
+    fn simultaneous_capture_avoiding() {
+        let (j, k, n) = (VarId('j'), VarId('k'), VarId('n'));
+        let e = (Expr::X * Expr::Var(k) * Expr::Var(n)).sum_over(k, Expr::Const(0), Expr::Var(n));
+        let map = HashMap::from([(VarId('x'), Expr::Var(k)), (n, Expr::Var(k) + 1)]);
+        assert_eq!(
+            e.substitute_all(&map),
+            (Expr::Var(k) * Expr::Var(j) * (Expr::Var(k) + 1)).sum_over(
+                j,
+                Expr::Const(0),
+                Expr::Var(k) + 1
+            )
+        );
+        // The bound k isn't replaced inside the sum
+        let map = HashMap::from([(k, Expr::Const(3))]);
+        let e = Expr::Var(k).sum_over(k, Expr::Const(1), Expr::Var(k));
+        assert_eq!(
+            e.substitute_all(&map),
+            Expr::Var(k).sum_over(k, Expr::Const(1), Expr::Const(3))
+        );
+    }
+}
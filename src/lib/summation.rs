@@ -0,0 +1,171 @@
+use super::{Expr, Num, VarId};
+
+/// The highest power of the index [`Expr::simplify_sum_of_polynomial`] has a formula for
+const MAX_DEGREE: usize = 3;
+
+impl Expr {
+    /// This function replaces the sum of a polynomial in its index with a formula in the bounds
+    /// e.g. `\sum_{k=1}^{n} k = n(n+1)/2` and `\sum_{k=0}^{10} k^2 = 385`
+    ///
+    /// The body can be any polynomial in the index of degree at most 3, whose coefficients don't
+    /// use the index (e.g. `3k^2 + 2k + y`). The sum of each power of k from 1 to m is a
+    /// polynomial `F(m)` in m (Faulhaber's formula), so the sum from a to b is `F(b) - F(a-1)`.
+    /// This is the sum of the terms when `b ≥ a - 1`, which is assumed when the bounds aren't
+    /// constants. Other bodies (e.g. `sin(k)`) are left alone.
+    pub fn simplify_sum_of_polynomial(&mut self) {
+        let Expr::SumOver(k, from, to, body) = self else {
+            return;
+        };
+        if let (Expr::Const(from), Expr::Const(to)) = (from.as_ref(), to.as_ref()) {
+            // The formula isn't 0 for these sums, which have no terms
+            if from.checked_sub(*to).is_none_or(|gap| gap > 1) {
+                return;
+            }
+        }
+        let Some(coefficients) = coefficients(body, *k) else {
+            return;
+        };
+        if coefficients.len() > MAX_DEGREE + 1 {
+            return;
+        }
+        let before = (**from).clone() - 1;
+        let terms = coefficients
+            .into_iter()
+            .enumerate()
+            .map(|(n, c)| c * (power_sum(n, to) - power_sum(n, &before)))
+            .collect();
+        *self = Expr::Sum(terms).simplified();
+    }
+}
+
+/// The coefficients of an expression as a polynomial in a variable, where `coefficients[n]` is
+/// the coefficient of `k^n`. The coefficients don't use the variable, but can be any other
+/// expression. `None` if the expression isn't a polynomial in the variable.
+fn coefficients(e: &Expr, k: VarId) -> Option<Vec<Expr>> {
+    if !e.uses_var(k) {
+        return Some(vec![e.clone()]);
+    }
+    match e {
+        Expr::Var(_) => Some(vec![Expr::Const(0), Expr::Const(1)]),
+        Expr::Sum(v) => v
+            .iter()
+            .try_fold(vec![], |acc, e| Some(add(acc, coefficients(e, k)?))),
+        Expr::Prod(v) => v.iter().try_fold(vec![Expr::Const(1)], |acc, e| {
+            Some(mul(&acc, &coefficients(e, k)?))
+        }),
+        Expr::Neg(e) => Some(coefficients(e, k)?.into_iter().map(|c| -c).collect()),
+        Expr::Pow(a, b) => match **b {
+            // A higher power would have too high a degree anyway
+            Expr::Const(n) if (0..=MAX_DEGREE as Num).contains(&n) => {
+                let a = coefficients(a, k)?;
+                Some((0..n).fold(vec![Expr::Const(1)], |acc, _| mul(&acc, &a)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn add(mut a: Vec<Expr>, b: Vec<Expr>) -> Vec<Expr> {
+    for (n, c) in b.into_iter().enumerate() {
+        match a.get_mut(n) {
+            Some(d) => *d = std::mem::take(d) + c,
+            None => a.push(c),
+        }
+    }
+    a
+}
+
+fn mul(a: &[Expr], b: &[Expr]) -> Vec<Expr> {
+    let mut product = vec![];
+    for (n, c) in a.iter().enumerate() {
+        let shifted = std::iter::repeat_n(Expr::Const(0), n)
+            .chain(b.iter().map(|d| c.clone() * d.clone()))
+            .collect();
+        product = add(product, shifted);
+    }
+    product
+}
+
+/// The sum of `k^n` for k from 1 to m, as a polynomial in m
+fn power_sum(n: usize, m: &Expr) -> Expr {
+    let m = || m.clone();
+    // m(m+1)/2, the mth triangular number
+    let triangular = || m() * (m() + 1) / 2;
+    match n {
+        0 => m(),
+        1 => triangular(),
+        2 => m() * (m() + 1) * (m() * 2 + 1) / 6,
+        3 => triangular().pow(Expr::Const(2)),
+        _ => unreachable!("there is no formula for sums of powers above {MAX_DEGREE}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, sum_over, var, x};
+    use super::super::ratio::Ratio;
+    use super::super::ConstFoldMode;
+    use super::*;
+
+    fn k() -> Expr {
+        var('k')
+    }
+
+    #[test]
+    fn triangular_numbers() {
+        let n = || var('n');
+        let e = sum_over('k', c(1), n(), k()).simplified();
+        assert!(!matches!(e, Expr::SumOver(..)), "{e:?}");
+        assert!(e.equivalent(&(n() * (n() + 1) / 2)), "{e:?}");
+        for m in 0..20 {
+            let value = e.substitute_var(VarId('n'), &c(m)).exact_value();
+            assert_eq!(value, Some(Ratio::whole(m * (m + 1) / 2)));
+        }
+    }
+
+    #[test]
+    fn constant_bounds() {
+        assert_eq!(
+            sum_over('k', c(0), c(10), pow(k(), c(2))).simplified(),
+            c(385)
+        );
+        assert_eq!(
+            sum_over('k', c(1), c(4), pow(k(), c(3))).simplified(),
+            c(100)
+        );
+        // A sum with no terms is left for folding
+        let mut empty = sum_over('k', c(5), c(2), k()).simplified();
+        assert!(matches!(empty, Expr::SumOver(..)));
+        empty.fold_constants(ConstFoldMode::Exact);
+        assert_eq!(empty, c(0));
+    }
+
+    #[test]
+    fn linearity() {
+        // Each power of k is summed separately, with any bounds and coefficients
+        let body = pow(k(), c(2)) * 3 + k() * 2 + 1;
+        for (from, to) in [(1, 6), (-4, 7), (3, 3), (5, 4)] {
+            let e = sum_over('k', c(from), c(to), body.clone());
+            let expected = e.exact_value();
+            assert_eq!(e.simplified().exact_value(), expected, "{from} to {to}");
+        }
+        let e = sum_over('k', var('a'), var('b'), (k() + var('y')) * (k() - 1));
+        let e = e.simplified();
+        assert!(!matches!(e, Expr::SumOver(..)));
+        let at = |a, b, y| {
+            let e = e.substitute_var(VarId('a'), &c(a));
+            let e = e.substitute_var(VarId('b'), &c(b));
+            e.substitute_var(VarId('y'), &c(y)).exact_value()
+        };
+        assert_eq!(at(2, 5, 3), Some(Ratio::whole(5 + 6 * 2 + 7 * 3 + 8 * 4)));
+    }
+
+    #[test]
+    fn other_bodies() {
+        for body in [sin(k()), pow(k(), c(4)), pow(c(2), k()), k().recip() * x()] {
+            let e = sum_over('k', c(1), var('n'), body.clone()).simplified();
+            assert!(matches!(e, Expr::SumOver(..)), "{body:?}");
+        }
+    }
+}
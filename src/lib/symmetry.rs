@@ -0,0 +1,108 @@
+use super::Expr;
+
+/// Whether an expression is symmetric in x, found by [`Expr::symmetry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    /// The expression is the same at -x as at x (e.g. `x^2`), so its graph is symmetric about
+    /// the y axis
+    Even(SymmetryEvidence),
+    /// The expression at -x is the negative of the expression at x (e.g. `x^3`), so its graph is
+    /// symmetric about the origin
+    Odd(SymmetryEvidence),
+    /// The expression is neither even nor odd (e.g. `x + 1`)
+    Neither,
+}
+
+/// How an expression was found to be even or odd
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetryEvidence {
+    /// The canonical forms (see [`Expr::canonical`]) are the same, so it is certain
+    Symbolic,
+    /// The values are the same at some sample points, which is very likely but not certain
+    Sampled,
+}
+
+/// The positive values of x which [`Expr::symmetry`] compares with their negatives
+const SAMPLES: &[f64] = &[0.3, 0.7, 1.1, 1.9, 2.6, 3.7, 5.3, 8.2];
+
+/// How far apart values can be to count as the same, relative to their size
+const TOLERANCE: f64 = 1e-9;
+
+impl Expr {
+    /// Find whether an expression is even or odd in x.
+    ///
+    /// The expression at -x is put in the canonical form (see [`Expr::canonical`]), and compared
+    /// with the canonical form of the expression and of its negative. When they are different,
+    /// which can happen for expressions which are equal but aren't polynomials (e.g. `cos(-x)`
+    /// and `cos(x)`), the expression is evaluated at some points and their negatives instead,
+    /// and the result says it was [`SymmetryEvidence::Sampled`]. An expression which is 0 is
+    /// both even and odd, and counts as even.
+    pub fn symmetry(&self) -> Symmetry {
+        let reflected = self.substitute(&-Expr::X).canonical();
+        if reflected == self.canonical() {
+            return Symmetry::Even(SymmetryEvidence::Symbolic);
+        }
+        if reflected == (-self.clone()).canonical() {
+            return Symmetry::Odd(SymmetryEvidence::Symbolic);
+        }
+        let close = |a: f64, b: f64| (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0);
+        let pairs: Vec<(f64, f64)> = SAMPLES
+            .iter()
+            .filter_map(|x| Some((self.eval(*x).ok()?, self.eval(-x).ok()?)))
+            .filter(|(a, b)| a.is_finite() && b.is_finite())
+            .collect();
+        if pairs.is_empty() {
+            return Symmetry::Neither;
+        }
+        if pairs.iter().all(|(a, b)| close(*a, *b)) {
+            Symmetry::Even(SymmetryEvidence::Sampled)
+        } else if pairs.iter().all(|(a, b)| close(*a, -b)) {
+            Symmetry::Odd(SymmetryEvidence::Sampled)
+        } else {
+            Symmetry::Neither
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, ln, pow, sin, sqrt, x};
+    use super::*;
+
+    #[test]
+    fn polynomials() {
+        assert_eq!(
+            (pow(x(), c(3)) - x()).symmetry(),
+            Symmetry::Odd(SymmetryEvidence::Symbolic)
+        );
+        assert_eq!(
+            (pow(x(), c(4)) * 2 - pow(x(), c(2)) + 7).symmetry(),
+            Symmetry::Even(SymmetryEvidence::Symbolic)
+        );
+        assert_eq!((x() + 1).symmetry(), Symmetry::Neither);
+        assert_eq!(
+            (x() - x()).symmetry(),
+            Symmetry::Even(SymmetryEvidence::Symbolic)
+        );
+    }
+
+    #[test]
+    fn sampled() {
+        // cos(-x) and sin(-x) aren't rewritten, so these are only found by evaluating them
+        assert_eq!(
+            (cos(x()) + pow(x(), c(2))).symmetry(),
+            Symmetry::Even(SymmetryEvidence::Sampled)
+        );
+        assert_eq!(
+            (sin(x()) * cos(x())).symmetry(),
+            Symmetry::Odd(SymmetryEvidence::Sampled)
+        );
+        assert_eq!((sin(x()) + cos(x())).symmetry(), Symmetry::Neither);
+        // Points where either side isn't defined are skipped
+        assert_eq!(
+            ln(cos(x())).symmetry(),
+            Symmetry::Even(SymmetryEvidence::Sampled)
+        );
+        assert_eq!(sqrt(x()).symmetry(), Symmetry::Neither);
+    }
+}
@@ -0,0 +1,466 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::analysis::eval_poly;
+use super::ratio::Ratio;
+use super::{Expr, ExprKind, MathError, Num, VarId};
+
+/// The name used for x in a [`LinearSystem`] and its [`Solution`], so that it can be treated the
+/// same as the other variables
+const X: VarId = VarId('x');
+
+/// An equation between two expressions, `left = right`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Equation {
+    /// The left hand side
+    pub left: Expr,
+    /// The right hand side
+    pub right: Expr,
+}
+
+impl Equation {
+    /// Make the equation `left = right`
+    pub fn new(left: Expr, right: Expr) -> Self {
+        Equation { left, right }
+    }
+}
+
+/// Find the values of a parameter which make an equation hold, e.g. `k = 4` and `k = -4` for
+/// `k^2 - 16 = 0` (the discriminant of `x^2 + kx + 4` being 0). The condition should be worked
+/// out by the caller so that it only uses the parameter, and not x.
+///
+/// The equation is solved exactly when it is linear or quadratic in the parameter after fractions
+/// are combined. Otherwise there are no solutions, along with when the solutions aren't real, when
+/// another variable is used, or when the equation holds for every value.
+pub fn solve_for_param(condition: &Equation, param: VarId) -> Vec<Expr> {
+    let difference = condition.left.clone() - condition.right.clone();
+    if difference.uses_x() || difference.free_vars().iter().any(|v| *v != param) {
+        return vec![];
+    }
+    // Make the parameter x to use the polynomial methods
+    let Some((numerator, denominator)) = difference.substitute_var(param, &Expr::X).as_rational()
+    else {
+        return vec![];
+    };
+    let Some(roots) = Expr::from_coefficients(&numerator).roots(false) else {
+        return vec![];
+    };
+    // A root of the numerator isn't a solution if the denominator is 0 there too
+    roots
+        .into_iter()
+        .filter(|root| {
+            root.eval(0.0)
+                .is_ok_and(|v| eval_poly(&denominator, v).abs() > 1e-9)
+        })
+        .collect()
+}
+
+/// The error from [`LinearSystem::from_equations`] when an equation isn't linear
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NotLinear {
+    /// The index of the equation which isn't linear
+    pub equation: usize,
+    /// The smallest subexpression which isn't linear, or which is a coefficient that isn't a
+    /// fraction, e.g. `xy` in `xy + 2 = 3` or `sin(1)x` in `sin(1)x = 2`
+    pub term: Expr,
+}
+
+impl fmt::Display for NotLinear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "equation {} isn't linear because of {}",
+            self.equation + 1,
+            self.term.to_latex()
+        )
+    }
+}
+
+impl std::error::Error for NotLinear {}
+
+/// A system of linear equations with fractional coefficients, e.g. `{2x + y = 5, x - y = 1}`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LinearSystem {
+    /// The variables in the system in order, where x is `VarId('x')`
+    vars: Vec<VarId>,
+    /// A row for each equation, with the coefficient of each variable and then the right hand
+    /// side
+    rows: Vec<Vec<Ratio>>,
+}
+
+/// The solutions of a [`LinearSystem`], where x is `VarId('x')`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Solution {
+    /// There is one solution, with this value for each variable
+    Unique(BTreeMap<VarId, Expr>),
+    /// There are infinitely many solutions. Each of the parameters can be anything, and the
+    /// value of every other variable is given in terms of them. A parameter's value is itself.
+    Infinite {
+        /// The value of each variable
+        values: BTreeMap<VarId, Expr>,
+        /// The variables which can be anything
+        parameters: Vec<VarId>,
+    },
+    /// There are no solutions
+    Inconsistent,
+}
+
+impl LinearSystem {
+    /// Make a system from some equations, which are linear in every variable in them (including
+    /// x). Both sides of each equation are multiplied out, so e.g. `2(x + y) = 3` is linear.
+    ///
+    /// Returns an error pointing to the first term which isn't linear, e.g. `x^2` or `xy`, or
+    /// which has a coefficient that isn't a fraction.
+    pub fn from_equations(equations: Vec<Equation>) -> Result<LinearSystem, NotLinear> {
+        let sides: Vec<Expr> = equations
+            .into_iter()
+            .map(|eq| (eq.left - eq.right).substitute(&Expr::Var(X)))
+            .collect();
+        let vars: Vec<VarId> = sides
+            .iter()
+            .flat_map(|e| e.free_vars())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut rows = vec![];
+        for (equation, side) in sides.iter().enumerate() {
+            let not_linear = |term: Expr| NotLinear {
+                equation,
+                term: term.substitute_var(X, &Expr::X),
+            };
+            let form = Linear::of(side).map_err(not_linear)?;
+            // a_1v_1 + ... + a_nv_n + k = 0 has a right hand side of -k
+            let mut row: Vec<Ratio> = vars
+                .iter()
+                .map(|v| form.coeffs.get(v).copied().unwrap_or(Ratio::ZERO))
+                .collect();
+            row.push(
+                form.constant
+                    .neg()
+                    .ok_or_else(|| not_linear(side.clone()))?,
+            );
+            rows.push(row);
+        }
+        Ok(LinearSystem { vars, rows })
+    }
+
+    /// The variables in the system, in order
+    pub fn vars(&self) -> &[VarId] {
+        &self.vars
+    }
+
+    /// Solve the system exactly, with Gaussian elimination over fractions
+    ///
+    /// Returns [`MathError::Overflow`] if a fraction overflows [`Num`] during the elimination.
+    pub fn solve(&self) -> Result<Solution, MathError> {
+        let mut rows = self.rows.clone();
+        let n = self.vars.len();
+        let mut pivots: Vec<usize> = vec![];
+
+        // Get the rows into reduced row echelon form
+        for col in 0..n {
+            let row = pivots.len();
+            let Some(found) = (row..rows.len()).find(|r| rows[*r][col] != Ratio::ZERO) else {
+                continue;
+            };
+            rows.swap(row, found);
+            let pivot = rows[row][col].recip().unwrap();
+            for value in rows[row].iter_mut() {
+                *value = overflowing(value.mul(pivot), ExprKind::Prod)?;
+            }
+            let pivot_row = rows[row].clone();
+            for (other, values) in rows.iter_mut().enumerate() {
+                let factor = values[col];
+                if other == row || factor == Ratio::ZERO {
+                    continue;
+                }
+                for (value, p) in values.iter_mut().zip(&pivot_row) {
+                    let product = overflowing(p.mul(factor), ExprKind::Prod)?;
+                    *value = overflowing(value.sub(product), ExprKind::Sum)?;
+                }
+            }
+            pivots.push(col);
+        }
+
+        // A row of 0 = k for k != 0
+        if rows[pivots.len()..].iter().any(|r| r[n] != Ratio::ZERO) {
+            return Ok(Solution::Inconsistent);
+        }
+
+        let parameters: Vec<VarId> = (0..n)
+            .filter(|col| !pivots.contains(col))
+            .map(|col| self.vars[col])
+            .collect();
+        let mut values: BTreeMap<VarId, Expr> =
+            parameters.iter().map(|v| (*v, var_expr(*v))).collect();
+        for (row, col) in pivots.iter().enumerate() {
+            // v + a_1p_1 + ... = k, so v = k - a_1p_1 - ...
+            let mut terms = vec![rows[row][n].to_expr()];
+            for p in (0..n).filter(|p| !pivots.contains(p)) {
+                if rows[row][p] != Ratio::ZERO {
+                    let coeff = overflowing(rows[row][p].neg(), ExprKind::Neg)?;
+                    terms.push(coeff.to_expr() * var_expr(self.vars[p]));
+                }
+            }
+            let mut value = Expr::Sum(terms);
+            value.simplify();
+            values.insert(self.vars[*col], value);
+        }
+
+        Ok(if parameters.is_empty() {
+            Solution::Unique(values)
+        } else {
+            Solution::Infinite { values, parameters }
+        })
+    }
+}
+
+fn var_expr(v: VarId) -> Expr {
+    if v == X {
+        Expr::X
+    } else {
+        Expr::Var(v)
+    }
+}
+
+/// A fraction from an operation which might have overflowed
+fn overflowing(r: Option<Ratio>, op: ExprKind) -> Result<Ratio, MathError> {
+    r.ok_or(MathError::Overflow { op })
+}
+
+/// A linear expression `a_1v_1 + ... + a_nv_n + k`
+struct Linear {
+    coeffs: BTreeMap<VarId, Ratio>,
+    constant: Ratio,
+}
+
+impl Linear {
+    fn constant(constant: Ratio) -> Self {
+        Linear {
+            coeffs: BTreeMap::new(),
+            constant,
+        }
+    }
+
+    fn is_constant(&self) -> bool {
+        self.coeffs.values().all(|c| *c == Ratio::ZERO)
+    }
+
+    /// Get the linear form of an expression, or the smallest subexpression which isn't linear
+    fn of(e: &Expr) -> Result<Linear, Expr> {
+        let not_linear = || e.clone();
+        match e {
+            Expr::Const(n) => Ok(Linear::constant(Ratio::whole(*n))),
+            Expr::Scaled(m, exp) => {
+                let power = Ratio::whole(10).pow(*exp as Num).ok_or_else(not_linear)?;
+                let value = Ratio::whole(*m).mul(power).ok_or_else(not_linear)?;
+                Ok(Linear::constant(value))
+            }
+            Expr::Var(v) => Ok(Linear {
+                coeffs: BTreeMap::from([(*v, Ratio::ONE)]),
+                constant: Ratio::ZERO,
+            }),
+            Expr::Neg(a) => Linear::of(a)?
+                .scale(Ratio::whole(-1))
+                .ok_or_else(not_linear),
+            Expr::Sum(v) => v.iter().try_fold(Linear::constant(Ratio::ZERO), |acc, e| {
+                acc.add(&Linear::of(e)?).ok_or_else(not_linear)
+            }),
+            Expr::Prod(v) => v.iter().try_fold(Linear::constant(Ratio::ONE), |acc, e| {
+                let factor = Linear::of(e)?;
+                match (acc.is_constant(), factor.is_constant()) {
+                    (true, _) => factor.scale(acc.constant),
+                    (false, true) => acc.scale(factor.constant),
+                    (false, false) => None,
+                }
+                .ok_or_else(not_linear)
+            }),
+            Expr::Pow(a, b) => {
+                let Expr::Const(n) = **b else {
+                    return Err(not_linear());
+                };
+                let base = Linear::of(a)?;
+                if n == 1 {
+                    Ok(base)
+                } else if base.is_constant() {
+                    let value = base.constant.pow(n).ok_or_else(not_linear)?;
+                    Ok(Linear::constant(value))
+                } else {
+                    Err(not_linear())
+                }
+            }
+            _ => Err(not_linear()),
+        }
+    }
+
+    fn scale(self, by: Ratio) -> Option<Linear> {
+        let coeffs = self
+            .coeffs
+            .into_iter()
+            .map(|(v, c)| Some((v, c.mul(by)?)))
+            .collect::<Option<_>>()?;
+        Some(Linear {
+            coeffs,
+            constant: self.constant.mul(by)?,
+        })
+    }
+
+    fn add(mut self, other: &Linear) -> Option<Linear> {
+        for (v, c) in &other.coeffs {
+            let sum = self.coeffs.get(v).copied().unwrap_or(Ratio::ZERO).add(*c)?;
+            self.coeffs.insert(*v, sum);
+        }
+        self.constant = self.constant.add(other.constant)?;
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, var, x};
+    use super::*;
+
+    #[test]
+    fn params() {
+        let k = VarId('k');
+        // The integral of x^2 + kx + 4 from 0 to 1 is 1/3 + k/2 + 4, which is 2 when k = -14/3
+        let integral = c(1) / c(3) + var('k') / c(2) + 4;
+        let solutions = solve_for_param(&Equation::new(integral, c(2)), k);
+        assert_eq!(solutions.len(), 1);
+        assert!(solutions[0].equivalent(&(c(-14) / c(3))));
+
+        // x^2 + kx + 4 has one root when the discriminant k^2 - 16 is 0
+        let discriminant = pow(var('k'), c(2)) - c(4) * c(4);
+        let mut solutions = solve_for_param(&Equation::new(discriminant, c(0)), k);
+        solutions.sort();
+        assert_eq!(solutions, vec![c(-4), c(4)]);
+
+        // There is no real k with k^2 = -1, and sin isn't a polynomial
+        let unsolvable = [
+            Equation::new(pow(var('k'), c(2)), c(-1)),
+            Equation::new(sin(var('k')), c(0)),
+            Equation::new(var('k') * var('y'), c(1)),
+            Equation::new(var('k') - var('k'), c(0)),
+            // k = 1 makes the denominator 0
+            Equation::new((var('k') - 1) / (var('k') - 1), c(0)),
+            // The roots overflow
+            Equation::new(pow(var('k'), c(2)) * Num::MAX + var('k'), c(0)),
+        ];
+        for condition in unsolvable {
+            assert_eq!(solve_for_param(&condition, k), vec![]);
+        }
+    }
+
+    fn system(equations: Vec<(Expr, Expr)>) -> LinearSystem {
+        let equations = equations
+            .into_iter()
+            .map(|(l, r)| Equation::new(l, r))
+            .collect();
+        LinearSystem::from_equations(equations).unwrap()
+    }
+
+    fn frac(n: Num, d: Num) -> Expr {
+        (c(n) / c(d)).simplified()
+    }
+
+    #[test]
+    fn unique() {
+        // 2x + y = 5, x - y = 1
+        let s = system(vec![(x() * 2 + var('y'), c(5)), (x() - var('y'), c(1))]);
+        assert_eq!(s.vars(), &[VarId('x'), VarId('y')]);
+        assert_eq!(
+            s.solve().unwrap(),
+            Solution::Unique(BTreeMap::from([(VarId('x'), c(2)), (VarId('y'), c(1))]))
+        );
+    }
+
+    #[test]
+    fn fractions() {
+        // x + y + z = 1, 2x - y = 0, 3y + 2(z - x) = 2
+        let s = system(vec![
+            (x() + var('y') + var('z'), c(1)),
+            (x() * 2 - var('y'), c(0)),
+            (var('y') * 3 + c(2) * (var('z') - x()), c(2)),
+        ]);
+        assert_eq!(
+            s.solve().unwrap(),
+            Solution::Unique(BTreeMap::from([
+                (VarId('x'), c(0)),
+                (VarId('y'), c(0)),
+                (VarId('z'), c(1))
+            ]))
+        );
+
+        // 3a + b = 1, a - b + c = 0, a/2 + c = 2
+        let s = system(vec![
+            (var('a') * 3 + var('b'), c(1)),
+            (var('a') - var('b') + var('c'), c(0)),
+            (var('a') * frac(1, 2) + var('c'), c(2)),
+        ]);
+        assert_eq!(
+            s.solve().unwrap(),
+            Solution::Unique(BTreeMap::from([
+                (VarId('a'), frac(-2, 7)),
+                (VarId('b'), frac(13, 7)),
+                (VarId('c'), frac(15, 7))
+            ]))
+        );
+    }
+
+    #[test]
+    fn infinite() {
+        // x + y = 2, 2x + 2y = 4
+        let s = system(vec![(x() + var('y'), c(2)), (x() * 2 + var('y') * 2, c(4))]);
+        assert_eq!(
+            s.solve().unwrap(),
+            Solution::Infinite {
+                values: BTreeMap::from([
+                    (VarId('x'), (c(2) + var('y') * c(-1)).simplified()),
+                    (VarId('y'), var('y'))
+                ]),
+                parameters: vec![VarId('y')]
+            }
+        );
+    }
+
+    #[test]
+    fn inconsistent() {
+        // x + y = 1, x + y = 2
+        let s = system(vec![(x() + var('y'), c(1)), (x() + var('y'), c(2))]);
+        assert_eq!(s.solve(), Ok(Solution::Inconsistent));
+    }
+
+    #[test]
+    fn overflow() {
+        let big = Num::MAX;
+        let s = system(vec![
+            (x() * big + var('y') * (big - 1), c(1)),
+            (x() * (big - 2) + var('y') * 3, c(2)),
+        ]);
+        assert!(matches!(s.solve(), Err(MathError::Overflow { .. })));
+    }
+
+    #[test]
+    fn not_linear() {
+        let check = |left: Expr, term: Expr| {
+            let equations = vec![Equation::new(x(), c(1)), Equation::new(left, c(3))];
+            assert_eq!(
+                LinearSystem::from_equations(equations),
+                Err(NotLinear { equation: 1, term })
+            );
+        };
+        check(x() * var('y') + 2, x() * var('y'));
+        check(x() + pow(var('y'), c(2)), pow(var('y'), c(2)));
+        check(sin(c(1)) * x(), sin(c(1)));
+        check(var('y') / x(), x().recip());
+
+        let equations = vec![Equation::new(x() * var('y'), c(1))];
+        assert_eq!(
+            LinearSystem::from_equations(equations)
+                .unwrap_err()
+                .to_string(),
+            "equation 1 isn't linear because of xy"
+        );
+    }
+}
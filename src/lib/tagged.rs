@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::ops::Deref;
+
+use super::sexpr::read_at;
+use super::{Expr, ParseError};
+
+/// The metadata of a [`Tagged`] value, as keys and values which only mean something to whoever
+/// tagged it, e.g. `seed = 42` and `difficulty = hard`
+pub type Meta = BTreeMap<String, String>;
+
+/// A value along with metadata saying where it came from, e.g. the generator settings and seed
+/// of a generated problem, so that it can be traced after being simplified and stored.
+///
+/// The metadata is on the whole value rather than inside the expression tree, so none of the
+/// math sees it: [`Tagged::simplified`] simplifies the expression and keeps the metadata, and
+/// anything which only reads the expression (like [`Expr::eval`]) can be used through
+/// [`Deref`]. This means a tagged expression is never merged with an untagged one as like
+/// terms, since they can only be put in one tree by taking the expression out first, with
+/// [`Tagged::strip_annotations`] or [`Tagged::map`] (which keeps the metadata of the value it is
+/// called on, and not of anything put in with it).
+///
+/// Tagged values are only equal when both the values and the metadata are, so compare the
+/// [`Tagged::value`]s to find whether the expressions are the same.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tagged<T = Expr> {
+    /// The value
+    pub value: T,
+    /// The metadata
+    pub meta: Meta,
+}
+
+impl<T> Tagged<T> {
+    /// Tag a value, with no metadata yet
+    pub fn new(value: T) -> Self {
+        Tagged {
+            value,
+            meta: Meta::new(),
+        }
+    }
+
+    /// Add an entry to the metadata, replacing any with the same key
+    pub fn with(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.meta.insert(key.into(), value.to_string());
+        self
+    }
+
+    /// Change the value, keeping the metadata
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Tagged<U> {
+        Tagged {
+            value: f(self.value),
+            meta: self.meta,
+        }
+    }
+
+    /// Throw away the metadata and get the value
+    pub fn strip_annotations(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for Tagged<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl Tagged {
+    /// Simplify the expression, keeping the metadata
+    pub fn simplified(self) -> Self {
+        self.map(Expr::simplified)
+    }
+
+    /// Write the tagged expression as an S-expression, with each entry of the metadata as a
+    /// pair of strings before the expression, e.g. `(tagged ("seed" "42") (+ x 1))`. Quotes and
+    /// backslashes in the strings are escaped with a backslash.
+    pub fn to_sexpr(&self) -> String {
+        let mut sexpr = "(tagged".to_string();
+        for (key, value) in &self.meta {
+            sexpr += &format!(" ({} {})", quoted(key), quoted(value));
+        }
+        sexpr += &format!(" {})", self.value.to_sexpr());
+        sexpr
+    }
+
+    /// Read a tagged expression written by [`Tagged::to_sexpr`]. The positions in errors are
+    /// byte offsets into the whole text, including in the expression.
+    pub fn from_sexpr(sexpr: &str) -> Result<Self, ParseError> {
+        let mut reader = Reader { sexpr, position: 0 };
+        reader.expect("(")?;
+        reader.expect("tagged")?;
+        let mut meta = Meta::new();
+        while reader.rest().starts_with("(\"") {
+            reader.expect("(")?;
+            let key = reader.string()?;
+            let value = reader.string()?;
+            reader.expect(")")?;
+            meta.insert(key, value);
+        }
+        let (value, end) = read_at(sexpr, reader.position)?;
+        reader.position = end;
+        reader.expect(")")?;
+        match reader.rest() {
+            "" => Ok(Tagged { value, meta }),
+            _ => Err(reader.unexpected()),
+        }
+    }
+}
+
+/// A string in quotes, with quotes and backslashes in it escaped
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads the parts of [`Tagged::to_sexpr`] around the expression
+struct Reader<'a> {
+    sexpr: &'a str,
+    position: usize,
+}
+
+impl Reader<'_> {
+    /// The text after the next whitespace, which is skipped
+    fn rest(&mut self) -> &str {
+        let rest = &self.sexpr[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+        &self.sexpr[self.position..]
+    }
+
+    /// The error for whatever is next
+    fn unexpected(&mut self) -> ParseError {
+        let position = self.position;
+        match self.rest().chars().next() {
+            Some(c) => ParseError::Unexpected {
+                found: format!("'{c}'"),
+                position,
+            },
+            None => ParseError::UnexpectedEnd,
+        }
+    }
+
+    /// Skip `token`, which has to be next
+    fn expect(&mut self, token: &str) -> Result<(), ParseError> {
+        match self.rest().starts_with(token) {
+            true => {
+                self.position += token.len();
+                Ok(())
+            }
+            false => Err(self.unexpected()),
+        }
+    }
+
+    /// A string in quotes, without its escapes
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect("\"")?;
+        let mut string = String::new();
+        let mut chars = self.sexpr[self.position..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += i + 1;
+                    return Ok(string);
+                }
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\'))) => string.push(c),
+                    Some((j, c)) => {
+                        return Err(ParseError::Unexpected {
+                            found: format!("'\\{c}'"),
+                            position: self.position + j - 1,
+                        })
+                    }
+                    None => break,
+                },
+                c => string.push(c),
+            }
+        }
+        Err(ParseError::UnexpectedEnd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, pow, sin, x};
+    use super::super::{generate_problem, Difficulty, GenConfig, Rng};
+    use super::*;
+
+    /// A generated problem, tagged with how it was made
+    fn generated(seed: u64) -> Tagged {
+        let config = GenConfig {
+            difficulty: Difficulty::Medium,
+            ..GenConfig::default()
+        };
+        let problem = generate_problem(&mut Rng::new(seed), &config).unwrap();
+        Tagged::new(problem.expr)
+            .with("seed", seed)
+            .with("difficulty", "medium")
+            .with("id", format!("quiz-{seed}"))
+    }
+
+    #[test]
+    fn simplifying() {
+        let tagged = Tagged::new(x() + x() + sin(x()) - sin(x())).with("seed", 7);
+        let simplified = tagged.clone().simplified();
+        assert_eq!(simplified.value, (x() + x()).simplified());
+        assert_eq!(simplified.meta, tagged.meta);
+        // The math only sees the expression
+        assert_eq!(tagged.eval(2.0), Ok(4.0));
+        let derivative = tagged.map(Expr::derivative);
+        assert_eq!(derivative.meta["seed"], "7");
+        assert_eq!(derivative.simplified().value, c(2));
+
+        for seed in 0..20 {
+            let tagged = generated(seed);
+            let expected = tagged.value.clone().simplified();
+            let simplified = tagged.clone().simplified();
+            assert_eq!(simplified.meta, tagged.meta);
+            assert_eq!(simplified.strip_annotations(), expected);
+        }
+    }
+
+    #[test]
+    fn equality() {
+        let a = Tagged::new(pow(x(), c(2))).with("seed", 1);
+        let b = Tagged::new(pow(x(), c(2))).with("seed", 2);
+        assert_ne!(a, b);
+        assert_eq!(a.value, b.value);
+        assert_eq!(a.clone(), a.clone().simplified().map(|_| pow(x(), c(2))));
+
+        // Putting a tagged expression together with an untagged twin keeps only the metadata of
+        // the one it is done on, and the twins are then like terms
+        let sum = a.clone().map(|e| e + pow(x(), c(2)));
+        assert_eq!(sum.meta, a.meta);
+        assert_eq!(sum.simplified().value, (pow(x(), c(2)) * 2).simplified());
+    }
+
+    #[test]
+    fn round_trip() {
+        for seed in 0..20 {
+            let tagged = generated(seed).simplified();
+            assert_eq!(Tagged::from_sexpr(&tagged.to_sexpr()), Ok(tagged));
+        }
+        let tagged = Tagged::new(x() + 1)
+            .with("note", "a \"quoted\" \\ string")
+            .with("empty", "");
+        let sexpr = tagged.to_sexpr();
+        assert_eq!(
+            sexpr,
+            r#"(tagged ("empty" "") ("note" "a \"quoted\" \\ string") (+ x 1))"#
+        );
+        assert_eq!(Tagged::from_sexpr(&sexpr), Ok(tagged));
+        assert_eq!(Tagged::from_sexpr("(tagged x)"), Ok(Tagged::new(x())));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Tagged::from_sexpr("(+ x 1)"),
+            Err(ParseError::Unexpected {
+                found: "'+'".to_string(),
+                position: 1
+            })
+        );
+        assert_eq!(
+            Tagged::from_sexpr(r#"(tagged ("seed" "1) x)"#),
+            Err(ParseError::UnexpectedEnd)
+        );
+        // Positions in the expression are offsets into the whole text
+        assert_eq!(
+            Tagged::from_sexpr(r#"(tagged ("a" "b") (frobnicate x))"#),
+            Err(ParseError::UnknownHead {
+                head: "frobnicate".to_string(),
+                position: 19
+            })
+        );
+        assert!(Tagged::from_sexpr("(tagged x) y").is_err());
+        assert!(Tagged::from_sexpr(r#"(tagged ("a" "\n") x)"#).is_err());
+    }
+}
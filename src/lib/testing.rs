@@ -0,0 +1,59 @@
+//! Helpers for writing tests with expressions.
+
+/// Assert that two expressions are equal, like `assert_eq!`. When they aren't, both are printed
+/// as trees (see [`crate::lib::Expr::debug_tree`]) along with where they differ (see
+/// [`crate::lib::Expr::diff_structure`]).
+#[macro_export]
+macro_rules! assert_expr_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left, right) => {
+                if left != right {
+                    let diffs: Vec<String> = left
+                        .diff_structure(right)
+                        .iter()
+                        .map(|diff| diff.to_string())
+                        .collect();
+                    panic!(
+                        "assertion `left == right` failed\nleft:\n{:#?}\nright:\n{:#?}\ndifferences:\n{}",
+                        left,
+                        right,
+                        diffs.join("\n")
+                    );
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Expr;
+
+    #[test]
+    fn equal() {
+        crate::assert_expr_eq!(Expr::X + 1, Expr::X + 1);
+    }
+
+    #[test]
+    fn message() {
+        let result = std::panic::catch_unwind(|| {
+            crate::assert_expr_eq!(Expr::X + 1, Expr::X + 2);
+        });
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            message,
+            "assertion `left == right` failed
+left:
+Sum
+├─ X
+└─ Const(1)
+right:
+Sum
+├─ X
+└─ Const(2)
+differences:
+at [1]: 1 != 2"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use super::Expr;
+
+impl Expr {
+    /// Get references to the direct children of an expression, in order.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Const(_)
+            | Expr::X
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Hole(_)
+            | Expr::Deriv(..) => {
+                vec![]
+            }
+            Expr::Sum(v) | Expr::Prod(v) => v.iter().collect(),
+            Expr::Pow(a, b) => vec![a, b],
+            Expr::SumOver(_, from, to, e) => vec![from, to, e],
+            Expr::Neg(e)
+            | Expr::Ln(e)
+            | Expr::Sin(e)
+            | Expr::Cos(e)
+            | Expr::Arcsin(e)
+            | Expr::Arccos(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Cosh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Arcosh(e)
+            | Expr::Artanh(e)
+            | Expr::Factorial(e) => vec![e],
+            Expr::Piecewise(v) => v
+                .iter()
+                .flat_map(|(c, e)| c.sides().into_iter().chain([e]))
+                .collect(),
+        }
+    }
+
+    /// Get mutable references to the direct children of an expression, in order.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Const(_)
+            | Expr::X
+            | Expr::Var(_)
+            | Expr::I
+            | Expr::E
+            | Expr::Scaled(..)
+            | Expr::Hole(_)
+            | Expr::Deriv(..) => {
+                vec![]
+            }
+            Expr::Sum(v) | Expr::Prod(v) => v.iter_mut().collect(),
+            Expr::Pow(a, b) => vec![a, b],
+            Expr::SumOver(_, from, to, e) => vec![from, to, e],
+            Expr::Neg(e)
+            | Expr::Ln(e)
+            | Expr::Sin(e)
+            | Expr::Cos(e)
+            | Expr::Arcsin(e)
+            | Expr::Arccos(e)
+            | Expr::Arctan(e)
+            | Expr::Sinh(e)
+            | Expr::Cosh(e)
+            | Expr::Tanh(e)
+            | Expr::Arsinh(e)
+            | Expr::Arcosh(e)
+            | Expr::Artanh(e)
+            | Expr::Factorial(e) => vec![e],
+            Expr::Piecewise(v) => v
+                .iter_mut()
+                .flat_map(|(c, e)| c.sides_mut().into_iter().chain([e]))
+                .collect(),
+        }
+    }
+
+    /// The terms of a sum, or the expression on its own if it isn't a sum, e.g. the terms of
+    /// `x^2 + 1` are `[x^2, 1]` and the terms of `2x` are `[2x]`. Prefer this to matching on
+    /// [`Expr::Sum`], so that code doesn't depend on how the terms are stored.
+    pub fn terms(&self) -> &[Expr] {
+        match self {
+            Expr::Sum(v) => v,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// The factors of a product, or the expression on its own if it isn't a product, like
+    /// [`Expr::terms`] for [`Expr::Prod`].
+    pub fn factors(&self) -> &[Expr] {
+        match self {
+            Expr::Prod(v) => v,
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// The number of nodes in the expression tree, counting the expression itself.
+    pub fn node_count(&self) -> usize {
+        1 + self
+            .children()
+            .iter()
+            .map(|e| e.node_count())
+            .sum::<usize>()
+    }
+
+    /// Apply a function to every subexpression, starting with the leaves so that the children
+    /// of an expression have always been transformed before it is.
+    pub fn transform_bottom_up(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        for e in self.children_mut() {
+            e.transform_bottom_up(f);
+        }
+        f(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ExprKind::*;
+    use super::*;
+
+    #[test]
+    fn bottom_up() {
+        let mut e = (Expr::X + 1).sin() * Expr::X;
+        let mut order = vec![];
+        e.transform_bottom_up(&mut |e| order.push(e.kind()));
+        assert_eq!(order, vec![X, Const, Sum, Sin, X, Prod]);
+        assert_eq!(e.node_count(), 6);
+    }
+
+    #[test]
+    fn terms_and_factors() {
+        let e = Expr::X * 2 + Expr::Const(1);
+        assert_eq!(e.terms(), &[Expr::X * 2, Expr::Const(1)]);
+        assert_eq!(e.factors(), std::slice::from_ref(&e));
+        assert_eq!(e.terms()[0].factors(), &[Expr::X, Expr::Const(2)]);
+        assert_eq!(Expr::Sum(vec![]).terms(), &[]);
+    }
+}
@@ -0,0 +1,264 @@
+use super::Expr;
+
+impl Expr {
+    /// This function expands sin and cos of a sum with the angle addition formulas
+    /// e.g. `sin(a+b) = sin(a)cos(b) + cos(a)sin(b)` and `cos(a+b) = cos(a)cos(b) - sin(a)sin(b)`
+    ///
+    /// A sum of more terms is split into its first term and the rest, and the sin and cos of the
+    /// rest are expanded again, so no sin or cos of a sum is left. This isn't done by
+    /// [`Expr::simplify`], since it makes expressions bigger. It is enabled with
+    /// [`super::SimplifyOptions::expand_trig`], and [`Expr::simplify_contract_trig`] does the
+    /// opposite.
+    pub fn simplify_expand_trig(&mut self) {
+        let (Expr::Sin(arg) | Expr::Cos(arg)) = self else {
+            return;
+        };
+        let Expr::Sum(v) = arg.as_mut() else {
+            return;
+        };
+        if v.len() < 2 {
+            return;
+        }
+        let a = v.remove(0);
+        let b = match v.len() {
+            1 => v.pop().unwrap(),
+            _ => Expr::Sum(std::mem::take(v)),
+        };
+        let expanded = |mut e: Expr| {
+            e.simplify_expand_trig();
+            e
+        };
+        let (sin_a, cos_a) = (Expr::Sin(Box::new(a.clone())), Expr::Cos(Box::new(a)));
+        let (sin_b, cos_b) = (
+            expanded(Expr::Sin(Box::new(b.clone()))),
+            expanded(Expr::Cos(Box::new(b))),
+        );
+        *self = match self {
+            Expr::Sin(_) => Expr::Sum(vec![
+                Expr::Prod(vec![sin_a, cos_b]),
+                Expr::Prod(vec![cos_a, sin_b]),
+            ]),
+            _ => Expr::Sum(vec![
+                Expr::Prod(vec![cos_a, cos_b]),
+                Expr::Prod(vec![Expr::Const(-1), sin_a, sin_b]),
+            ]),
+        };
+    }
+
+    /// This function writes a sum of two products of sins and coses as the sin or cos of a sum
+    /// or difference, which is the opposite of [`Expr::simplify_expand_trig`]
+    /// e.g. `sin(a)cos(b) + cos(a)sin(b) = sin(a+b)` and `cos(a)cos(b) + sin(a)sin(b) = cos(a-b)`
+    ///
+    /// Only sums of exactly two terms are contracted, where each term is a product of a sin or
+    /// cos of a and a sin or cos of b, possibly negated.
+    pub fn simplify_contract_trig(&mut self) {
+        let Expr::Sum(v) = self else {
+            return;
+        };
+        let [first, second] = v.as_slice() else {
+            return;
+        };
+        let (Some(first), Some(second)) = (TrigProduct::new(first), TrigProduct::new(second))
+        else {
+            return;
+        };
+        let contracted = contract(&first, &second).or_else(|| contract(&second, &first));
+        if let Some(contracted) = contracted {
+            *self = contracted;
+        }
+    }
+}
+
+/// A term like `±sin(a)cos(b)`
+struct TrigProduct<'a> {
+    negative: bool,
+    /// Whether each factor is a sin (rather than a cos), with its argument
+    factors: [(bool, &'a Expr); 2],
+}
+
+impl<'a> TrigProduct<'a> {
+    fn new(term: &'a Expr) -> Option<Self> {
+        let (negative, factors) = match term {
+            Expr::Neg(e) => (true, e.as_ref()),
+            e => (false, e),
+        };
+        let Expr::Prod(v) = factors else {
+            return None;
+        };
+        let mut negative = negative;
+        let mut trig = vec![];
+        for factor in v {
+            match factor {
+                Expr::Const(-1) => negative = !negative,
+                Expr::Sin(arg) => trig.push((true, arg.as_ref())),
+                Expr::Cos(arg) => trig.push((false, arg.as_ref())),
+                _ => return None,
+            }
+        }
+        let [first, second] = trig.as_slice() else {
+            return None;
+        };
+        Some(TrigProduct {
+            negative,
+            factors: [*first, *second],
+        })
+    }
+
+    /// How many of the factors are sins
+    fn sins(&self) -> usize {
+        self.factors.iter().filter(|(sin, _)| *sin).count()
+    }
+
+    /// The argument of the sin (or cos when `sin` is false), if the term has one
+    fn arg(&self, sin: bool) -> Option<&'a Expr> {
+        self.factors
+            .iter()
+            .find(|(s, _)| *s == sin)
+            .map(|(_, arg)| *arg)
+    }
+}
+
+/// Contract two terms, where `first` has the first factor of the formula (`sin(a)` in
+/// `sin(a)cos(b) ± cos(a)sin(b)`, and `cos(a)` in `cos(a)cos(b) ∓ sin(a)sin(b)`)
+fn contract(first: &TrigProduct, second: &TrigProduct) -> Option<Expr> {
+    let sum = |a: &Expr, b: &Expr, minus: bool| {
+        let b = if minus {
+            Expr::Prod(vec![Expr::Const(-1), b.clone()])
+        } else {
+            b.clone()
+        };
+        Box::new(Expr::Sum(vec![a.clone(), b]))
+    };
+    match (first.sins(), second.sins()) {
+        // sin(a)cos(b) ± cos(a)sin(b) = sin(a ± b)
+        (1, 1) => {
+            let (a, b) = (first.arg(true)?, first.arg(false)?);
+            if second.arg(false)? != a || second.arg(true)? != b {
+                return None;
+            }
+            let e = Expr::Sin(sum(a, b, first.negative != second.negative));
+            Some(if first.negative {
+                Expr::Prod(vec![Expr::Const(-1), e])
+            } else {
+                e
+            })
+        }
+        // cos(a)cos(b) ∓ sin(a)sin(b) = cos(a ± b)
+        (0, 2) => {
+            let (a, b) = (first.factors[0].1, first.factors[1].1);
+            let (c, d) = (second.factors[0].1, second.factors[1].1);
+            if !((a, b) == (c, d) || (a, b) == (d, c)) {
+                return None;
+            }
+            let e = Expr::Cos(sum(a, b, first.negative == second.negative));
+            Some(if first.negative {
+                Expr::Prod(vec![Expr::Const(-1), e])
+            } else {
+                e
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{c, cos, ln, sin, var, x};
+    use super::super::{conformance, ConstFoldMode, SimplifyOptions};
+    use super::*;
+
+    fn expanded(mut e: Expr) -> Expr {
+        e.transform_bottom_up(&mut Expr::simplify_expand_trig);
+        e
+    }
+
+    fn contracted(mut e: Expr) -> Expr {
+        e.simplify_contract_trig();
+        e
+    }
+
+    /// Check that two expressions have the same value at a few points
+    fn same_values(a: &Expr, b: &Expr) {
+        conformance::check_value_preserving(a, b, &[-2.3, 0.1, 1.7]).unwrap();
+    }
+
+    #[test]
+    fn two_terms() {
+        let y = || var('y');
+        assert_eq!(
+            expanded(sin(x() + y())),
+            sin(x()) * cos(y()) + cos(x()) * sin(y())
+        );
+        assert_eq!(
+            expanded(cos(x() + y())),
+            cos(x()) * cos(y()) + Expr::Prod(vec![c(-1), sin(x()), sin(y())])
+        );
+        let e = cos(x() * 2 + 1);
+        same_values(&expanded(e.clone()), &e);
+        // Only sin and cos of a sum are expanded
+        for e in [sin(x() * 2), cos(x()), ln(x() + 1)] {
+            assert_eq!(expanded(e.clone()), e);
+        }
+    }
+
+    #[test]
+    fn three_terms() {
+        let e = sin(x() + x() * x() + 3);
+        let expansion = expanded(e.clone());
+        same_values(&expansion, &e);
+        // No sin or cos of a sum is left
+        let mut nested = vec![];
+        expansion.clone().transform_bottom_up(&mut |e| {
+            if let Expr::Sin(arg) | Expr::Cos(arg) = e {
+                nested.push(matches!(**arg, Expr::Sum(_)));
+            }
+        });
+        assert_eq!(nested.len(), 10);
+        assert!(nested.iter().all(|sum| !sum));
+
+        let e = cos(Expr::Sum(vec![x(), c(1), x() * 3, c(-2)]));
+        same_values(&expanded(e.clone()), &e);
+    }
+
+    #[test]
+    fn contraction() {
+        let y = || var('y');
+        let e = sin(x()) * cos(y()) + cos(x()) * sin(y());
+        assert_eq!(contracted(e), sin(x() + y()));
+        // The terms can be in any order
+        let e = cos(y()) * sin(x()) + sin(y()) * cos(x());
+        assert_eq!(contracted(e), sin(x() + y()));
+        assert_eq!(
+            contracted(sin(x()) * cos(y()) - cos(x()) * sin(y())),
+            sin(Expr::Sum(vec![x(), Expr::Prod(vec![c(-1), y()])]))
+        );
+        assert_eq!(contracted(expanded(cos(x() + y()))), cos(x() + y()));
+        let e = cos(x()) * cos(y()) + sin(x()) * sin(y());
+        assert_eq!(
+            contracted(e),
+            cos(Expr::Sum(vec![x(), Expr::Prod(vec![c(-1), y()])]))
+        );
+        let e = sin(x()) * cos(x() * 2) + cos(x()) * sin(x() * 2) + 1;
+        same_values(&contracted(e.clone()), &e);
+        // These don't fit either formula
+        for e in [
+            sin(x()) * cos(y()) + cos(y()) * sin(x()),
+            sin(x()) * sin(y()) + cos(x()) * sin(y()),
+            sin(x()) * cos(y()) * 2 + cos(x()) * sin(y()),
+        ] {
+            assert_eq!(contracted(e.clone()), e);
+        }
+    }
+
+    #[test]
+    fn with_folding() {
+        // cos(x + ln(1)) expands to cos(x)cos(ln(1)) - sin(x)sin(ln(1)), which folds to cos(x)
+        let options = SimplifyOptions {
+            expand_trig: true,
+            ..Default::default()
+        };
+        let mut e = cos(x() + ln(c(1))).simplified_with(&options);
+        e.fold_constants(ConstFoldMode::Exact);
+        assert_eq!(e.simplified(), cos(x()));
+    }
+}
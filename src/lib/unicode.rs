@@ -0,0 +1,389 @@
+use super::sexpr::head;
+use super::{Condition, Expr, ExprKind, Num};
+
+/// Settings for how an expression is written by [`Expr::to_unicode_with`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UnicodeOptions {
+    /// Only write ASCII characters, for terminals which can't show anything else: powers are
+    /// written with `^`, multiplication with `*`, and roots and π as `sqrt(x)` and `pi`.
+    pub ascii: bool,
+    /// Start a new line between two terms of the outermost sum after this many terms, like
+    /// [`LatexOptions::max_terms_per_line`](super::LatexOptions::max_terms_per_line). The new
+    /// lines are indented, and start with the sign of their first term. By default expressions
+    /// are written on one line.
+    pub max_terms_per_line: Option<usize>,
+}
+
+/// The characters which are different when only ASCII is written
+struct Symbols {
+    ascii: bool,
+    times: &'static str,
+    /// Between the mantissa and power of ten of a [`Expr::Scaled`]
+    scale_times: &'static str,
+    sqrt: &'static str,
+    pi: &'static str,
+    less_eq: &'static str,
+    sum: &'static str,
+    hole: &'static str,
+}
+
+const UNICODE: Symbols = Symbols {
+    ascii: false,
+    times: "·",
+    scale_times: "×",
+    sqrt: "√",
+    pi: "π",
+    less_eq: "≤",
+    sum: "∑",
+    hole: "□",
+};
+
+const ASCII: Symbols = Symbols {
+    ascii: true,
+    times: "*",
+    scale_times: "*",
+    sqrt: "sqrt",
+    pi: "pi",
+    less_eq: "<=",
+    sum: "sum",
+    hole: "?",
+};
+
+/// The superscript of each digit
+const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+impl Expr {
+    /// Write the expression as text for a terminal (see [`Expr::to_unicode_with`]).
+    pub fn to_unicode(&self) -> String {
+        self.to_unicode_with(&UnicodeOptions::default())
+    }
+
+    /// Write the expression as compact text for a terminal, e.g. `3x² + 1/(x + 1)`.
+    ///
+    /// Whole number exponents are written as superscripts (e.g. `x⁻¹`), and other exponents
+    /// with `^`. Negative powers in a product are written as a fraction with `/`. Factors are
+    /// written next to each other unless that would run two numbers together, in which case they
+    /// are separated with `·`. Brackets are only added where they are needed, which is decided
+    /// in the same way as for [`Expr::to_latex`]. `arccos(-1)` is written as π.
+    pub fn to_unicode_with(&self, options: &UnicodeOptions) -> String {
+        let symbols = match options.ascii {
+            true => &ASCII,
+            false => &UNICODE,
+        };
+        match self {
+            Expr::Sum(v) if !v.is_empty() => sum(v, symbols, options.max_terms_per_line),
+            _ => self.unicode(symbols),
+        }
+    }
+
+    fn unicode(&self, symbols: &Symbols) -> String {
+        if let (Expr::Prod(_), Some((numerator, denominator))) = (self, self.as_fraction()) {
+            return fraction(&numerator, &denominator, symbols);
+        }
+        match self {
+            Expr::Const(n) => n.to_string(),
+            Expr::X => "x".to_string(),
+            Expr::Var(v) => v.0.to_string(),
+            Expr::I => "i".to_string(),
+            Expr::E => "e".to_string(),
+            Expr::Hole(id) => format!("{}{}", symbols.hole, id.0),
+            Expr::Deriv(v, t) => format!("d{}/d{}", v.0, t.0),
+            Expr::Scaled(1, e) => format!("10{}", exponent(*e as Num, symbols)),
+            Expr::Scaled(m, e) => format!(
+                "{m}{}10{}",
+                symbols.scale_times,
+                exponent(*e as Num, symbols)
+            ),
+            Expr::Arccos(x) if **x == Expr::Const(-1) => symbols.pi.to_string(),
+            Expr::Neg(e) => match e.as_ref() {
+                Expr::Sum(_) | Expr::Neg(_) | Expr::Const(..=-1) => {
+                    format!("-({})", e.unicode(symbols))
+                }
+                e => format!("-{}", e.unicode(symbols)),
+            },
+            Expr::Sum(v) if v.is_empty() => "0".to_string(),
+            Expr::Sum(v) => sum(v, symbols, None),
+            Expr::Prod(v) => product(v, symbols),
+            Expr::Pow(a, b) => power(a, b, symbols),
+            Expr::Ln(x)
+            | Expr::Sin(x)
+            | Expr::Cos(x)
+            | Expr::Arcsin(x)
+            | Expr::Arccos(x)
+            | Expr::Arctan(x)
+            | Expr::Sinh(x)
+            | Expr::Cosh(x)
+            | Expr::Tanh(x)
+            | Expr::Arsinh(x)
+            | Expr::Arcosh(x)
+            | Expr::Artanh(x) => format!("{}({})", head(self.kind()), x.unicode(symbols)),
+            Expr::Factorial(x) if x.kind().is_atom() => format!("{}!", x.unicode(symbols)),
+            Expr::Factorial(x) => format!("({})!", x.unicode(symbols)),
+            Expr::SumOver(k, from, to, x) => {
+                let body = match x.kind() {
+                    ExprKind::Sum | ExprKind::Neg => format!("({})", x.unicode(symbols)),
+                    _ => x.unicode(symbols),
+                };
+                format!(
+                    "{}({}={}..{}) {body}",
+                    symbols.sum,
+                    k.0,
+                    from.unicode(symbols),
+                    to.unicode(symbols)
+                )
+            }
+            Expr::Piecewise(v) => {
+                let branches: Vec<String> = v
+                    .iter()
+                    .map(|(condition, e)| {
+                        let condition = match condition {
+                            Condition::Less(a, b) => {
+                                format!("if {} < {}", a.unicode(symbols), b.unicode(symbols))
+                            }
+                            Condition::LessEq(a, b) => format!(
+                                "if {} {} {}",
+                                a.unicode(symbols),
+                                symbols.less_eq,
+                                b.unicode(symbols)
+                            ),
+                            Condition::Equal(a, b) => {
+                                format!("if {} = {}", a.unicode(symbols), b.unicode(symbols))
+                            }
+                            Condition::Otherwise => "otherwise".to_string(),
+                        };
+                        format!("{} {condition}", e.unicode(symbols))
+                    })
+                    .collect();
+                format!("{{{}}}", branches.join("; "))
+            }
+        }
+    }
+}
+
+/// Write a sum which has at least one term, starting a new line after every `max_terms` terms
+fn sum(v: &[Expr], symbols: &Symbols, max_terms: Option<usize>) -> String {
+    let mut str = v[0].unicode(symbols);
+    for (i, e) in v.iter().enumerate().skip(1) {
+        // The sign is written with the term, so a new line never separates them
+        let (sign, term) = match e {
+            Expr::Neg(e) if !matches!(**e, Expr::Sum(_) | Expr::Neg(_)) => {
+                ("-", e.unicode(symbols))
+            }
+            Expr::Const(n) if *n < 0 => ("-", n.unsigned_abs().to_string()),
+            e => ("+", e.unicode(symbols)),
+        };
+        match max_terms.is_some_and(|max| i % max.max(1) == 0) {
+            true => str += &format!("\n  {sign} {term}"),
+            false => str += &format!(" {sign} {term}"),
+        }
+    }
+    str
+}
+
+/// Write a product, with the factors next to each other where that can't be misread
+fn product(v: &[Expr], symbols: &Symbols) -> String {
+    let Some((first, rest)) = v.split_first() else {
+        return "1".to_string();
+    };
+    // A 1 or -1 in front can be left out, unless a digit would then start the product
+    let droppable = rest
+        .first()
+        .is_some_and(|e| !factor(e, symbols).starts_with(|c: char| c.is_ascii_digit()));
+    let mut str = match first {
+        Expr::Const(1) if droppable => String::new(),
+        Expr::Const(-1) if droppable => "-".to_string(),
+        Expr::Const(_) | Expr::Scaled(..) | Expr::Neg(_) => first.unicode(symbols),
+        e => factor(e, symbols),
+    };
+    for e in rest {
+        let factor = factor(e, symbols);
+        // A digit after anything could be read as part of it, and so could anything after an
+        // exponent written with ^
+        let previous = str.rsplit(symbols.times).next().unwrap_or_default();
+        if factor.starts_with(|c: char| c.is_ascii_digit()) || previous.contains('^') {
+            str += symbols.times;
+        }
+        str += &factor;
+    }
+    str
+}
+
+/// Write a factor of a product which isn't the first one, in brackets if it needs them
+fn factor(e: &Expr, symbols: &Symbols) -> String {
+    let text = e.unicode(symbols);
+    let grouped = match e {
+        Expr::Const(n) => *n < 0,
+        Expr::Scaled(..) => true,
+        e => e.kind().needs_parens_in_prod() || e.kind() == ExprKind::Prod,
+    };
+    match grouped {
+        true => format!("({text})"),
+        false => text,
+    }
+}
+
+/// Write a power, as a root if it is one
+fn power(a: &Expr, b: &Expr, symbols: &Symbols) -> String {
+    let radical = match b.root_index() {
+        Some(2) => Some(symbols.sqrt),
+        Some(3) if !symbols.ascii => Some("∛"),
+        Some(4) if !symbols.ascii => Some("∜"),
+        _ => None,
+    };
+    if let Some(radical) = radical {
+        let radicand = a.unicode(symbols);
+        return match a.kind().is_atom() && !symbols.ascii {
+            true => format!("{radical}{radicand}"),
+            false => format!("{radical}({radicand})"),
+        };
+    }
+    let grouped = match a {
+        Expr::Const(n) => *n < 0,
+        Expr::Pow(..) => true,
+        a => a.kind().needs_parens_in_pow_base(),
+    };
+    let base = match grouped {
+        true => format!("({})", a.unicode(symbols)),
+        false => a.unicode(symbols),
+    };
+    match b {
+        Expr::Const(n) => format!("{base}{}", exponent(*n, symbols)),
+        Expr::X | Expr::Var(_) | Expr::E | Expr::I => format!("{base}^{}", b.unicode(symbols)),
+        _ => format!("{base}^({})", b.unicode(symbols)),
+    }
+}
+
+/// Write a whole number exponent, as a superscript unless only ASCII is written
+fn exponent(n: Num, symbols: &Symbols) -> String {
+    if symbols.ascii {
+        return match n < 0 {
+            true => format!("^({n})"),
+            false => format!("^{n}"),
+        };
+    }
+    n.to_string()
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => SUPERSCRIPTS[d as usize],
+            None => '⁻',
+        })
+        .collect()
+}
+
+/// Write a fraction with `/`, with brackets around the numerator and denominator when they
+/// would otherwise be split up
+fn fraction(numerator: &Expr, denominator: &Expr, symbols: &Symbols) -> String {
+    let n = numerator.unicode(symbols);
+    let d = denominator.unicode(symbols);
+    let n = match numerator.kind() {
+        ExprKind::Sum | ExprKind::SumOver => format!("({n})"),
+        _ => n,
+    };
+    let d = match denominator {
+        Expr::Const(n) if *n >= 0 => d,
+        Expr::Pow(a, b) if !matches!(**b, Expr::Const(..=-1)) && b.root_index().is_none() => {
+            match a.kind().is_atom() {
+                true => d,
+                false => format!("({d})"),
+            }
+        }
+        e if e.kind().is_atom() => d,
+        e if e.kind().arity() == Some(1) && e.kind() != ExprKind::Neg => d,
+        _ => format!("({d})"),
+    };
+    format!("{n}/{d}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::prelude::{
+        c, cos, factorial, ln, pi, pow, recip, scaled, sin, sqrt, sum_over, var, x,
+    };
+    use super::*;
+
+    const ASCII_ONLY: UnicodeOptions = UnicodeOptions {
+        ascii: true,
+        max_terms_per_line: None,
+    };
+
+    #[test]
+    fn golden() {
+        let cases = [
+            (c(3) * pow(x(), c(2)) - x() + 1, "3x² - x + 1"),
+            (pow(x(), c(-1)), "x⁻¹"),
+            (x() * pow(var('y'), c(-2)), "x/y²"),
+            ((x() + 1) / (x() - 1), "(x + 1)/(x - 1)"),
+            (c(1) / (c(1) / x() + 1), "1/(1/x + 1)"),
+            (sqrt(x() + 1) * sin(x()), "√(x + 1)sin(x)"),
+            (c(2) * pi(), "2π"),
+            (pow(x(), x() + 1), "x^(x + 1)"),
+            (pow(pow(x(), c(2)), var('y')), "(x²)^y"),
+            (pow(c(-2), c(3)) * c(5), "(-2)³·5"),
+            (Expr::Prod(vec![c(1), c(3)]), "1·3"),
+            (Expr::Prod(vec![c(-1), c(3)]), "-1·3"),
+            (Expr::Prod(vec![c(-1), x()]), "-x"),
+            (-(x() + 1) * ln(x()), "-(x + 1)ln(x)"),
+            (scaled(6, 23) * x(), "6×10²³x"),
+            (factorial(x() + 1) + cos(x()), "(x + 1)! + cos(x)"),
+            (
+                sum_over('k', c(1), var('n'), pow(var('k'), c(2))),
+                "∑(k=1..n) k²",
+            ),
+        ];
+        for (e, text) in cases {
+            assert_eq!(e.to_unicode(), text, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn ascii() {
+        let cases = [
+            (c(3) * pow(x(), c(2)) - x() + 1, "3x^2 - x + 1"),
+            (pow(x(), c(-1)), "x^(-1)"),
+            (pow(x(), c(2)) * var('y'), "x^2*y"),
+            (sqrt(x()) * c(2) * pi(), "sqrt(x)*2pi"),
+            (recip(x() + 1) * pow(x(), c(1) / 3), "x^(1/3)/(x + 1)"),
+            (scaled(6, 23), "6*10^23"),
+        ];
+        for (e, text) in cases {
+            let printed = e.to_unicode_with(&ASCII_ONLY);
+            assert!(printed.is_ascii(), "{printed}");
+            assert_eq!(printed, text, "{e:?}");
+        }
+    }
+
+    #[test]
+    fn long_sums() {
+        // x^9 - x^8 + ... + x - 1, with every other term negative
+        let e = Expr::Sum(
+            (0..10)
+                .map(|n| {
+                    let term = pow(x(), c(9 - n));
+                    if n % 2 == 1 {
+                        -term
+                    } else {
+                        term
+                    }
+                })
+                .collect(),
+        );
+        let options = UnicodeOptions {
+            max_terms_per_line: Some(3),
+            ..UnicodeOptions::default()
+        };
+        assert_eq!(
+            e.to_unicode_with(&options),
+            "x⁹ - x⁸ + x⁷\n  \
+             - x⁶ + x⁵ - x⁴\n  \
+             + x³ - x² + x¹\n  \
+             - x⁰"
+        );
+        // Only the outermost sum is split
+        let inner = x() + 1 + pow(x(), c(2)) + pow(x(), c(3));
+        let e = Expr::Sum(vec![x(), sin(inner), c(-1), c(2)]);
+        assert_eq!(
+            e.to_unicode_with(&options),
+            "x + sin(x + 1 + x² + x³) - 1\n  + 2"
+        );
+    }
+}
@@ -1,3 +1,213 @@
+//! A command line interface to the library, for working with expressions written as latex, e.g.
+//! `math-program derive "sin(x^2)" --order 2`. Run `math-program --help` for the commands.
+
+#![allow(special_module_name)]
+
 pub mod lib;
+mod repl;
+
+use std::io::Read;
+use std::process::ExitCode;
+
+use lib::{Expr, MathError};
+
+const USAGE: &str = "\
+usage: math-program <command> [expression] [options]
+
+commands:
+    simplify    simplify the expression
+    derive      differentiate the expression with respect to x
+    latex       read the expression and write it out again
+    eval        evaluate the expression at a value of x
+    repl        read expressions and commands a line at a time (see :help)
+
+The expression is read from standard input if it isn't given or is `-`.
+
+options:
+    --order <n>       how many times to differentiate, for derive (default 1)
+    --at <x>          the value of x, for eval (required)
+    --format <form>   how to write the result: latex (default), sexpr or both
+    -h, --help        show this message";
+
+/// How results which are expressions are written
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Format {
+    Latex,
+    Sexpr,
+    Both,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Command {
+    Simplify,
+    Derive,
+    Latex,
+    Eval,
+    Repl,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    /// The expression, or `None` to read it from standard input
+    expr: Option<String>,
+    order: u32,
+    at: Option<f64>,
+    format: Format,
+}
+
+/// Read the arguments after the program name, or get a message saying what is wrong with them
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("simplify") => Command::Simplify,
+        Some("derive") => Command::Derive,
+        Some("latex") => Command::Latex,
+        Some("eval") => Command::Eval,
+        Some("repl") => Command::Repl,
+        Some(command) => return Err(format!("unknown command `{command}`")),
+        None => return Err("no command given".to_string()),
+    };
+    let mut parsed = Args {
+        command,
+        expr: None,
+        order: 1,
+        at: None,
+        format: Format::Latex,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "--order" => {
+                let order = value("--order")?;
+                parsed.order = order
+                    .parse()
+                    .map_err(|_| format!("`{order}` isn't a valid order"))?;
+            }
+            "--at" => {
+                let at = value("--at")?;
+                parsed.at = Some(at.parse().map_err(|_| format!("`{at}` isn't a number"))?);
+            }
+            "--format" => {
+                parsed.format = match value("--format")?.as_str() {
+                    "latex" => Format::Latex,
+                    "sexpr" => Format::Sexpr,
+                    "both" => Format::Both,
+                    format => return Err(format!("unknown format `{format}`")),
+                };
+            }
+            "-" if parsed.expr.is_none() => (),
+            arg if arg.starts_with("--") => return Err(format!("unknown option `{arg}`")),
+            _ if parsed.expr.is_some() => return Err("more than one expression given".to_string()),
+            expr => parsed.expr = Some(expr.to_string()),
+        }
+    }
+    if command == Command::Repl && parsed.expr.is_some() {
+        return Err("repl reads its expressions from standard input".to_string());
+    }
+    if command == Command::Eval && parsed.at.is_none() {
+        return Err("eval needs a value of x with --at".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Run a command on an expression, giving the text to print
+fn run(args: &Args, expr: &str) -> Result<String, MathError> {
+    let e = Expr::from_latex(expr)?;
+    let result = match args.command {
+        Command::Simplify => e.simplified(),
+        Command::Derive => {
+            let mut e = e;
+            for _ in 0..args.order {
+                e = e.try_derivative()?.simplified();
+            }
+            e
+        }
+        Command::Latex => e,
+        Command::Eval => return Ok(e.try_eval(args.at.unwrap_or_default())?.to_string()),
+        Command::Repl => unreachable!("the repl doesn't run a single expression"),
+    };
+    Ok(match args.format {
+        Format::Latex => result.to_latex(),
+        Format::Sexpr => result.to_sexpr(),
+        Format::Both => format!("{}\n{}", result.to_latex(), result.to_sexpr()),
+    })
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(args) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.command == Command::Repl {
+        return match repl::run(std::io::stdin().lock(), std::io::stdout().lock()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("error: {error}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let expr = match &args.expr {
+        Some(expr) => expr.clone(),
+        None => {
+            let mut expr = String::new();
+            if let Err(error) = std::io::stdin().read_to_string(&mut expr) {
+                eprintln!("error: couldn't read the expression: {error}");
+                return ExitCode::FAILURE;
+            }
+            expr
+        }
+    };
+    match run(&args, expr.trim()) {
+        Ok(output) => {
+            println!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
 
-fn main() {}
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            args(&["derive", "sin(x^2)", "--order", "2", "--format", "both"]),
+            Ok(Args {
+                command: Command::Derive,
+                expr: Some("sin(x^2)".to_string()),
+                order: 2,
+                at: None,
+                format: Format::Both,
+            })
+        );
+        let eval = args(&["eval", "--at", "-2.5", "-"]).unwrap();
+        assert_eq!((eval.at, eval.expr), (Some(-2.5), None));
+        assert!(args(&["eval", "x"]).is_err());
+        assert!(args(&["integrate", "x"]).is_err());
+        assert!(args(&["simplify", "x", "y"]).is_err());
+        assert!(args(&["derive", "x", "--order"]).is_err());
+        assert!(args(&["latex", "x", "--format", "html"]).is_err());
+        assert!(args(&[]).is_err());
+        assert_eq!(args(&["repl"]).map(|args| args.command), Ok(Command::Repl));
+        assert!(args(&["repl", "x"]).is_err());
+    }
+}
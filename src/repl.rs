@@ -0,0 +1,146 @@
+//! An interactive session for `math-program repl`, where expressions can be named and used in
+//! later lines.
+
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::lib::{Expr, MathError, ParseOptions};
+
+/// The name of the last result
+const ANS: &str = "ans";
+
+const HELP: &str = "\
+Type an expression to see it, or `name = expression` to name it. The last result is `ans`.
+
+commands:
+    :simplify <expr>    simplify the expression
+    :derive <expr>      differentiate the expression with respect to x, and simplify
+    :latex <expr>       write the expression as latex
+    :eval <expr> <x>    evaluate the expression at a value of x
+    :help               show this message";
+
+/// The named expressions of a session, which are read as the expression they stand for
+#[derive(Debug, Default)]
+pub struct Session {
+    options: ParseOptions,
+}
+
+impl Session {
+    /// Run one line, and get the text to print, or a message saying what went wrong. The result
+    /// of any line which gives an expression becomes `ans`.
+    pub fn run_line(&mut self, line: &str) -> Result<Option<String>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        if let Some(command) = line.strip_prefix(':') {
+            let (command, rest) = command.split_once(' ').unwrap_or((command, ""));
+            return self.command(command, rest.trim()).map(Some);
+        }
+        // The left of an `=` has to be a name, since `=` is also in sums like `\sum_{k=1}^n k`
+        let (name, expr) = match line.split_once('=') {
+            Some((name, expr)) if is_word(name.trim()) => (Some(name.trim()), expr),
+            _ => (None, line),
+        };
+        if let Some(name) = name {
+            check_name(name)?;
+        }
+        let e = self.read(expr)?;
+        if let Some(name) = name {
+            self.options.names.insert(name.to_string(), e.clone());
+        }
+        Ok(Some(self.result(e)))
+    }
+
+    fn command(&mut self, command: &str, rest: &str) -> Result<String, String> {
+        let result = match command {
+            "simplify" => self.read(rest)?.simplified(),
+            "derive" => self
+                .read(rest)?
+                .try_derivative()
+                .map_err(|e| MathError::from(e).to_string())?
+                .simplified(),
+            "latex" => return Ok(self.read(rest)?.to_latex()),
+            "eval" => {
+                let (expr, at) = rest
+                    .rsplit_once(char::is_whitespace)
+                    .ok_or(":eval needs an expression and a value of x")?;
+                let at: f64 = at.parse().map_err(|_| format!("`{at}` isn't a number"))?;
+                let value = self.read(expr)?.try_eval(at).map_err(|e| e.to_string())?;
+                return Ok(value.to_string());
+            }
+            "help" => return Ok(HELP.to_string()),
+            command => return Err(format!("unknown command `:{command}`, try :help")),
+        };
+        Ok(self.result(result))
+    }
+
+    /// Read an expression, with the names in it replaced by what they stand for
+    fn read(&self, expr: &str) -> Result<Expr, String> {
+        Expr::from_latex_with(expr.trim(), &self.options)
+            .map_err(|e| MathError::from(e).to_string())
+    }
+
+    /// Keep an expression as `ans`, and write it out
+    fn result(&mut self, e: Expr) -> String {
+        let text = e.to_unicode();
+        self.options.names.insert(ANS.to_string(), e);
+        text
+    }
+}
+
+/// Whether some text is one word, which might be a name
+fn is_word(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Check that a word can be a name, without hiding something which is needed
+fn check_name(name: &str) -> Result<(), String> {
+    // A function name on its own (e.g. `sin`) isn't an expression, so it can't be read either
+    let reserved = matches!(name, "x" | "e" | "i" | ANS) || Expr::from_latex(name).is_err();
+    match reserved {
+        true => Err(format!("`{name}` can't be used as a name")),
+        false => Ok(()),
+    }
+}
+
+/// Run a session on each line of `input`, writing the results to `output` and errors to
+/// standard error. A prompt is only written when the input is a terminal.
+pub fn run(input: impl BufRead + IsTerminal, mut output: impl Write) -> io::Result<()> {
+    let prompt = input.is_terminal();
+    let mut session = Session::default();
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        match session.run_line(&line?) {
+            Ok(Some(text)) => writeln!(output, "{text}")?,
+            Ok(None) => (),
+            Err(message) => eprintln!("error: {message}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let mut session = Session::default();
+        let mut run = |line: &str| session.run_line(line);
+        assert_eq!(run("f = x^2 + 1"), Ok(Some("x² + 1".to_string())));
+        assert_eq!(run("2f"), Ok(Some("2(x² + 1)".to_string())));
+        assert_eq!(run(":eval ans 3"), Ok(Some("20".to_string())));
+        assert_eq!(run("  # a comment"), Ok(None));
+        assert!(run("x = 2").is_err());
+        assert!(run("sin = 2").is_err());
+        assert!(run(":integrate f").is_err());
+        // A sum isn't a name
+        assert!(run("\\sum_{k=1}^{3} k").is_ok());
+    }
+}
@@ -0,0 +1,125 @@
+//! Run the command line interface on some expressions, and check what it prints
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], stdin: Option<&str>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_math-program"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut input = child.stdin.take().unwrap();
+    input.write_all(stdin.unwrap_or("").as_bytes()).unwrap();
+    drop(input);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    let output = run(args, None);
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn simplify() {
+    assert_eq!(stdout(&["simplify", "x + x"]), "x(2)");
+    assert_eq!(stdout(&["simplify", "2^3 + 1"]), "9");
+}
+
+#[test]
+fn derive() {
+    assert_eq!(stdout(&["derive", "x^3"]), "x^{2}(3)");
+    assert_eq!(stdout(&["derive", "x^3", "--order", "3"]), "6");
+    assert_eq!(
+        stdout(&["derive", "x^2", "--format", "both"]),
+        "x(2)\n(* x 2)"
+    );
+}
+
+#[test]
+fn latex_and_sexpr() {
+    assert_eq!(stdout(&["latex", "\\frac{1}{x}"]), "x^{-1}");
+    assert_eq!(stdout(&["latex", "sin(x)", "--format", "sexpr"]), "(sin x)");
+}
+
+#[test]
+fn eval() {
+    assert_eq!(stdout(&["eval", "x^2 + 1", "--at", "2.5"]), "7.25");
+    let output = run(&["eval", "--at", "3"], Some("2x\n"));
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+}
+
+#[test]
+fn errors() {
+    // Text which can't be read
+    let output = run(&["simplify", "x + )"], None);
+    assert_eq!(output.status.code(), Some(1));
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.starts_with("error: unexpected ')' at 4"), "{error}");
+    assert!(output.stdout.is_empty());
+
+    // A value which can't be evaluated
+    let output = run(&["eval", "ln(x)", "--at", "-1"], None);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("ln"));
+
+    // Arguments which don't make sense
+    for args in [
+        &["eval", "x"][..],
+        &["frobnicate"],
+        &["derive", "x", "--order", "two"],
+    ] {
+        let output = run(args, None);
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(String::from_utf8(output.stderr).unwrap().contains("usage"));
+    }
+}
+
+#[test]
+fn repl() {
+    let script = "\
+f = x^2 + 1
+:derive f
+:simplify ans + x
+:latex 2f
+:eval f 3
+g = \\frac{1}{f}
+:eval g 0
+";
+    let output = run(&["repl"], Some(script));
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "x² + 1\nx·2\nx·3\n(2)(x^{2}+1)\n10\n1/(x² + 1)\n1\n"
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn repl_errors() {
+    // Errors are reported, and the session carries on with what it had
+    let script = "\
+f = x +
+f = x + 1
+:frobnicate f
+ans = 2
+:eval ln(f) -5
+:eval f
+sin = 3
+f
+";
+    let output = run(&["repl"], Some(script));
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "x + 1\nx + 1\n");
+    let errors = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(errors.lines().count(), 6, "{errors}");
+    assert!(errors.lines().all(|line| line.starts_with("error: ")));
+    assert!(errors.contains("unknown command `:frobnicate`"), "{errors}");
+}